@@ -0,0 +1,2 @@
+ALTER TABLE public.peers
+    DROP COLUMN connectable;
@@ -0,0 +1,2 @@
+ALTER TABLE public.peers
+    ADD COLUMN connectable boolean;
@@ -0,0 +1,2 @@
+ALTER TABLE public.categories
+    DROP COLUMN allow_web_seeds;
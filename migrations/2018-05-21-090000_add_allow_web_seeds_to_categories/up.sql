@@ -0,0 +1,2 @@
+ALTER TABLE public.categories
+    ADD COLUMN allow_web_seeds boolean NOT NULL DEFAULT true;
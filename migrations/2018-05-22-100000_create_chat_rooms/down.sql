@@ -0,0 +1,5 @@
+ALTER TABLE public.chat_messages
+    DROP CONSTRAINT chat_messages_chat_fkey,
+    ALTER COLUMN chat SET DEFAULT 0;
+
+DROP TABLE public.chat_rooms;
@@ -0,0 +1,27 @@
+CREATE TABLE public.chat_rooms
+(
+    id smallserial NOT NULL,
+    slug character varying(32) COLLATE pg_catalog."default" NOT NULL,
+    name character varying(100) COLLATE pg_catalog."default" NOT NULL,
+    topic text COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    updated_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT chat_rooms_pkey PRIMARY KEY (id),
+    CONSTRAINT chat_rooms_slug_key UNIQUE (slug)
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+-- the former hard coded chatrooms, the slugs match the existing acl namespaces
+INSERT INTO public.chat_rooms (id, slug, name) VALUES (1, 'public', 'Shoutbox'), (2, 'team', 'Teambox');
+SELECT setval('public.chat_rooms_id_seq', (SELECT MAX(id) FROM public.chat_rooms));
+
+DELETE FROM public.chat_messages WHERE chat NOT IN (SELECT id FROM public.chat_rooms);
+ALTER TABLE public.chat_messages
+    ALTER COLUMN chat DROP DEFAULT,
+    ADD CONSTRAINT chat_messages_chat_fkey FOREIGN KEY (chat)
+        REFERENCES public.chat_rooms (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE;
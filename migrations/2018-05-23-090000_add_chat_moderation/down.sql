@@ -0,0 +1,8 @@
+DROP TABLE public.chat_mutes;
+
+DROP INDEX public.chat_messages_deleted_at_key;
+ALTER TABLE public.chat_messages
+    DROP COLUMN deleted_at;
+
+ALTER TABLE public.chat_rooms
+    DROP COLUMN slow_mode;
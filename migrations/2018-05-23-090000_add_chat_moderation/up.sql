@@ -0,0 +1,44 @@
+ALTER TABLE public.chat_rooms
+    ADD COLUMN slow_mode integer NOT NULL DEFAULT 0;
+
+ALTER TABLE public.chat_messages
+    ADD COLUMN deleted_at timestamp with time zone;
+
+CREATE INDEX chat_messages_deleted_at_key
+    ON public.chat_messages USING btree
+    (deleted_at ASC NULLS LAST)
+    TABLESPACE pg_default;
+
+CREATE TABLE public.chat_mutes
+(
+    id uuid NOT NULL,
+    chat smallint NOT NULL,
+    user_id uuid NOT NULL,
+    issuer_id uuid NOT NULL,
+    reason text COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    expires_at timestamp with time zone NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT chat_mutes_pkey PRIMARY KEY (id),
+    CONSTRAINT chat_mutes_chat_user_id_key UNIQUE (chat, user_id),
+    CONSTRAINT chat_mutes_chat_fkey FOREIGN KEY (chat)
+        REFERENCES public.chat_rooms (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT chat_mutes_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT chat_mutes_issuer_id_fkey FOREIGN KEY (issuer_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX chat_mutes_expires_at_key
+    ON public.chat_mutes USING btree
+    (expires_at)
+    TABLESPACE pg_default;
@@ -0,0 +1,4 @@
+DROP TABLE public.chat_mentions;
+
+ALTER TABLE public.chat_messages
+    DROP COLUMN rendered;
@@ -0,0 +1,28 @@
+ALTER TABLE public.chat_messages
+    ADD COLUMN rendered text COLLATE pg_catalog."default" NOT NULL DEFAULT '';
+
+CREATE TABLE public.chat_mentions
+(
+    message_id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    seen boolean NOT NULL DEFAULT false,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT chat_mentions_pkey PRIMARY KEY (message_id, user_id),
+    CONSTRAINT chat_mentions_message_id_fkey FOREIGN KEY (message_id)
+        REFERENCES public.chat_messages (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT chat_mentions_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX chat_mentions_user_id_seen_key
+    ON public.chat_mentions USING btree
+    (user_id, seen)
+    TABLESPACE pg_default;
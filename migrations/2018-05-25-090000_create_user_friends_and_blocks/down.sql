@@ -0,0 +1,2 @@
+DROP TABLE public.user_blocks;
+DROP TABLE public.user_friends;
@@ -0,0 +1,41 @@
+CREATE TABLE public.user_friends
+(
+    user_id uuid NOT NULL,
+    friend_id uuid NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_friends_pkey PRIMARY KEY (user_id, friend_id),
+    CONSTRAINT user_friends_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT user_friends_friend_id_fkey FOREIGN KEY (friend_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT user_friends_check CHECK (user_id <> friend_id)
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE TABLE public.user_blocks
+(
+    user_id uuid NOT NULL,
+    blocked_id uuid NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_blocks_pkey PRIMARY KEY (user_id, blocked_id),
+    CONSTRAINT user_blocks_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT user_blocks_blocked_id_fkey FOREIGN KEY (blocked_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT user_blocks_check CHECK (user_id <> blocked_id)
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
@@ -0,0 +1,2 @@
+DROP INDEX public.messages_created_at_key;
+DROP INDEX public.messages_search_key;
@@ -0,0 +1,9 @@
+CREATE INDEX messages_search_key
+    ON public.messages USING gin
+    (to_tsvector('simple', subject || ' ' || body))
+    TABLESPACE pg_default;
+
+CREATE INDEX messages_created_at_key
+    ON public.messages USING btree
+    (created_at)
+    TABLESPACE pg_default;
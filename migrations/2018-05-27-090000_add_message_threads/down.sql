@@ -0,0 +1,5 @@
+DROP INDEX public.messages_thread_id_key;
+
+ALTER TABLE public.messages
+    DROP COLUMN parent_id,
+    DROP COLUMN thread_id;
@@ -0,0 +1,14 @@
+ALTER TABLE public.messages
+    ADD COLUMN thread_id uuid,
+    ADD COLUMN parent_id uuid;
+
+-- every existing message starts its own thread
+UPDATE public.messages SET thread_id = id;
+
+ALTER TABLE public.messages
+    ALTER COLUMN thread_id SET NOT NULL;
+
+CREATE INDEX messages_thread_id_key
+    ON public.messages USING btree
+    (thread_id)
+    TABLESPACE pg_default;
@@ -0,0 +1,29 @@
+-- Table: public.notifications
+
+-- DROP TABLE public.notifications;
+
+CREATE TABLE public.notifications
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    kind character varying(32) COLLATE pg_catalog."default" NOT NULL,
+    title character varying(255) COLLATE pg_catalog."default" NOT NULL,
+    body text COLLATE pg_catalog."default" NOT NULL DEFAULT ''::text,
+    link character varying(255) COLLATE pg_catalog."default",
+    is_read boolean NOT NULL DEFAULT false,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT notifications_pkey PRIMARY KEY (id),
+    CONSTRAINT notifications_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX notifications_user_id_is_read_key
+    ON public.notifications USING btree
+    (user_id, is_read)
+    TABLESPACE pg_default;
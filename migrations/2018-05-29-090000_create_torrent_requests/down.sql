@@ -0,0 +1,3 @@
+DROP TABLE public.torrent_request_votes;
+DROP TABLE public.torrent_request_bounties;
+DROP TABLE public.torrent_requests;
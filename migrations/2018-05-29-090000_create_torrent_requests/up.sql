@@ -0,0 +1,101 @@
+-- Table: public.torrent_requests
+
+-- DROP TABLE public.torrent_requests;
+
+CREATE TABLE public.torrent_requests
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    category_id uuid NOT NULL,
+    title character varying(255) COLLATE pg_catalog."default" NOT NULL,
+    description text COLLATE pg_catalog."default" NOT NULL DEFAULT ''::text,
+    bounty bigint NOT NULL DEFAULT 0,
+    votes integer NOT NULL DEFAULT 0,
+    torrent_id uuid,
+    filled_by uuid,
+    filled_at timestamp with time zone,
+    paid_at timestamp with time zone,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    updated_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT torrent_requests_pkey PRIMARY KEY (id),
+    CONSTRAINT torrent_requests_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT torrent_requests_category_id_fkey FOREIGN KEY (category_id)
+        REFERENCES public.categories (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE RESTRICT,
+    CONSTRAINT torrent_requests_torrent_id_fkey FOREIGN KEY (torrent_id)
+        REFERENCES public.torrents (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE SET NULL,
+    CONSTRAINT torrent_requests_filled_by_fkey FOREIGN KEY (filled_by)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE SET NULL
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX torrent_requests_created_at_key
+    ON public.torrent_requests USING btree
+    (created_at)
+    TABLESPACE pg_default;
+
+-- Table: public.torrent_request_bounties
+
+-- DROP TABLE public.torrent_request_bounties;
+
+CREATE TABLE public.torrent_request_bounties
+(
+    id uuid NOT NULL,
+    request_id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    amount bigint NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT torrent_request_bounties_pkey PRIMARY KEY (id),
+    CONSTRAINT torrent_request_bounties_request_id_fkey FOREIGN KEY (request_id)
+        REFERENCES public.torrent_requests (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT torrent_request_bounties_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX torrent_request_bounties_request_id_key
+    ON public.torrent_request_bounties USING btree
+    (request_id)
+    TABLESPACE pg_default;
+
+-- Table: public.torrent_request_votes
+
+-- DROP TABLE public.torrent_request_votes;
+
+CREATE TABLE public.torrent_request_votes
+(
+    request_id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT torrent_request_votes_pkey PRIMARY KEY (request_id, user_id),
+    CONSTRAINT torrent_request_votes_request_id_fkey FOREIGN KEY (request_id)
+        REFERENCES public.torrent_requests (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT torrent_request_votes_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
@@ -0,0 +1,47 @@
+-- Table: public.torrent_reseeds
+
+-- DROP TABLE public.torrent_reseeds;
+
+CREATE TABLE public.torrent_reseeds
+(
+    id uuid NOT NULL,
+    torrent_id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    reseeded_by uuid,
+    reseeded_at timestamp with time zone,
+    CONSTRAINT torrent_reseeds_pkey PRIMARY KEY (id),
+    CONSTRAINT torrent_reseeds_torrent_id_fkey FOREIGN KEY (torrent_id)
+        REFERENCES public.torrents (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT torrent_reseeds_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT torrent_reseeds_reseeded_by_fkey FOREIGN KEY (reseeded_by)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE SET NULL
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX torrent_reseeds_torrent_id_key
+    ON public.torrent_reseeds USING btree
+    (torrent_id, created_at)
+    TABLESPACE pg_default;
+
+-- only one open reseed request per torrent
+CREATE UNIQUE INDEX torrent_reseeds_open_key
+    ON public.torrent_reseeds USING btree
+    (torrent_id)
+    TABLESPACE pg_default
+    WHERE reseeded_at IS NULL;
+
+CREATE INDEX torrent_reseeds_user_id_key
+    ON public.torrent_reseeds USING btree
+    (user_id, created_at)
+    TABLESPACE pg_default;
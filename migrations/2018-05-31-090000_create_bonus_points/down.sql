@@ -0,0 +1,3 @@
+DROP TABLE public.freeleech_torrents;
+DROP TABLE public.bonus_transactions;
+DROP TABLE public.user_bonus;
@@ -0,0 +1,81 @@
+-- Table: public.user_bonus
+
+-- DROP TABLE public.user_bonus;
+
+CREATE TABLE public.user_bonus
+(
+    user_id uuid NOT NULL,
+    points bigint NOT NULL DEFAULT 0,
+    freeleech_tokens integer NOT NULL DEFAULT 0,
+    updated_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_bonus_pkey PRIMARY KEY (user_id),
+    CONSTRAINT user_bonus_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT user_bonus_points_check CHECK (points >= 0),
+    CONSTRAINT user_bonus_freeleech_tokens_check CHECK (freeleech_tokens >= 0)
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+-- Table: public.bonus_transactions
+
+-- DROP TABLE public.bonus_transactions;
+
+CREATE TABLE public.bonus_transactions
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    kind character varying(32) COLLATE pg_catalog."default" NOT NULL,
+    amount bigint NOT NULL,
+    balance bigint NOT NULL,
+    description text COLLATE pg_catalog."default" NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT bonus_transactions_pkey PRIMARY KEY (id),
+    CONSTRAINT bonus_transactions_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX bonus_transactions_user_id_key
+    ON public.bonus_transactions USING btree
+    (user_id, created_at)
+    TABLESPACE pg_default;
+
+CREATE INDEX bonus_transactions_kind_key
+    ON public.bonus_transactions USING btree
+    (kind, created_at)
+    TABLESPACE pg_default;
+
+-- Table: public.freeleech_torrents
+
+-- DROP TABLE public.freeleech_torrents;
+
+CREATE TABLE public.freeleech_torrents
+(
+    user_id uuid NOT NULL,
+    torrent_id uuid NOT NULL,
+    expires_at timestamp with time zone NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT freeleech_torrents_pkey PRIMARY KEY (user_id, torrent_id),
+    CONSTRAINT freeleech_torrents_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT freeleech_torrents_torrent_id_fkey FOREIGN KEY (torrent_id)
+        REFERENCES public.torrents (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
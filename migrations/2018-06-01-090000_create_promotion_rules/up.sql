@@ -0,0 +1,25 @@
+-- Table: public.promotion_rules
+
+-- DROP TABLE public.promotion_rules;
+
+CREATE TABLE public.promotion_rules
+(
+    id uuid NOT NULL,
+    group_id uuid NOT NULL,
+    min_age_days integer NOT NULL DEFAULT 0,
+    min_uploaded bigint NOT NULL DEFAULT 0,
+    min_ratio double precision NOT NULL DEFAULT 0,
+    min_uploads integer NOT NULL DEFAULT 0,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    updated_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT promotion_rules_pkey PRIMARY KEY (id),
+    CONSTRAINT promotion_rules_group_id_key UNIQUE (group_id),
+    CONSTRAINT promotion_rules_group_id_fkey FOREIGN KEY (group_id)
+        REFERENCES public.groups (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
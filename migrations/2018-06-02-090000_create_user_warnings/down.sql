@@ -0,0 +1,3 @@
+DROP TABLE public.user_bans;
+
+DROP TABLE public.user_warnings;
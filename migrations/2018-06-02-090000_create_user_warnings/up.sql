@@ -0,0 +1,64 @@
+CREATE TABLE public.user_warnings
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    issuer_id uuid NOT NULL,
+    reason text COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    severity smallint NOT NULL DEFAULT 1,
+    expires_at timestamp with time zone NOT NULL,
+    revoked_at timestamp with time zone,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_warnings_pkey PRIMARY KEY (id),
+    CONSTRAINT user_warnings_severity_check CHECK (severity > 0),
+    CONSTRAINT user_warnings_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT user_warnings_issuer_id_fkey FOREIGN KEY (issuer_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX user_warnings_user_id_key
+    ON public.user_warnings USING btree
+    (user_id, expires_at)
+    TABLESPACE pg_default;
+
+CREATE TABLE public.user_bans
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    issuer_id uuid NOT NULL,
+    reason text COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    expires_at timestamp with time zone,
+    lifted_at timestamp with time zone,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_bans_pkey PRIMARY KEY (id),
+    CONSTRAINT user_bans_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE,
+    CONSTRAINT user_bans_issuer_id_fkey FOREIGN KEY (issuer_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX user_bans_user_id_key
+    ON public.user_bans USING btree
+    (user_id, lifted_at)
+    TABLESPACE pg_default;
+
+CREATE INDEX user_bans_expires_at_key
+    ON public.user_bans USING btree
+    (expires_at)
+    TABLESPACE pg_default;
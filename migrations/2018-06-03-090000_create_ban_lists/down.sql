@@ -0,0 +1,3 @@
+DROP TABLE public.email_domain_bans;
+
+DROP TABLE public.ip_bans;
@@ -0,0 +1,37 @@
+CREATE TABLE public.ip_bans
+(
+    id uuid NOT NULL,
+    network inet NOT NULL,
+    reason text COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    issuer_id uuid NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT ip_bans_pkey PRIMARY KEY (id),
+    CONSTRAINT ip_bans_network_key UNIQUE (network),
+    CONSTRAINT ip_bans_issuer_id_fkey FOREIGN KEY (issuer_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE TABLE public.email_domain_bans
+(
+    id uuid NOT NULL,
+    domain character varying(255) COLLATE pg_catalog."default" NOT NULL,
+    reason text COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    issuer_id uuid NOT NULL,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT email_domain_bans_pkey PRIMARY KEY (id),
+    CONSTRAINT email_domain_bans_domain_key UNIQUE (domain),
+    CONSTRAINT email_domain_bans_issuer_id_fkey FOREIGN KEY (issuer_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
@@ -0,0 +1,3 @@
+DROP TABLE public.user_client_history;
+
+DROP TABLE public.user_ip_history;
@@ -0,0 +1,45 @@
+CREATE TABLE public.user_ip_history
+(
+    user_id uuid NOT NULL,
+    ip_address inet NOT NULL,
+    source character varying(16) COLLATE pg_catalog."default" NOT NULL,
+    first_seen timestamp with time zone NOT NULL DEFAULT now(),
+    last_seen timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_ip_history_pkey PRIMARY KEY (user_id, ip_address, source),
+    CONSTRAINT user_ip_history_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX user_ip_history_ip_address_key
+    ON public.user_ip_history USING btree
+    (ip_address, last_seen)
+    TABLESPACE pg_default;
+
+CREATE TABLE public.user_client_history
+(
+    user_id uuid NOT NULL,
+    peer_id bytea NOT NULL,
+    user_agent character varying(255) COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    first_seen timestamp with time zone NOT NULL DEFAULT now(),
+    last_seen timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_client_history_pkey PRIMARY KEY (user_id, peer_id),
+    CONSTRAINT user_client_history_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX user_client_history_peer_id_key
+    ON public.user_client_history USING btree
+    (peer_id, last_seen)
+    TABLESPACE pg_default;
@@ -0,0 +1,23 @@
+CREATE TABLE public.user_audit_log
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    action character varying(32) COLLATE pg_catalog."default" NOT NULL,
+    ip_address inet,
+    details text COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_audit_log_pkey PRIMARY KEY (id),
+    CONSTRAINT user_audit_log_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX user_audit_log_user_id_key
+    ON public.user_audit_log USING btree
+    (user_id, created_at)
+    TABLESPACE pg_default;
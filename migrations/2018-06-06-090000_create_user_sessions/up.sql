@@ -0,0 +1,23 @@
+CREATE TABLE public.user_sessions
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    ip_address inet,
+    user_agent character varying(255) COLLATE pg_catalog."default" NOT NULL DEFAULT '',
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    last_seen timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT user_sessions_pkey PRIMARY KEY (id),
+    CONSTRAINT user_sessions_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX user_sessions_user_id_key
+    ON public.user_sessions USING btree
+    (user_id, last_seen)
+    TABLESPACE pg_default;
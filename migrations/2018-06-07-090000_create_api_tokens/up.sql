@@ -0,0 +1,25 @@
+CREATE TABLE public.api_tokens
+(
+    id uuid NOT NULL,
+    user_id uuid NOT NULL,
+    name character varying(64) COLLATE pg_catalog."default" NOT NULL,
+    scopes text[] NOT NULL,
+    expires_at timestamp with time zone NOT NULL,
+    last_used_at timestamp with time zone,
+    revoked_at timestamp with time zone,
+    created_at timestamp with time zone NOT NULL DEFAULT now(),
+    CONSTRAINT api_tokens_pkey PRIMARY KEY (id),
+    CONSTRAINT api_tokens_user_id_fkey FOREIGN KEY (user_id)
+        REFERENCES public.users (id) MATCH SIMPLE
+        ON UPDATE CASCADE
+        ON DELETE CASCADE
+)
+WITH (
+    OIDS = FALSE
+)
+TABLESPACE pg_default;
+
+CREATE INDEX api_tokens_user_id_key
+    ON public.api_tokens USING btree
+    (user_id)
+    TABLESPACE pg_default;
@@ -0,0 +1,2 @@
+DROP TRIGGER torrent_requests_refund_bounties ON public.torrent_requests;
+DROP FUNCTION public.refund_request_bounties();
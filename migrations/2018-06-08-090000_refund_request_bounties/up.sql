@@ -0,0 +1,27 @@
+-- Refund the bounties of unpaid requests, whenever a request is deleted.
+-- This also covers requests, which are deleted by the cascade of a deleted requester.
+
+CREATE OR REPLACE FUNCTION public.refund_request_bounties()
+    RETURNS trigger
+    LANGUAGE plpgsql
+AS $BODY$
+BEGIN
+    IF OLD.paid_at IS NULL THEN
+        UPDATE public.users u SET uploaded = u.uploaded + b.amount
+        FROM (
+            SELECT user_id, SUM(amount)::int8 AS amount
+            FROM public.torrent_request_bounties
+            WHERE request_id = OLD.id
+            GROUP BY user_id
+        ) b
+        WHERE u.id = b.user_id;
+    END IF;
+    RETURN OLD;
+END;
+$BODY$;
+
+CREATE TRIGGER torrent_requests_refund_bounties
+    BEFORE DELETE
+    ON public.torrent_requests
+    FOR EACH ROW
+    EXECUTE PROCEDURE public.refund_request_bounties();
@@ -0,0 +1,155 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Authentication API
+//!
+//! Issues and revokes [**ApiTokens**](../../models/api_token/struct.ApiToken.html). These
+//! endpoints require a logged in session, an API token can't be used to manage tokens.
+
+use super::*;
+
+use actix_web::AsyncResponder;
+use actix_web::Json;
+use handlers::api_token::{CreateTokenMsg, LoadTokensMsg, NewToken, RevokeTokenMsg};
+use identity::RequestIdentity;
+use models::api_token::ApiToken;
+
+/// New token payload
+#[derive(Deserialize)]
+pub struct NewTokenMsg {
+    /// A name to recognize the token
+    pub name: String,
+    /// The granted scopes, e.g. `message:read`
+    pub scopes: Vec<String>,
+    /// Lifetime of the token in days
+    pub days: i64,
+}
+
+/// Revoke token payload
+#[derive(Deserialize)]
+pub struct RevokeTokenPayload {
+    /// The id of the token
+    pub id: Uuid,
+}
+
+fn session_user(req: &HttpRequest<State>) -> actix_web::Result<Uuid> {
+    match (session_id(req), req.credentials()) {
+        (Some(_), Some((user_id, _))) => Ok(*user_id),
+        (None, Some(_)) => Err(ErrorForbidden("api tokens can only be managed with a session")),
+        _ => Err(ErrorUnauthorized("unauthorized")),
+    }
+}
+
+/// Issue a new API token
+///
+/// `POST /api/v1/auth/token`
+///
+/// # Payload
+///
+/// [**NewTokenMsg**](struct.NewTokenMsg.html) as JSON.
+///
+/// # Returns
+///
+/// If successful, `token` returns [**NewToken**](../../handlers/api_token/struct.NewToken.html).
+/// The JWT is only returned once, it has to be sent as `Authorization: Bearer {token}`.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorForbidden` if the client is not authorized by a session.
+/// - `ErrorBadRequest` if the name, the scopes or the lifetime are invalid.
+pub fn token(req: HttpRequest<State>, data: Json<NewTokenMsg>) -> FutureResponse<HttpResponse> {
+    let user_id = match session_user(&req) {
+        Ok(user_id) => user_id,
+        Err(e) => return Box::new(FutErr(e)),
+    };
+
+    let NewTokenMsg { name, scopes, days } = data.into_inner();
+    req.state()
+        .db()
+        .send(CreateTokenMsg { user_id, name, scopes, days })
+        .from_err()
+        .and_then(|result: Result<NewToken>| match result {
+            Ok(token) => Ok(HttpResponse::Ok().json(token)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Fetch the API tokens of the current user
+///
+/// `GET /api/v1/auth/tokens`
+///
+/// # Returns
+///
+/// If successful, `tokens` returns a list of
+/// [**ApiTokens**](../../models/api_token/struct.ApiToken.html), the newest first.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorForbidden` if the client is not authorized by a session.
+pub fn tokens(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match session_user(&req) {
+        Ok(user_id) => user_id,
+        Err(e) => return Box::new(FutErr(e)),
+    };
+
+    req.state()
+        .db()
+        .send(LoadTokensMsg(user_id))
+        .from_err()
+        .and_then(|result: Result<Vec<ApiToken>>| match result {
+            Ok(tokens) => Ok(HttpResponse::Ok().json(tokens)),
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
+/// Revoke an API token of the current user
+///
+/// `POST /api/v1/auth/revoke`
+///
+/// # Payload
+///
+/// [**RevokeTokenPayload**](struct.RevokeTokenPayload.html) as JSON.
+///
+/// # Returns
+///
+/// The number of revoked tokens.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorForbidden` if the client is not authorized by a session.
+pub fn revoke(req: HttpRequest<State>, data: Json<RevokeTokenPayload>) -> FutureResponse<HttpResponse> {
+    let user_id = match session_user(&req) {
+        Ok(user_id) => user_id,
+        Err(e) => return Box::new(FutErr(e)),
+    };
+
+    req.state()
+        .db()
+        .send(RevokeTokenMsg(data.into_inner().id, user_id))
+        .from_err()
+        .and_then(|result: Result<usize>| match result {
+            Ok(num) => Ok(HttpResponse::Ok().json(num)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
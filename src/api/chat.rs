@@ -18,7 +18,7 @@
 
 //! Chat API
 //!
-//! [**ChatRoom**](../../models/chat/enum.ChatRoom.html) is used to identify the chatroom.
+//! [**ChatRoom**](../../models/chat/struct.ChatRoom.html) is used to identify the chatroom.
 //!
 //! [**ChatMessageWithUser**](../../models/chat/struct.ChatMessageWithUser.html) is used whenever a message should be returned.
 
@@ -26,10 +26,16 @@ use super::*;
 
 use actix_web::AsyncResponder;
 use actix_web::Json;
+use actix_web::ws;
+use chat_server::{ChatBroadcastMsg, ConnectMsg, DisconnectMsg, SubscribeMsg, UnsubscribeMsg};
 use identity::RequestIdentity;
-use handlers::chat::{LoadChatMessagesMsg, PublishChatMessagesMsg};
+use handlers::chat::{
+    DeleteChatMessageMsg, LoadChatMentionsMsg, LoadChatMessagesMsg, LoadChatRoomMsg, LoadChatRoomsMsg, MarkChatMentionsSeenMsg,
+    MuteChatUserMsg, PublishChatMessagesMsg, UnmuteChatUserMsg,
+};
 use handlers::UserSubjectMsg;
-use models::chat::{ChatMessageWithUser, ChatRoom};
+use models::chat::{ChatMessageWithUser, ChatMute, ChatRoom};
+use serde_json;
 use std::convert::TryFrom;
 
 /// Fetch chat messages
@@ -40,7 +46,7 @@ use std::convert::TryFrom;
 ///
 /// | Parameter | Type  | Description |
 /// |-----------|-------|-------------|
-/// | `chat`    | `i16` | Which chatroom to use. 1: [**Public**](../../models/chat/enum.ChatRoom.html#variant.Public), 2: [**Team**](../../models/chat/enum.ChatRoom.html#variant.Team) |
+/// | `chat`    | `i16` | Which chatroom to use, the id of a [**ChatRoom**](../../models/chat/struct.ChatRoom.html). Defaults to `1`. |
 /// | `since`   | `i64` | Unix Timestamp. Fetch only messages newer than this Timestamp. |
 /// | `limit`   | `i64` | Limit response to the latest `limit` messages. |
 ///
@@ -59,6 +65,11 @@ use std::convert::TryFrom;
 /// | `created_at` | `Datetime<Utc>` | Timestamp when the message was created |
 /// | `user_name`  | `String`        | Name of the User |
 /// | `user_group` | `Uuid`          | User Group ID |
+/// | `deleted_at` | `Datetime<Utc>` | Timestamp when the message was deleted, or `null` |
+/// | `rendered`   | `String`        | The message rendered as HTML, see [util::chat](../../util/chat/index.html) |
+///
+/// If `since` is given, messages deleted after `since` are returned with an empty `message`, so
+/// clients can remove them.
 ///
 /// # Errors
 ///
@@ -85,6 +96,45 @@ pub fn messages(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
         .responder()
 }
 
+/// Fetch the chatrooms
+///
+/// `GET /api/v1/chat/rooms`
+///
+/// # Returns
+///
+/// If successful, `rooms` returns a list of all [**ChatRooms**](../../models/chat/struct.ChatRoom.html)
+/// the client may read.
+///
+/// | Field        | Type            | Description |
+/// |--------------|-----------------|-------------|
+/// | `id`         | `i16`           | Chatroom ID |
+/// | `slug`       | `String`        | Short name, the ACL namespace is `chat#<slug>` |
+/// | `name`       | `String`        | Name of the chatroom |
+/// | `topic`      | `String`        | Topic of the chatroom |
+/// | `slow_mode`  | `i32`           | Seconds a user has to wait between two messages, `0` if disabled |
+/// | `created_at` | `Datetime<Utc>` | Timestamp when the chatroom was created |
+/// | `updated_at` | `Datetime<Utc>` | Timestamp when the chatroom was last updated |
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+pub fn rooms(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user = match UserSubjectMsg::try_from(&req) {
+        Ok(user) => user,
+        Err(_) => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    req.state()
+        .db()
+        .send(LoadChatRoomsMsg::new(user))
+        .from_err()
+        .and_then(|result: Result<Vec<ChatRoom>>| match result {
+            Ok(rooms) => Ok(HttpResponse::Ok().json(rooms)),
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
 /// Publish Message Payload
 #[derive(Deserialize)]
 pub struct PublishMessage {
@@ -115,12 +165,16 @@ pub struct PublishMessage {
 /// | `created_at` | `Datetime<Utc>` | Timestamp when the message was created |
 /// | `user_name`  | `String`        | Name of the User |
 /// | `user_group` | `Uuid`          | User Group ID |
+/// | `rendered`   | `String`        | The message rendered as HTML |
+///
+/// Mentioned users, who may read the chatroom, get an unseen mention.
 ///
 /// # Errors
 ///
 /// - `ErrorUnauthorized` if the client is not authorized.
 /// - `ErrorBadRequest` if the request payload is invalid.
-/// - `ErrorForbidden` if the client is not allowed to write to the given chatroom.
+/// - `ErrorForbidden` if the client is not allowed to write to the given chatroom, is muted or has
+///   to wait because of the slow mode.
 pub fn publish(req: HttpRequest<State>, data: Json<PublishMessage>) -> FutureResponse<HttpResponse> {
     let mut credentials = req.credentials();
     if credentials.is_none() {
@@ -130,19 +184,385 @@ pub fn publish(req: HttpRequest<State>, data: Json<PublishMessage>) -> FutureRes
 
     let PublishMessage { chat, message } = data.into_inner();
     let user = UserSubjectMsg::new(*user_id, *group_id, req.state().acl().clone());
-    let chat = match ChatRoom::try_from(chat) {
-        Ok(chat) => chat,
-        Err(e) => return Box::new(FutErr(ErrorBadRequest(e.to_string()))),
-    };
     let msg = PublishChatMessagesMsg::new(chat, message, user);
 
+    let chat_server = req.state().chat_server().cloned();
     req.state()
         .db()
         .send(msg)
         .from_err()
         .and_then(move |result: Result<ChatMessageWithUser>| match result {
-            Ok(message) => Ok(HttpResponse::Ok().json(message)),
+            Ok(message) => {
+                if let Some(chat_server) = chat_server {
+                    chat_server.do_send(ChatBroadcastMsg(message.clone()));
+                }
+                Ok(HttpResponse::Ok().json(message))
+            },
+            Err(e) => Err(ErrorForbidden(e.to_string())),
+        })
+        .responder()
+}
+
+/// Delete Message Payload
+#[derive(Deserialize)]
+pub struct DeleteMessage {
+    /// The id of the message
+    pub id: Uuid,
+}
+
+/// Delete a chat message
+///
+/// `POST /api/v1/chat/delete`
+///
+/// The client needs the `delete` permission on the chatroom. The deleted message is sent to all
+/// WebSocket subscribers of the chatroom.
+///
+/// # Payload
+///
+/// [**DeleteMessage**](struct.DeleteMessage.html) as JSON.
+///
+/// # Returns
+///
+/// If successful, delete returns the [deleted message](../../models/chat/struct.ChatMessageWithUser.html)
+/// with an empty `message` and `deleted_at` set.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest` if the request payload is invalid.
+/// - `ErrorForbidden` if the client is not allowed to moderate the chatroom.
+pub fn delete(req: HttpRequest<State>, data: Json<DeleteMessage>) -> FutureResponse<HttpResponse> {
+    let user = match UserSubjectMsg::try_from(&req) {
+        Ok(user) => user,
+        Err(_) => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    let chat_server = req.state().chat_server().cloned();
+    req.state()
+        .db()
+        .send(DeleteChatMessageMsg::new(data.into_inner().id, user))
+        .from_err()
+        .and_then(move |result: Result<ChatMessageWithUser>| match result {
+            Ok(message) => {
+                if let Some(chat_server) = chat_server {
+                    chat_server.do_send(ChatBroadcastMsg(message.clone()));
+                }
+                Ok(HttpResponse::Ok().json(message))
+            },
+            Err(e) => Err(ErrorForbidden(e.to_string())),
+        })
+        .responder()
+}
+
+/// Mute User Payload
+#[derive(Deserialize)]
+pub struct MuteUser {
+    /// The chatroom
+    pub chat: i16,
+    /// The user to mute
+    pub user_id: Uuid,
+    /// Duration of the mute in seconds
+    pub duration: i64,
+    /// The reason shown to the user
+    #[serde(default)]
+    pub reason: String,
+}
+
+/// Mute a user in a chatroom
+///
+/// `POST /api/v1/chat/mute`
+///
+/// The client needs the `delete` permission on the chatroom. An existing mute is replaced.
+///
+/// # Payload
+///
+/// [**MuteUser**](struct.MuteUser.html) as JSON.
+///
+/// # Returns
+///
+/// If successful, mute returns the [**ChatMute**](../../models/chat/struct.ChatMute.html).
+///
+/// | Field        | Type            | Description |
+/// |--------------|-----------------|-------------|
+/// | `id`         | `Uuid`          | Unique Mute ID |
+/// | `chat`       | `i16`           | Chatroom |
+/// | `user_id`    | `Uuid`          | The muted user |
+/// | `issuer_id`  | `Uuid`          | The moderator |
+/// | `reason`     | `String`        | The reason |
+/// | `expires_at` | `Datetime<Utc>` | Timestamp when the mute ends |
+/// | `created_at` | `Datetime<Utc>` | Timestamp when the mute was created |
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest` if the request payload is invalid.
+/// - `ErrorForbidden` if the client is not allowed to moderate the chatroom.
+pub fn mute(req: HttpRequest<State>, data: Json<MuteUser>) -> FutureResponse<HttpResponse> {
+    let user = match UserSubjectMsg::try_from(&req) {
+        Ok(user) => user,
+        Err(_) => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    let MuteUser { chat, user_id, duration, reason } = data.into_inner();
+    req.state()
+        .db()
+        .send(MuteChatUserMsg::new(chat, user_id, duration, reason, user))
+        .from_err()
+        .and_then(|result: Result<ChatMute>| match result {
+            Ok(mute) => Ok(HttpResponse::Ok().json(mute)),
+            Err(e) => Err(ErrorForbidden(e.to_string())),
+        })
+        .responder()
+}
+
+/// Unmute User Payload
+#[derive(Deserialize)]
+pub struct UnmuteUser {
+    /// The chatroom
+    pub chat: i16,
+    /// The muted user
+    pub user_id: Uuid,
+}
+
+/// Remove the mute of a user in a chatroom
+///
+/// `POST /api/v1/chat/unmute`
+///
+/// The client needs the `delete` permission on the chatroom.
+///
+/// # Payload
+///
+/// [**UnmuteUser**](struct.UnmuteUser.html) as JSON.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest` if the request payload is invalid.
+/// - `ErrorForbidden` if the client is not allowed to moderate the chatroom.
+pub fn unmute(req: HttpRequest<State>, data: Json<UnmuteUser>) -> FutureResponse<HttpResponse> {
+    let user = match UserSubjectMsg::try_from(&req) {
+        Ok(user) => user,
+        Err(_) => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    let UnmuteUser { chat, user_id } = data.into_inner();
+    req.state()
+        .db()
+        .send(UnmuteChatUserMsg::new(chat, user_id, user))
+        .from_err()
+        .and_then(|result: Result<()>| match result {
+            Ok(_) => Ok(HttpResponse::Ok().finish()),
             Err(e) => Err(ErrorForbidden(e.to_string())),
         })
         .responder()
 }
+
+/// Unseen mentions
+#[derive(Serialize)]
+pub struct Mentions {
+    /// Number of unseen mentions
+    pub unseen: i64,
+}
+
+/// Fetch the number of unseen mentions
+///
+/// `GET /api/v1/chat/mentions`
+///
+/// # Returns
+///
+/// If successful, mentions returns [**Mentions**](struct.Mentions.html).
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+pub fn mentions(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.credentials() {
+        Some((user_id, _)) => *user_id,
+        None => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    req.state()
+        .db()
+        .send(LoadChatMentionsMsg(user_id))
+        .from_err()
+        .and_then(|result: Result<i64>| match result {
+            Ok(unseen) => Ok(HttpResponse::Ok().json(Mentions { unseen })),
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
+/// Mark all mentions as seen
+///
+/// `POST /api/v1/chat/mentions/seen`
+///
+/// # Returns
+///
+/// If successful, returns [**Mentions**](struct.Mentions.html) with `unseen` set to `0`.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+pub fn mentions_seen(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.credentials() {
+        Some((user_id, _)) => *user_id,
+        None => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    req.state()
+        .db()
+        .send(MarkChatMentionsSeenMsg(user_id))
+        .from_err()
+        .and_then(|result: Result<usize>| match result {
+            Ok(_) => Ok(HttpResponse::Ok().json(Mentions { unseen: 0 })),
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
+/// Subscribe to chat messages via WebSocket
+///
+/// `GET /api/v1/chat/ws`
+///
+/// After the connection is established, the client subscribes to chatrooms by sending
+/// [**WsCommand**](struct.WsCommand.html) objects as JSON text frames, e.g. `{"subscribe": 1}`.
+///
+/// # Returns
+///
+/// Each message posted to a subscribed chatroom is sent as a text frame containing the
+/// [message](../../models/chat/struct.ChatMessageWithUser.html) as JSON.
+///
+/// If a command fails, a text frame with `{"error": "..."}` is sent.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorInternalServerError` if the chat server is not running.
+pub fn ws(req: HttpRequest<State>) -> actix_web::Result<HttpResponse> {
+    let (user_id, group_id) = match req.credentials() {
+        Some((user_id, group_id)) => (*user_id, *group_id),
+        None => return Err(ErrorUnauthorized("unauthorized")),
+    };
+    if req.state().chat_server().is_none() {
+        return Err(ErrorInternalServerError("chat server not running"));
+    }
+
+    ws::start(req, ChatSession::new(user_id, group_id))
+}
+
+/// WebSocket command
+#[derive(Debug, Default, Deserialize)]
+pub struct WsCommand {
+    /// Subscribe to this chatroom
+    pub subscribe: Option<i16>,
+    /// Unsubscribe from this chatroom
+    pub unsubscribe: Option<i16>,
+}
+
+/// WebSocket chat session
+pub struct ChatSession {
+    id: usize,
+    user_id: Uuid,
+    group_id: Uuid,
+}
+
+impl ChatSession {
+    fn new(user_id: Uuid, group_id: Uuid) -> Self {
+        ChatSession { id: 0, user_id, group_id }
+    }
+
+    fn command(&mut self, text: &str, ctx: &mut ws::WebsocketContext<Self, State>) -> Result<()> {
+        let command: WsCommand = serde_json::from_str(text).map_err(|e| format!("invalid command: {}", e))?;
+        let chat_server = ctx.state().chat_server().cloned().ok_or("chat server not running")?;
+
+        if let Some(chat) = command.subscribe {
+            // loading the chatroom checks the read permission
+            let user = UserSubjectMsg::new(self.user_id, self.group_id, ctx.state().acl().clone());
+            let chat_server = chat_server.clone();
+            ctx.state()
+                .db()
+                .send(LoadChatRoomMsg::new(chat, user))
+                .into_actor(self)
+                .then(move |result, act, ctx| {
+                    match result {
+                        Ok(Ok(room)) => chat_server.do_send(SubscribeMsg { id: act.id, chat: room.id }),
+                        Ok(Err(e)) => send_error(ctx, &e.to_string()),
+                        Err(e) => send_error(ctx, &e.to_string()),
+                    }
+                    fut::ok(())
+                })
+                .spawn(ctx);
+        }
+        if let Some(chat) = command.unsubscribe {
+            chat_server.do_send(UnsubscribeMsg { id: self.id, chat });
+        }
+
+        Ok(())
+    }
+}
+
+impl Actor for ChatSession {
+    type Context = ws::WebsocketContext<Self, State>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let chat_server = ctx.state().chat_server().cloned();
+        let chat_server = match chat_server {
+            Some(chat_server) => chat_server,
+            None => {
+                ctx.stop();
+                return;
+            }
+        };
+        let addr: Addr<Syn, _> = ctx.address();
+        chat_server
+            .send(ConnectMsg { addr: addr.recipient() })
+            .into_actor(self)
+            .then(|result, act, ctx| {
+                match result {
+                    Ok(id) => act.id = id,
+                    Err(_) => ctx.stop(),
+                }
+                fut::ok(())
+            })
+            .wait(ctx);
+    }
+
+    fn stopping(&mut self, ctx: &mut Self::Context) -> Running {
+        if let Some(chat_server) = ctx.state().chat_server() {
+            chat_server.do_send(DisconnectMsg { id: self.id });
+        }
+        Running::Stop
+    }
+}
+
+impl Handler<ChatBroadcastMsg> for ChatSession {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChatBroadcastMsg, ctx: &mut Self::Context) {
+        match serde_json::to_string(&msg.0) {
+            Ok(json) => ctx.text(json),
+            Err(e) => warn!("failed to serialize chat message: {}", e),
+        }
+    }
+}
+
+impl StreamHandler<ws::Message, ws::ProtocolError> for ChatSession {
+    fn handle(&mut self, msg: ws::Message, ctx: &mut Self::Context) {
+        match msg {
+            ws::Message::Ping(msg) => ctx.pong(&msg),
+            ws::Message::Text(text) => {
+                if let Err(e) = self.command(&text, ctx) {
+                    send_error(ctx, &e.to_string());
+                }
+            }
+            ws::Message::Close(_) => ctx.stop(),
+            _ => {}
+        }
+    }
+}
+
+fn send_error(ctx: &mut ws::WebsocketContext<ChatSession, State>, error: &str) {
+    if let Ok(json) = serde_json::to_string(&JsonErr { error: error.to_string() }) {
+        ctx.text(json);
+    }
+}
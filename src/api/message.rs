@@ -26,7 +26,12 @@ use actix_web::AsyncResponder;
 use actix_web::FromRequest;
 use actix_web::Json;
 use identity::RequestIdentity;
-use handlers::message::{DeleteMessagesMsg, LoadMessageMsg, LoadMessagesMsg, MarkMessagesMsg, NewMessageMsg};
+use handlers::message::{
+    send_system_messages, CreateMessageFolderMsg, DeleteMessageFolderMsg, DeleteMessagesMsg, LoadMassMessageReceiversMsg,
+    LoadMessageFoldersMsg, LoadMessageMsg, LoadMessagesMsg, LoadThreadMsg, MarkMessagesMsg, MoveMessagesMsg,
+    NewMessageMsg, SearchMessagesMsg, UpdateMessageFolderMsg,
+};
+use handlers::UserSubjectMsg;
 
 /// New message payload
 #[derive(Deserialize)]
@@ -35,7 +40,7 @@ pub struct NewMessage {
     pub receiver: String,
     pub subject: String,
     pub body: String,
-    /// Some Message ID if it's a reply
+    /// Some Message ID if it's a reply, the new message joins the conversation of this message
     pub reply_to: Option<Uuid>,
 }
 
@@ -46,6 +51,49 @@ pub struct MessageListMsg {
     pub messages: Vec<Uuid>,
 }
 
+/// Move messages payload
+#[derive(Deserialize)]
+pub struct MoveMessages {
+    /// A list of Message IDs
+    pub messages: Vec<Uuid>,
+    /// The target folder
+    pub folder_id: Uuid,
+}
+
+/// Message folder payload
+#[derive(Deserialize)]
+pub struct MessageFolderData {
+    /// Folder ID, only required when updating a folder
+    pub id: Option<Uuid>,
+    pub name: String,
+    /// Delete messages after this number of days, `0` keeps them forever
+    #[serde(default)]
+    pub purge: i16,
+}
+
+/// Delete message folder payload
+#[derive(Deserialize)]
+pub struct DeleteMessageFolder {
+    /// Folder ID
+    pub id: Uuid,
+}
+
+/// Mass message payload
+#[derive(Deserialize)]
+pub struct MassMessage {
+    /// IDs of the receiving groups
+    pub groups: Vec<Uuid>,
+    pub subject: String,
+    pub body: String,
+}
+
+/// Mass message response
+#[derive(Serialize)]
+pub struct MassMessageResponse {
+    /// Number of receivers
+    pub receivers: usize,
+}
+
 /// Fetch messages
 ///
 /// `GET /api/v1/message/messages`
@@ -54,7 +102,7 @@ pub struct MessageListMsg {
 ///
 /// | Parameter | Type     | Description |
 /// |-----------|----------|-------------|
-/// | `folder`  | `String` | Message folder. `inbox`, `sent` or `system` |
+/// | `folder`  | `String` | Message folder. `inbox`, `sent`, `system` or the name of a custom folder |
 /// | `unread`  | `String` | Fetch only unread messages. `0` or `1` |
 ///
 /// # Returns
@@ -103,7 +151,7 @@ pub fn messages(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
 ///
 /// | Parameter | Type     | Description |
 /// |-----------|----------|-------------|
-/// | `folder`  | `String` | Message folder. `inbox`, `sent` or `system` |
+/// | `folder`  | `String` | Message folder. `inbox`, `sent`, `system` or the name of a custom folder |
 ///
 /// # Returns
 ///
@@ -196,6 +244,58 @@ pub fn message(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
         .responder()
 }
 
+/// Fetch the conversation of a message
+///
+/// `GET /api/v1/message/thread`
+///
+/// # Parameters
+///
+/// | Parameter | Type     | Description |
+/// |-----------|----------|-------------|
+/// | `id`      | `Uuid`   | ID of any message of the conversation |
+///
+/// # Returns
+///
+/// If successful, `thread` returns a list of [**Messages**](../../handlers/message/struct.MessageResponse.html),
+/// the oldest first. Received messages and the copies of sent messages are included.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest`
+///     - if the request parameters are invalid.
+///     - if the message does not exist.
+///     - if the user is not allowed to read the message.
+pub fn thread(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, _) = credentials.take().unwrap();
+
+    let query = match Query::<HashMap<String, String>>::extract(&req) {
+        Ok(q) => q,
+        Err(e) => return Box::new(FutErr(ErrorInternalServerError(e))),
+    };
+
+    let id = match query.get("id").map(|id| Uuid::parse_str(id)) {
+        Some(Ok(id)) => id,
+        Some(Err(_)) => return Box::new(FutErr(ErrorBadRequest("invalid message id"))),
+        None => return Box::new(FutErr(ErrorBadRequest("no message id"))),
+    };
+    let loadmsg = LoadThreadMsg::new(id, *user_id);
+
+    req.state()
+        .db()
+        .send(loadmsg)
+        .from_err()
+        .and_then(|result| match result {
+            Ok(messages) => Ok(HttpResponse::Ok().json(messages)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
 /// Send a new message to a user
 ///
 /// `POST /api/v1/message/send`
@@ -219,16 +319,22 @@ pub fn message(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
 ///     - if the sender does not exist.
 ///     - if the receiver does not exist.
 ///     - if the receiver has no inbox folder. *(should never happen)*
+///     - if the receiver does not accept messages from the sender (`accept_messages` setting).
+///     - if `reply_to` is not a message of the sender.
 ///     - if any error occurs when storing the message.
+///
+/// Messages from users blocked by the receiver are dropped silently, the response looks like the
+/// message was delivered.
 pub fn send(req: HttpRequest<State>, data: Json<NewMessage>) -> FutureResponse<HttpResponse> {
     let mut credentials = req.credentials();
     if credentials.is_none() {
         return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
     }
-    let (user_id, _) = credentials.take().unwrap();
+    let (user_id, group_id) = credentials.take().unwrap();
 
     let data = data.into_inner();
-    let sendmsg = NewMessageMsg::new(data, *user_id);
+    let user = UserSubjectMsg::new(*user_id, *group_id, req.state().acl().clone());
+    let sendmsg = NewMessageMsg::new(data, user);
 
     req.state()
         .db()
@@ -318,3 +424,306 @@ pub fn mark_read(req: HttpRequest<State>, data: Json<MessageListMsg>) -> FutureR
         })
         .responder()
 }
+
+/// Move one or more messages into another folder
+///
+/// `POST /api/v1/message/move`
+///
+/// # Payload
+///
+/// [**MoveMessages**](struct.MoveMessages.html) as JSON.
+///
+/// # Returns
+///
+/// A list of Message IDs, which were moved.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest`
+///     - if the request parameters are invalid.
+///     - if the target folder does not exist or belongs to another user.
+///     - if any error occurs when storing the message.
+pub fn move_messages(req: HttpRequest<State>, data: Json<MoveMessages>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, _) = credentials.take().unwrap();
+
+    let data = data.into_inner();
+    let movemsg = MoveMessagesMsg::new(data.messages, data.folder_id, *user_id);
+
+    req.state()
+        .db()
+        .send(movemsg)
+        .from_err()
+        .and_then(|result| match result {
+            Ok(messages) => Ok(HttpResponse::Ok().json(messages)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Search messages
+///
+/// `GET /api/v1/message/search`
+///
+/// Searches subject and body of the messages in all folders of the current user.
+///
+/// # Parameters
+///
+/// | Parameter | Type     | Description |
+/// |-----------|----------|-------------|
+/// | `q`       | `String` | Search query |
+///
+/// # Returns
+///
+/// If successful, `search` returns a list of [**Messages**](../../handlers/message/struct.MessageResponse.html),
+/// the newest first.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest` if the request parameters are invalid.
+pub fn search(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, _) = credentials.take().unwrap();
+
+    let mut query = match Query::<HashMap<String, String>>::extract(&req) {
+        Ok(q) => q,
+        Err(e) => return Box::new(FutErr(ErrorInternalServerError(e))),
+    };
+
+    let q = query.remove("q").unwrap_or_default();
+    let searchmsg = SearchMessagesMsg::new(q, *user_id);
+
+    req.state()
+        .db()
+        .send(searchmsg)
+        .from_err()
+        .and_then(|result| match result {
+            Ok(messages) => Ok(HttpResponse::Ok().json(messages)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Fetch the message folders
+///
+/// `GET /api/v1/message/folders`
+///
+/// # Returns
+///
+/// If successful, `folders` returns a list of all
+/// [**MessageFolders**](../../models/message/struct.MessageFolder.html) of the current user.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+pub fn folders(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, _) = credentials.take().unwrap();
+
+    req.state()
+        .db()
+        .send(LoadMessageFoldersMsg(*user_id))
+        .from_err()
+        .and_then(|result| match result {
+            Ok(folders) => Ok(HttpResponse::Ok().json(folders)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Create a custom message folder
+///
+/// `POST /api/v1/message/folder/create`
+///
+/// # Payload
+///
+/// [**MessageFolderData**](struct.MessageFolderData.html) as JSON.
+///
+/// # Returns
+///
+/// If successful, `create_folder` returns the new
+/// [**MessageFolder**](../../models/message/struct.MessageFolder.html).
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest`
+///     - if the request parameters are invalid.
+///     - if the name is invalid or already used.
+///     - if `purge` is negative.
+pub fn create_folder(req: HttpRequest<State>, data: Json<MessageFolderData>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, _) = credentials.take().unwrap();
+
+    let data = data.into_inner();
+    let createmsg = CreateMessageFolderMsg::new(data.name, data.purge, *user_id);
+
+    req.state()
+        .db()
+        .send(createmsg)
+        .from_err()
+        .and_then(|result| match result {
+            Ok(folder) => Ok(HttpResponse::Ok().json(folder)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Rename a custom message folder or change the purge period of a folder
+///
+/// `POST /api/v1/message/folder/update`
+///
+/// System folders (`inbox`, `sent` and `system`) can not be renamed, but their purge period can
+/// be changed.
+///
+/// # Payload
+///
+/// [**MessageFolderData**](struct.MessageFolderData.html) as JSON.
+///
+/// # Returns
+///
+/// If successful, `update_folder` returns the updated
+/// [**MessageFolder**](../../models/message/struct.MessageFolder.html).
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest`
+///     - if the request parameters are invalid.
+///     - if the folder does not exist or belongs to another user.
+///     - if the name is invalid or already used.
+///     - if `purge` is negative.
+pub fn update_folder(req: HttpRequest<State>, data: Json<MessageFolderData>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, _) = credentials.take().unwrap();
+
+    let data = data.into_inner();
+    let id = match data.id {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorBadRequest("no folder id"))),
+    };
+    let updatemsg = UpdateMessageFolderMsg::new(id, data.name, data.purge, *user_id);
+
+    req.state()
+        .db()
+        .send(updatemsg)
+        .from_err()
+        .and_then(|result| match result {
+            Ok(folder) => Ok(HttpResponse::Ok().json(folder)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Delete a custom message folder and all messages in it
+///
+/// `POST /api/v1/message/folder/delete`
+///
+/// # Payload
+///
+/// [**DeleteMessageFolder**](struct.DeleteMessageFolder.html) as JSON.
+///
+/// # Returns
+///
+/// The ID of the deleted folder.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest`
+///     - if the request parameters are invalid.
+///     - if the folder does not exist or belongs to another user.
+///     - if the folder is a system folder.
+pub fn delete_folder(req: HttpRequest<State>, data: Json<DeleteMessageFolder>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, _) = credentials.take().unwrap();
+
+    let deletemsg = DeleteMessageFolderMsg::new(data.into_inner().id, *user_id);
+
+    req.state()
+        .db()
+        .send(deletemsg)
+        .from_err()
+        .and_then(|result| match result {
+            Ok(id) => Ok(HttpResponse::Ok().json(id)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Send a system message to all active members of one or more groups
+///
+/// `POST /api/v1/message/mass`
+///
+/// Requires the `create` permission on the `message#mass` ACL namespace. The messages are
+/// delivered in batches in the background, the response is sent as soon as the receivers are
+/// known.
+///
+/// # Payload
+///
+/// [**MassMessage**](struct.MassMessage.html) as JSON.
+///
+/// # Returns
+///
+/// If successful, `mass` returns a [**MassMessageResponse**](struct.MassMessageResponse.html).
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest`
+///     - if the request parameters are invalid.
+///     - if subject or body are empty or no group is selected.
+///     - if the user is not allowed to send mass messages.
+pub fn mass(req: HttpRequest<State>, data: Json<MassMessage>) -> FutureResponse<HttpResponse> {
+    let mut credentials = req.credentials();
+    if credentials.is_none() {
+        return Box::new(FutErr(ErrorUnauthorized("unauthorized")));
+    }
+    let (user_id, group_id) = credentials.take().unwrap();
+
+    let MassMessage { groups, subject, body } = data.into_inner();
+    if subject.trim().is_empty() || body.trim().is_empty() {
+        return Box::new(FutOk(HttpResponse::BadRequest().json(JsonErr {
+            error: "subject and body must not be empty".to_string(),
+        })));
+    }
+    let user = UserSubjectMsg::new(*user_id, *group_id, req.state().acl().clone());
+    let db = req.state().db().clone();
+
+    req.state()
+        .db()
+        .send(LoadMassMessageReceiversMsg::new(groups, user))
+        .from_err()
+        .and_then(move |result| match result {
+            Ok(receivers) => {
+                let num = receivers.len();
+                let fut = send_system_messages(db, receivers, subject, body)
+                    .map(|num| info!("sent {} mass messages", num))
+                    .map_err(|e| warn!("failed to send mass messages: {}", e));
+                Arbiter::handle().spawn(fut);
+                Ok(HttpResponse::Ok().json(MassMessageResponse { receivers: num }))
+            }
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
@@ -20,11 +20,14 @@
 
 use super::*;
 
+use chat_server::ChatServer;
 use identity::{ApiIdentityPolicy, IdentityService};
 
+pub mod auth;
 pub mod chat;
 pub mod comment;
 pub mod message;
+pub mod notification;
 pub mod user;
 
 #[derive(Serialize)]
@@ -34,7 +37,7 @@ pub struct JsonErr {
 }
 
 
-pub(crate) fn build(db: Addr<Syn, DbExecutor>, acl: Arc<RwLock<Acl>>) -> App<State> {
+pub(crate) fn build(db: Addr<Syn, DbExecutor>, acl: Arc<RwLock<Acl>>, chat_server: Addr<Syn, ChatServer>) -> App<State> {
     let settings = SETTINGS.read().unwrap();
     let jwt_secret = util::from_hex(&settings.jwt_secret).unwrap();
     let session_secret = util::from_hex(&settings.session_secret).unwrap();
@@ -51,7 +54,10 @@ pub(crate) fn build(db: Addr<Syn, DbExecutor>, acl: Arc<RwLock<Acl>>) -> App<Sta
         settings.domain
     );
 
-    App::with_state(State::new(db, acl))
+    let mut state = State::new(db, acl);
+    state.set_chat_server(chat_server);
+
+    App::with_state(state)
         .middleware(Logger::default())
         .middleware(DefaultHeaders::new().header("X-Version", env!("CARGO_PKG_VERSION")))
         .middleware(
@@ -69,20 +75,45 @@ pub(crate) fn build(db: Addr<Syn, DbExecutor>, acl: Arc<RwLock<Acl>>) -> App<Sta
             &jwt_secret,
         )))
         .prefix("/api/v1")
+        .scope("/auth", |scope| {
+            scope.resource("/token", |r| r.method(Method::POST).with2(auth::token))
+            .route("/tokens", Method::GET, auth::tokens)
+            .resource("/revoke", |r| r.method(Method::POST).with2(auth::revoke))
+        })
         .scope("/user", |scope| {
             scope.route("/stats", Method::GET, user::stats)
         })
         .scope("/chat", |scope| {
             scope.route("/messages", Method::GET, chat::messages)
+            .route("/rooms", Method::GET, chat::rooms)
             .resource("/publish", |r| r.method(Method::POST).with2(chat::publish))
+            .resource("/delete", |r| r.method(Method::POST).with2(chat::delete))
+            .resource("/mute", |r| r.method(Method::POST).with2(chat::mute))
+            .resource("/unmute", |r| r.method(Method::POST).with2(chat::unmute))
+            .route("/mentions", Method::GET, chat::mentions)
+            .route("/mentions/seen", Method::POST, chat::mentions_seen)
+            .route("/ws", Method::GET, chat::ws)
         })
         .scope("/message", |scope| {
             scope.route("/messages", Method::GET, message::messages)
             .route("/unread", Method::GET, message::unread)
             .route("/read", Method::GET, message::message)
+            .route("/thread", Method::GET, message::thread)
             .resource("/send", |r| r.method(Method::POST).with2(message::send))
             .resource("/delete", |r| r.method(Method::POST).with2(message::delete))
             .resource("/mark_read", |r| r.method(Method::POST).with2(message::mark_read))
+            .resource("/move", |r| r.method(Method::POST).with2(message::move_messages))
+            .route("/search", Method::GET, message::search)
+            .route("/folders", Method::GET, message::folders)
+            .resource("/folder/create", |r| r.method(Method::POST).with2(message::create_folder))
+            .resource("/folder/update", |r| r.method(Method::POST).with2(message::update_folder))
+            .resource("/folder/delete", |r| r.method(Method::POST).with2(message::delete_folder))
+            .resource("/mass", |r| r.method(Method::POST).with2(message::mass))
+        })
+        .scope("/notification", |scope| {
+            scope.route("/notifications", Method::GET, notification::notifications)
+            .route("/unread", Method::GET, notification::unread)
+            .resource("/mark_read", |r| r.method(Method::POST).with2(notification::mark_read))
         })
         .scope("/comment", |scope| {
             scope.route("/torrent", Method::GET, comment::torrent)
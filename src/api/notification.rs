@@ -0,0 +1,150 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Notification API
+//!
+//! [**Notification**](../../models/notification/struct.Notification.html) is used whenever a notification should be returned
+
+use super::*;
+
+use actix_web::AsyncResponder;
+use actix_web::FromRequest;
+use actix_web::Json;
+use handlers::notification::{CountNotificationsMsg, LoadNotificationsMsg, MarkNotificationsMsg};
+use identity::RequestIdentity;
+use models::notification::Notification;
+
+/// Notification list payload
+#[derive(Deserialize)]
+pub struct NotificationListMsg {
+    /// A list of Notification IDs, all notifications if empty
+    #[serde(default)]
+    pub notifications: Vec<Uuid>,
+}
+
+/// Unread notifications
+#[derive(Serialize)]
+pub struct UnreadNotifications {
+    /// Number of unread notifications
+    pub unread: i64,
+}
+
+/// Fetch the latest notifications
+///
+/// `GET /api/v1/notification/notifications`
+///
+/// # Parameters
+///
+/// | Parameter | Type     | Description |
+/// |-----------|----------|-------------|
+/// | `unread`  | `String` | Fetch only unread notifications. `0` or `1` |
+///
+/// # Returns
+///
+/// If successful, `notifications` returns a list of
+/// [**Notifications**](../../models/notification/struct.Notification.html), the newest first.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+pub fn notifications(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.credentials() {
+        Some((user_id, _)) => *user_id,
+        None => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    let query = match Query::<HashMap<String, String>>::extract(&req) {
+        Ok(q) => q,
+        Err(e) => return Box::new(FutErr(ErrorInternalServerError(e))),
+    };
+    let unread = query.get("unread").map(|unread| unread == "1").unwrap_or(false);
+
+    req.state()
+        .db()
+        .send(LoadNotificationsMsg::new(user_id, unread))
+        .from_err()
+        .and_then(|result: Result<Vec<Notification>>| match result {
+            Ok(notifications) => Ok(HttpResponse::Ok().json(notifications)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
+
+/// Fetch the number of unread notifications
+///
+/// `GET /api/v1/notification/unread`
+///
+/// # Returns
+///
+/// If successful, `unread` returns [**UnreadNotifications**](struct.UnreadNotifications.html).
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+pub fn unread(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.credentials() {
+        Some((user_id, _)) => *user_id,
+        None => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    req.state()
+        .db()
+        .send(CountNotificationsMsg(user_id))
+        .from_err()
+        .and_then(|result: Result<i64>| match result {
+            Ok(unread) => Ok(HttpResponse::Ok().json(UnreadNotifications { unread })),
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
+/// Mark notifications as read
+///
+/// `POST /api/v1/notification/mark_read`
+///
+/// # Payload
+///
+/// [**NotificationListMsg**](struct.NotificationListMsg.html) as JSON. An empty list marks all
+/// notifications as read.
+///
+/// # Returns
+///
+/// The number of notifications, which were marked as read.
+///
+/// # Errors
+///
+/// - `ErrorUnauthorized` if the client is not authorized.
+/// - `ErrorBadRequest` if the request parameters are invalid.
+pub fn mark_read(req: HttpRequest<State>, data: Json<NotificationListMsg>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.credentials() {
+        Some((user_id, _)) => *user_id,
+        None => return Box::new(FutErr(ErrorUnauthorized("unauthorized"))),
+    };
+
+    let markmsg = MarkNotificationsMsg::new(data.into_inner().notifications, user_id);
+
+    req.state()
+        .db()
+        .send(markmsg)
+        .from_err()
+        .and_then(|result: Result<usize>| match result {
+            Ok(num) => Ok(HttpResponse::Ok().json(num)),
+            Err(e) => Ok(HttpResponse::BadRequest().json(JsonErr { error: e.to_string() })),
+        })
+        .responder()
+}
@@ -0,0 +1,146 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Bonus point store and ledger
+
+use super::*;
+use actix_web::AsyncResponder;
+use actix_web::Form;
+use actix_web::FromRequest;
+use handlers::UserSubjectMsg;
+use handlers::bonus::*;
+use models::bonus::{StoreItem, MAX_TITLE_LENGTH};
+use std::convert::TryFrom;
+
+#[derive(Deserialize)]
+pub struct BuyForm {
+    item: String,
+    title: Option<String>,
+}
+
+fn failed(req: &HttpRequest<State>, title: &str, error: &str, back_link: &str) -> SyncResponse<HttpResponse> {
+    let mut ctx = Context::new();
+    ctx.insert("error", error);
+    ctx.insert("back_link", back_link);
+    ctx.insert("title", title);
+
+    Template::render_with_user(req, "bonus/failed.html", &mut ctx)
+}
+
+fn load_bonus(mut req: HttpRequest<State>, user_id: Option<Uuid>) -> FutureResponse<HttpResponse> {
+    let (current_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let own = user_id.is_none();
+    let user_id = user_id.unwrap_or(current_id);
+
+    let page = match Query::<HashMap<String, String>>::extract(&req) {
+        Ok(query) => query.get("page").and_then(|page| page.parse::<i64>().ok()).unwrap_or(1).max(1),
+        Err(_) => 1,
+    };
+
+    let user = UserSubjectMsg::new(current_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LoadBonusMsg::new(user_id, page, user))
+        .from_err()
+        .and_then(move |result: Result<BonusDetails>| {
+            match result {
+                Ok(details) => {
+                    let mut ctx = Context::new();
+                    {
+                        let settings = SETTINGS.read().unwrap();
+                        ctx.insert("upload_price", &settings.bonus.upload_price);
+                        ctx.insert("upload_amount", &settings.bonus.upload_amount);
+                        ctx.insert("title_price", &settings.bonus.title_price);
+                        ctx.insert("freeleech_price", &settings.bonus.freeleech_price);
+                        ctx.insert("freeleech_hours", &settings.bonus.freeleech_hours);
+                    }
+                    ctx.insert("max_title_length", &MAX_TITLE_LENGTH);
+                    ctx.insert("own", &own);
+                    ctx.insert("has_next", &(details.transactions.len() as i64 == TRANSACTIONS_PER_PAGE));
+                    ctx.insert("details", &details);
+                    ctx.insert("page", &page);
+                    Template::render_with_user(&req, "bonus/store.html", &mut ctx)
+                },
+                Err(e) => Err(ErrorNotFound(e.to_string())),
+            }
+        })
+        .responder()
+}
+
+/// Show the balance, the store and the ledger of the current user
+pub fn store(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    load_bonus(req, None)
+}
+
+/// Show the balance and the ledger of another user
+pub fn ledger(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no user id"))),
+    };
+    load_bonus(req, Some(user_id))
+}
+
+/// Buy an item from the store
+pub fn buy(mut req: HttpRequest<State>, data: Form<BuyForm>) -> FutureResponse<HttpResponse> {
+    let user_id = match session_creds(&mut req) {
+        Some((u, _)) => u,
+        None => return async_redirect("/login"),
+    };
+
+    let BuyForm{item, title} = data.into_inner();
+    let item = match StoreItem::try_from(&item[..]) {
+        Ok(item) => item,
+        Err(e) => return Box::new(future::result(failed(&req, "Purchase failed", &e.to_string(), "/bonus"))),
+    };
+
+    req.clone().state().db().send(BuyMsg::new(user_id, item, title))
+        .from_err()
+        .and_then(move |result: Result<i64>| {
+            match result {
+                Ok(_) => sync_redirect("/bonus"),
+                Err(e) => failed(&req, "Purchase failed", &e.to_string(), "/bonus"),
+            }
+        })
+        .responder()
+}
+
+/// Use a freeleech token for a torrent
+pub fn freeleech(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no torrent id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(UseFreeleechMsg::new(id, user))
+        .from_err()
+        .and_then(move |result| {
+            let link = format!("/torrent/{}", id);
+            match result {
+                Ok(_) => sync_redirect(&link),
+                Err(e) => failed(&req, "Freeleech token", &e.to_string(), &link),
+            }
+        })
+        .responder()
+}
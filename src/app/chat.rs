@@ -0,0 +1,177 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Chatroom administration
+
+use super::*;
+use actix_web::AsyncResponder;
+use actix_web::Form;
+use handlers::UserSubjectMsg;
+use handlers::chat::{CreateChatRoomMsg, DeleteChatRoomMsg, LoadChatRoomAdminMsg, UpdateChatRoomMsg};
+use models::chat::ChatRoom;
+
+#[derive(Deserialize)]
+pub struct NewChatRoomForm {
+    slug: String,
+    name: String,
+    topic: String,
+    slow_mode: i32,
+    /// the chatroom to copy the ACL rules from, `0` for none
+    template: i16,
+}
+
+#[derive(Deserialize)]
+pub struct ChatRoomForm {
+    name: String,
+    topic: String,
+    slow_mode: i32,
+}
+
+fn room_id(req: &HttpRequest<State>) -> Option<i16> {
+    req.match_info().get("id").and_then(|id| id.parse::<i16>().ok())
+}
+
+fn failed(req: &HttpRequest<State>, title: &str, error: &str, back_link: &str) -> SyncResponse<HttpResponse> {
+    let mut ctx = Context::new();
+    ctx.insert("error", error);
+    ctx.insert("back_link", back_link);
+    ctx.insert("title", title);
+
+    Template::render_with_user(req, "chat/failed.html", &mut ctx)
+}
+
+/// List all chatrooms
+pub fn rooms(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LoadChatRoomAdminMsg::new(user))
+        .from_err()
+        .and_then(move |result: Result<Vec<ChatRoom>>| {
+            match result {
+                Ok(rooms) => {
+                    let mut ctx = Context::new();
+                    ctx.insert("rooms", &rooms);
+                    Template::render_with_user(&req, "chat/rooms.html", &mut ctx)
+                },
+                Err(_) => sync_redirect("/"),
+            }
+        })
+        .responder()
+}
+
+/// Show the edit form for a chatroom
+pub fn edit(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match room_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no chatroom id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LoadChatRoomAdminMsg::new(user))
+        .from_err()
+        .and_then(move |result: Result<Vec<ChatRoom>>| {
+            match result {
+                Ok(rooms) => match rooms.into_iter().find(|room| room.id == id) {
+                    Some(room) => {
+                        let mut ctx = Context::new();
+                        ctx.insert("room", &room);
+                        Template::render_with_user(&req, "chat/edit.html", &mut ctx)
+                    },
+                    None => Err(ErrorNotFound("chatroom not found")),
+                },
+                Err(_) => sync_redirect("/"),
+            }
+        })
+        .responder()
+}
+
+/// Create a new chatroom
+pub fn create(mut req: HttpRequest<State>, data: Form<NewChatRoomForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let NewChatRoomForm{slug, name, topic, slow_mode, template} = data.into_inner();
+    let template = if template > 0 { Some(template) } else { None };
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(CreateChatRoomMsg::new(slug, name, topic, slow_mode, template, user))
+        .from_err()
+        .and_then(move |result: Result<ChatRoom>| {
+            match result {
+                Ok(_) => sync_redirect("/chat/rooms"),
+                Err(e) => failed(&req, "Create chatroom failed", &e.to_string(), "/chat/rooms"),
+            }
+        })
+        .responder()
+}
+
+/// Update a chatroom
+pub fn update(mut req: HttpRequest<State>, data: Form<ChatRoomForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match room_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no chatroom id"))),
+    };
+
+    let ChatRoomForm{name, topic, slow_mode} = data.into_inner();
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(UpdateChatRoomMsg::new(id, name, topic, slow_mode, user))
+        .from_err()
+        .and_then(move |result: Result<ChatRoom>| {
+            match result {
+                Ok(_) => sync_redirect("/chat/rooms"),
+                Err(e) => failed(&req, "Edit chatroom failed", &e.to_string(), &format!("/chat/room/{}", id)),
+            }
+        })
+        .responder()
+}
+
+/// Delete a chatroom
+pub fn delete(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match room_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no chatroom id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(DeleteChatRoomMsg::new(id, user))
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/chat/rooms"),
+                Err(e) => failed(&req, "Delete chatroom failed", &e.to_string(), "/chat/rooms"),
+            }
+        })
+        .responder()
+}
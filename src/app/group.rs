@@ -0,0 +1,170 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Group promotion rules
+
+use super::*;
+use actix_web::AsyncResponder;
+use actix_web::Form;
+use handlers::UserSubjectMsg;
+use handlers::group::*;
+use models::group::{Group, GroupChange, PromotionRule};
+
+/// The uploaded bytes are entered in GiB
+const UPLOADED_UNIT: i64 = 1024 * 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct RuleForm {
+    min_age_days: i32,
+    min_uploaded: i64,
+    min_ratio: f64,
+    min_uploads: i32,
+}
+
+/// A row of the rule table
+#[derive(Serialize)]
+struct RuleRow<'a> {
+    group: &'a Group,
+    parent_name: Option<&'a str>,
+    rule: Option<&'a PromotionRule>,
+    min_uploaded: i64,
+    team: bool,
+}
+
+fn path_id(req: &HttpRequest<State>) -> Option<Uuid> {
+    req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok())
+}
+
+fn failed(req: &HttpRequest<State>, title: &str, error: &str) -> SyncResponse<HttpResponse> {
+    let mut ctx = Context::new();
+    ctx.insert("error", error);
+    ctx.insert("back_link", "/group/promotion");
+    ctx.insert("title", title);
+
+    Template::render_with_user(req, "group/failed.html", &mut ctx)
+}
+
+/// Show the promotion rules of all groups
+pub fn rules(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LoadPromotionRulesMsg(user))
+        .from_err()
+        .and_then(move |result: Result<PromotionRules>| {
+            match result {
+                Ok(rules) => {
+                    let rows: Vec<RuleRow> = rules.groups
+                        .iter()
+                        .filter(|g| g.parent_id.is_some())
+                        .map(|g| {
+                            let rule = rules.rules.iter().find(|r| r.group_id == g.id);
+                            RuleRow {
+                                group: g,
+                                parent_name: rules.groups.iter().find(|p| Some(p.id) == g.parent_id).map(|p| &p.name[..]),
+                                rule,
+                                min_uploaded: rule.map(|r| r.min_uploaded / UPLOADED_UNIT).unwrap_or_default(),
+                                team: rules.team.contains(&g.id),
+                            }
+                        })
+                        .collect();
+                    let mut ctx = Context::new();
+                    ctx.insert("rows", &rows);
+                    ctx.insert("may_edit", &rules.may_edit);
+                    ctx.insert("no_excluded", &rules.team.is_empty());
+                    Template::render_with_user(&req, "group/promotion.html", &mut ctx)
+                },
+                Err(_) => sync_redirect("/"),
+            }
+        })
+        .responder()
+}
+
+/// Save the promotion rule of a group
+pub fn save(mut req: HttpRequest<State>, data: Form<RuleForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no group id"))),
+    };
+
+    let RuleForm{min_age_days, min_uploaded, min_ratio, min_uploads} = data.into_inner();
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let msg = SavePromotionRuleMsg::new(id, min_age_days, min_uploaded.saturating_mul(UPLOADED_UNIT), min_ratio, min_uploads, user);
+    req.clone().state().db().send(msg)
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/group/promotion"),
+                Err(e) => failed(&req, "Save promotion rule failed", &e.to_string()),
+            }
+        })
+        .responder()
+}
+
+/// Delete the promotion rule of a group
+pub fn delete(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no group id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(DeletePromotionRuleMsg::new(id, user))
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/group/promotion"),
+                Err(e) => failed(&req, "Delete promotion rule failed", &e.to_string()),
+            }
+        })
+        .responder()
+}
+
+/// Show who would be promoted or demoted, without changing anything
+pub fn report(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(PromotionReportMsg(user))
+        .from_err()
+        .and_then(move |result: Result<Vec<GroupChange>>| {
+            match result {
+                Ok(changes) => {
+                    let mut ctx = Context::new();
+                    ctx.insert("changes", &changes);
+                    Template::render_with_user(&req, "group/report.html", &mut ctx)
+                },
+                Err(e) => failed(&req, "Promotion report failed", &e.to_string()),
+            }
+        })
+        .responder()
+}
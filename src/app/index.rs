@@ -18,8 +18,10 @@
 
 use super::*;
 
-use models::chat::ChatRoom;
 use models::acl::Subject;
+use models::chat::ChatRoom;
+use handlers::UserSubjectMsg;
+use handlers::chat::LoadChatRoomsMsg;
 use handlers::user::ActiveUsersMsg;
 use chrono::Duration;
 use actix_web::AsyncResponder;
@@ -29,7 +31,22 @@ struct Chat {
     id: String,
     nid: i16,
     name: String,
+    topic: String,
     active: bool,
+    moderate: bool,
+}
+
+impl From<ChatRoom> for Chat {
+    fn from(room: ChatRoom) -> Self {
+        Chat {
+            id: room.slug,
+            nid: room.id,
+            name: room.name,
+            topic: room.topic,
+            active: false,
+            moderate: false,
+        }
+    }
 }
 
 pub fn authenticated(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
@@ -40,19 +57,35 @@ pub fn authenticated(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse
     let mut ctx = Context::new();
 
     // Chat
-    let subj = UserSubject::new(&user_id, &group_id, req.state().acl());
-    let mut chatrooms = vec![Chat{id: ChatRoom::Public.to_string(), nid: ChatRoom::Public.into(), name: "Shoutbox".to_string(), active: true}];
-    if subj.may_read(&ChatRoom::Team) {
-        chatrooms.push(Chat{id: ChatRoom::Team.to_string(), nid: ChatRoom::Team.into(), name: "Teambox".to_string(), active: false});
-    }
-    ctx.insert("chatrooms", &chatrooms);
-
-    // Active Users
-    let active_users = ActiveUsersMsg(Duration::minutes(30));
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let db = req.state().db().clone();
     let cloned = req.clone();
-    req.state().db().send(active_users)
+    let acl = req.state().acl().clone();
+    req.state().db().send(LoadChatRoomsMsg::new(user))
         .from_err()
         .and_then(move |result| {
+            let mut chatrooms: Vec<Chat> = match result {
+                Ok(rooms) => {
+                    let subj = UserSubject::new(&user_id, &group_id, &acl);
+                    rooms.into_iter().map(|room| {
+                        let moderate = subj.may_delete(&room);
+                        Chat { moderate, ..Chat::from(room) }
+                    }).collect()
+                },
+                Err(e) => {
+                    warn!("could not fetch chatrooms: {}", e);
+                    Vec::new()
+                }
+            };
+            if let Some(chat) = chatrooms.first_mut() {
+                chat.active = true;
+            }
+            ctx.insert("chatrooms", &chatrooms);
+
+            // Active Users
+            db.send(ActiveUsersMsg(Duration::minutes(30))).from_err().map(move |result| (result, ctx))
+        })
+        .and_then(move |(result, mut ctx)| {
             match result {
                 Ok(active_users) => {
                     ctx.insert("active_users", &active_users);
@@ -17,7 +17,10 @@
  */
 
 use super::*;
-use handlers::user::LoginForm;
+use handlers::session::RevokeSessionMsg;
+use handlers::user::{LoginForm, LoginLockoutMsg};
+use models::session::UserSession;
+use login_throttle::{CheckLoginMsg, LoginSucceededMsg, LoginThrottle};
 use actix_web::AsyncResponder;
 use actix_web::HttpMessage;
 
@@ -30,49 +33,87 @@ pub fn login(req: HttpRequest<State>) -> SyncResponse<HttpResponse> {
 
 pub fn take_login(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
     let cloned = req.clone();
-    let form = match cloned.urlencoded::<LoginForm>().wait() {
+    let mut form = match cloned.urlencoded::<LoginForm>().wait() {
         Ok(form) => form,
         Err(e) => return Box::new(FutErr(ErrorInternalServerError(format!("{}", e))))
     };
+    form.ip_address = req.peer_addr().map(|addr| addr.ip());
+    form.user_agent = user_agent(&req);
+    form.ban_list = req.state().ban_list().clone();
 
-    let cloned = req.clone();
-    cloned.state()
-        .db()
-        .send(form.clone())
+    let throttle = match req.state().login_throttle() {
+        Some(throttle) => throttle.clone(),
+        None => return Box::new(FutErr(ErrorInternalServerError("login throttle not running"))),
+    };
+
+    // throttled attempts are rejected before the password is hashed, accepted attempts count as
+    // failed until the password is verified
+    let check = CheckLoginMsg {
+        username: form.username.clone(),
+        ip_address: form.ip_address,
+    };
+    throttle.send(check)
         .from_err()
-        .and_then(move |r: Result<User>| {
-            let mut ctx = Context::new();
-            let mut fail = true;
+        .and_then(move |result: Result<bool>| -> FutureResponse<HttpResponse> {
+            match result {
+                Ok(locked) => verify_login(req, form, throttle, locked),
+                Err(e) => Box::new(future::result(login_failed(&req, &form.username, &e.to_string()))),
+            }
+        })
+        .responder()
+}
 
+fn verify_login(req: HttpRequest<State>, form: LoginForm, throttle: Addr<Syn, LoginThrottle>, locked: bool) -> FutureResponse<HttpResponse> {
+    let username = form.username.clone();
+    let ip_address = form.ip_address;
+
+    req.clone().state()
+        .db()
+        .send(form)
+        .from_err()
+        .and_then(move |r: Result<(User, UserSession)>| -> SyncResponse<HttpResponse> {
             match r {
-                Ok(user) => {
-                    match req.session().set("user_id", user.id) {
-                        Ok(_) => {},
-                        Err(e) => return Err(ErrorInternalServerError(format!("{}", e))),
-                    };
-                    match req.session().set("group_id", user.group_id) {
-                        Ok(_) => {},
-                        Err(e) => return Err(ErrorInternalServerError(format!("{}", e))),
-                    };
-                    fail = false;
+                Ok((user, session)) => {
+                    throttle.do_send(LoginSucceededMsg {
+                        username,
+                        ip_address,
+                    });
+                    start_session(&req, &session, user.group_id)
+                        .map_err(|e| ErrorInternalServerError(format!("{}", e)))?;
+                    sync_redirect("/")
                 },
                 Err(e) => {
-                    ctx.insert("error", &format!("{}", e));
-                    ctx.insert("username", &form.username);
-                }
-            }
+                    // the attempt was already counted as failed
+                    if !locked {
+                        return login_failed(&req, &username, &e.to_string());
+                    }
 
-            if fail {
-                let tpl = req.state().template();
-                Template::render(&tpl, "login/login.html", &ctx)
-            } else {
-                Ok(redirect("/"))
+                    let minutes = SETTINGS.read().unwrap().login.lockout_minutes;
+                    req.state().db().do_send(LoginLockoutMsg {
+                        username: username.clone(),
+                        ip_address,
+                        minutes,
+                    });
+                    let error = format!("too many failed logins, try again in {} minutes", minutes);
+                    login_failed(&req, &username, &error)
+                }
             }
         })
         .responder()
 }
 
-pub fn logout(req: HttpRequest<State>) -> SyncResponse<HttpResponse> {
+fn login_failed(req: &HttpRequest<State>, username: &str, error: &str) -> SyncResponse<HttpResponse> {
+    let mut ctx = Context::new();
+    ctx.insert("error", error);
+    ctx.insert("username", username);
+    let tpl = req.state().template();
+    Template::render(&tpl, "login/login.html", &ctx)
+}
+
+pub fn logout(mut req: HttpRequest<State>) -> SyncResponse<HttpResponse> {
+    if let (Some(session_id), Some((user_id, _))) = (session_id(&req), session_creds(&mut req)) {
+        req.state().db().do_send(RevokeSessionMsg(session_id, user_id));
+    }
     req.session().clear();
     let t: Vec<&str> = vec![];
     let url = req.url_for("index", &t).unwrap();
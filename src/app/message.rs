@@ -19,7 +19,9 @@
 use super::*;
 use actix_web::AsyncResponder;
 use actix_web::FromRequest;
-use handlers::message::{LoadMessageMsg, LoadMessagesMsg};
+use handlers::message::{LoadMassMessageGroupsMsg, LoadMessageMsg, LoadMessagesMsg, LoadThreadMsg, SearchMessagesMsg};
+use handlers::UserSubjectMsg;
+use models::group::Group;
 
 #[derive(Deserialize)]
 pub struct NewMessage {
@@ -54,6 +56,35 @@ pub fn messages(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
         .responder()
 }
 
+pub fn search(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match session_creds(&mut req) {
+        Some((u, _)) => u,
+        None => return async_redirect("/login"),
+    };
+
+    let query = match Query::<HashMap<String, String>>::extract(&req) {
+        Ok(query) => query.get("q").cloned().unwrap_or_default(),
+        Err(e) => return Box::new(FutErr(ErrorBadRequest(e))),
+    };
+    let searchmsg = SearchMessagesMsg::new(query.clone(), user_id);
+
+    req.clone().state()
+        .db()
+        .send(searchmsg)
+        .from_err()
+        .and_then(move |result| match result {
+            Ok(messages) => {
+                let mut ctx = Context::new();
+                ctx.insert("messages", &messages);
+                ctx.insert("folder", "search");
+                ctx.insert("query", &query);
+                Template::render_with_user(&req, "message/list.html", &mut ctx)
+            },
+            Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+        })
+        .responder()
+}
+
 pub fn message(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
     let user_id = match session_creds(&mut req) {
         Some((u, _)) => u,
@@ -89,6 +120,34 @@ pub fn message(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
         .responder()
 }
 
+pub fn thread(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match session_creds(&mut req) {
+        Some((u, _)) => u,
+        None => return async_redirect("/login"),
+    };
+
+    let id = match req.match_info().get("id").map(Uuid::parse_str) {
+        Some(Ok(id)) => id,
+        _ => return Box::new(FutErr(ErrorNotFound("invalid message id"))),
+    };
+    let loadmsg = LoadThreadMsg::new(id, user_id);
+
+    req.clone().state()
+        .db()
+        .send(loadmsg)
+        .from_err()
+        .and_then(move |result| match result {
+            Ok(messages) => {
+                let mut ctx = Context::new();
+                ctx.insert("messages", &messages);
+                ctx.insert("current_id", &id);
+                Template::render_with_user(&req, "message/thread.html", &mut ctx)
+            },
+            Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+        })
+        .responder()
+}
+
 pub fn new(req: HttpRequest<State>) -> SyncResponse<HttpResponse> {
     let mut ctx = Context::new();
     let query = Query::<HashMap<String, String>>::extract(&req)?;
@@ -132,3 +191,26 @@ pub fn reply(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
         })
         .responder()
 }
+
+/// Show the mass message form
+pub fn mass(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state()
+        .db()
+        .send(LoadMassMessageGroupsMsg(user))
+        .from_err()
+        .and_then(move |result: Result<Vec<Group>>| match result {
+            Ok(groups) => {
+                let mut ctx = Context::new();
+                ctx.insert("groups", &groups);
+                Template::render_with_user(&req, "message/mass.html", &mut ctx)
+            },
+            Err(_) => sync_redirect("/"),
+        })
+        .responder()
+}
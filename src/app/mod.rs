@@ -25,19 +25,27 @@ use std::fmt::Write;
 use std::cmp::Ordering;
 
 use models::User;
+use models::ban_list::BanList;
 use handlers::torrent::LoadCategoriesMsg;
 
 use actix_web::HttpMessage;
 use bytes::Bytes;
 use identity::{AppIdentityPolicy, IdentityService};
+use login_throttle::LoginThrottle;
 use multipart::server::{save::SavedData, Entries, Multipart, SaveResult};
 use std::io::Cursor;
 use template::TemplateContainer;
 use tera::Context;
 
+mod bonus;
+mod chat;
+mod group;
 mod index;
 mod login;
 mod message;
+mod moderation;
+mod notification;
+mod request;
 mod signup;
 mod static_content;
 mod torrent;
@@ -57,7 +65,13 @@ fn redirect(loc: &str) -> HttpResponse {
         .finish()
 }
 
-pub fn build(db: Addr<Syn, DbExecutor>, tpl: TemplateContainer, acl: Arc<RwLock<Acl>>) -> App<State> {
+pub fn build(
+    db: Addr<Syn, DbExecutor>,
+    tpl: TemplateContainer,
+    acl: Arc<RwLock<Acl>>,
+    ban_list: Arc<RwLock<BanList>>,
+    login_throttle: Addr<Syn, LoginThrottle>,
+) -> App<State> {
     let settings = SETTINGS.read().unwrap();
     //    let redis = env::var("REDIS").unwrap_or(String::from("127.0.0.1::6379"));
     let session_secret = util::from_hex(&settings.session_secret).unwrap();
@@ -68,6 +82,8 @@ pub fn build(db: Addr<Syn, DbExecutor>, tpl: TemplateContainer, acl: Arc<RwLock<
 
     let mut state = State::new(db, acl);
     state.set_template(tpl);
+    state.set_ban_list(ban_list);
+    state.set_login_throttle(login_throttle);
     App::with_state(state)
         .middleware(Logger::default())
         .middleware(DefaultHeaders::new().header("X-Version", env!("CARGO_PKG_VERSION")))
@@ -142,6 +158,14 @@ pub fn build(db: Addr<Syn, DbExecutor>, tpl: TemplateContainer, acl: Arc<RwLock<
             r.name("torrent#delete");
             r.method(Method::POST).filter(require_user()).a(app::torrent::do_delete);
         })
+        .resource("/torrent/reseed/{id}", |r| {
+            r.name("torrent#reseed");
+            r.method(Method::POST).filter(require_user()).a(app::torrent::reseed);
+        })
+        .resource("/torrent/freeleech/{id}", |r| {
+            r.name("torrent#freeleech");
+            r.method(Method::POST).filter(require_user()).a(app::bonus::freeleech);
+        })
         .resource("/torrent/download/{id}", |r| {
             r.name("torrent#download");
             r.method(Method::GET).filter(require_user()).f(app::torrent::download);
@@ -154,6 +178,72 @@ pub fn build(db: Addr<Syn, DbExecutor>, tpl: TemplateContainer, acl: Arc<RwLock<
             r.name("torrent#read");
             r.method(Method::GET).filter(require_user()).f(app::torrent::torrent);
         })
+        .resource("/bonus", |r| {
+            r.name("bonus#store");
+            r.method(Method::GET).filter(require_user()).a(app::bonus::store);
+        })
+        .resource("/bonus/buy", |r| {
+            r.name("bonus#buy");
+            r.method(Method::POST).filter(require_user()).with2(app::bonus::buy);
+        })
+        .resource("/bonus/user/{id}", |r| {
+            r.name("bonus#ledger");
+            r.method(Method::GET).filter(require_user()).a(app::bonus::ledger);
+        })
+        .resource("/group/promotion", |r| {
+            r.name("group#promotion");
+            r.method(Method::GET).filter(require_user()).a(app::group::rules);
+        })
+        .resource("/group/promotion/report", |r| {
+            r.name("group#promotion_report");
+            r.method(Method::GET).filter(require_user()).a(app::group::report);
+        })
+        .resource("/group/promotion/delete/{id}", |r| {
+            r.name("group#promotion_delete");
+            r.method(Method::POST).filter(require_user()).a(app::group::delete);
+        })
+        .resource("/group/promotion/{id}", |r| {
+            r.name("group#promotion_save");
+            r.method(Method::POST).filter(require_user()).with2(app::group::save);
+        })
+        .resource("/requests", |r| {
+            r.name("request#list");
+            r.method(Method::GET).filter(require_user()).a(app::request::requests);
+        })
+        .resource("/request/new", |r| {
+            r.name("request#new");
+            r.method(Method::GET).filter(require_user()).f(app::request::new);
+            r.method(Method::POST).filter(require_user()).with2(app::request::create);
+        })
+        .resource("/request/bounty/{id}", |r| {
+            r.name("request#bounty");
+            r.method(Method::POST).filter(require_user()).with2(app::request::bounty);
+        })
+        .resource("/request/vote/{id}", |r| {
+            r.name("request#vote");
+            r.method(Method::POST).filter(require_user()).a(app::request::vote);
+        })
+        .resource("/request/fill/{id}", |r| {
+            r.name("request#fill");
+            r.method(Method::POST).filter(require_user()).with2(app::request::fill);
+        })
+        .resource("/request/confirm/{id}", |r| {
+            r.name("request#confirm");
+            r.method(Method::POST).filter(require_user()).a(app::request::confirm);
+        })
+        .resource("/request/reject/{id}", |r| {
+            r.name("request#reject");
+            r.method(Method::POST).filter(require_user()).a(app::request::reject);
+        })
+        .resource("/request/delete/{id}", |r| {
+            r.name("request#delete");
+            r.method(Method::POST).filter(require_user()).a(app::request::delete);
+        })
+        .resource("/request/{id}", |r| {
+            r.name("request#read");
+            r.method(Method::GET).filter(require_user()).a(app::request::request);
+            r.method(Method::POST).filter(require_user()).with2(app::request::update);
+        })
         .resource("/user/profile", |r| {
             r.name("user#profile");
             r.method(Method::GET).filter(require_user()).f(app::user::profile);
@@ -163,6 +253,71 @@ pub fn build(db: Addr<Syn, DbExecutor>, tpl: TemplateContainer, acl: Arc<RwLock<
             r.method(Method::GET).filter(require_user()).a(app::user::settings);
             r.method(Method::POST).filter(require_user()).a(app::user::update_settings);
         })
+        .resource("/bans", |r| {
+            r.name("user#ban_list");
+            r.method(Method::GET).filter(require_user()).a(app::moderation::ban_lists);
+        })
+        .resource("/bans/ip", |r| {
+            r.name("user#ban_list_ip");
+            r.method(Method::POST).filter(require_user()).with2(app::moderation::add_ip_ban);
+        })
+        .resource("/bans/ip/delete/{id}", |r| {
+            r.name("user#ban_list_ip_delete");
+            r.method(Method::POST).filter(require_user()).a(app::moderation::delete_ip_ban);
+        })
+        .resource("/bans/email", |r| {
+            r.name("user#ban_list_email");
+            r.method(Method::POST).filter(require_user()).with2(app::moderation::add_email_ban);
+        })
+        .resource("/bans/email/delete/{id}", |r| {
+            r.name("user#ban_list_email_delete");
+            r.method(Method::POST).filter(require_user()).a(app::moderation::delete_email_ban);
+        })
+        .resource("/user/warn/{id}", |r| {
+            r.name("user#warn");
+            r.method(Method::POST).filter(require_user()).with2(app::moderation::warn);
+        })
+        .resource("/user/warning/revoke/{id}", |r| {
+            r.name("user#warning_revoke");
+            r.method(Method::POST).filter(require_user()).a(app::moderation::revoke);
+        })
+        .resource("/user/ban/{id}", |r| {
+            r.name("user#ban");
+            r.method(Method::POST).filter(require_user()).with2(app::moderation::ban);
+        })
+        .resource("/user/unban/{id}", |r| {
+            r.name("user#unban");
+            r.method(Method::POST).filter(require_user()).a(app::moderation::lift);
+        })
+        .resource("/user/sessions", |r| {
+            r.name("user#sessions");
+            r.method(Method::GET).filter(require_user()).a(app::user::sessions);
+        })
+        .resource("/user/sessions/revoke/{id}", |r| {
+            r.name("user#session_revoke");
+            r.method(Method::POST).filter(require_user()).a(app::user::revoke_session);
+        })
+        .resource("/user/sessions/revoke_others", |r| {
+            r.name("user#sessions_revoke_others");
+            r.method(Method::POST).filter(require_user()).a(app::user::revoke_other_sessions);
+        })
+        .resource("/user/tokens", |r| {
+            r.name("user#tokens");
+            r.method(Method::GET).filter(require_user()).a(app::user::tokens);
+            r.method(Method::POST).filter(require_user()).with2(app::user::create_token);
+        })
+        .resource("/user/tokens/revoke/{id}", |r| {
+            r.name("user#token_revoke");
+            r.method(Method::POST).filter(require_user()).a(app::user::revoke_token);
+        })
+        .resource("/user/duplicates", |r| {
+            r.name("user#duplicates");
+            r.method(Method::GET).filter(require_user()).a(app::moderation::duplicates);
+        })
+        .resource("/user/relation/{id}", |r| {
+            r.name("user#relation");
+            r.method(Method::POST).filter(require_user()).with2(app::user::relation);
+        })
         .resource("/user/{id}", |r| {
             r.name("user#profile");
             r.method(Method::GET).filter(require_user()).f(app::user::view);
@@ -175,6 +330,22 @@ pub fn build(db: Addr<Syn, DbExecutor>, tpl: TemplateContainer, acl: Arc<RwLock<
             r.name("message#new");
             r.method(Method::GET).filter(require_user()).f(app::message::new);
         })
+        .resource("/notifications", |r| {
+            r.name("notification#notifications");
+            r.method(Method::GET).filter(require_user()).f(app::notification::notifications);
+        })
+        .resource("/message/search", |r| {
+            r.name("message#search");
+            r.method(Method::GET).filter(require_user()).f(app::message::search);
+        })
+        .resource("/message/mass", |r| {
+            r.name("message#mass");
+            r.method(Method::GET).filter(require_user()).f(app::message::mass);
+        })
+        .resource("/message/thread/{id}", |r| {
+            r.name("message#thread");
+            r.method(Method::GET).filter(require_user()).f(app::message::thread);
+        })
         .resource("/message/reply/{id}", |r| {
             r.name("message#reply");
             r.method(Method::GET).filter(require_user()).f(app::message::reply);
@@ -196,6 +367,20 @@ pub fn build(db: Addr<Syn, DbExecutor>, tpl: TemplateContainer, acl: Arc<RwLock<
             r.method(Method::GET).filter(require_user()).a(app::static_content::edit);
             r.method(Method::POST).filter(require_user()).with2(app::static_content::update);
         })
+        .resource("/chat/rooms", |r| {
+            r.name("chat#rooms");
+            r.method(Method::GET).filter(require_user()).a(app::chat::rooms);
+            r.method(Method::POST).filter(require_user()).with2(app::chat::create);
+        })
+        .resource("/chat/room/delete/{id}", |r| {
+            r.name("chat#delete");
+            r.method(Method::POST).filter(require_user()).a(app::chat::delete);
+        })
+        .resource("/chat/room/{id}", |r| {
+            r.name("chat#edit");
+            r.method(Method::GET).filter(require_user()).a(app::chat::edit);
+            r.method(Method::POST).filter(require_user()).with2(app::chat::update);
+        })
         .default_resource(|r| r.f(app::not_found))
 }
 
@@ -0,0 +1,304 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Warnings and bans, managed on the user profile, the IP and email domain ban lists and the
+//! duplicate account report
+
+use super::*;
+use actix_web::AsyncResponder;
+use actix_web::Form;
+use actix_web::FromRequest;
+use handlers::UserSubjectMsg;
+use handlers::moderation::*;
+use models::history::Duplicates;
+use std::collections::HashMap;
+
+/// Default time window of the duplicate account report in days
+const DUPLICATES_DAYS: i64 = 7;
+
+#[derive(Deserialize)]
+pub struct WarnForm {
+    reason: String,
+    severity: i16,
+    days: i64,
+}
+
+#[derive(Deserialize)]
+pub struct BanListForm {
+    /// a network, e.g. `10.0.0.0/8`, or an email domain
+    entry: String,
+    reason: String,
+}
+
+#[derive(Deserialize)]
+pub struct BanForm {
+    reason: String,
+    /// `0` bans permanently
+    days: i64,
+}
+
+fn path_id(req: &HttpRequest<State>) -> Option<Uuid> {
+    req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok())
+}
+
+fn failed(req: &HttpRequest<State>, title: &str, error: &str, back_link: &str) -> SyncResponse<HttpResponse> {
+    let mut ctx = Context::new();
+    ctx.insert("error", error);
+    ctx.insert("back_link", back_link);
+    ctx.insert("title", title);
+
+    Template::render_with_user(req, "user/moderation_failed.html", &mut ctx)
+}
+
+/// Warn a user
+pub fn warn(mut req: HttpRequest<State>, data: Form<WarnForm>) -> FutureResponse<HttpResponse> {
+    let (current_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let user_id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no user id"))),
+    };
+
+    let WarnForm{reason, severity, days} = data.into_inner();
+    let user = UserSubjectMsg::new(current_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(WarnUserMsg::new(user_id, reason, severity, days, user))
+        .from_err()
+        .and_then(move |result| {
+            match result {
+                Ok(_) => sync_redirect(&format!("/user/{}", user_id)),
+                Err(e) => failed(&req, "Warning failed", &e.to_string(), &format!("/user/{}", user_id)),
+            }
+        })
+        .responder()
+}
+
+/// Revoke a warning
+pub fn revoke(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (current_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let warning_id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no warning id"))),
+    };
+
+    let user = UserSubjectMsg::new(current_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(RevokeWarningMsg::new(warning_id, user))
+        .from_err()
+        .and_then(move |result: Result<Uuid>| {
+            match result {
+                Ok(user_id) => sync_redirect(&format!("/user/{}", user_id)),
+                Err(e) => Err(ErrorBadRequest(e.to_string())),
+            }
+        })
+        .responder()
+}
+
+/// Ban a user
+pub fn ban(mut req: HttpRequest<State>, data: Form<BanForm>) -> FutureResponse<HttpResponse> {
+    let (current_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let user_id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no user id"))),
+    };
+
+    let BanForm{reason, days} = data.into_inner();
+    let user = UserSubjectMsg::new(current_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(BanUserMsg::new(user_id, reason, days, user))
+        .from_err()
+        .and_then(move |result| {
+            match result {
+                Ok(_) => sync_redirect(&format!("/user/{}", user_id)),
+                Err(e) => failed(&req, "Ban failed", &e.to_string(), &format!("/user/{}", user_id)),
+            }
+        })
+        .responder()
+}
+
+/// Lift the ban of a user
+pub fn lift(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (current_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let user_id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no user id"))),
+    };
+
+    let user = UserSubjectMsg::new(current_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LiftBanMsg::new(user_id, user))
+        .from_err()
+        .and_then(move |result| {
+            match result {
+                Ok(_) => sync_redirect(&format!("/user/{}", user_id)),
+                Err(e) => failed(&req, "Lift ban failed", &e.to_string(), &format!("/user/{}", user_id)),
+            }
+        })
+        .responder()
+}
+
+/// Show the IP and email domain ban lists
+pub fn ban_lists(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LoadBanListsMsg(user))
+        .from_err()
+        .and_then(move |result: Result<BanLists>| {
+            match result {
+                Ok(lists) => {
+                    let mut ctx = Context::new();
+                    ctx.insert("lists", &lists);
+                    Template::render_with_user(&req, "user/ban_list.html", &mut ctx)
+                },
+                Err(_) => sync_redirect("/"),
+            }
+        })
+        .responder()
+}
+
+/// Add a network to the ban list
+pub fn add_ip_ban(mut req: HttpRequest<State>, data: Form<BanListForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let BanListForm{entry, reason} = data.into_inner();
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let msg = AddIpBanMsg::new(entry, reason, user, req.state().ban_list().clone());
+    req.clone().state().db().send(msg)
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/bans"),
+                Err(e) => failed(&req, "Ban network failed", &e.to_string(), "/bans"),
+            }
+        })
+        .responder()
+}
+
+/// Remove a network from the ban list
+pub fn delete_ip_ban(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no ban id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let msg = DeleteIpBanMsg::new(id, user, req.state().ban_list().clone());
+    req.clone().state().db().send(msg)
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/bans"),
+                Err(e) => failed(&req, "Delete network ban failed", &e.to_string(), "/bans"),
+            }
+        })
+        .responder()
+}
+
+/// Add an email domain to the ban list
+pub fn add_email_ban(mut req: HttpRequest<State>, data: Form<BanListForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let BanListForm{entry, reason} = data.into_inner();
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let msg = AddEmailDomainBanMsg::new(entry, reason, user, req.state().ban_list().clone());
+    req.clone().state().db().send(msg)
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/bans"),
+                Err(e) => failed(&req, "Ban email domain failed", &e.to_string(), "/bans"),
+            }
+        })
+        .responder()
+}
+
+/// Remove an email domain from the ban list
+pub fn delete_email_ban(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match path_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no ban id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let msg = DeleteEmailDomainBanMsg::new(id, user, req.state().ban_list().clone());
+    req.clone().state().db().send(msg)
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/bans"),
+                Err(e) => failed(&req, "Delete email domain ban failed", &e.to_string(), "/bans"),
+            }
+        })
+        .responder()
+}
+
+/// Show the users sharing addresses, passkeys or clients
+///
+/// The time window is given in days by the `days` query parameter.
+pub fn duplicates(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let days = match Query::<HashMap<String, String>>::extract(&req) {
+        Ok(query) => query.get("days").and_then(|days| days.parse::<i64>().ok()).unwrap_or(DUPLICATES_DAYS).max(1),
+        Err(_) => DUPLICATES_DAYS,
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LoadDuplicatesMsg(days, user))
+        .from_err()
+        .and_then(move |result: Result<Duplicates>| {
+            match result {
+                Ok(duplicates) => {
+                    let mut ctx = Context::new();
+                    ctx.insert("duplicates", &duplicates);
+                    ctx.insert("days", &days);
+                    Template::render_with_user(&req, "user/duplicates.html", &mut ctx)
+                },
+                Err(_) => sync_redirect("/"),
+            }
+        })
+        .responder()
+}
@@ -0,0 +1,46 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Notification center
+
+use super::*;
+use actix_web::AsyncResponder;
+use handlers::notification::LoadNotificationsMsg;
+use models::notification::Notification;
+
+/// List the latest notifications
+pub fn notifications(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match session_creds(&mut req) {
+        Some((u, _)) => u,
+        None => return async_redirect("/login"),
+    };
+
+    req.clone().state()
+        .db()
+        .send(LoadNotificationsMsg::new(user_id, false))
+        .from_err()
+        .and_then(move |result: Result<Vec<Notification>>| match result {
+            Ok(notifications) => {
+                let mut ctx = Context::new();
+                ctx.insert("notifications", &notifications);
+                Template::render_with_user(&req, "notification/list.html", &mut ctx)
+            },
+            Err(e) => Ok(HttpResponse::InternalServerError().body(e.to_string())),
+        })
+        .responder()
+}
@@ -0,0 +1,325 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Request board
+
+use super::*;
+use actix_web::AsyncResponder;
+use actix_web::Form;
+use actix_web::FromRequest;
+use handlers::UserSubjectMsg;
+use handlers::request::*;
+use models::request::{parse_torrent_id, RequestListItem, RequestOrder, RequestStatus, TorrentRequest};
+
+/// Bounties are entered in MiB
+const BOUNTY_UNIT: i64 = 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct RequestForm {
+    title: String,
+    category: Uuid,
+    description: String,
+    bounty: i64,
+}
+
+#[derive(Deserialize)]
+pub struct EditRequestForm {
+    title: String,
+    category: Uuid,
+    description: String,
+}
+
+#[derive(Deserialize)]
+pub struct BountyForm {
+    bounty: i64,
+}
+
+#[derive(Deserialize)]
+pub struct FillForm {
+    torrent: String,
+}
+
+fn request_id(req: &HttpRequest<State>) -> Option<Uuid> {
+    req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok())
+}
+
+fn failed(req: &HttpRequest<State>, title: &str, error: &str, back_link: &str) -> SyncResponse<HttpResponse> {
+    let mut ctx = Context::new();
+    ctx.insert("error", error);
+    ctx.insert("back_link", back_link);
+    ctx.insert("title", title);
+
+    Template::render_with_user(req, "request/failed.html", &mut ctx)
+}
+
+/// List and search the requests
+pub fn requests(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let query = match Query::<HashMap<String, String>>::extract(&req) {
+        Ok(query) => query.into_inner(),
+        Err(_) => HashMap::new(),
+    };
+    let title = query.get("title").cloned().unwrap_or_default();
+    let category = query.get("category").and_then(|id| Uuid::parse_str(id).ok());
+    let status = RequestStatus::from(query.get("status").map(|s| &s[..]).unwrap_or_default());
+    let order = RequestOrder::from(query.get("order").map(|s| &s[..]).unwrap_or_default());
+    let page = query.get("page").and_then(|page| page.parse::<i64>().ok()).unwrap_or(1).max(1);
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let loadmsg = LoadRequestsMsg::new(Some(title.clone()), category, status, order, page, user);
+    req.clone().state().db().send(loadmsg)
+        .from_err()
+        .and_then(move |result: Result<Vec<RequestListItem>>| {
+            match result {
+                Ok(requests) => {
+                    let mut ctx = Context::new();
+                    ctx.insert("categories", &categories(req.state()));
+                    ctx.insert("has_next", &(requests.len() as i64 == REQUESTS_PER_PAGE));
+                    ctx.insert("requests", &requests);
+                    ctx.insert("title", &title);
+                    ctx.insert("category", &category.map(|id| id.to_string()).unwrap_or_default());
+                    ctx.insert("status", status.as_str());
+                    ctx.insert("order", order.as_str());
+                    ctx.insert("page", &page);
+                    Template::render_with_user(&req, "request/list.html", &mut ctx)
+                },
+                Err(_) => sync_redirect("/"),
+            }
+        })
+        .responder()
+}
+
+/// Show the form for a new request
+pub fn new(req: HttpRequest<State>) -> SyncResponse<HttpResponse> {
+    let min_bounty = SETTINGS.read().unwrap().request.min_bounty;
+    let mut ctx = Context::new();
+    ctx.insert("categories", &categories(req.state()));
+    ctx.insert("min_bounty", &((min_bounty + BOUNTY_UNIT - 1) / BOUNTY_UNIT));
+    Template::render_with_user(&req, "request/new.html", &mut ctx)
+}
+
+/// Create a new request
+pub fn create(mut req: HttpRequest<State>, data: Form<RequestForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+
+    let RequestForm{title, category, description, bounty} = data.into_inner();
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let createmsg = CreateRequestMsg::new(title, category, description, bounty.saturating_mul(BOUNTY_UNIT), user);
+    req.clone().state().db().send(createmsg)
+        .from_err()
+        .and_then(move |result: Result<TorrentRequest>| {
+            match result {
+                Ok(request) => sync_redirect(&request.link()),
+                Err(e) => failed(&req, "Create request failed", &e.to_string(), "/request/new"),
+            }
+        })
+        .responder()
+}
+
+/// Show a request
+pub fn request(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match request_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no request id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(LoadRequestMsg::new(id, user))
+        .from_err()
+        .and_then(move |result: Result<RequestDetails>| {
+            match result {
+                Ok(details) => {
+                    let min_bounty = SETTINGS.read().unwrap().request.min_bounty;
+                    let mut ctx = Context::new();
+                    ctx.insert("categories", &categories(req.state()));
+                    ctx.insert("details", &details);
+                    ctx.insert("min_bounty", &((min_bounty + BOUNTY_UNIT - 1) / BOUNTY_UNIT));
+                    Template::render_with_user(&req, "request/show.html", &mut ctx)
+                },
+                Err(e) => Err(ErrorNotFound(e.to_string())),
+            }
+        })
+        .responder()
+}
+
+/// Update a request
+pub fn update(mut req: HttpRequest<State>, data: Form<EditRequestForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match request_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no request id"))),
+    };
+
+    let EditRequestForm{title, category, description} = data.into_inner();
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(UpdateRequestMsg::new(id, title, category, description, user))
+        .from_err()
+        .and_then(move |result: Result<TorrentRequest>| {
+            match result {
+                Ok(request) => sync_redirect(&request.link()),
+                Err(e) => failed(&req, "Edit request failed", &e.to_string(), &format!("/request/{}", id)),
+            }
+        })
+        .responder()
+}
+
+/// Add a bounty to a request
+pub fn bounty(mut req: HttpRequest<State>, data: Form<BountyForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match request_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no request id"))),
+    };
+
+    let amount = data.into_inner().bounty.saturating_mul(BOUNTY_UNIT);
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(AddBountyMsg::new(id, amount, user))
+        .from_err()
+        .and_then(move |result: Result<TorrentRequest>| {
+            match result {
+                Ok(request) => sync_redirect(&request.link()),
+                Err(e) => failed(&req, "Add bounty failed", &e.to_string(), &format!("/request/{}", id)),
+            }
+        })
+        .responder()
+}
+
+/// Vote for a request
+pub fn vote(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match request_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no request id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(VoteRequestMsg::new(id, user))
+        .from_err()
+        .and_then(move |result: Result<TorrentRequest>| {
+            match result {
+                Ok(request) => sync_redirect(&request.link()),
+                Err(e) => failed(&req, "Vote failed", &e.to_string(), &format!("/request/{}", id)),
+            }
+        })
+        .responder()
+}
+
+/// Fill a request
+pub fn fill(mut req: HttpRequest<State>, data: Form<FillForm>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match request_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no request id"))),
+    };
+    let torrent_id = match parse_torrent_id(&data.torrent) {
+        Some(torrent_id) => torrent_id,
+        None => return Box::new(future::result(failed(&req, "Fill request failed", "invalid torrent link", &format!("/request/{}", id)))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(FillRequestMsg::new(id, torrent_id, user))
+        .from_err()
+        .and_then(move |result: Result<TorrentRequest>| {
+            match result {
+                Ok(request) => sync_redirect(&request.link()),
+                Err(e) => failed(&req, "Fill request failed", &e.to_string(), &format!("/request/{}", id)),
+            }
+        })
+        .responder()
+}
+
+/// Confirm the fill of a request, which pays out the bounty
+pub fn confirm(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    confirm_or_reject(req, true)
+}
+
+/// Reject the fill of a request
+pub fn reject(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    confirm_or_reject(req, false)
+}
+
+fn confirm_or_reject(mut req: HttpRequest<State>, accept: bool) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match request_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no request id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(ConfirmRequestMsg::new(id, accept, user))
+        .from_err()
+        .and_then(move |result: Result<TorrentRequest>| {
+            match result {
+                Ok(request) => sync_redirect(&request.link()),
+                Err(e) => {
+                    let title = if accept { "Confirm fill failed" } else { "Reject fill failed" };
+                    failed(&req, title, &e.to_string(), &format!("/request/{}", id))
+                },
+            }
+        })
+        .responder()
+}
+
+/// Delete a request
+pub fn delete(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match request_id(&req) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no request id"))),
+    };
+
+    let user = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    req.clone().state().db().send(DeleteRequestMsg::new(id, user))
+        .from_err()
+        .and_then(move |result: Result<()>| {
+            match result {
+                Ok(_) => sync_redirect("/requests"),
+                Err(e) => failed(&req, "Delete request failed", &e.to_string(), &format!("/request/{}", id)),
+            }
+        })
+        .responder()
+}
@@ -32,10 +32,12 @@ pub fn signup(req: HttpRequest<State>) -> SyncResponse<HttpResponse> {
 
 pub fn take_signup(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
     let cloned = req.clone();
-    let form = match cloned.urlencoded::<SignupForm>().wait() {
+    let mut form = match cloned.urlencoded::<SignupForm>().wait() {
         Ok(form) => form,
         Err(e) => return Box::new(future::err(actix_web::error::ErrorInternalServerError(format!("{}", e))))
     };
+    form.ip_address = req.peer_addr().map(|addr| addr.ip());
+    form.ban_list = req.state().ban_list().clone();
 
     let cloned = req.clone();
     cloned.state()
@@ -92,6 +94,7 @@ pub fn confirm(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
     let confirm = ConfirmMsg {
         id,
         ip_address,
+        user_agent: user_agent(&req),
     };
 
     let cloned = req.clone();
@@ -104,12 +107,8 @@ pub fn confirm(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
             let mut fail = true;
 
             match res {
-                Ok(user) => {
-                    match req.session().set("user_id", user.id) {
-                        Ok(_) => {},
-                        Err(e) => return Err(actix_web::error::ErrorInternalServerError(format!("{}", e))),
-                    };
-                    match req.session().set("group_id", user.group_id) {
+                Ok((user, session)) => {
+                    match start_session(&req, &session, user.group_id) {
                         Ok(_) => {},
                         Err(e) => return Err(actix_web::error::ErrorInternalServerError(format!("{}", e))),
                     };
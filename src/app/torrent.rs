@@ -28,10 +28,12 @@ use std::path::Path;
 use std::convert::TryFrom;
 use tempfile::NamedTempFile;
 
+use handlers::notification::send_notifications;
 use handlers::torrent::*;
 use handlers::UserSubjectMsg;
 use models::acl::Subject;
-use models::{torrent::{TorrentFile, TorrentImage, TorrentCommentResponse},
+use models::notification::NotificationKind;
+use models::{torrent::{TorrentFile, TorrentImage, TorrentCommentResponse, TorrentReseedResponse},
              Torrent,
              TorrentMsg};
 
@@ -346,7 +348,11 @@ struct ShowContext<'a> {
     may_delete: bool,
     timezone: i32,
     current_user: Option<User>,
-    comments: &'a Vec<TorrentCommentResponse>
+    comments: &'a Vec<TorrentCommentResponse>,
+    reseed: &'a Option<TorrentReseedResponse>,
+    may_reseed: bool,
+    freeleech_until: &'a Option<DateTime<Utc>>,
+    freeleech_tokens: i32,
 }
 
 impl<'a> From<&'a TorrentMsg> for ShowContext<'a> {
@@ -391,6 +397,10 @@ impl<'a> From<&'a TorrentMsg> for ShowContext<'a> {
             timezone: tc.timezone,
             current_user: None,
             comments: &tc.comments,
+            reseed: &tc.reseed,
+            may_reseed: tc.may_reseed,
+            freeleech_until: &tc.freeleech_until,
+            freeleech_tokens: tc.freeleech_tokens,
         }
     }
 }
@@ -869,7 +879,20 @@ pub fn do_delete(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
             ctx.insert("id", &id);
 
             match result {
-                Ok(_) => {
+                Ok(deleted) => {
+                    // notify the uploader and the snatchers in the background
+                    let (title, body) = deleted.notification();
+                    let notify = send_notifications(
+                        cloned.state().db().clone(),
+                        deleted.notify,
+                        NotificationKind::TorrentDeleted,
+                        title,
+                        body,
+                        None,
+                    ).map(|num| debug!("sent {} torrent deletion notifications", num))
+                        .map_err(|e| warn!("failed to send torrent deletion notifications: {}", e));
+                    Arbiter::handle().spawn(notify);
+
                     ctx.insert("message", "Torrent was deleted");
                     ctx.insert("title", "Delete Torrent");
                     ctx.insert("sub_title", "Delete Succeeded");
@@ -891,6 +914,63 @@ pub fn do_delete(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
         .responder()
 }
 
+pub fn reseed(mut req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, group_id) = match session_creds(&mut req) {
+        Some((u, g)) => (u, g),
+        None => return async_redirect("/login"),
+    };
+    let id = match req.match_info().query::<String>("id") {
+        Ok(id) => match Uuid::parse_str(&id[..]) {
+            Ok(id) => id,
+            Err(e) => return Box::new(FutErr(ErrorInternalServerError(e))),
+        },
+        Err(e) => return Box::new(FutErr(ErrorNotFound(e))),
+    };
+
+    let subj = UserSubjectMsg::new(user_id, group_id, req.state().acl().clone());
+    let cloned = req.clone();
+    req.clone()
+        .state()
+        .db()
+        .send(RequestReseedMsg::new(id, subj))
+        .from_err()
+        .and_then(move |result| {
+            let mut ctx = Context::new();
+            ctx.insert("id", &id);
+            let link = cloned.url_for("torrent#read", &[id.to_string()]).unwrap().to_string();
+
+            match result {
+                Ok(reseed) => {
+                    // notify the uploader and the snatchers in the background
+                    let notify = send_notifications(
+                        cloned.state().db().clone(),
+                        reseed.notify,
+                        NotificationKind::Reseed,
+                        format!("Reseed requested: {}", reseed.name),
+                        String::from("The torrent has no seeders. Please seed it again, if you still have the files."),
+                        Some(link.clone()),
+                    ).map(|num| debug!("sent {} reseed notifications", num))
+                        .map_err(|e| warn!("failed to send reseed notifications: {}", e));
+                    Arbiter::handle().spawn(notify);
+
+                    ctx.insert("message", "The uploader and the snatchers have been asked to reseed the torrent");
+                    ctx.insert("title", "Request Reseed");
+                    ctx.insert("sub_title", "Reseed Requested");
+                    ctx.insert("continue_link", &link);
+                    Template::render_with_user(&cloned, "torrent/success.html", &mut ctx)
+                }
+                Err(e) => {
+                    ctx.insert("error", &e.to_string());
+                    ctx.insert("title", "Request Reseed");
+                    ctx.insert("sub_title", "Reseed Request Failed");
+                    ctx.insert("back_link", &link);
+                    Template::render_with_user(&cloned, "torrent/failed.html", &mut ctx)
+                }
+            }
+        })
+        .responder()
+}
+
 pub fn download(req: HttpRequest<State>) -> Either<HttpResponse, FutureResponse<HttpResponse>> {
     let id = match req.match_info().query::<String>("id") {
         Ok(id) => match Uuid::parse_str(&id[..]) {
@@ -905,13 +985,24 @@ pub fn download(req: HttpRequest<State>) -> Either<HttpResponse, FutureResponse<
         .db()
         .send(LoadTorrentMetaMsg { id, uid })
         .from_err()
-        .and_then(move |result: Result<(String, Vec<u8>, Vec<u8>)>| match result {
-            Ok((name, meta_file, passcode)) => {
-                let announce_url = &SETTINGS.read().unwrap().tracker.announce_url[..];
-                let comment = &SETTINGS.read().unwrap().tracker.comment[..];
-                let announce_url = format!("{}/{}", announce_url, util::to_hex(&passcode));
-                let meta_file = util::torrent::rewrite(&meta_file, &announce_url[..], comment)
-                    .map_err(|e| actix_web::error::ErrorInternalServerError(format!("{}", e)))?;
+        .and_then(move |result: Result<(String, Vec<u8>, Vec<u8>, bool)>| match result {
+            Ok((name, meta_file, passcode, allow_web_seeds)) => {
+                let settings = SETTINGS.read().unwrap();
+                let passcode = util::to_hex(&passcode);
+                let announce_url = format!("{}/{}", settings.tracker.announce_url, passcode);
+                let backup_urls: Vec<String> = settings
+                    .tracker
+                    .backup_announce_urls
+                    .iter()
+                    .map(|url| format!("{}/{}", url, passcode))
+                    .collect();
+                let meta_file = util::torrent::rewrite(
+                    &meta_file,
+                    &announce_url[..],
+                    &backup_urls,
+                    &settings.tracker.comment[..],
+                    allow_web_seeds,
+                ).map_err(|e| actix_web::error::ErrorInternalServerError(format!("{}", e)))?;
 
                 Ok(HttpResponse::build(StatusCode::OK)
                     .header(header::CONTENT_TYPE, "application/x-bittorent")
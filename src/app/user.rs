@@ -19,7 +19,15 @@
 use super::*;
 
 use actix_web::AsyncResponder;
-use handlers::user::{LoadSettingsMsg, LoadUserProfileMsg, UpdateProfileMsg, UpdateUserSettingsMsg};
+use actix_web::Form;
+use handlers::api_token::{CreateTokenMsg, LoadTokensMsg, NewToken, RevokeTokenMsg};
+use handlers::session::{LoadSessionsMsg, RevokeOtherSessionsMsg, RevokeSessionMsg};
+use handlers::user::{LoadSettingsMsg, LoadUserProfileMsg, UpdateProfileMsg, UpdateRelationMsg, UpdateUserSettingsMsg};
+use models::api_token::{self, ApiToken};
+use models::moderation::MAX_SEVERITY;
+use models::notification::{NotificationDelivery, NotificationKind, NotificationSetting};
+use models::relation::Relation;
+use models::session::UserSession;
 use models::user::{UserProfileMsg, UserSettingsMsg};
 use std::fs;
 use std::io::{self, Write};
@@ -49,6 +57,19 @@ pub fn profile(req: HttpRequest<State>) -> Either<HttpResponse, FutureResponse<H
                 ctx.insert("timezone", &user.timezone);
                 ctx.insert("may_view_passcode", &user.may_view_passcode);
                 ctx.insert("profile", &user.profile);
+                ctx.insert("friends", &user.friends);
+                ctx.insert("blocks", &user.blocks);
+                ctx.insert("is_friend", &user.is_friend);
+                ctx.insert("is_blocked", &user.is_blocked);
+                ctx.insert("warnings", &user.warnings);
+                ctx.insert("ban", &user.ban);
+                ctx.insert("may_view_warnings", &user.may_view_warnings);
+                ctx.insert("may_moderate", &user.may_moderate);
+                ctx.insert("may_view_history", &user.may_view_history);
+                ctx.insert("ip_history", &user.ip_history);
+                ctx.insert("client_history", &user.client_history);
+                ctx.insert("audit_log", &user.audit_log);
+                ctx.insert("severities", &(1..=MAX_SEVERITY).collect::<Vec<i16>>());
                 Template::render_with_user(&cloned, "user/profile.html", &mut ctx)
             }
             Err(e) => {
@@ -90,6 +111,19 @@ pub fn view(req: HttpRequest<State>) -> Either<HttpResponse, FutureResponse<Http
                 ctx.insert("timezone", &user.timezone);
                 ctx.insert("may_view_passcode", &user.may_view_passcode);
                 ctx.insert("profile", &user.profile);
+                ctx.insert("friends", &user.friends);
+                ctx.insert("blocks", &user.blocks);
+                ctx.insert("is_friend", &user.is_friend);
+                ctx.insert("is_blocked", &user.is_blocked);
+                ctx.insert("warnings", &user.warnings);
+                ctx.insert("ban", &user.ban);
+                ctx.insert("may_view_warnings", &user.may_view_warnings);
+                ctx.insert("may_moderate", &user.may_moderate);
+                ctx.insert("may_view_history", &user.may_view_history);
+                ctx.insert("ip_history", &user.ip_history);
+                ctx.insert("client_history", &user.client_history);
+                ctx.insert("audit_log", &user.audit_log);
+                ctx.insert("severities", &(1..=MAX_SEVERITY).collect::<Vec<i16>>());
                 Template::render_with_user(&cloned, "user/profile.html", &mut ctx)
             }
             Err(e) => {
@@ -101,6 +135,211 @@ pub fn view(req: HttpRequest<State>) -> Either<HttpResponse, FutureResponse<Http
     Either::B(fut.responder())
 }
 
+#[derive(Deserialize)]
+pub struct RelationForm {
+    action: String,
+    back: Option<String>,
+}
+
+/// Add a user to or remove a user from the friends or block list
+pub fn relation(req: HttpRequest<State>, data: Form<RelationForm>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.user_id() {
+        Some(user_id) => *user_id,
+        None => return async_redirect("/login"),
+    };
+    let other_id = match req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(other_id) => other_id,
+        None => return Box::new(FutErr(ErrorNotFound("no user id"))),
+    };
+
+    let RelationForm { action, back } = data.into_inner();
+    let (relation, add) = match &action[..] {
+        "friend" => (Relation::Friend, true),
+        "unfriend" => (Relation::Friend, false),
+        "block" => (Relation::Block, true),
+        "unblock" => (Relation::Block, false),
+        _ => return Box::new(FutErr(ErrorBadRequest("invalid action"))),
+    };
+    // only redirect to local urls
+    let back = match back {
+        Some(ref back) if back.starts_with('/') && !back.starts_with("//") => back.to_string(),
+        _ => format!("/user/{}", other_id),
+    };
+
+    req.state()
+        .db()
+        .send(UpdateRelationMsg(user_id, other_id, relation, add))
+        .from_err()
+        .and_then(move |result: Result<usize>| match result {
+            Ok(_) => Ok(redirect(&back)),
+            Err(e) => Err(ErrorBadRequest(e.to_string())),
+        })
+        .responder()
+}
+
+/// List the active sessions of the current user
+pub fn sessions(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.user_id() {
+        Some(user_id) => *user_id,
+        None => return async_redirect("/login"),
+    };
+    let current = session_id(&req);
+
+    req.clone().state()
+        .db()
+        .send(LoadSessionsMsg(user_id))
+        .from_err()
+        .and_then(move |result: Result<Vec<UserSession>>| match result {
+            Ok(sessions) => {
+                let mut ctx = Context::new();
+                ctx.insert("sessions", &sessions);
+                ctx.insert("current_session", &current);
+                Template::render_with_user(&req, "user/sessions.html", &mut ctx)
+            }
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
+/// Revoke a session of the current user
+///
+/// Revoking the current session logs the user out.
+pub fn revoke_session(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.user_id() {
+        Some(user_id) => *user_id,
+        None => return async_redirect("/login"),
+    };
+    let id = match req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no session id"))),
+    };
+
+    req.clone().state()
+        .db()
+        .send(RevokeSessionMsg(id, user_id))
+        .from_err()
+        .and_then(move |result: Result<usize>| match result {
+            Ok(_) => {
+                if session_id(&req) == Some(id) {
+                    req.session().clear();
+                    Ok(redirect("/login"))
+                } else {
+                    Ok(redirect("/user/sessions"))
+                }
+            }
+            Err(e) => Err(ErrorBadRequest(e.to_string())),
+        })
+        .responder()
+}
+
+/// Revoke all sessions of the current user except the current one
+pub fn revoke_other_sessions(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let (user_id, current) = match (req.user_id(), session_id(&req)) {
+        (Some(user_id), Some(current)) => (*user_id, current),
+        _ => return async_redirect("/login"),
+    };
+
+    req.state()
+        .db()
+        .send(RevokeOtherSessionsMsg(current, user_id))
+        .from_err()
+        .and_then(move |result: Result<usize>| match result {
+            Ok(_) => Ok(redirect("/user/sessions")),
+            Err(e) => Err(ErrorBadRequest(e.to_string())),
+        })
+        .responder()
+}
+
+/// Render the API tokens of the user
+///
+/// `new_token` is the JWT of a just created token, it is shown only once.
+fn render_tokens(req: HttpRequest<State>, user_id: Uuid, new_token: Option<String>, error: Option<String>) -> FutureResponse<HttpResponse> {
+    req.clone().state()
+        .db()
+        .send(LoadTokensMsg(user_id))
+        .from_err()
+        .and_then(move |result: Result<Vec<ApiToken>>| match result {
+            Ok(tokens) => {
+                let mut ctx = Context::new();
+                ctx.insert("tokens", &tokens);
+                ctx.insert("scopes", &api_token::all_scopes());
+                ctx.insert("max_days", &SETTINGS.read().unwrap().api.token_max_days);
+                ctx.insert("new_token", &new_token);
+                ctx.insert("error", &error);
+                Template::render_with_user(&req, "user/tokens.html", &mut ctx)
+            }
+            Err(e) => Err(ErrorInternalServerError(e.to_string())),
+        })
+        .responder()
+}
+
+/// List the API tokens of the current user
+pub fn tokens(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.user_id() {
+        Some(user_id) => *user_id,
+        None => return async_redirect("/login"),
+    };
+
+    render_tokens(req, user_id, None, None)
+}
+
+/// Create an API token
+///
+/// The form holds `name`, `days` and one `scope` field per granted scope.
+pub fn create_token(req: HttpRequest<State>, data: Form<Vec<(String, String)>>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.user_id() {
+        Some(user_id) => *user_id,
+        None => return async_redirect("/login"),
+    };
+
+    let mut msg = CreateTokenMsg {
+        user_id,
+        name: String::new(),
+        scopes: Vec::new(),
+        days: 0,
+    };
+    for (key, value) in data.into_inner() {
+        match &key[..] {
+            "name" => msg.name = value,
+            "days" => msg.days = value.parse().unwrap_or_default(),
+            "scope" => msg.scopes.push(value),
+            _ => {}
+        }
+    }
+
+    req.clone().state()
+        .db()
+        .send(msg)
+        .from_err()
+        .and_then(move |result: Result<NewToken>| match result {
+            Ok(token) => render_tokens(req, user_id, Some(token.token), None),
+            Err(e) => render_tokens(req, user_id, None, Some(e.to_string())),
+        })
+        .responder()
+}
+
+/// Revoke an API token of the current user
+pub fn revoke_token(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
+    let user_id = match req.user_id() {
+        Some(user_id) => *user_id,
+        None => return async_redirect("/login"),
+    };
+    let id = match req.match_info().get("id").and_then(|id| Uuid::parse_str(id).ok()) {
+        Some(id) => id,
+        None => return Box::new(FutErr(ErrorNotFound("no token id"))),
+    };
+
+    req.state()
+        .db()
+        .send(RevokeTokenMsg(id, user_id))
+        .from_err()
+        .and_then(move |result: Result<usize>| match result {
+            Ok(_) => Ok(redirect("/user/tokens")),
+            Err(e) => Err(ErrorBadRequest(e.to_string())),
+        })
+        .responder()
+}
+
 pub fn settings(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
     let user_id = match req.user_id() {
         Some(user_id) => *user_id,
@@ -117,6 +356,7 @@ pub fn settings(req: HttpRequest<State>) -> FutureResponse<HttpResponse> {
                 ctx.insert("user", &user.user);
                 ctx.insert("profile", &user.profile);
                 ctx.insert("properties", &user.properties);
+                ctx.insert("notification_kinds", &NotificationSetting::for_properties(&user.properties));
                 ctx.insert("timezones", &timezones);
                 ctx.insert("defaults", &defaults);
                 ctx.insert("categories", &user.categories);
@@ -185,6 +425,7 @@ pub fn update_settings(req: HttpRequest<State>) -> FutureResponse<HttpResponse>
                 ctx.insert("user", &user.user);
                 ctx.insert("profile", &user.profile);
                 ctx.insert("properties", &user.properties);
+                ctx.insert("notification_kinds", &NotificationSetting::for_properties(&user.properties));
                 ctx.insert("timezones", &timezones);
                 ctx.insert("defaults", &defaults);
                 ctx.insert("categories", &user.categories);
@@ -244,6 +485,21 @@ fn process_update_settings(entries: &Entries, mut user: UpdateUserSettingsMsg) -
         }
     }
 
+    for kind in NotificationKind::ALL {
+        let name = kind.property_name();
+        if let Some(fields) = entries.fields.get(&name) {
+            if let Some(field) = fields.get(0) {
+                if let SavedData::Text(ref data) = field.data {
+                    let delivery = NotificationDelivery::from(&data[..]);
+                    if !kind.allows(delivery) {
+                        bail!("invalid delivery for {}", kind.as_str());
+                    }
+                    user.push_create_property(name, delivery.as_str());
+                }
+            }
+        }
+    }
+
     if let Some(fields) = entries.fields.get(&"default_categories".to_string()) {
         let default_categories: Vec<Uuid> = fields
             .into_iter()
@@ -272,14 +528,6 @@ fn process_update_profile(entries: &Entries, user: &mut UpdateProfileMsg, prefix
         }
     }
 
-    if let Some(fields) = entries.fields.get(&format!("{}flair", prefix)) {
-        if let Some(field) = fields.get(0) {
-            if let SavedData::Text(ref data) = field.data {
-                user.set_flair(Some(data.clone()));
-            }
-        }
-    }
-
     if let Some(fields) = entries.fields.get(&format!("{}avatar", prefix)) {
         if let Some(field) = fields.get(0) {
             if let Some(ref name) = field.headers.filename {
@@ -0,0 +1,158 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Chat broadcast server
+//!
+//! The [**ChatServer**](struct.ChatServer.html) keeps track of all connected chat sessions and the
+//! chatrooms they are subscribed to. Published messages are fanned out to all subscribers of the
+//! chatroom.
+//!
+//! Permissions are not checked here, sessions have to check them before subscribing.
+
+use super::*;
+
+use std::collections::{HashMap, HashSet};
+
+use models::chat::ChatMessageWithUser;
+
+/// A chat message, which is sent to the subscribers of its chatroom
+#[derive(Clone, Debug)]
+pub struct ChatBroadcastMsg(pub ChatMessageWithUser);
+
+impl Message for ChatBroadcastMsg {
+    type Result = ();
+}
+
+/// Register a new session
+///
+/// Returns the id of the session.
+pub struct ConnectMsg {
+    pub addr: Recipient<Syn, ChatBroadcastMsg>,
+}
+
+impl Message for ConnectMsg {
+    type Result = usize;
+}
+
+/// Remove a session and all of its subscriptions
+pub struct DisconnectMsg {
+    pub id: usize,
+}
+
+impl Message for DisconnectMsg {
+    type Result = ();
+}
+
+/// Subscribe the session `id` to `chat`
+pub struct SubscribeMsg {
+    pub id: usize,
+    pub chat: i16,
+}
+
+impl Message for SubscribeMsg {
+    type Result = ();
+}
+
+/// Unsubscribe the session `id` from `chat`
+pub struct UnsubscribeMsg {
+    pub id: usize,
+    pub chat: i16,
+}
+
+impl Message for UnsubscribeMsg {
+    type Result = ();
+}
+
+/// Chat broadcast actor
+#[derive(Default)]
+pub struct ChatServer {
+    next_id: usize,
+    sessions: HashMap<usize, Recipient<Syn, ChatBroadcastMsg>>,
+    rooms: HashMap<i16, HashSet<usize>>,
+}
+
+impl ChatServer {
+    fn remove(&mut self, id: usize) {
+        self.sessions.remove(&id);
+        for subscribers in self.rooms.values_mut() {
+            subscribers.remove(&id);
+        }
+    }
+}
+
+impl Actor for ChatServer {
+    type Context = Context<Self>;
+}
+
+impl Handler<ConnectMsg> for ChatServer {
+    type Result = usize;
+
+    fn handle(&mut self, msg: ConnectMsg, _: &mut Self::Context) -> Self::Result {
+        self.next_id = self.next_id.wrapping_add(1);
+        self.sessions.insert(self.next_id, msg.addr);
+        self.next_id
+    }
+}
+
+impl Handler<DisconnectMsg> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: DisconnectMsg, _: &mut Self::Context) -> Self::Result {
+        self.remove(msg.id);
+    }
+}
+
+impl Handler<SubscribeMsg> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SubscribeMsg, _: &mut Self::Context) -> Self::Result {
+        if self.sessions.contains_key(&msg.id) {
+            self.rooms.entry(msg.chat).or_insert_with(HashSet::new).insert(msg.id);
+        }
+    }
+}
+
+impl Handler<UnsubscribeMsg> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: UnsubscribeMsg, _: &mut Self::Context) -> Self::Result {
+        if let Some(subscribers) = self.rooms.get_mut(&msg.chat) {
+            subscribers.remove(&msg.id);
+        }
+    }
+}
+
+impl Handler<ChatBroadcastMsg> for ChatServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: ChatBroadcastMsg, _: &mut Self::Context) -> Self::Result {
+        let mut gone = Vec::new();
+        if let Some(subscribers) = self.rooms.get(&msg.0.chat) {
+            for id in subscribers {
+                if let Some(addr) = self.sessions.get(id) {
+                    if addr.do_send(msg.clone()).is_err() {
+                        gone.push(*id);
+                    }
+                }
+            }
+        }
+        for id in gone {
+            self.remove(id);
+        }
+    }
+}
@@ -24,6 +24,15 @@ use std::thread;
 use chrono::Duration;
 
 use db::DbExecutor;
+use models::api_token::ApiToken;
+use models::bonus::{self, BonusTransaction, FreeleechTorrent};
+use models::chat::{ChatMessage, ChatMute};
+use models::group::Promotion;
+use models::history::{ClientHistory, IpHistory};
+use models::message::Message;
+use models::moderation::UserBan;
+use models::request::TorrentRequest;
+use models::session::UserSession;
 use schema::peers;
 
 const CLEANUP_INTERVAL: u64 = 60;
@@ -32,6 +41,10 @@ const SLEEP_PER_LOOP: u64 = 2;
 pub fn cleanup(dbe: DbExecutor, rx: &mpsc::Receiver<bool>) {
     info!("started cleanup thread");
 
+    // seeding points are awarded hourly, the last award survives restarts
+    let mut next_award = BonusTransaction::last_award(&dbe.conn()).unwrap_or_else(Utc::now) + Duration::hours(1);
+    let mut next_promotion = Utc::now();
+
     loop {
         // delete stale peers older than 60 minutes
         let date = Utc::now()
@@ -47,6 +60,95 @@ pub fn cleanup(dbe: DbExecutor, rx: &mpsc::Receiver<bool>) {
             Err(e) => warn!("error while cleaning orphaned peers: {}", e),
         }
 
+        // delete chat messages after the retention period, deleted messages are kept for an hour
+        // so polling clients learn about the deletion
+        let retention_days = SETTINGS.read().unwrap().chat.retention_days;
+        let created_before = if retention_days > 0 {
+            Some(Utc::now() - Duration::days(retention_days))
+        } else {
+            None
+        };
+        match ChatMessage::prune(created_before, Utc::now() - Duration::hours(1), db) {
+            Ok(num) => debug!("deleted {} chat messages", num),
+            Err(e) => warn!("error while cleaning chat messages: {}", e),
+        }
+        match ChatMute::prune(db) {
+            Ok(num) => debug!("deleted {} expired chat mutes", num),
+            Err(e) => warn!("error while cleaning chat mutes: {}", e),
+        }
+
+        // delete messages after the purge period of their folder
+        match Message::purge(db) {
+            Ok(num) => debug!("purged {} messages", num),
+            Err(e) => warn!("error while purging messages: {}", e),
+        }
+
+        // requests, which lost their torrent, are open again. Fills, which were not confirmed or
+        // rejected in time, are paid out
+        match TorrentRequest::reopen_orphaned(db) {
+            Ok(num) => debug!("reopened {} requests", num),
+            Err(e) => warn!("error while reopening requests: {}", e),
+        }
+        let confirm_timeout = SETTINGS.read().unwrap().request.confirm_timeout;
+        match TorrentRequest::pay_out_expired(Utc::now() - Duration::hours(confirm_timeout), db) {
+            Ok(num) => debug!("paid out {} requests", num),
+            Err(e) => warn!("error while paying out requests: {}", e),
+        }
+
+        if Utc::now() >= next_award {
+            match bonus::award_seeding(db) {
+                Ok(num) => debug!("awarded seeding points to {} users", num),
+                Err(e) => warn!("error while awarding seeding points: {}", e),
+            }
+            next_award = Utc::now() + Duration::hours(1);
+        }
+        match FreeleechTorrent::prune(db) {
+            Ok(num) => debug!("deleted {} expired freeleeches", num),
+            Err(e) => warn!("error while cleaning freeleeches: {}", e),
+        }
+
+        match UserBan::lift_expired(db) {
+            Ok(num) => debug!("lifted {} expired bans", num),
+            Err(e) => warn!("error while lifting expired bans: {}", e),
+        }
+
+        let retention_days = SETTINGS.read().unwrap().history.retention_days;
+        if retention_days > 0 {
+            let seen_before = Utc::now() - Duration::days(retention_days);
+            match IpHistory::prune(&seen_before, db) {
+                Ok(num) => debug!("deleted {} addresses from the history", num),
+                Err(e) => warn!("error while cleaning the ip history: {}", e),
+            }
+            match ClientHistory::prune(&seen_before, db) {
+                Ok(num) => debug!("deleted {} clients from the history", num),
+                Err(e) => warn!("error while cleaning the client history: {}", e),
+            }
+        }
+
+        let session_idle_days = SETTINGS.read().unwrap().session_idle_days;
+        if session_idle_days > 0 {
+            match UserSession::prune(&(Utc::now() - Duration::days(session_idle_days)), db) {
+                Ok(num) => debug!("deleted {} idle sessions", num),
+                Err(e) => warn!("error while cleaning sessions: {}", e),
+            }
+        }
+
+        match ApiToken::prune(db) {
+            Ok(num) => debug!("deleted {} expired api tokens", num),
+            Err(e) => warn!("error while cleaning api tokens: {}", e),
+        }
+
+        // move users between groups according to the promotion rules
+        let promotion_interval = SETTINGS.read().unwrap().promotion.interval;
+        if promotion_interval > 0 && Utc::now() >= next_promotion {
+            let promotion = Promotion::load(db);
+            match promotion.changes(db).and_then(|changes| promotion.apply(&changes, db)) {
+                Ok(num) => info!("moved {} users to other groups", num),
+                Err(e) => warn!("error while applying the promotion rules: {}", e),
+            }
+            next_promotion = Utc::now() + Duration::hours(promotion_interval);
+        }
+
         let mut count: u64 = CLEANUP_INTERVAL;
         while count > 0 {
             // try to receive from the main_rx in order to terminate
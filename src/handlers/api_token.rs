@@ -0,0 +1,132 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! API Token Handlers
+
+use super::*;
+
+use identity::Claims;
+use jwt::{encode, Header};
+use models::api_token::ApiToken;
+use models::User;
+
+/// Issue a new API token
+///
+/// Returns the signed JWT together with the stored token, the JWT is not stored and can't be
+/// shown again.
+pub struct CreateTokenMsg {
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub days: i64,
+}
+
+/// A newly issued token
+#[derive(Serialize)]
+pub struct NewToken {
+    pub token: String,
+    pub token_info: ApiToken,
+}
+
+impl Message for CreateTokenMsg {
+    type Result = Result<NewToken>;
+}
+
+impl Handler<CreateTokenMsg> for DbExecutor {
+    type Result = Result<NewToken>;
+
+    fn handle(&mut self, msg: CreateTokenMsg, _: &mut Self::Context) -> <Self as Handler<CreateTokenMsg>>::Result {
+        let conn = self.conn();
+        let user = User::find(&msg.user_id, &conn).ok_or("user not found")?;
+
+        let (max_days, key) = {
+            let settings = SETTINGS.read().unwrap();
+            (settings.api.token_max_days, util::from_hex(&settings.jwt_secret)?)
+        };
+        if msg.days < 1 || msg.days > max_days {
+            bail!("tokens expire after 1 to {} days", max_days);
+        }
+        let token_info = ApiToken::new(user.id, msg.name.trim().to_string(), msg.scopes, msg.days);
+        token_info.validate()?;
+
+        let claims = Claims::new(&token_info, user.group_id);
+        let token = encode(&Header::default(), &claims, &key)
+            .map_err(|e| -> Error { format!("failed to sign api token: {}", e).into() })?;
+        token_info.save(&conn)?;
+
+        Ok(NewToken { token, token_info })
+    }
+}
+
+/// Load the API tokens of a user
+///
+/// LoadTokensMsg(user_id)
+pub struct LoadTokensMsg(pub Uuid);
+
+impl Message for LoadTokensMsg {
+    type Result = Result<Vec<ApiToken>>;
+}
+
+impl Handler<LoadTokensMsg> for DbExecutor {
+    type Result = Result<Vec<ApiToken>>;
+
+    fn handle(&mut self, msg: LoadTokensMsg, _: &mut Self::Context) -> <Self as Handler<LoadTokensMsg>>::Result {
+        let conn = self.conn();
+        Ok(ApiToken::for_user(&msg.0, &conn))
+    }
+}
+
+/// Revoke an API token of a user
+///
+/// RevokeTokenMsg(token_id, user_id)
+pub struct RevokeTokenMsg(pub Uuid, pub Uuid);
+
+impl Message for RevokeTokenMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<RevokeTokenMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: RevokeTokenMsg, _: &mut Self::Context) -> <Self as Handler<RevokeTokenMsg>>::Result {
+        let conn = self.conn();
+        ApiToken::revoke(&msg.0, &msg.1, &conn)
+    }
+}
+
+/// Check an API token for a request
+///
+/// Returns the current group id of the user, or `None` if the token must be rejected.
+pub struct ValidateTokenMsg {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub scope: String,
+}
+
+impl Message for ValidateTokenMsg {
+    type Result = Result<Option<Uuid>>;
+}
+
+impl Handler<ValidateTokenMsg> for DbExecutor {
+    type Result = Result<Option<Uuid>>;
+
+    fn handle(&mut self, msg: ValidateTokenMsg, _: &mut Self::Context) -> <Self as Handler<ValidateTokenMsg>>::Result {
+        let conn = self.conn();
+        ApiToken::check(&msg.id, &msg.user_id, &msg.scope, &conn)
+    }
+}
@@ -0,0 +1,130 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Bonus point Handlers
+
+use super::*;
+
+use models::{Timestamp, Torrent};
+use models::acl::{Subject, BONUS_AUDIT_NAMESPACE};
+use models::bonus::{BonusTransaction, StoreItem, UserBonus};
+use models::user::username;
+
+/// Number of ledger entries per page
+pub const TRANSACTIONS_PER_PAGE: i64 = 50;
+
+/// Loads the balance and the ledger of a user
+///
+/// The ledger of other users needs the `read` permission on the `bonus#audit` namespace.
+pub struct LoadBonusMsg {
+    user_id: Uuid,
+    page: i64,
+    user: UserSubjectMsg,
+}
+
+impl LoadBonusMsg {
+    pub fn new(user_id: Uuid, page: i64, user: UserSubjectMsg) -> Self {
+        Self{user_id, page, user}
+    }
+}
+
+/// The balance and a page of the ledger of a user
+#[derive(Serialize)]
+pub struct BonusDetails {
+    pub user_name: String,
+    pub bonus: UserBonus,
+    pub transactions: Vec<BonusTransaction>,
+}
+
+impl Message for LoadBonusMsg {
+    type Result = Result<BonusDetails>;
+}
+
+impl Handler<LoadBonusMsg> for DbExecutor {
+    type Result = Result<BonusDetails>;
+
+    fn handle(&mut self, msg: LoadBonusMsg, _: &mut Self::Context) -> <Self as Handler<LoadBonusMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if msg.user_id != msg.user.uid && !subj.is_allowed(BONUS_AUDIT_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+
+        let user_name = username(&msg.user_id, &conn).ok_or_else(|| "user not found")?;
+        let bonus = UserBonus::find(&msg.user_id, &conn);
+        let transactions = BonusTransaction::for_user(&msg.user_id, msg.page, TRANSACTIONS_PER_PAGE, &conn);
+        Ok(BonusDetails{user_name, bonus, transactions})
+    }
+}
+
+/// Buys an item from the store, returns the new balance
+pub struct BuyMsg {
+    user_id: Uuid,
+    item: StoreItem,
+    title: Option<String>,
+}
+
+impl BuyMsg {
+    pub fn new(user_id: Uuid, item: StoreItem, title: Option<String>) -> Self {
+        Self{user_id, item, title}
+    }
+}
+
+impl Message for BuyMsg {
+    type Result = Result<i64>;
+}
+
+impl Handler<BuyMsg> for DbExecutor {
+    type Result = Result<i64>;
+
+    fn handle(&mut self, msg: BuyMsg, _: &mut Self::Context) -> <Self as Handler<BuyMsg>>::Result {
+        let conn = self.conn();
+        UserBonus::buy(&msg.user_id, msg.item, msg.title.as_ref().map(|t| &t[..]), &conn)
+    }
+}
+
+/// Uses a freeleech token for a torrent, returns the expiry of the freeleech
+pub struct UseFreeleechMsg {
+    torrent_id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl UseFreeleechMsg {
+    pub fn new(torrent_id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{torrent_id, user}
+    }
+}
+
+impl Message for UseFreeleechMsg {
+    type Result = Result<Timestamp>;
+}
+
+impl Handler<UseFreeleechMsg> for DbExecutor {
+    type Result = Result<Timestamp>;
+
+    fn handle(&mut self, msg: UseFreeleechMsg, _: &mut Self::Context) -> <Self as Handler<UseFreeleechMsg>>::Result {
+        let conn = self.conn();
+        let torrent = Torrent::find(&msg.torrent_id, &conn).ok_or_else(|| "torrent not found")?;
+        let subj = UserSubject::from(&msg.user);
+        if !subj.may_read(&torrent) {
+            bail!("not allowed");
+        }
+
+        UserBonus::use_freeleech_token(&msg.user.uid, &torrent, &conn)
+    }
+}
@@ -22,12 +22,16 @@ use super::*;
 
 use actix_web::FromRequest;
 use identity::RequestIdentity;
-use models::chat::{ChatMessageWithUser, ChatRoom, NewChatMessage};
-use models::acl::Subject;
+use models::chat::{self, ChatMention, ChatMessage, ChatMessageWithUser, ChatMute, ChatRoom, NewChatMessage, NewChatRoom};
+use std::collections::HashMap;
+use util;
+use models::acl::{self, Subject};
+use models::notification::{Notification, NotificationKind};
+use chrono::Duration;
 
 /// Loads chat messages from the database backend
 pub struct LoadChatMessagesMsg {
-    chat: ChatRoom,
+    chat: i16,
     since: Option<DateTime<Utc>>,
     limit: i64,
     user: UserSubjectMsg,
@@ -35,7 +39,7 @@ pub struct LoadChatMessagesMsg {
 
 impl LoadChatMessagesMsg {
     /// Construct a new `LoadChatMessagesMsg` instance
-    pub fn new(chat: ChatRoom, since: Option<DateTime<Utc>>, limit: i64, user: UserSubjectMsg) -> Self {
+    pub fn new(chat: i16, since: Option<DateTime<Utc>>, limit: i64, user: UserSubjectMsg) -> Self {
         Self{chat, since, limit, user}
     }
 }
@@ -50,7 +54,6 @@ impl<'req> TryFrom<&'req HttpRequest<State>> for LoadChatMessagesMsg {
         let default_chat = "1".to_string();
         let default_limit = "50".to_string();
         let chat: i16 = query.get("chat").unwrap_or_else(|| &default_chat).parse()?;
-        let chat = ChatRoom::try_from(chat)?;
         let since = match query.get("since") {
             Some(s) => {
                 let ts: i64 = s.parse()?;
@@ -77,27 +80,28 @@ impl Handler<LoadChatMessagesMsg> for DbExecutor {
 
     fn handle(&mut self, msg: LoadChatMessagesMsg, _: &mut Self::Context) -> <Self as Handler<LoadChatMessagesMsg>>::Result {
         let conn = self.conn();
+        let chat = ChatRoom::find(msg.chat, &conn).ok_or("no chatroom with this id")?;
         let subj = UserSubject::from(&msg.user);
-        if !subj.may_read(&msg.chat) {
+        if !subj.may_read(&chat) {
             bail!("not allowed");
         }
 
-        Ok(ChatMessageWithUser::messages_for_chat(msg.chat.into(), msg.since, msg.limit, &conn))
+        Ok(ChatMessageWithUser::messages_for_chat(chat.id, msg.since, msg.limit, &conn))
     }
 }
 
 /// Publishes a new chat message.
 pub struct PublishChatMessagesMsg {
-    chat: ChatRoom,
+    chat: i16,
     message: String,
     user: UserSubjectMsg,
 }
 
 impl PublishChatMessagesMsg {
     /// Construct a new `PublishChatMessagesMsg` instance
-    pub fn new<T: Into<ChatRoom>>(chat: T, message: String, user: UserSubjectMsg) -> Self {
+    pub fn new(chat: i16, message: String, user: UserSubjectMsg) -> Self {
         Self{
-            chat: chat.into(),
+            chat,
             message,
             user,
         }
@@ -113,16 +117,455 @@ impl Handler<PublishChatMessagesMsg> for DbExecutor {
 
     fn handle(&mut self, msg: PublishChatMessagesMsg, _: &mut Self::Context) -> <Self as Handler<PublishChatMessagesMsg>>::Result {
         let conn = self.conn();
+        let chat = ChatRoom::find(msg.chat, &conn).ok_or("no chatroom with this id")?;
         let subj = UserSubject::from(&msg.user);
-        if !subj.may_write(&msg.chat) {
+        if !subj.may_write(&chat) {
             bail!("not allowed");
         }
+        // moderators are neither muted nor slowed down
+        if !subj.may_delete(&chat) {
+            if let Some(mute) = ChatMute::find_active(chat.id, &msg.user.uid, &conn) {
+                bail!("you are muted until {}", mute.expires_at.format("%Y-%m-%d %H:%M:%S UTC"));
+            }
+            let last_message = ChatMessage::latest_for_user(chat.id, &msg.user.uid, &conn).map(|m| m.created_at);
+            if let Some(wait) = chat::slow_mode_wait(chat.slow_mode, last_message, Utc::now()) {
+                bail!("slow mode is active, wait {} seconds", wait);
+            }
+        }
+
+        let parsed = util::chat::parse(&msg.message);
+        // only users who may read the chatroom can be mentioned
+        let mut users = HashMap::new();
+        for name in &parsed.mentions {
+            if let Some(user) = models::User::find_by_name(name, &conn) {
+                if UserSubject::new(&user.id, &user.group_id, &msg.user.acl).may_read(&chat) {
+                    users.insert(name.to_string(), user.id);
+                }
+            }
+        }
+        let mut torrents = HashMap::new();
+        for id in &parsed.torrents {
+            if let Some(torrent) = models::Torrent::find(id, &conn) {
+                if subj.may_read(&torrent) {
+                    torrents.insert(torrent.id, torrent.name);
+                }
+            }
+        }
+        let rendered = util::chat::render(&parsed, &users, &torrents);
 
-        let chat: i16 = msg.chat.into();
-        let new_message = NewChatMessage::new(&msg.user.uid, &chat, &msg.message);
+        let new_message = NewChatMessage::new(&msg.user.uid, &chat.id, &msg.message, &rendered);
         let mut message = ChatMessageWithUser::from(new_message.save(&conn)?);
+        let mentioned: Vec<Uuid> = users.values().filter(|id| **id != msg.user.uid).cloned().collect();
+        if !mentioned.is_empty() {
+            ChatMention::create(&message.id, &mentioned, &conn)?;
+        }
         message.user_name = models::username(&message.user_id, &conn).unwrap();
         message.user_group = *subj.group_id();
         Ok(message)
     }
 }
+
+/// ACL namespace for managing chatrooms
+const CHAT_ADMIN_NAMESPACE: &str = "chat#admin";
+/// Maximum duration of a mute in seconds (one year)
+pub const MAX_MUTE_DURATION: i64 = 365 * 24 * 60 * 60;
+
+/// Loads all chatrooms the user may read
+pub struct LoadChatRoomsMsg {
+    user: UserSubjectMsg,
+}
+
+impl LoadChatRoomsMsg {
+    /// Construct a new `LoadChatRoomsMsg` instance
+    pub fn new(user: UserSubjectMsg) -> Self {
+        Self{user}
+    }
+}
+
+impl Message for LoadChatRoomsMsg {
+    type Result = Result<Vec<ChatRoom>>;
+}
+
+impl Handler<LoadChatRoomsMsg> for DbExecutor {
+    type Result = Result<Vec<ChatRoom>>;
+
+    fn handle(&mut self, msg: LoadChatRoomsMsg, _: &mut Self::Context) -> <Self as Handler<LoadChatRoomsMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+
+        Ok(ChatRoom::all(&conn).into_iter().filter(|chat| subj.may_read(chat)).collect())
+    }
+}
+
+/// Loads a single chatroom, if the user may read it
+pub struct LoadChatRoomMsg {
+    id: i16,
+    user: UserSubjectMsg,
+}
+
+impl LoadChatRoomMsg {
+    /// Construct a new `LoadChatRoomMsg` instance
+    pub fn new(id: i16, user: UserSubjectMsg) -> Self {
+        Self{id, user}
+    }
+}
+
+impl Message for LoadChatRoomMsg {
+    type Result = Result<ChatRoom>;
+}
+
+impl Handler<LoadChatRoomMsg> for DbExecutor {
+    type Result = Result<ChatRoom>;
+
+    fn handle(&mut self, msg: LoadChatRoomMsg, _: &mut Self::Context) -> <Self as Handler<LoadChatRoomMsg>>::Result {
+        let conn = self.conn();
+        let chat = ChatRoom::find(msg.id, &conn).ok_or("no chatroom with this id")?;
+        let subj = UserSubject::from(&msg.user);
+        if !subj.may_read(&chat) {
+            bail!("not allowed");
+        }
+
+        Ok(chat)
+    }
+}
+
+/// Loads all chatrooms for the chatroom administration
+pub struct LoadChatRoomAdminMsg {
+    user: UserSubjectMsg,
+}
+
+impl LoadChatRoomAdminMsg {
+    /// Construct a new `LoadChatRoomAdminMsg` instance
+    pub fn new(user: UserSubjectMsg) -> Self {
+        Self{user}
+    }
+}
+
+impl Message for LoadChatRoomAdminMsg {
+    type Result = Result<Vec<ChatRoom>>;
+}
+
+impl Handler<LoadChatRoomAdminMsg> for DbExecutor {
+    type Result = Result<Vec<ChatRoom>>;
+
+    fn handle(&mut self, msg: LoadChatRoomAdminMsg, _: &mut Self::Context) -> <Self as Handler<LoadChatRoomAdminMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(CHAT_ADMIN_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+
+        Ok(ChatRoom::all(&conn))
+    }
+}
+
+/// Creates a new chatroom
+///
+/// The user needs the `create` permission on the `chat#admin` namespace.
+/// The ACL rules of the `template` chatroom are copied to the new chatroom. Without a template
+/// only the group of the user gets access.
+pub struct CreateChatRoomMsg {
+    slug: String,
+    name: String,
+    topic: String,
+    slow_mode: i32,
+    template: Option<i16>,
+    user: UserSubjectMsg,
+}
+
+impl CreateChatRoomMsg {
+    /// Construct a new `CreateChatRoomMsg` instance
+    pub fn new(slug: String, name: String, topic: String, slow_mode: i32, template: Option<i16>, user: UserSubjectMsg) -> Self {
+        Self{slug, name, topic, slow_mode, template, user}
+    }
+}
+
+impl Message for CreateChatRoomMsg {
+    type Result = Result<ChatRoom>;
+}
+
+impl Handler<CreateChatRoomMsg> for DbExecutor {
+    type Result = Result<ChatRoom>;
+
+    fn handle(&mut self, msg: CreateChatRoomMsg, _: &mut Self::Context) -> <Self as Handler<CreateChatRoomMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(CHAT_ADMIN_NAMESPACE, &Permission::Create) {
+            bail!("not allowed");
+        }
+        if !ChatRoom::is_valid_slug(&msg.slug) {
+            bail!("invalid slug");
+        }
+        if msg.name.trim().is_empty() {
+            bail!("name must not be empty");
+        }
+        if msg.slow_mode < 0 {
+            bail!("slow mode must not be negative");
+        }
+
+        let chat = conn.transaction::<_, Error, _>(|| {
+            let chat = NewChatRoom::new(&msg.slug, msg.name.trim(), msg.topic.trim(), msg.slow_mode).save(&conn)?;
+            match msg.template {
+                Some(template) => {
+                    let template = ChatRoom::find(template, &conn).ok_or("template chatroom not found")?;
+                    acl::copy_rules(&template.acl_namespace(), &chat.acl_namespace(), &conn)?;
+                },
+                None => {
+                    acl::grant_group(&chat.acl_namespace(), subj.group_id(), Permission::Delete, &conn)?;
+                },
+            }
+            Ok(chat)
+        })?;
+        msg.user.acl().reload(&conn);
+
+        Ok(chat)
+    }
+}
+
+/// Updates a chatroom
+///
+/// The user needs the `write` permission on the `chat#admin` namespace.
+/// The slug can't be changed, it is the ACL namespace of the chatroom.
+pub struct UpdateChatRoomMsg {
+    id: i16,
+    name: String,
+    topic: String,
+    slow_mode: i32,
+    user: UserSubjectMsg,
+}
+
+impl UpdateChatRoomMsg {
+    /// Construct a new `UpdateChatRoomMsg` instance
+    pub fn new(id: i16, name: String, topic: String, slow_mode: i32, user: UserSubjectMsg) -> Self {
+        Self{id, name, topic, slow_mode, user}
+    }
+}
+
+impl Message for UpdateChatRoomMsg {
+    type Result = Result<ChatRoom>;
+}
+
+impl Handler<UpdateChatRoomMsg> for DbExecutor {
+    type Result = Result<ChatRoom>;
+
+    fn handle(&mut self, msg: UpdateChatRoomMsg, _: &mut Self::Context) -> <Self as Handler<UpdateChatRoomMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(CHAT_ADMIN_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+        if msg.name.trim().is_empty() {
+            bail!("name must not be empty");
+        }
+        if msg.slow_mode < 0 {
+            bail!("slow mode must not be negative");
+        }
+
+        let mut chat = ChatRoom::find(msg.id, &conn).ok_or("no chatroom with this id")?;
+        chat.name = msg.name.trim().to_string();
+        chat.topic = msg.topic.trim().to_string();
+        chat.slow_mode = msg.slow_mode;
+        chat.updated_at = Utc::now();
+        chat.save(&conn)?;
+
+        Ok(chat)
+    }
+}
+
+/// Deletes a chatroom, all of its messages and its ACL rules
+///
+/// The user needs the `delete` permission on the `chat#admin` namespace.
+pub struct DeleteChatRoomMsg {
+    id: i16,
+    user: UserSubjectMsg,
+}
+
+impl DeleteChatRoomMsg {
+    /// Construct a new `DeleteChatRoomMsg` instance
+    pub fn new(id: i16, user: UserSubjectMsg) -> Self {
+        Self{id, user}
+    }
+}
+
+impl Message for DeleteChatRoomMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<DeleteChatRoomMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: DeleteChatRoomMsg, _: &mut Self::Context) -> <Self as Handler<DeleteChatRoomMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(CHAT_ADMIN_NAMESPACE, &Permission::Delete) {
+            bail!("not allowed");
+        }
+
+        let chat = ChatRoom::find(msg.id, &conn).ok_or("no chatroom with this id")?;
+        conn.transaction::<_, Error, _>(|| {
+            chat.delete(&conn)?;
+            acl::delete_rules(&chat.acl_namespace(), &conn)
+        })?;
+        msg.user.acl().reload(&conn);
+
+        Ok(())
+    }
+}
+
+/// Deletes a chat message
+///
+/// The user needs the `delete` permission on the chatroom.
+pub struct DeleteChatMessageMsg {
+    id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl DeleteChatMessageMsg {
+    /// Construct a new `DeleteChatMessageMsg` instance
+    pub fn new(id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{id, user}
+    }
+}
+
+impl Message for DeleteChatMessageMsg {
+    type Result = Result<ChatMessageWithUser>;
+}
+
+impl Handler<DeleteChatMessageMsg> for DbExecutor {
+    type Result = Result<ChatMessageWithUser>;
+
+    fn handle(&mut self, msg: DeleteChatMessageMsg, _: &mut Self::Context) -> <Self as Handler<DeleteChatMessageMsg>>::Result {
+        let conn = self.conn();
+        let mut message = ChatMessage::find(&msg.id, &conn).ok_or("chat message not found")?;
+        let chat = ChatRoom::find(message.chat, &conn).ok_or("no chatroom with this id")?;
+        let subj = UserSubject::from(&msg.user);
+        if !subj.may_delete(&chat) {
+            bail!("not allowed");
+        }
+
+        message.delete(&conn)?;
+        let mut message = ChatMessageWithUser::from(message);
+        message.message.clear();
+        message.rendered.clear();
+        Ok(message)
+    }
+}
+
+/// Mutes a user in a chatroom for `duration` seconds
+///
+/// The user needs the `delete` permission on the chatroom.
+pub struct MuteChatUserMsg {
+    chat: i16,
+    user_id: Uuid,
+    duration: i64,
+    reason: String,
+    user: UserSubjectMsg,
+}
+
+impl MuteChatUserMsg {
+    /// Construct a new `MuteChatUserMsg` instance
+    pub fn new(chat: i16, user_id: Uuid, duration: i64, reason: String, user: UserSubjectMsg) -> Self {
+        Self{chat, user_id, duration, reason, user}
+    }
+}
+
+impl Message for MuteChatUserMsg {
+    type Result = Result<ChatMute>;
+}
+
+impl Handler<MuteChatUserMsg> for DbExecutor {
+    type Result = Result<ChatMute>;
+
+    fn handle(&mut self, msg: MuteChatUserMsg, _: &mut Self::Context) -> <Self as Handler<MuteChatUserMsg>>::Result {
+        let conn = self.conn();
+        let chat = ChatRoom::find(msg.chat, &conn).ok_or("no chatroom with this id")?;
+        let subj = UserSubject::from(&msg.user);
+        if !subj.may_delete(&chat) {
+            bail!("not allowed");
+        }
+        if msg.duration <= 0 || msg.duration > MAX_MUTE_DURATION {
+            bail!("duration must be between 1 and {} seconds", MAX_MUTE_DURATION);
+        }
+        models::User::find(&msg.user_id, &conn).ok_or("user not found")?;
+
+        let expires_at = Utc::now()
+            .checked_add_signed(Duration::seconds(msg.duration))
+            .ok_or("invalid duration")?;
+        let mute = ChatMute::new(chat.id, msg.user_id, msg.user.uid, msg.reason, expires_at);
+        mute.save(&conn)?;
+
+        let title = format!("You have been muted in {}", chat.name);
+        let body = format!("Until: {}\n\nReason: {}", expires_at.format("%Y-%m-%d %H:%M UTC"), mute.reason);
+        if let Err(e) = Notification::send(&[msg.user_id], NotificationKind::Staff, &title, &body, None, &conn) {
+            warn!("failed to notify about a mute: {}", e);
+        }
+
+        Ok(mute)
+    }
+}
+
+/// Removes the mute of a user in a chatroom
+///
+/// The user needs the `delete` permission on the chatroom.
+pub struct UnmuteChatUserMsg {
+    chat: i16,
+    user_id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl UnmuteChatUserMsg {
+    /// Construct a new `UnmuteChatUserMsg` instance
+    pub fn new(chat: i16, user_id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{chat, user_id, user}
+    }
+}
+
+impl Message for UnmuteChatUserMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<UnmuteChatUserMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: UnmuteChatUserMsg, _: &mut Self::Context) -> <Self as Handler<UnmuteChatUserMsg>>::Result {
+        let conn = self.conn();
+        let chat = ChatRoom::find(msg.chat, &conn).ok_or("no chatroom with this id")?;
+        let subj = UserSubject::from(&msg.user);
+        if !subj.may_delete(&chat) {
+            bail!("not allowed");
+        }
+
+        ChatMute::delete(chat.id, &msg.user_id, &conn)?;
+        Ok(())
+    }
+}
+
+/// Loads the number of unseen mentions of the user
+pub struct LoadChatMentionsMsg(pub Uuid);
+
+impl Message for LoadChatMentionsMsg {
+    type Result = Result<i64>;
+}
+
+impl Handler<LoadChatMentionsMsg> for DbExecutor {
+    type Result = Result<i64>;
+
+    fn handle(&mut self, msg: LoadChatMentionsMsg, _: &mut Self::Context) -> <Self as Handler<LoadChatMentionsMsg>>::Result {
+        Ok(ChatMention::unseen_count(&msg.0, &self.conn()))
+    }
+}
+
+/// Marks all mentions of the user as seen
+pub struct MarkChatMentionsSeenMsg(pub Uuid);
+
+impl Message for MarkChatMentionsSeenMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<MarkChatMentionsSeenMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: MarkChatMentionsSeenMsg, _: &mut Self::Context) -> <Self as Handler<MarkChatMentionsSeenMsg>>::Result {
+        ChatMention::mark_seen(&msg.0, &self.conn())
+    }
+}
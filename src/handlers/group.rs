@@ -0,0 +1,176 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Group Handlers
+
+use super::*;
+
+use models::acl::PROMOTION_NAMESPACE;
+use models::group::{self, Group, GroupChange, Promotion, PromotionRule};
+
+/// The groups with their promotion rules
+#[derive(Serialize)]
+pub struct PromotionRules {
+    pub groups: Vec<Group>,
+    pub rules: Vec<PromotionRule>,
+    /// ids of the excluded groups, which are never changed
+    pub team: Vec<Uuid>,
+    pub may_edit: bool,
+}
+
+/// Loads all groups and promotion rules
+///
+/// Requires the `read` permission on the `group#promotion` namespace.
+pub struct LoadPromotionRulesMsg(pub UserSubjectMsg);
+
+impl Message for LoadPromotionRulesMsg {
+    type Result = Result<PromotionRules>;
+}
+
+impl Handler<LoadPromotionRulesMsg> for DbExecutor {
+    type Result = Result<PromotionRules>;
+
+    fn handle(&mut self, msg: LoadPromotionRulesMsg, _: &mut Self::Context) -> <Self as Handler<LoadPromotionRulesMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.0);
+        if !subj.is_allowed(PROMOTION_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+
+        let groups = Group::all(&conn);
+        let team = group::excluded_groups(&groups, &conn).into_iter().collect();
+        Ok(PromotionRules {
+            groups,
+            rules: PromotionRule::all(&conn),
+            team,
+            may_edit: subj.is_allowed(PROMOTION_NAMESPACE, &Permission::Write),
+        })
+    }
+}
+
+/// Creates or replaces the promotion rule of a group
+///
+/// Requires the `write` permission on the `group#promotion` namespace.
+pub struct SavePromotionRuleMsg {
+    group_id: Uuid,
+    min_age_days: i32,
+    min_uploaded: i64,
+    min_ratio: f64,
+    min_uploads: i32,
+    user: UserSubjectMsg,
+}
+
+impl SavePromotionRuleMsg {
+    pub fn new(
+        group_id: Uuid,
+        min_age_days: i32,
+        min_uploaded: i64,
+        min_ratio: f64,
+        min_uploads: i32,
+        user: UserSubjectMsg,
+    ) -> Self {
+        Self{group_id, min_age_days, min_uploaded, min_ratio, min_uploads, user}
+    }
+}
+
+impl Message for SavePromotionRuleMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<SavePromotionRuleMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: SavePromotionRuleMsg, _: &mut Self::Context) -> <Self as Handler<SavePromotionRuleMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(PROMOTION_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+
+        let group = Group::find(&msg.group_id, &conn).ok_or_else(|| "group not found")?;
+        if group.parent_id.is_none() {
+            bail!("the group has no parent group, its members can not be promoted or demoted");
+        }
+        let excluded = group::excluded_groups(&Group::all(&conn), &conn);
+        if excluded.is_empty() {
+            bail!("no group is excluded from promotions, set promotion.excluded_groups or grant the team namespace first");
+        }
+        if excluded.contains(&group.id) {
+            bail!("team groups are excluded from promotions");
+        }
+        if msg.min_age_days < 0 || msg.min_uploaded < 0 || msg.min_uploads < 0 || msg.min_ratio < 0.0 || msg.min_ratio.is_nan() {
+            bail!("the requirements must not be negative");
+        }
+
+        PromotionRule::save(&group.id, msg.min_age_days, msg.min_uploaded, msg.min_ratio, msg.min_uploads, &conn)?;
+        Ok(())
+    }
+}
+
+/// Deletes the promotion rule of a group
+///
+/// Requires the `write` permission on the `group#promotion` namespace.
+pub struct DeletePromotionRuleMsg {
+    group_id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl DeletePromotionRuleMsg {
+    pub fn new(group_id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{group_id, user}
+    }
+}
+
+impl Message for DeletePromotionRuleMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<DeletePromotionRuleMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: DeletePromotionRuleMsg, _: &mut Self::Context) -> <Self as Handler<DeletePromotionRuleMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(PROMOTION_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+
+        PromotionRule::delete_for_group(&msg.group_id, &conn)?;
+        Ok(())
+    }
+}
+
+/// Evaluates the promotion rules without changing any group (dry run)
+///
+/// Requires the `read` permission on the `group#promotion` namespace.
+pub struct PromotionReportMsg(pub UserSubjectMsg);
+
+impl Message for PromotionReportMsg {
+    type Result = Result<Vec<GroupChange>>;
+}
+
+impl Handler<PromotionReportMsg> for DbExecutor {
+    type Result = Result<Vec<GroupChange>>;
+
+    fn handle(&mut self, msg: PromotionReportMsg, _: &mut Self::Context) -> <Self as Handler<PromotionReportMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.0).is_allowed(PROMOTION_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+
+        Promotion::load(&conn).changes(&conn)
+    }
+}
@@ -20,9 +20,20 @@
 
 use super::*;
 
-use models::message::{MessageFolder, NewMessage};
+use futures::stream;
+use models::group::Group;
+use models::message::{Delivery, MessageFolder, NewMessage, NewMessageFolder};
+use models::notification::{Notification, NotificationKind};
+use models::relation::Relation;
 use std::collections::HashMap;
 
+/// Maximum number of messages returned by a search
+const MAX_SEARCH_RESULTS: i64 = 100;
+/// Number of receivers per [SystemMessageMsg](struct.SystemMessageMsg.html)
+pub const SYSTEM_MESSAGE_BATCH_SIZE: usize = 500;
+/// ACL namespace for sending mass messages to groups
+pub const MASS_MESSAGE_NAMESPACE: &str = "message#mass";
+
 /// Response for all handlers which return a message
 #[derive(Serialize)]
 pub struct MessageResponse {
@@ -34,6 +45,8 @@ pub struct MessageResponse {
     pub body: String,
     pub is_read: bool,
     pub created_at: DateTime<Utc>,
+    pub thread_id: Uuid,
+    pub parent_id: Option<Uuid>,
     pub sender_name: String,
     pub receiver_name: String,
     pub folder_name: String,
@@ -61,6 +74,8 @@ impl From<models::Message> for MessageResponse {
             body,
             is_read,
             created_at,
+            thread_id,
+            parent_id,
         } = msg;
         MessageResponse {
             id,
@@ -71,6 +86,8 @@ impl From<models::Message> for MessageResponse {
             body,
             is_read,
             created_at,
+            thread_id,
+            parent_id,
             sender_name: Default::default(),
             receiver_name: Default::default(),
             folder_name: Default::default(),
@@ -182,27 +199,73 @@ impl Handler<LoadMessageMsg> for DbExecutor {
     }
 }
 
+/// Load the conversation of a message.
+///
+/// Returns all messages of the thread, which are in the folders of the user, the oldest first.
+pub struct LoadThreadMsg {
+    id: Uuid,
+    user_id: Uuid,
+}
+
+impl LoadThreadMsg {
+    /// Construct a new `LoadThreadMsg` instance.
+    pub fn new(id: Uuid, user_id: Uuid) -> Self {
+        LoadThreadMsg { id, user_id }
+    }
+}
+
+impl Message for LoadThreadMsg {
+    type Result = Result<Vec<MessageResponse>>;
+}
+
+impl Handler<LoadThreadMsg> for DbExecutor {
+    type Result = Result<Vec<MessageResponse>>;
+
+    fn handle(&mut self, msg: LoadThreadMsg, _: &mut Self::Context) -> <Self as Handler<LoadThreadMsg>>::Result {
+        let conn = self.conn();
+        let message = match models::Message::find(&msg.id, &conn) {
+            Some(message) => message,
+            None => bail!("message not found"),
+        };
+        match MessageFolder::find(&message.folder_id, &conn) {
+            Some(ref folder) if message.owner(folder) == msg.user_id => {}
+            Some(_) => bail!("not allowed"),
+            None => bail!("message folder not found"),
+        }
+
+        let folders: HashMap<Uuid, String> = MessageFolder::fetch_for_user(&msg.user_id, &conn)
+            .into_iter()
+            .map(|f| (f.id, f.name))
+            .collect();
+        let messages = models::Message::fetch_thread(&message.thread_id, &msg.user_id, &conn)
+            .into_iter()
+            .map(|m| {
+                let mut mr = MessageResponse::from(m);
+                mr.set_user_names(&conn);
+                mr.folder_name = folders.get(&mr.folder_id).cloned().unwrap_or_default();
+                mr
+            })
+            .collect();
+
+        Ok(messages)
+    }
+}
+
 /// Publish a new message to the database backend.
+///
+/// The `accept_messages` setting of the receiver is enforced. Messages from blocked users are
+/// dropped silently, messages from the team always get through.
 pub struct NewMessageMsg {
     receiver: String,
     subject: String,
     body: String,
     reply_to: Option<Uuid>,
-    user_id: Uuid,
+    user: UserSubjectMsg,
 }
 
 impl NewMessageMsg {
     /// Construct a new `NewMessageMsg` instance.
-    pub fn new(msg: api::message::NewMessage, user_id: Uuid) -> Self {
-        NewMessageMsg {
-            user_id,
-            ..NewMessageMsg::from(msg)
-        }
-    }
-}
-
-impl From<api::message::NewMessage> for NewMessageMsg {
-    fn from(msg: api::message::NewMessage) -> Self {
+    pub fn new(msg: api::message::NewMessage, user: UserSubjectMsg) -> Self {
         let api::message::NewMessage {
             receiver,
             subject,
@@ -214,7 +277,7 @@ impl From<api::message::NewMessage> for NewMessageMsg {
             subject,
             body,
             reply_to,
-            user_id: Uuid::default(),
+            user,
         }
     }
 }
@@ -228,7 +291,7 @@ impl Handler<NewMessageMsg> for DbExecutor {
 
     fn handle(&mut self, msg: NewMessageMsg, _: &mut Self::Context) -> <Self as Handler<NewMessageMsg>>::Result {
         let conn = self.conn();
-        let sender = match models::User::find(&msg.user_id, &conn) {
+        let sender = match models::User::find(&msg.user.uid, &conn) {
             Some(sender) => sender,
             None => bail!("sender not found"),
         };
@@ -240,19 +303,63 @@ impl Handler<NewMessageMsg> for DbExecutor {
             Some(folder) => folder,
             None => bail!("inbox not found"),
         };
-        let message = NewMessage::new(&rec_inbox.id, &sender.id, &receiver.id, &msg.subject, &msg.body);
-        let mut message = message.save(&conn)?;
+
+        let delivery = Delivery::decide(
+            util::user::user_accept_messages(&receiver.id, &conn),
+            UserSubject::from(&msg.user).is_team(),
+            Relation::Friend.exists(&receiver.id, &sender.id, &conn),
+            Relation::Block.exists(&receiver.id, &sender.id, &conn),
+        );
+        if delivery == Delivery::Reject {
+            bail!("{} does not accept messages from you", receiver.name);
+        }
+
+        // only a message of the sender can be replied to
+        let original_message = match msg.reply_to {
+            Some(ref reply_to) => match models::Message::find(reply_to, &conn) {
+                Some(original_message) => match MessageFolder::find(&original_message.folder_id, &conn) {
+                    Some(ref folder) if original_message.owner(folder) == sender.id => Some(original_message),
+                    _ => bail!("not allowed"),
+                },
+                None => bail!("message not found"),
+            },
+            None => None,
+        };
+
         let settings = SETTINGS.read().unwrap();
-        if settings.user.default_save_message_in_sent {
-            if let Some(snd_sent) = MessageFolder::find_by_name("sent", &sender.id, &conn) {
-                message = message.copy_to_folder(&snd_sent.id, &conn)?;
-            }
+        let snd_sent = if settings.user.default_save_message_in_sent {
+            MessageFolder::find_by_name("sent", &sender.id, &conn)
+        } else {
+            None
+        };
+        let mut new_message = NewMessage::new(&rec_inbox.id, &sender.id, &receiver.id, &msg.subject, &msg.body);
+        if let Some(ref original_message) = original_message {
+            new_message.set_parent(original_message);
         }
-        if let Some(ref reply_to) = msg.reply_to {
-            if settings.user.default_delete_message_on_reply {
-                if let Some(original_message) = models::Message::find(reply_to, &conn) {
-                    original_message.delete(&conn)?;
+        let message = if delivery == Delivery::Deliver {
+            let message = new_message.save(&conn)?;
+            let title = format!("New message from {}", sender.name);
+            let link = format!("/message/{}", message.id);
+            if let Err(e) = Notification::send(&[receiver.id], NotificationKind::Message, &title, &msg.subject, Some(&link), &conn) {
+                warn!("failed to notify {} about a new message: {}", receiver.name, e);
+            }
+            match snd_sent {
+                Some(ref snd_sent) => message.copy_to_folder(&snd_sent.id, &conn)?,
+                None => message,
+            }
+        } else {
+            // the sender must not notice, that the message was dropped
+            match snd_sent {
+                Some(ref snd_sent) => {
+                    new_message.set_folder(&snd_sent.id);
+                    new_message.save(&conn)?
                 }
+                None => models::Message::from(new_message),
+            }
+        };
+        if let Some(ref original_message) = original_message {
+            if settings.user.default_delete_message_on_reply {
+                original_message.delete(&conn)?;
             }
         }
 
@@ -297,8 +404,11 @@ impl Handler<DeleteMessagesMsg> for DbExecutor {
 
         for mid in &msg.messages {
             if let Some(message) = models::Message::find(mid, &conn) {
-                let folder = folders.entry(message.folder_id)
-                    .or_insert_with(|| MessageFolder::find(&message.folder_id, &conn).unwrap());
+                if !folders.contains_key(&message.folder_id) {
+                    let folder = MessageFolder::find(&message.folder_id, &conn).ok_or("message folder not found")?;
+                    folders.insert(message.folder_id, folder);
+                }
+                let folder = &folders[&message.folder_id];
 
                 if message.owner(folder) == msg.user_id {
                     message.delete(&conn)?;
@@ -344,8 +454,11 @@ impl Handler<MarkMessagesMsg> for DbExecutor {
 
         for mid in &msg.messages {
             if let Some(mut message) = models::Message::find(mid, &conn) {
-                let folder = folders.entry(message.folder_id)
-                    .or_insert_with(|| MessageFolder::find(&message.folder_id, &conn).unwrap());
+                if !folders.contains_key(&message.folder_id) {
+                    let folder = MessageFolder::find(&message.folder_id, &conn).ok_or("message folder not found")?;
+                    folders.insert(message.folder_id, folder);
+                }
+                let folder = &folders[&message.folder_id];
 
                 if message.owner(folder) == msg.user_id {
                     if msg.mark_as_read {
@@ -361,3 +474,376 @@ impl Handler<MarkMessagesMsg> for DbExecutor {
         Ok(marked)
     }
 }
+
+/// Load all message folders of a user.
+pub struct LoadMessageFoldersMsg(pub Uuid);
+
+impl Message for LoadMessageFoldersMsg {
+    type Result = Result<Vec<MessageFolder>>;
+}
+
+impl Handler<LoadMessageFoldersMsg> for DbExecutor {
+    type Result = Result<Vec<MessageFolder>>;
+
+    fn handle(
+        &mut self,
+        msg: LoadMessageFoldersMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<LoadMessageFoldersMsg>>::Result {
+        let conn = self.conn();
+        Ok(MessageFolder::fetch_for_user(&msg.0, &conn))
+    }
+}
+
+/// Create a custom message folder.
+pub struct CreateMessageFolderMsg {
+    name: String,
+    purge: i16,
+    user_id: Uuid,
+}
+
+impl CreateMessageFolderMsg {
+    /// Construct a new `CreateMessageFolderMsg` instance.
+    pub fn new(name: String, purge: i16, user_id: Uuid) -> Self {
+        CreateMessageFolderMsg { name, purge, user_id }
+    }
+}
+
+impl Message for CreateMessageFolderMsg {
+    type Result = Result<MessageFolder>;
+}
+
+impl Handler<CreateMessageFolderMsg> for DbExecutor {
+    type Result = Result<MessageFolder>;
+
+    fn handle(
+        &mut self,
+        msg: CreateMessageFolderMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<CreateMessageFolderMsg>>::Result {
+        let conn = self.conn();
+        if !MessageFolder::is_valid_name(&msg.name) {
+            bail!("invalid folder name");
+        }
+        if msg.purge < 0 {
+            bail!("purge must not be negative");
+        }
+        if MessageFolder::find_by_name(&msg.name, &msg.user_id, &conn).is_some() {
+            bail!("folder already exists");
+        }
+        NewMessageFolder::new(&msg.user_id, &msg.name, msg.purge).create(&conn)
+    }
+}
+
+/// Rename a custom message folder or change the purge period of a folder.
+///
+/// System folders can not be renamed.
+pub struct UpdateMessageFolderMsg {
+    id: Uuid,
+    name: String,
+    purge: i16,
+    user_id: Uuid,
+}
+
+impl UpdateMessageFolderMsg {
+    /// Construct a new `UpdateMessageFolderMsg` instance.
+    pub fn new(id: Uuid, name: String, purge: i16, user_id: Uuid) -> Self {
+        UpdateMessageFolderMsg { id, name, purge, user_id }
+    }
+}
+
+impl Message for UpdateMessageFolderMsg {
+    type Result = Result<MessageFolder>;
+}
+
+impl Handler<UpdateMessageFolderMsg> for DbExecutor {
+    type Result = Result<MessageFolder>;
+
+    fn handle(
+        &mut self,
+        msg: UpdateMessageFolderMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<UpdateMessageFolderMsg>>::Result {
+        let conn = self.conn();
+        let mut folder = match MessageFolder::find(&msg.id, &conn) {
+            Some(ref folder) if folder.user_id != msg.user_id => bail!("not allowed"),
+            Some(folder) => folder,
+            None => bail!("message folder not found"),
+        };
+        if msg.purge < 0 {
+            bail!("purge must not be negative");
+        }
+        if folder.name != msg.name {
+            if folder.is_system() {
+                bail!("system folders can not be renamed");
+            }
+            if !MessageFolder::is_valid_name(&msg.name) {
+                bail!("invalid folder name");
+            }
+            if MessageFolder::find_by_name(&msg.name, &msg.user_id, &conn).is_some() {
+                bail!("folder already exists");
+            }
+            folder.name = msg.name;
+        }
+        folder.purge = msg.purge;
+        folder.save(&conn)?;
+        Ok(folder)
+    }
+}
+
+/// Delete a custom message folder and all messages in it.
+pub struct DeleteMessageFolderMsg {
+    id: Uuid,
+    user_id: Uuid,
+}
+
+impl DeleteMessageFolderMsg {
+    /// Construct a new `DeleteMessageFolderMsg` instance.
+    pub fn new(id: Uuid, user_id: Uuid) -> Self {
+        DeleteMessageFolderMsg { id, user_id }
+    }
+}
+
+impl Message for DeleteMessageFolderMsg {
+    type Result = Result<Uuid>;
+}
+
+impl Handler<DeleteMessageFolderMsg> for DbExecutor {
+    type Result = Result<Uuid>;
+
+    fn handle(
+        &mut self,
+        msg: DeleteMessageFolderMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<DeleteMessageFolderMsg>>::Result {
+        let conn = self.conn();
+        match MessageFolder::find(&msg.id, &conn) {
+            Some(folder) => {
+                if folder.user_id != msg.user_id {
+                    bail!("not allowed");
+                }
+                folder.delete(&conn)?;
+                Ok(folder.id)
+            }
+            None => bail!("message folder not found"),
+        }
+    }
+}
+
+/// Move messages into another folder of the same user.
+pub struct MoveMessagesMsg {
+    messages: Vec<Uuid>,
+    folder_id: Uuid,
+    user_id: Uuid,
+}
+
+impl MoveMessagesMsg {
+    /// Construct a new `MoveMessagesMsg` instance.
+    pub fn new(messages: Vec<Uuid>, folder_id: Uuid, user_id: Uuid) -> Self {
+        MoveMessagesMsg {
+            messages,
+            folder_id,
+            user_id,
+        }
+    }
+}
+
+impl Message for MoveMessagesMsg {
+    type Result = Result<Vec<Uuid>>;
+}
+
+impl Handler<MoveMessagesMsg> for DbExecutor {
+    type Result = Result<Vec<Uuid>>;
+
+    fn handle(&mut self, msg: MoveMessagesMsg, _: &mut Self::Context) -> <Self as Handler<MoveMessagesMsg>>::Result {
+        let conn = self.conn();
+        match MessageFolder::find(&msg.folder_id, &conn) {
+            Some(ref folder) if folder.user_id == msg.user_id => {}
+            Some(_) => bail!("not allowed"),
+            None => bail!("message folder not found"),
+        }
+
+        let mut moved = Vec::with_capacity(msg.messages.len());
+        let mut folders = HashMap::new();
+
+        for mid in &msg.messages {
+            if let Some(mut message) = models::Message::find(mid, &conn) {
+                if !folders.contains_key(&message.folder_id) {
+                    let folder = MessageFolder::find(&message.folder_id, &conn).ok_or("message folder not found")?;
+                    folders.insert(message.folder_id, folder);
+                }
+                let folder = &folders[&message.folder_id];
+
+                if message.owner(folder) == msg.user_id {
+                    message.move_to_folder(&msg.folder_id, &conn)?;
+                    moved.push(message.id);
+                }
+            }
+        }
+
+        Ok(moved)
+    }
+}
+
+/// Search the messages of a user.
+pub struct SearchMessagesMsg {
+    query: String,
+    user_id: Uuid,
+}
+
+impl SearchMessagesMsg {
+    /// Construct a new `SearchMessagesMsg` instance.
+    pub fn new(query: String, user_id: Uuid) -> Self {
+        SearchMessagesMsg { query, user_id }
+    }
+}
+
+impl Message for SearchMessagesMsg {
+    type Result = Result<Vec<MessageResponse>>;
+}
+
+impl Handler<SearchMessagesMsg> for DbExecutor {
+    type Result = Result<Vec<MessageResponse>>;
+
+    fn handle(&mut self, msg: SearchMessagesMsg, _: &mut Self::Context) -> <Self as Handler<SearchMessagesMsg>>::Result {
+        let conn = self.conn();
+        if msg.query.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let folders: HashMap<Uuid, String> = MessageFolder::fetch_for_user(&msg.user_id, &conn)
+            .into_iter()
+            .map(|f| (f.id, f.name))
+            .collect();
+        let messages = models::Message::search(&msg.user_id, msg.query.trim(), MAX_SEARCH_RESULTS, &conn)?
+            .into_iter()
+            .map(|m| {
+                let mut mr = MessageResponse::from(m);
+                mr.set_user_names(&conn);
+                mr.folder_name = folders.get(&mr.folder_id).cloned().unwrap_or_default();
+                mr
+            })
+            .collect();
+
+        Ok(messages)
+    }
+}
+
+/// Send a system message to a batch of users.
+///
+/// Use [send_system_messages](fn.send_system_messages.html) for an arbitrary number of receivers.
+pub struct SystemMessageMsg {
+    receivers: Vec<Uuid>,
+    subject: String,
+    body: String,
+}
+
+impl SystemMessageMsg {
+    /// Construct a new `SystemMessageMsg` instance.
+    pub fn new(receivers: Vec<Uuid>, subject: String, body: String) -> Self {
+        SystemMessageMsg {
+            receivers,
+            subject,
+            body,
+        }
+    }
+}
+
+impl Message for SystemMessageMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<SystemMessageMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: SystemMessageMsg, _: &mut Self::Context) -> <Self as Handler<SystemMessageMsg>>::Result {
+        let conn = self.conn();
+        models::Message::send_system(&msg.receivers, &msg.subject, &msg.body, &conn)
+    }
+}
+
+/// Send a system message to any number of users.
+///
+/// The receivers are split into batches of
+/// [SYSTEM_MESSAGE_BATCH_SIZE](constant.SYSTEM_MESSAGE_BATCH_SIZE.html), every batch is sent as
+/// its own `SystemMessageMsg`, so the `DbExecutor` handles other requests in between.
+///
+/// Resolves to the number of delivered messages.
+pub fn send_system_messages(
+    db: Addr<Syn, DbExecutor>,
+    receivers: Vec<Uuid>,
+    subject: String,
+    body: String,
+) -> Box<Future<Item = usize, Error = Error>> {
+    let batches: Vec<Vec<Uuid>> = receivers
+        .chunks(SYSTEM_MESSAGE_BATCH_SIZE)
+        .map(|batch| batch.to_vec())
+        .collect();
+    Box::new(stream::iter_ok(batches).fold(0, move |sent, batch| {
+        db.send(SystemMessageMsg::new(batch, subject.clone(), body.clone()))
+            .map_err(|e| -> Error { format!("failed to send system messages: {}", e).into() })
+            .and_then(move |result| result.map(|num| sent + num))
+    }))
+}
+
+/// Fetch the receivers of a mass message.
+///
+/// Returns the ids of all active users in the groups, requires the `create` permission on the
+/// `message#mass` ACL namespace.
+pub struct LoadMassMessageReceiversMsg {
+    groups: Vec<Uuid>,
+    user: UserSubjectMsg,
+}
+
+impl LoadMassMessageReceiversMsg {
+    /// Construct a new `LoadMassMessageReceiversMsg` instance.
+    pub fn new(groups: Vec<Uuid>, user: UserSubjectMsg) -> Self {
+        LoadMassMessageReceiversMsg { groups, user }
+    }
+}
+
+impl Message for LoadMassMessageReceiversMsg {
+    type Result = Result<Vec<Uuid>>;
+}
+
+impl Handler<LoadMassMessageReceiversMsg> for DbExecutor {
+    type Result = Result<Vec<Uuid>>;
+
+    fn handle(
+        &mut self,
+        msg: LoadMassMessageReceiversMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<LoadMassMessageReceiversMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(MASS_MESSAGE_NAMESPACE, &Permission::Create) {
+            bail!("not allowed");
+        }
+        if msg.groups.is_empty() {
+            bail!("no groups selected");
+        }
+        Ok(Group::member_ids(&msg.groups, &conn))
+    }
+}
+
+/// Load all groups, which can receive a mass message.
+pub struct LoadMassMessageGroupsMsg(pub UserSubjectMsg);
+
+impl Message for LoadMassMessageGroupsMsg {
+    type Result = Result<Vec<Group>>;
+}
+
+impl Handler<LoadMassMessageGroupsMsg> for DbExecutor {
+    type Result = Result<Vec<Group>>;
+
+    fn handle(
+        &mut self,
+        msg: LoadMassMessageGroupsMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<LoadMassMessageGroupsMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.0).is_allowed(MASS_MESSAGE_NAMESPACE, &Permission::Create) {
+            bail!("not allowed");
+        }
+        Ok(Group::all(&conn))
+    }
+}
@@ -19,8 +19,15 @@
 use super::*;
 use std::convert::TryFrom;
 
+pub mod api_token;
+pub mod bonus;
 pub mod chat;
+pub mod group;
 pub mod message;
+pub mod moderation;
+pub mod notification;
+pub mod request;
+pub mod session;
 pub mod static_content;
 pub mod torrent;
 pub mod user;
@@ -36,6 +43,11 @@ impl UserSubjectMsg {
     pub fn new(uid: Uuid, gid: Uuid, acl: AclContainer) -> Self {
         Self { uid, gid, acl }
     }
+
+    /// The shared ACL, e.g. to reload it after the rules were changed
+    pub fn acl(&self) -> &AclContainer {
+        &self.acl
+    }
 }
 
 impl<'req> TryFrom<&'req HttpRequest<State>> for UserSubjectMsg {
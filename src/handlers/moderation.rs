@@ -0,0 +1,425 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Warning, ban, ban list and duplicate account Handlers
+
+use super::*;
+
+use std::str::FromStr;
+
+use chrono::Duration;
+use ipnetwork::IpNetwork;
+use models::acl::{BAN_LIST_NAMESPACE, USER_MODERATE_NAMESPACE};
+use models::ban_list::{EmailDomainBan, IpBan};
+use models::history::Duplicates;
+use models::moderation::{UserBan, UserWarning, MAX_SEVERITY};
+use models::notification::{Notification, NotificationKind};
+use state::BanListContainer;
+
+/// Warns a user
+///
+/// Requires the `write` permission on the `user#moderate` namespace.
+pub struct WarnUserMsg {
+    user_id: Uuid,
+    reason: String,
+    severity: i16,
+    days: i64,
+    user: UserSubjectMsg,
+}
+
+impl WarnUserMsg {
+    pub fn new(user_id: Uuid, reason: String, severity: i16, days: i64, user: UserSubjectMsg) -> Self {
+        Self{user_id, reason, severity, days, user}
+    }
+}
+
+impl Message for WarnUserMsg {
+    type Result = Result<UserWarning>;
+}
+
+impl Handler<WarnUserMsg> for DbExecutor {
+    type Result = Result<UserWarning>;
+
+    fn handle(&mut self, msg: WarnUserMsg, _: &mut Self::Context) -> <Self as Handler<WarnUserMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(USER_MODERATE_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+        if msg.severity < 1 || msg.severity > MAX_SEVERITY {
+            bail!("the severity must be between 1 and {}", MAX_SEVERITY);
+        }
+        if msg.days <= 0 {
+            bail!("the duration must be positive");
+        }
+        let reason = msg.reason.trim().to_string();
+        if reason.is_empty() {
+            bail!("the reason must not be empty");
+        }
+        models::User::find(&msg.user_id, &conn).ok_or("user not found")?;
+
+        let expires_at = Utc::now() + Duration::days(msg.days);
+        let warning = UserWarning::new(msg.user_id, msg.user.uid, reason, msg.severity, expires_at);
+        warning.save(&conn)?;
+
+        let title = String::from("You have been warned");
+        let body = format!(
+            "Severity: {}\nUntil: {}\n\nReason: {}",
+            warning.severity,
+            expires_at.format("%Y-%m-%d %H:%M UTC"),
+            warning.reason
+        );
+        let link = format!("/user/{}", msg.user_id);
+        if let Err(e) = Notification::send(&[msg.user_id], NotificationKind::Staff, &title, &body, Some(&link), &conn) {
+            warn!("failed to notify about a warning: {}", e);
+        }
+
+        Ok(warning)
+    }
+}
+
+/// Revokes a warning, returns the id of the warned user
+///
+/// Requires the `write` permission on the `user#moderate` namespace.
+pub struct RevokeWarningMsg {
+    warning_id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl RevokeWarningMsg {
+    pub fn new(warning_id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{warning_id, user}
+    }
+}
+
+impl Message for RevokeWarningMsg {
+    type Result = Result<Uuid>;
+}
+
+impl Handler<RevokeWarningMsg> for DbExecutor {
+    type Result = Result<Uuid>;
+
+    fn handle(&mut self, msg: RevokeWarningMsg, _: &mut Self::Context) -> <Self as Handler<RevokeWarningMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(USER_MODERATE_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+
+        let warning = UserWarning::find(&msg.warning_id, &conn).ok_or("warning not found")?;
+        UserWarning::revoke(&warning.id, &conn)?;
+        Ok(warning.user_id)
+    }
+}
+
+/// Bans a user, `days == 0` bans permanently
+///
+/// Requires the `write` permission on the `user#moderate` namespace.
+pub struct BanUserMsg {
+    user_id: Uuid,
+    reason: String,
+    days: i64,
+    user: UserSubjectMsg,
+}
+
+impl BanUserMsg {
+    pub fn new(user_id: Uuid, reason: String, days: i64, user: UserSubjectMsg) -> Self {
+        Self{user_id, reason, days, user}
+    }
+}
+
+impl Message for BanUserMsg {
+    type Result = Result<UserBan>;
+}
+
+impl Handler<BanUserMsg> for DbExecutor {
+    type Result = Result<UserBan>;
+
+    fn handle(&mut self, msg: BanUserMsg, _: &mut Self::Context) -> <Self as Handler<BanUserMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(USER_MODERATE_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+        if msg.user_id == msg.user.uid {
+            bail!("you can not ban yourself");
+        }
+        if msg.days < 0 {
+            bail!("the duration must not be negative");
+        }
+        let reason = msg.reason.trim().to_string();
+        if reason.is_empty() {
+            bail!("the reason must not be empty");
+        }
+        models::User::find(&msg.user_id, &conn).ok_or("user not found")?;
+
+        let expires_at = if msg.days > 0 {
+            Some(Utc::now() + Duration::days(msg.days))
+        } else {
+            None
+        };
+        let ban = UserBan::new(msg.user_id, msg.user.uid, reason, expires_at);
+        ban.save(&conn)?;
+
+        // the ban is delivered by email, if the user chose so
+        let title = String::from("You have been banned");
+        if let Err(e) = Notification::send(&[msg.user_id], NotificationKind::Staff, &title, &ban.describe(), None, &conn) {
+            warn!("failed to notify about a ban: {}", e);
+        }
+
+        Ok(ban)
+    }
+}
+
+/// Lifts the ban of a user
+///
+/// Requires the `write` permission on the `user#moderate` namespace.
+pub struct LiftBanMsg {
+    user_id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl LiftBanMsg {
+    pub fn new(user_id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{user_id, user}
+    }
+}
+
+impl Message for LiftBanMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<LiftBanMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: LiftBanMsg, _: &mut Self::Context) -> <Self as Handler<LiftBanMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(USER_MODERATE_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+
+        let num = UserBan::lift(&msg.user_id, &conn)?;
+        if num > 0 {
+            let title = String::from("Your ban has been lifted");
+            if let Err(e) = Notification::send(&[msg.user_id], NotificationKind::Staff, &title, "", None, &conn) {
+                warn!("failed to notify about a lifted ban: {}", e);
+            }
+        }
+        Ok(num)
+    }
+}
+
+/// The IP and email domain ban lists
+#[derive(Serialize)]
+pub struct BanLists {
+    pub networks: Vec<IpBan>,
+    pub domains: Vec<EmailDomainBan>,
+    pub may_edit: bool,
+}
+
+/// Loads the ban lists
+///
+/// Requires the `read` permission on the `user#ban_list` namespace.
+pub struct LoadBanListsMsg(pub UserSubjectMsg);
+
+impl Message for LoadBanListsMsg {
+    type Result = Result<BanLists>;
+}
+
+impl Handler<LoadBanListsMsg> for DbExecutor {
+    type Result = Result<BanLists>;
+
+    fn handle(&mut self, msg: LoadBanListsMsg, _: &mut Self::Context) -> <Self as Handler<LoadBanListsMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.0);
+        if !subj.is_allowed(BAN_LIST_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+
+        Ok(BanLists {
+            networks: IpBan::all(&conn),
+            domains: EmailDomainBan::all(&conn),
+            may_edit: subj.is_allowed(BAN_LIST_NAMESPACE, &Permission::Write),
+        })
+    }
+}
+
+/// Adds a network (CIDR) or a single address to the ban list
+///
+/// Requires the `write` permission on the `user#ban_list` namespace.
+pub struct AddIpBanMsg {
+    network: String,
+    reason: String,
+    user: UserSubjectMsg,
+    ban_list: BanListContainer,
+}
+
+impl AddIpBanMsg {
+    pub fn new(network: String, reason: String, user: UserSubjectMsg, ban_list: BanListContainer) -> Self {
+        Self{network, reason, user, ban_list}
+    }
+}
+
+impl Message for AddIpBanMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<AddIpBanMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: AddIpBanMsg, _: &mut Self::Context) -> <Self as Handler<AddIpBanMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(BAN_LIST_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+        let network = IpNetwork::from_str(msg.network.trim()).map_err(|_| "invalid network")?;
+
+        IpBan::new(network, msg.reason.trim().to_string(), msg.user.uid).save(&conn)?;
+        msg.ban_list.reload(&conn);
+        Ok(())
+    }
+}
+
+/// Removes a network from the ban list
+///
+/// Requires the `write` permission on the `user#ban_list` namespace.
+pub struct DeleteIpBanMsg {
+    id: Uuid,
+    user: UserSubjectMsg,
+    ban_list: BanListContainer,
+}
+
+impl DeleteIpBanMsg {
+    pub fn new(id: Uuid, user: UserSubjectMsg, ban_list: BanListContainer) -> Self {
+        Self{id, user, ban_list}
+    }
+}
+
+impl Message for DeleteIpBanMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<DeleteIpBanMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: DeleteIpBanMsg, _: &mut Self::Context) -> <Self as Handler<DeleteIpBanMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(BAN_LIST_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+
+        IpBan::delete(&msg.id, &conn)?;
+        msg.ban_list.reload(&conn);
+        Ok(())
+    }
+}
+
+/// Adds an email domain to the ban list
+///
+/// Requires the `write` permission on the `user#ban_list` namespace.
+pub struct AddEmailDomainBanMsg {
+    domain: String,
+    reason: String,
+    user: UserSubjectMsg,
+    ban_list: BanListContainer,
+}
+
+impl AddEmailDomainBanMsg {
+    pub fn new(domain: String, reason: String, user: UserSubjectMsg, ban_list: BanListContainer) -> Self {
+        Self{domain, reason, user, ban_list}
+    }
+}
+
+impl Message for AddEmailDomainBanMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<AddEmailDomainBanMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: AddEmailDomainBanMsg, _: &mut Self::Context) -> <Self as Handler<AddEmailDomainBanMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(BAN_LIST_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+        let ban = EmailDomainBan::new(&msg.domain, msg.reason.trim().to_string(), msg.user.uid);
+        if ban.domain.is_empty() || ban.domain.contains('@') || ban.domain.contains(char::is_whitespace) {
+            bail!("invalid domain");
+        }
+
+        ban.save(&conn)?;
+        msg.ban_list.reload(&conn);
+        Ok(())
+    }
+}
+
+/// Removes an email domain from the ban list
+///
+/// Requires the `write` permission on the `user#ban_list` namespace.
+pub struct DeleteEmailDomainBanMsg {
+    id: Uuid,
+    user: UserSubjectMsg,
+    ban_list: BanListContainer,
+}
+
+impl DeleteEmailDomainBanMsg {
+    pub fn new(id: Uuid, user: UserSubjectMsg, ban_list: BanListContainer) -> Self {
+        Self{id, user, ban_list}
+    }
+}
+
+impl Message for DeleteEmailDomainBanMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<DeleteEmailDomainBanMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: DeleteEmailDomainBanMsg, _: &mut Self::Context) -> <Self as Handler<DeleteEmailDomainBanMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.user).is_allowed(BAN_LIST_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+
+        EmailDomainBan::delete(&msg.id, &conn)?;
+        msg.ban_list.reload(&conn);
+        Ok(())
+    }
+}
+
+/// Loads the users sharing addresses, passkeys or clients within the last `days`
+///
+/// Requires the `read` permission on the `user#moderate` namespace.
+pub struct LoadDuplicatesMsg(pub i64, pub UserSubjectMsg);
+
+impl Message for LoadDuplicatesMsg {
+    type Result = Result<Duplicates>;
+}
+
+impl Handler<LoadDuplicatesMsg> for DbExecutor {
+    type Result = Result<Duplicates>;
+
+    fn handle(&mut self, msg: LoadDuplicatesMsg, _: &mut Self::Context) -> <Self as Handler<LoadDuplicatesMsg>>::Result {
+        let conn = self.conn();
+        if !UserSubject::from(&msg.1).is_allowed(USER_MODERATE_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+        if msg.0 < 1 {
+            bail!("the time window must be at least one day");
+        }
+
+        Duplicates::find(&(Utc::now() - Duration::days(msg.0)), &conn)
+    }
+}
@@ -0,0 +1,176 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Notification Handlers
+
+use super::*;
+
+use futures::stream;
+use models::notification::{Notification, NotificationKind};
+
+/// Number of receivers per [NotifyMsg](struct.NotifyMsg.html)
+pub const NOTIFY_BATCH_SIZE: usize = 500;
+/// Maximum number of notifications returned
+const MAX_NOTIFICATIONS: i64 = 100;
+
+/// Send a notification to a batch of users.
+///
+/// Use [send_notifications](fn.send_notifications.html) for an arbitrary number of receivers.
+pub struct NotifyMsg {
+    receivers: Vec<Uuid>,
+    kind: NotificationKind,
+    title: String,
+    body: String,
+    link: Option<String>,
+}
+
+impl NotifyMsg {
+    /// Construct a new `NotifyMsg` instance.
+    pub fn new(receivers: Vec<Uuid>, kind: NotificationKind, title: String, body: String, link: Option<String>) -> Self {
+        NotifyMsg {
+            receivers,
+            kind,
+            title,
+            body,
+            link,
+        }
+    }
+}
+
+impl Message for NotifyMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<NotifyMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: NotifyMsg, _: &mut Self::Context) -> <Self as Handler<NotifyMsg>>::Result {
+        let conn = self.conn();
+        Notification::send(
+            &msg.receivers,
+            msg.kind,
+            &msg.title,
+            &msg.body,
+            msg.link.as_ref().map(|link| &link[..]),
+            &conn,
+        )
+    }
+}
+
+/// Send a notification to any number of users.
+///
+/// The receivers are split into batches of [NOTIFY_BATCH_SIZE](constant.NOTIFY_BATCH_SIZE.html),
+/// every batch is sent as its own `NotifyMsg`, so the `DbExecutor` handles other requests in
+/// between.
+///
+/// Resolves to the number of delivered notifications.
+pub fn send_notifications(
+    db: Addr<Syn, DbExecutor>,
+    receivers: Vec<Uuid>,
+    kind: NotificationKind,
+    title: String,
+    body: String,
+    link: Option<String>,
+) -> Box<Future<Item = usize, Error = Error>> {
+    let batches: Vec<Vec<Uuid>> = receivers.chunks(NOTIFY_BATCH_SIZE).map(|batch| batch.to_vec()).collect();
+    Box::new(stream::iter_ok(batches).fold(0, move |sent, batch| {
+        db.send(NotifyMsg::new(batch, kind, title.clone(), body.clone(), link.clone()))
+            .map_err(|e| -> Error { format!("failed to send notifications: {}", e).into() })
+            .and_then(move |result| result.map(|num| sent + num))
+    }))
+}
+
+/// Load the latest notifications of a user.
+pub struct LoadNotificationsMsg {
+    user_id: Uuid,
+    only_unread: bool,
+}
+
+impl LoadNotificationsMsg {
+    /// Construct a new `LoadNotificationsMsg` instance.
+    pub fn new(user_id: Uuid, only_unread: bool) -> Self {
+        LoadNotificationsMsg { user_id, only_unread }
+    }
+}
+
+impl Message for LoadNotificationsMsg {
+    type Result = Result<Vec<Notification>>;
+}
+
+impl Handler<LoadNotificationsMsg> for DbExecutor {
+    type Result = Result<Vec<Notification>>;
+
+    fn handle(
+        &mut self,
+        msg: LoadNotificationsMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<LoadNotificationsMsg>>::Result {
+        let conn = self.conn();
+        Ok(Notification::fetch_for_user(&msg.user_id, msg.only_unread, MAX_NOTIFICATIONS, &conn))
+    }
+}
+
+/// Count the unread notifications of a user.
+pub struct CountNotificationsMsg(pub Uuid);
+
+impl Message for CountNotificationsMsg {
+    type Result = Result<i64>;
+}
+
+impl Handler<CountNotificationsMsg> for DbExecutor {
+    type Result = Result<i64>;
+
+    fn handle(
+        &mut self,
+        msg: CountNotificationsMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<CountNotificationsMsg>>::Result {
+        let conn = self.conn();
+        Ok(Notification::unread_count(&msg.0, &conn))
+    }
+}
+
+/// Mark notifications as read, all notifications of the user if the list is empty.
+pub struct MarkNotificationsMsg {
+    notifications: Vec<Uuid>,
+    user_id: Uuid,
+}
+
+impl MarkNotificationsMsg {
+    /// Construct a new `MarkNotificationsMsg` instance.
+    pub fn new(notifications: Vec<Uuid>, user_id: Uuid) -> Self {
+        MarkNotificationsMsg { notifications, user_id }
+    }
+}
+
+impl Message for MarkNotificationsMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<MarkNotificationsMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(
+        &mut self,
+        msg: MarkNotificationsMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<MarkNotificationsMsg>>::Result {
+        let conn = self.conn();
+        Notification::mark_read(&msg.notifications, &msg.user_id, &conn)
+    }
+}
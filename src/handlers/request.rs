@@ -0,0 +1,490 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Request Handlers
+
+use super::*;
+
+use models::{Category, HasUser, Torrent};
+use models::acl::{Subject, REQUEST_MODERATE_NAMESPACE, REQUEST_NAMESPACE};
+use models::notification::{Notification, NotificationKind};
+use models::request::{NewTorrentRequest, RequestBounty, RequestListItem, RequestOrder, RequestStatus, TorrentRequest};
+use models::user::username;
+
+/// Number of requests per page
+pub const REQUESTS_PER_PAGE: i64 = 50;
+/// Maximum length of a request title
+const MAX_TITLE_LENGTH: usize = 255;
+
+fn check_request(title: &str, category_id: &Uuid, db: &PgConnection) -> Result<()> {
+    if title.trim().is_empty() {
+        bail!("title must not be empty");
+    }
+    if title.trim().chars().count() > MAX_TITLE_LENGTH {
+        bail!("title is too long");
+    }
+    if Category::find(category_id, db).is_none() {
+        bail!("no category with this id");
+    }
+
+    Ok(())
+}
+
+fn check_bounty(amount: i64) -> Result<()> {
+    let min_bounty = SETTINGS.read().unwrap().request.min_bounty;
+    if amount < min_bounty {
+        bail!("the bounty must be at least {} bytes", min_bounty);
+    }
+
+    Ok(())
+}
+
+/// Searches the requests
+///
+/// The user needs the `read` permission on the `request` namespace.
+pub struct LoadRequestsMsg {
+    title: Option<String>,
+    category_id: Option<Uuid>,
+    status: RequestStatus,
+    order: RequestOrder,
+    page: i64,
+    user: UserSubjectMsg,
+}
+
+impl LoadRequestsMsg {
+    /// Construct a new `LoadRequestsMsg` instance
+    pub fn new(
+        title: Option<String>,
+        category_id: Option<Uuid>,
+        status: RequestStatus,
+        order: RequestOrder,
+        page: i64,
+        user: UserSubjectMsg,
+    ) -> Self {
+        Self{title, category_id, status, order, page, user}
+    }
+}
+
+impl Message for LoadRequestsMsg {
+    type Result = Result<Vec<RequestListItem>>;
+}
+
+impl Handler<LoadRequestsMsg> for DbExecutor {
+    type Result = Result<Vec<RequestListItem>>;
+
+    fn handle(&mut self, msg: LoadRequestsMsg, _: &mut Self::Context) -> <Self as Handler<LoadRequestsMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(REQUEST_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+
+        let title = msg.title.as_ref().map(|title| title.trim());
+        Ok(RequestListItem::search(
+            title.and_then(|title| if title.is_empty() { None } else { Some(title) }),
+            msg.category_id.as_ref(),
+            msg.status,
+            msg.order,
+            msg.page,
+            REQUESTS_PER_PAGE,
+            &conn,
+        ))
+    }
+}
+
+/// A request with everything shown on the request page
+#[derive(Serialize)]
+pub struct RequestDetails {
+    pub request: TorrentRequest,
+    pub user_name: String,
+    pub category_name: String,
+    pub filler_name: Option<String>,
+    pub torrent_name: Option<String>,
+    pub bounties: Vec<RequestBounty>,
+    /// the current user voted for the request
+    pub voted: bool,
+    /// the current user may edit and delete the request
+    pub may_edit: bool,
+    /// the current user may confirm or reject the fill
+    pub may_confirm: bool,
+}
+
+/// Loads a single request
+///
+/// The user needs the `read` permission on the `request` namespace.
+pub struct LoadRequestMsg {
+    id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl LoadRequestMsg {
+    /// Construct a new `LoadRequestMsg` instance
+    pub fn new(id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{id, user}
+    }
+}
+
+impl Message for LoadRequestMsg {
+    type Result = Result<RequestDetails>;
+}
+
+impl Handler<LoadRequestMsg> for DbExecutor {
+    type Result = Result<RequestDetails>;
+
+    fn handle(&mut self, msg: LoadRequestMsg, _: &mut Self::Context) -> <Self as Handler<LoadRequestMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(REQUEST_NAMESPACE, &Permission::Read) {
+            bail!("not allowed");
+        }
+
+        let request = TorrentRequest::find(&msg.id, &conn).ok_or("request not found")?;
+        let moderator = subj.is_allowed(REQUEST_MODERATE_NAMESPACE, &Permission::Write);
+        let user_name = request.user_name(&conn);
+        let category_name = Category::find(&request.category_id, &conn).map(|c| c.name).unwrap_or_default();
+        let filler_name = request.filled_by.and_then(|id| username(&id, &conn));
+        let torrent_name = request.torrent_id.and_then(|id| Torrent::find(&id, &conn)).map(|t| t.name);
+        let bounties = request.bounties(&conn);
+        let voted = request.voted(subj.user_id(), &conn);
+        let may_edit = subj.may_write(&request) && (request.filled_by.is_none() || moderator);
+        let may_confirm = request.paid_at.is_none() && (request.user_id == *subj.user_id() || moderator);
+
+        Ok(RequestDetails{request, user_name, category_name, filler_name, torrent_name, bounties, voted, may_edit, may_confirm})
+    }
+}
+
+/// Creates a new request with the first bounty of the requester
+///
+/// The user needs the `create` permission on the `request` namespace.
+pub struct CreateRequestMsg {
+    title: String,
+    category_id: Uuid,
+    description: String,
+    bounty: i64,
+    user: UserSubjectMsg,
+}
+
+impl CreateRequestMsg {
+    /// Construct a new `CreateRequestMsg` instance
+    pub fn new(title: String, category_id: Uuid, description: String, bounty: i64, user: UserSubjectMsg) -> Self {
+        Self{title, category_id, description, bounty, user}
+    }
+}
+
+impl Message for CreateRequestMsg {
+    type Result = Result<TorrentRequest>;
+}
+
+impl Handler<CreateRequestMsg> for DbExecutor {
+    type Result = Result<TorrentRequest>;
+
+    fn handle(&mut self, msg: CreateRequestMsg, _: &mut Self::Context) -> <Self as Handler<CreateRequestMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(REQUEST_NAMESPACE, &Permission::Create) {
+            bail!("not allowed");
+        }
+        check_request(&msg.title, &msg.category_id, &conn)?;
+        check_bounty(msg.bounty)?;
+
+        NewTorrentRequest::new(subj.user_id(), &msg.category_id, msg.title.trim(), msg.description.trim()).create(msg.bounty, &conn)
+    }
+}
+
+/// Updates title, category and description of a request
+///
+/// Only the requester may update an open request, filled requests need the `write` permission
+/// on the `request#moderate` namespace.
+pub struct UpdateRequestMsg {
+    id: Uuid,
+    title: String,
+    category_id: Uuid,
+    description: String,
+    user: UserSubjectMsg,
+}
+
+impl UpdateRequestMsg {
+    /// Construct a new `UpdateRequestMsg` instance
+    pub fn new(id: Uuid, title: String, category_id: Uuid, description: String, user: UserSubjectMsg) -> Self {
+        Self{id, title, category_id, description, user}
+    }
+}
+
+impl Message for UpdateRequestMsg {
+    type Result = Result<TorrentRequest>;
+}
+
+impl Handler<UpdateRequestMsg> for DbExecutor {
+    type Result = Result<TorrentRequest>;
+
+    fn handle(&mut self, msg: UpdateRequestMsg, _: &mut Self::Context) -> <Self as Handler<UpdateRequestMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        let mut request = TorrentRequest::find(&msg.id, &conn).ok_or("request not found")?;
+        if !subj.may_write(&request) {
+            bail!("not allowed");
+        }
+        if request.filled_by.is_some() && !subj.is_allowed(REQUEST_MODERATE_NAMESPACE, &Permission::Write) {
+            bail!("the request is already filled");
+        }
+        check_request(&msg.title, &msg.category_id, &conn)?;
+
+        request.update(msg.title.trim(), &msg.category_id, msg.description.trim(), &conn)?;
+        Ok(request)
+    }
+}
+
+/// Adds a bounty to an open request
+///
+/// The user needs the `create` permission on the `request` namespace.
+pub struct AddBountyMsg {
+    id: Uuid,
+    amount: i64,
+    user: UserSubjectMsg,
+}
+
+impl AddBountyMsg {
+    /// Construct a new `AddBountyMsg` instance
+    pub fn new(id: Uuid, amount: i64, user: UserSubjectMsg) -> Self {
+        Self{id, amount, user}
+    }
+}
+
+impl Message for AddBountyMsg {
+    type Result = Result<TorrentRequest>;
+}
+
+impl Handler<AddBountyMsg> for DbExecutor {
+    type Result = Result<TorrentRequest>;
+
+    fn handle(&mut self, msg: AddBountyMsg, _: &mut Self::Context) -> <Self as Handler<AddBountyMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(REQUEST_NAMESPACE, &Permission::Create) {
+            bail!("not allowed");
+        }
+        check_bounty(msg.amount)?;
+
+        let mut request = TorrentRequest::find(&msg.id, &conn).ok_or("request not found")?;
+        request.add_bounty(subj.user_id(), msg.amount, &conn)?;
+        Ok(request)
+    }
+}
+
+/// Votes for a request
+///
+/// The user needs the `create` permission on the `request` namespace.
+pub struct VoteRequestMsg {
+    id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl VoteRequestMsg {
+    /// Construct a new `VoteRequestMsg` instance
+    pub fn new(id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{id, user}
+    }
+}
+
+impl Message for VoteRequestMsg {
+    type Result = Result<TorrentRequest>;
+}
+
+impl Handler<VoteRequestMsg> for DbExecutor {
+    type Result = Result<TorrentRequest>;
+
+    fn handle(&mut self, msg: VoteRequestMsg, _: &mut Self::Context) -> <Self as Handler<VoteRequestMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(REQUEST_NAMESPACE, &Permission::Create) {
+            bail!("not allowed");
+        }
+
+        let mut request = TorrentRequest::find(&msg.id, &conn).ok_or("request not found")?;
+        if !request.vote(subj.user_id(), &conn)? {
+            bail!("you already voted for this request");
+        }
+        Ok(request)
+    }
+}
+
+/// Fills a request with a torrent of the user
+///
+/// The user needs the `create` permission on the `request` namespace. The requester and the
+/// users, who added a bounty, are notified.
+pub struct FillRequestMsg {
+    id: Uuid,
+    torrent_id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl FillRequestMsg {
+    /// Construct a new `FillRequestMsg` instance
+    pub fn new(id: Uuid, torrent_id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{id, torrent_id, user}
+    }
+}
+
+impl Message for FillRequestMsg {
+    type Result = Result<TorrentRequest>;
+}
+
+impl Handler<FillRequestMsg> for DbExecutor {
+    type Result = Result<TorrentRequest>;
+
+    fn handle(&mut self, msg: FillRequestMsg, _: &mut Self::Context) -> <Self as Handler<FillRequestMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        if !subj.is_allowed(REQUEST_NAMESPACE, &Permission::Create) {
+            bail!("not allowed");
+        }
+
+        let mut request = TorrentRequest::find(&msg.id, &conn).ok_or("request not found")?;
+        let torrent = Torrent::find(&msg.torrent_id, &conn).ok_or("torrent not found")?;
+        request.fill(&torrent, subj.user_id(), &conn)?;
+
+        let timeout = SETTINGS.read().unwrap().request.confirm_timeout;
+        let title = format!("Request \"{}\" filled", request.title);
+        let body = format!(
+            "The request has been filled with [{}](/torrent/{}). The requester can reject the fill within {} hours, \
+             afterwards the bounty is paid out.",
+            torrent.name, torrent.id, timeout
+        );
+        let mut receivers = request.contributor_ids(&conn);
+        receivers.retain(|id| id != subj.user_id());
+        let link = request.link();
+        if let Err(e) = Notification::send(&receivers, NotificationKind::Request, &title, &body, Some(&link), &conn) {
+            warn!("failed to notify about filled request {}: {}", request.id, e);
+        }
+
+        Ok(request)
+    }
+}
+
+/// Confirms or rejects the fill of a request
+///
+/// Only the requester or users with the `write` permission on the `request#moderate` namespace
+/// may confirm or reject a fill. A confirmed fill pays the bounty out to the filler, a rejected
+/// fill opens the request again.
+pub struct ConfirmRequestMsg {
+    id: Uuid,
+    accept: bool,
+    user: UserSubjectMsg,
+}
+
+impl ConfirmRequestMsg {
+    /// Construct a new `ConfirmRequestMsg` instance
+    pub fn new(id: Uuid, accept: bool, user: UserSubjectMsg) -> Self {
+        Self{id, accept, user}
+    }
+}
+
+impl Message for ConfirmRequestMsg {
+    type Result = Result<TorrentRequest>;
+}
+
+impl Handler<ConfirmRequestMsg> for DbExecutor {
+    type Result = Result<TorrentRequest>;
+
+    fn handle(&mut self, msg: ConfirmRequestMsg, _: &mut Self::Context) -> <Self as Handler<ConfirmRequestMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        let mut request = TorrentRequest::find(&msg.id, &conn).ok_or("request not found")?;
+        if request.user_id != *subj.user_id() && !subj.is_allowed(REQUEST_MODERATE_NAMESPACE, &Permission::Write) {
+            bail!("not allowed");
+        }
+
+        if msg.accept {
+            request.pay_out(&conn)?;
+            if let Err(e) = request.notify_paid(&conn) {
+                warn!("failed to notify filler of request {}: {}", request.id, e);
+            }
+        } else {
+            let filled_by = request.filled_by;
+            request.reset_fill(&conn)?;
+            if let Some(filled_by) = filled_by {
+                let res = Notification::send(
+                    &[filled_by],
+                    NotificationKind::Request,
+                    &format!("Fill of \"{}\" rejected", request.title),
+                    "Your fill has been rejected, the request is open again.",
+                    Some(&request.link()),
+                    &conn,
+                );
+                if let Err(e) = res {
+                    warn!("failed to notify filler of request {}: {}", request.id, e);
+                }
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+/// Deletes a request and refunds the bounties
+///
+/// The requester may delete an open request, everything else needs the `delete` permission on
+/// the `request#moderate` namespace.
+pub struct DeleteRequestMsg {
+    id: Uuid,
+    user: UserSubjectMsg,
+}
+
+impl DeleteRequestMsg {
+    /// Construct a new `DeleteRequestMsg` instance
+    pub fn new(id: Uuid, user: UserSubjectMsg) -> Self {
+        Self{id, user}
+    }
+}
+
+impl Message for DeleteRequestMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<DeleteRequestMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: DeleteRequestMsg, _: &mut Self::Context) -> <Self as Handler<DeleteRequestMsg>>::Result {
+        let conn = self.conn();
+        let subj = UserSubject::from(&msg.user);
+        let request = TorrentRequest::find(&msg.id, &conn).ok_or("request not found")?;
+        if !subj.may_delete(&request) {
+            bail!("not allowed");
+        }
+        if request.filled_by.is_some() && !subj.is_allowed(REQUEST_MODERATE_NAMESPACE, &Permission::Delete) {
+            bail!("the request is already filled");
+        }
+
+        // the contributors are gone after the deletion
+        let title = format!("Request \"{}\" deleted", request.title);
+        let body = if request.paid_at.is_none() {
+            "The request has been deleted, your bounty has been refunded."
+        } else {
+            "The request has been deleted."
+        };
+        let mut receivers = request.contributor_ids(&conn);
+        receivers.retain(|id| id != subj.user_id());
+
+        request.delete(&conn)?;
+        if let Err(e) = Notification::send(&receivers, NotificationKind::Request, &title, body, None, &conn) {
+            warn!("failed to notify about deleted request {}: {}", request.id, e);
+        }
+
+        Ok(())
+    }
+}
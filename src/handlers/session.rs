@@ -0,0 +1,101 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Session Handlers
+
+use super::*;
+
+use std::net::IpAddr;
+
+use models::session::UserSession;
+
+/// Check a session and record the request
+///
+/// Returns the user id and the current group id, or `None` if the session was revoked.
+pub struct TouchSessionMsg {
+    pub id: Uuid,
+    pub ip_address: Option<IpAddr>,
+    pub user_agent: String,
+}
+
+impl Message for TouchSessionMsg {
+    type Result = Result<Option<(Uuid, Uuid)>>;
+}
+
+impl Handler<TouchSessionMsg> for DbExecutor {
+    type Result = Result<Option<(Uuid, Uuid)>>;
+
+    fn handle(&mut self, msg: TouchSessionMsg, _: &mut Self::Context) -> <Self as Handler<TouchSessionMsg>>::Result {
+        let conn = self.conn();
+        UserSession::touch(&msg.id, msg.ip_address, &msg.user_agent, &conn)
+    }
+}
+
+/// Load the sessions of a user
+///
+/// LoadSessionsMsg(user_id)
+pub struct LoadSessionsMsg(pub Uuid);
+
+impl Message for LoadSessionsMsg {
+    type Result = Result<Vec<UserSession>>;
+}
+
+impl Handler<LoadSessionsMsg> for DbExecutor {
+    type Result = Result<Vec<UserSession>>;
+
+    fn handle(&mut self, msg: LoadSessionsMsg, _: &mut Self::Context) -> <Self as Handler<LoadSessionsMsg>>::Result {
+        let conn = self.conn();
+        Ok(UserSession::for_user(&msg.0, &conn))
+    }
+}
+
+/// Revoke a session of a user
+///
+/// RevokeSessionMsg(session_id, user_id)
+pub struct RevokeSessionMsg(pub Uuid, pub Uuid);
+
+impl Message for RevokeSessionMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<RevokeSessionMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: RevokeSessionMsg, _: &mut Self::Context) -> <Self as Handler<RevokeSessionMsg>>::Result {
+        let conn = self.conn();
+        UserSession::revoke(&msg.0, &msg.1, &conn)
+    }
+}
+
+/// Revoke all sessions of a user except the current one
+///
+/// RevokeOtherSessionsMsg(current_session_id, user_id)
+pub struct RevokeOtherSessionsMsg(pub Uuid, pub Uuid);
+
+impl Message for RevokeOtherSessionsMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<RevokeOtherSessionsMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: RevokeOtherSessionsMsg, _: &mut Self::Context) -> <Self as Handler<RevokeOtherSessionsMsg>>::Result {
+        let conn = self.conn();
+        UserSession::revoke_others(&msg.0, &msg.1, &conn)
+    }
+}
@@ -17,9 +17,14 @@
  */
 
 use super::*;
+use chrono::Duration;
 use image::{self, DynamicImage, GenericImage};
 use models::acl::Subject;
-use models::torrent::{NewTorrentComment, TorrentComment, TorrentCommentResponse};
+use models::notification::{quoted_users, Notification, NotificationKind};
+use models::HasUser;
+use models::moderation::UserWarning;
+use models::torrent::{NewTorrentComment, NewTorrentReseed, TorrentComment, TorrentCommentResponse, TorrentReseed,
+                      Transfer};
 use std::fs::{self, File};
 use std::io::BufReader;
 use std::path::Path;
@@ -508,12 +513,13 @@ pub struct LoadTorrentMetaMsg {
     pub uid: Uuid,
 }
 
+/// Returns the file name, the meta file, the users passcode and if web seeds are allowed
 impl Message for LoadTorrentMetaMsg {
-    type Result = Result<(String, Vec<u8>, Vec<u8>)>;
+    type Result = Result<(String, Vec<u8>, Vec<u8>, bool)>;
 }
 
 impl Handler<LoadTorrentMetaMsg> for DbExecutor {
-    type Result = Result<(String, Vec<u8>, Vec<u8>)>;
+    type Result = Result<(String, Vec<u8>, Vec<u8>, bool)>;
 
     fn handle(
         &mut self,
@@ -522,14 +528,20 @@ impl Handler<LoadTorrentMetaMsg> for DbExecutor {
     ) -> <Self as Handler<LoadTorrentMetaMsg>>::Result {
         let conn = self.conn();
         let torrent = models::torrent::Torrent::find(&msg.id, &conn).ok_or("torrent not found")?;
+        if UserWarning::blocks_downloads(&msg.uid, &conn) {
+            bail!("downloads are blocked by an active warning");
+        }
         let meta_file =
             models::torrent::TorrentMetaFile::find(&msg.id, &conn).ok_or("meta file not found")?;
         let passcode = models::User::find(&msg.uid, &conn)
             .ok_or("user not found")?
             .passcode;
+        let allow_web_seeds = models::category::Category::find(&torrent.category_id, &conn)
+            .map(|category| category.allow_web_seeds)
+            .unwrap_or(false);
         let name = format!("{}.torrent", torrent.name);
 
-        Ok((name, meta_file.data, passcode))
+        Ok((name, meta_file.data, passcode, allow_web_seeds))
     }
 }
 
@@ -728,12 +740,31 @@ impl DeleteTorrentMsg {
     }
 }
 
+/// A deleted torrent
+///
+/// The uploader and the snatchers should be notified, see [notification](#method.notification).
+pub struct DeletedTorrent {
+    pub name: String,
+    pub reason: String,
+    /// the uploader and all snatchers, except the user who deleted the torrent
+    pub notify: Vec<Uuid>,
+}
+
+impl DeletedTorrent {
+    /// Title and body of the notification for the uploader and the snatchers
+    pub fn notification(&self) -> (String, String) {
+        let title = format!("Torrent deleted: {}", self.name);
+        let body = format!("Reason: {}", self.reason);
+        (title, body)
+    }
+}
+
 impl Message for DeleteTorrentMsg {
-    type Result = Result<usize>;
+    type Result = Result<DeletedTorrent>;
 }
 
 impl Handler<DeleteTorrentMsg> for DbExecutor {
-    type Result = Result<usize>;
+    type Result = Result<DeletedTorrent>;
 
     fn handle(
         &mut self,
@@ -748,13 +779,100 @@ impl Handler<DeleteTorrentMsg> for DbExecutor {
             bail!("user is not allowed");
         }
 
+        // the transfers are deleted with the torrent
+        let mut notify = Transfer::snatcher_ids(&torrent.id, &conn);
+        if let Some(uploader) = torrent.user_id {
+            notify.push(uploader);
+        }
+        notify.sort();
+        notify.dedup();
+        notify.retain(|id| *id != msg.subj.uid);
+
         // delete images on disk
         let path = format!("webroot/timg/{}", msg.id);
         if fs::metadata(&path).is_ok() {
             fs::remove_dir_all(&path)?;
         }
 
-        torrent.delete(&conn)
+        torrent.delete(&conn)?;
+        Ok(DeletedTorrent {
+            name: torrent.name,
+            reason: msg.reason,
+            notify,
+        })
+    }
+}
+
+pub struct RequestReseedMsg {
+    id: Uuid,
+    subj: UserSubjectMsg,
+}
+
+impl RequestReseedMsg {
+    pub fn new(id: Uuid, subj: UserSubjectMsg) -> Self {
+        Self{id, subj}
+    }
+}
+
+/// A reseed request for a dead torrent
+pub struct ReseedRequest {
+    pub name: String,
+    /// the uploader and all snatchers, except the user who requested the reseed
+    pub notify: Vec<Uuid>,
+}
+
+impl Message for RequestReseedMsg {
+    type Result = Result<ReseedRequest>;
+}
+
+impl Handler<RequestReseedMsg> for DbExecutor {
+    type Result = Result<ReseedRequest>;
+
+    fn handle(
+        &mut self,
+        msg: RequestReseedMsg,
+        _: &mut Self::Context,
+    ) -> <Self as Handler<RequestReseedMsg>>::Result {
+        let conn = self.conn();
+
+        let torrent = models::Torrent::find(&msg.id, &conn).ok_or_else(|| "torrent not found")?;
+        let subj = UserSubject::from(&msg.subj);
+        if !subj.may_read(&torrent) {
+            bail!("user is not allowed");
+        }
+
+        let (after_days, per_day) = {
+            let settings = SETTINGS.read().unwrap();
+            (settings.torrent.reseed_after_days, settings.torrent.reseed_requests_per_day)
+        };
+        let (seeder, _) = models::TorrentList::peer_count(&torrent.id, &conn);
+        if !torrent.dead_since(seeder, &TorrentReseed::dead_threshold()) {
+            bail!("a reseed can only be requested for torrents without seeders for {} days", after_days);
+        }
+        if TorrentReseed::find_open(&torrent.id, &conn).is_some() {
+            bail!("a reseed has already been requested");
+        }
+        if TorrentReseed::count_since(&msg.subj.uid, &(Utc::now() - Duration::days(1)), &conn) >= per_day {
+            bail!("you may only request {} reseeds per day", per_day);
+        }
+
+        // the open request check above is racy, the unique index on open requests decides
+        if NewTorrentReseed::new(&torrent.id, &msg.subj.uid).create(&conn)?.is_none() {
+            bail!("a reseed has already been requested");
+        }
+
+        let mut notify = Transfer::snatcher_ids(&torrent.id, &conn);
+        if let Some(uploader) = torrent.user_id {
+            notify.push(uploader);
+        }
+        notify.sort();
+        notify.dedup();
+        notify.retain(|id| *id != msg.subj.uid);
+
+        Ok(ReseedRequest {
+            name: torrent.name,
+            notify,
+        })
     }
 }
 
@@ -839,7 +957,7 @@ impl Handler<NewCommentMsg> for DbExecutor {
 
     fn handle(&mut self, msg: NewCommentMsg, _: &mut Self::Context) -> <Self as Handler<NewCommentMsg>>::Result {
         let conn = self.conn();
-        let _torrent = models::Torrent::find(&msg.torrent_id, &conn).ok_or_else(|| "torrent not found")?;
+        let torrent = models::Torrent::find(&msg.torrent_id, &conn).ok_or_else(|| "torrent not found")?;
         let subj = UserSubject::from(&msg.subj);
         if !subj.is_allowed("torrent#new_comment", &Permission::Create) {
             bail!("not allowed");
@@ -847,6 +965,28 @@ impl Handler<NewCommentMsg> for DbExecutor {
 
         let comment = NewTorrentComment::new(subj.user_id(), &msg.torrent_id, &msg.content);
         let comment = comment.create(&conn)?;
+
+        let author = models::username(subj.user_id(), &conn).unwrap_or_default();
+        let link = format!("/torrent/{}", torrent.id);
+        if let Some(uploader) = torrent.user_id {
+            if uploader != *subj.user_id() {
+                let title = format!("{} commented on {}", author, torrent.name);
+                if let Err(e) = Notification::send(&[uploader], NotificationKind::Comment, &title, "", Some(&link), &conn) {
+                    warn!("failed to notify the uploader about a comment: {}", e);
+                }
+            }
+        }
+        let quoted: Vec<Uuid> = quoted_users(&msg.content)
+            .iter()
+            .filter_map(|name| models::User::find_by_name(name, &conn))
+            .map(|user| user.id)
+            .filter(|id| id != subj.user_id())
+            .collect();
+        let title = format!("{} quoted you in a comment on {}", author, torrent.name);
+        if let Err(e) = Notification::send(&quoted, NotificationKind::Quote, &title, "", Some(&link), &conn) {
+            warn!("failed to notify quoted users: {}", e);
+        }
+
         Ok(TorrentCommentResponse::new(comment, &conn, &subj))
     }
 }
@@ -874,7 +1014,16 @@ impl Handler<DeleteCommentMsg> for DbExecutor {
         let comment = TorrentComment::find(&msg.id, &conn).ok_or_else(|| "comment not found")?;
         let subj = UserSubject::from(&msg.subj);
         if subj.may_delete(&comment) {
-            comment.delete(&conn)
+            let res = comment.delete(&conn)?;
+            if comment.user_id() != subj.user_id() {
+                let name = models::Torrent::find(comment.torrent_id(), &conn).map(|t| t.name).unwrap_or_default();
+                let title = format!("Your comment on {} was deleted by the staff", name);
+                let link = format!("/torrent/{}", comment.torrent_id());
+                if let Err(e) = Notification::send(&[*comment.user_id()], NotificationKind::Staff, &title, comment.content(), Some(&link), &conn) {
+                    warn!("failed to notify about a deleted comment: {}", e);
+                }
+            }
+            Ok(res)
         } else {
             bail!("not allowed")
         }
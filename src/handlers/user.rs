@@ -24,7 +24,14 @@ use fast_chemail;
 use image::DynamicImage;
 use image::GenericImage;
 use models::{
+    acl::USER_MODERATE_NAMESPACE,
+    audit::{self, AuditEntry},
+    history::{self, ClientHistory, IpHistory},
+    moderation::{self, UserBan, UserWarning, UserWarningResponse},
+    notification::{Notification, NotificationKind},
     user::{CompletedTorrent, Property, UserConnection, UserProfileMsg, UserSettingsMsg, UserTransfer, UserUpload},
+    relation::Relation,
+    session::UserSession,
     Category, Group, User,
 };
 use regex::Regex;
@@ -35,6 +42,7 @@ use std::fs::File;
 use std::io::BufReader;
 use std::net::IpAddr;
 use std::path::Path;
+use state::BanListContainer;
 use tempfile::TempPath;
 
 /// Load User Message
@@ -52,6 +60,7 @@ impl Handler<RequireUserMsg> for DbExecutor {
     fn handle(&mut self, msg: RequireUserMsg, _ctx: &mut Self::Context) -> <Self as Handler<RequireUserMsg>>::Result {
         match models::User::find(&msg.0, &self.conn()) {
             Some(mut user) => {
+                moderation::check_status(&user, &self.conn())?;
                 if msg.1 {
                     user.update_last_active(&self.conn())?;
                 }
@@ -66,32 +75,88 @@ impl Handler<RequireUserMsg> for DbExecutor {
 pub struct LoginForm {
     pub username: String,
     pub password: String,
+    /// the address of the client
+    #[serde(skip)]
+    pub ip_address: Option<IpAddr>,
+    /// the user agent of the client, stored with the new session
+    #[serde(skip)]
+    pub user_agent: String,
+    #[serde(skip)]
+    pub ban_list: BanListContainer,
 }
 
 impl Message for LoginForm {
-    type Result = Result<User>;
+    type Result = Result<(User, UserSession)>;
 }
 
 impl Handler<LoginForm> for DbExecutor {
-    type Result = Result<User>;
+    type Result = Result<(User, UserSession)>;
 
     fn handle(&mut self, msg: LoginForm, _: &mut Self::Context) -> <Self as Handler<LoginForm>>::Result {
         let conn = self.conn();
+        if let Some(ref ip_address) = msg.ip_address {
+            msg.ban_list.check_ip(ip_address)?;
+        }
 
         let user = match User::find_by_name(&msg.username, &conn) {
             Some(user) => user,
             None => bail!("User not found"),
         };
 
-        if user.status != models::user::STATUS_ACTIVE {
-            bail!("User not active");
+        if !user.verify_password(&msg.password) {
+            bail!("Wrong password")
         }
 
-        if user.verify_password(&msg.password) {
-            Ok(user)
-        } else {
-            bail!("Wrong password")
+        // banned users learn the reason and the expiry of the ban, after the password is verified
+        moderation::check_status(&user, &conn)?;
+
+        if let Some(ref ip_address) = msg.ip_address {
+            if let Err(e) = IpHistory::record(&user.id, ip_address, history::SOURCE_LOGIN, &conn) {
+                warn!("{}", e);
+            }
         }
+
+        let session = UserSession::new(user.id, msg.ip_address, &msg.user_agent);
+        session.save(&conn)?;
+        Ok((user, session))
+    }
+}
+
+/// Record the lockout of an account after too many failed logins
+///
+/// Writes the audit log and notifies the owner. Unknown user names are ignored.
+pub struct LoginLockoutMsg {
+    pub username: String,
+    pub ip_address: Option<IpAddr>,
+    pub minutes: u64,
+}
+
+impl Message for LoginLockoutMsg {
+    type Result = Result<()>;
+}
+
+impl Handler<LoginLockoutMsg> for DbExecutor {
+    type Result = Result<()>;
+
+    fn handle(&mut self, msg: LoginLockoutMsg, _: &mut Self::Context) -> <Self as Handler<LoginLockoutMsg>>::Result {
+        let conn = self.conn();
+        let user = match User::find_by_name(&msg.username, &conn) {
+            Some(user) => user,
+            None => return Ok(()),
+        };
+
+        let ip = msg.ip_address.map(|ip| ip.to_string()).unwrap_or_else(|| String::from("an unknown address"));
+        let details = format!("locked for {} minutes after failed logins from {}", msg.minutes, ip);
+        AuditEntry::new(user.id, audit::ACTION_LOGIN_LOCKOUT, msg.ip_address, details).save(&conn)?;
+
+        let title = String::from("Your account has been locked");
+        let body = format!(
+            "There were too many failed logins to your account, the last one from {}. Logins are blocked \
+             for {} minutes. If this was not you, choose a strong password.",
+            ip, msg.minutes
+        );
+        Notification::send(&[user.id], NotificationKind::Security, &title, &body, Some("/user/settings"), &conn)?;
+        Ok(())
     }
 }
 
@@ -102,6 +167,11 @@ pub struct SignupForm {
     pub password: String,
     pub password_confirmation: String,
     pub terms: String,
+    /// the address of the client
+    #[serde(skip)]
+    pub ip_address: Option<IpAddr>,
+    #[serde(skip)]
+    pub ban_list: BanListContainer,
 }
 
 impl SignupForm {
@@ -140,12 +210,16 @@ impl SignupForm {
     }
 
     pub fn is_valid(&self, conn: &DbConn) -> Result<bool> {
+        if let Some(ref ip_address) = self.ip_address {
+            self.ban_list.check_ip(ip_address)?;
+        }
         if !self.username_valid() {
             bail!("username is invalid");
         }
         if !self.email_valid() {
             bail!("email address is invalid")
         }
+        self.ban_list.check_email(&self.email)?;
         if !self.passwords_match() {
             bail!("passwords do not match");
         }
@@ -206,14 +280,15 @@ impl Handler<SignupForm> for DbExecutor {
 pub struct ConfirmMsg {
     pub id: String,
     pub ip_address: IpAddr,
+    pub user_agent: String,
 }
 
 impl Message for ConfirmMsg {
-    type Result = Result<User>;
+    type Result = Result<(User, UserSession)>;
 }
 
 impl Handler<ConfirmMsg> for DbExecutor {
-    type Result = Result<User>;
+    type Result = Result<(User, UserSession)>;
 
     fn handle(&mut self, msg: ConfirmMsg, _: &mut Self::Context) -> <Self as Handler<ConfirmMsg>>::Result {
         let conn = self.conn();
@@ -222,14 +297,16 @@ impl Handler<ConfirmMsg> for DbExecutor {
             if let Some(mut user) = models::User::find(property.user_id(), &conn) {
                 user.status = models::user::STATUS_ACTIVE;
                 user.ip_address = Some(msg.ip_address.into());
-
-                match user.save(&conn) {
-                    Ok(_) => match property.delete(&conn) {
-                        Ok(_) => Ok(user),
-                        Err(e) => Err(e),
-                    },
-                    Err(e) => Err(e),
+                if let Err(e) = IpHistory::record(&user.id, &msg.ip_address, history::SOURCE_LOGIN, &conn) {
+                    warn!("{}", e);
                 }
+
+                user.save(&conn)?;
+                property.delete(&conn)?;
+
+                let session = UserSession::new(user.id, Some(msg.ip_address), &msg.user_agent);
+                session.save(&conn)?;
+                Ok((user, session))
             } else {
                 bail!("User not found")
             }
@@ -323,6 +400,9 @@ impl Handler<LoadUserProfileMsg> for DbExecutor {
                 let completed = CompletedTorrent::fetch_for_user(&user.id, &db);
                 let connections: Vec<UserConnection>;
                 let may_view_passcode: bool;
+                let may_view_warnings: bool;
+                let may_view_history: bool;
+                let may_moderate: bool;
 
                 {
                     // get the current user
@@ -353,10 +433,51 @@ impl Handler<LoadUserProfileMsg> for DbExecutor {
                             "user#passcode",
                             &Permission::Read,
                         );
+
+                    may_view_history = acl.is_allowed(
+                        &current_user.id,
+                        &current_user.group_id,
+                        USER_MODERATE_NAMESPACE,
+                        &Permission::Read,
+                    );
+                    may_view_warnings = user.id == msg.1 || may_view_history;
+                    may_moderate = acl.is_allowed(
+                        &current_user.id,
+                        &current_user.group_id,
+                        USER_MODERATE_NAMESPACE,
+                        &Permission::Write,
+                    );
                 }
                 let uploads = UserUpload::fetch_for_user(&user.id, &db);
                 let timezone = util::user::user_timezone(&msg.1, &db);
                 let profile = user.profile(&db);
+                // the lists are only shown on the own profile
+                let (friends, blocks) = if user.id == msg.1 {
+                    (Relation::Friend.list(&user.id, &db), Relation::Block.list(&user.id, &db))
+                } else {
+                    (Vec::new(), Vec::new())
+                };
+                let is_friend = Relation::Friend.exists(&msg.1, &user.id, &db);
+                let is_blocked = Relation::Block.exists(&msg.1, &user.id, &db);
+                let (warnings, ban) = if may_view_warnings {
+                    let warnings = UserWarning::for_user(&user.id, &db)
+                        .into_iter()
+                        .map(|w| UserWarningResponse::new(w, &db))
+                        .collect();
+                    (warnings, UserBan::find_active(&user.id, &db))
+                } else {
+                    (Vec::new(), None)
+                };
+                // the history is only shown to the moderators
+                let (ip_history, client_history, audit_log) = if may_view_history {
+                    (
+                        IpHistory::for_user(&user.id, &db),
+                        ClientHistory::for_user(&user.id, &db),
+                        AuditEntry::for_user(&user.id, &db),
+                    )
+                } else {
+                    (Vec::new(), Vec::new(), Vec::new())
+                };
 
                 Ok(UserProfileMsg {
                     user,
@@ -368,6 +489,18 @@ impl Handler<LoadUserProfileMsg> for DbExecutor {
                     timezone,
                     may_view_passcode,
                     profile,
+                    friends,
+                    blocks,
+                    is_friend,
+                    is_blocked,
+                    warnings,
+                    ban,
+                    may_view_warnings,
+                    may_moderate,
+                    may_view_history,
+                    ip_history,
+                    client_history,
+                    audit_log,
                 })
             }
             None => bail!("user not found"),
@@ -375,6 +508,33 @@ impl Handler<LoadUserProfileMsg> for DbExecutor {
     }
 }
 
+/// Add a user to or remove a user from the friends or block list
+///
+/// UpdateRelationMsg(user_id, other_id, relation, add)
+pub struct UpdateRelationMsg(pub Uuid, pub Uuid, pub Relation, pub bool);
+
+impl Message for UpdateRelationMsg {
+    type Result = Result<usize>;
+}
+
+impl Handler<UpdateRelationMsg> for DbExecutor {
+    type Result = Result<usize>;
+
+    fn handle(&mut self, msg: UpdateRelationMsg, _: &mut Self::Context) -> <Self as Handler<UpdateRelationMsg>>::Result {
+        let conn = self.conn();
+        let UpdateRelationMsg(user_id, other_id, relation, add) = msg;
+        if User::find(&other_id, &conn).is_none() {
+            bail!("user not found");
+        }
+
+        if add {
+            relation.add(&user_id, &other_id, &conn)
+        } else {
+            relation.remove(&user_id, &other_id, &conn)
+        }
+    }
+}
+
 #[derive(Serialize, Debug)]
 pub struct ActiveUsers {
     pub group_order: Vec<Uuid>,
@@ -475,7 +635,6 @@ impl Handler<LoadSettingsMsg> for DbExecutor {
 pub struct UpdateProfileMsg {
     id: Uuid,
     avatar: Option<(String, TempPath)>,
-    flair: Option<String>,
     about: Option<String>,
 }
 
@@ -507,16 +666,6 @@ impl UpdateProfileMsg {
         old
     }
 
-    /// Set the new flair
-    ///
-    /// # Returns
-    /// the previous flair
-    pub fn set_flair(&mut self, flair: Option<String>) -> Option<String> {
-        let old = self.flair.take();
-        self.flair = flair;
-        old
-    }
-
     /// Set the new about text
     ///
     /// # Returns
@@ -600,7 +749,6 @@ impl Handler<UpdateUserSettingsMsg> for DbExecutor {
         }
 
         profile.about = msg.profile_mut().about.take();
-        profile.flair = msg.profile_mut().flair.take();
         if let Some((name, path)) = msg.profile_mut().avatar.take() {
             {
                 let avatar = Avatar::new(&msg.profile().id, &name, &path);
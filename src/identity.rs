@@ -58,7 +58,10 @@ use super::*;
 use actix_web::middleware::{Middleware, Response, Started};
 use actix_web::error::{Error as AwError, Result as AwResult};
 use actix_web::HttpMessage;
+use handlers::api_token::ValidateTokenMsg;
+use handlers::session::TouchSessionMsg;
 use jwt::{decode, Validation};
+use models::api_token::{self, ApiToken};
 use std::rc::Rc;
 
 /// Identity policy definition.
@@ -240,17 +243,26 @@ impl ApiIdentityPolicy {
     }
 }
 
-impl<S> IdentityPolicy<S> for ApiIdentityPolicy {
+impl IdentityPolicy<State> for ApiIdentityPolicy {
     type Identity = ApiIdentity;
-    type Future = FutureResult<ApiIdentity, actix_web::Error>;
+    type Future = Box<Future<Item = ApiIdentity, Error = AwError>>;
 
-    fn from_request(&self, request: &mut HttpRequest<S>) -> Self::Future {
-        let identity = self.0.load(request);
-        if identity.is_some() {
-            FutOk(ApiIdentity::new(identity))
+    fn from_request(&self, request: &mut HttpRequest<State>) -> Self::Future {
+        let identity: Box<Future<Item = Option<(Uuid, Uuid)>, Error = AwError>> = if session_id(request).is_some() {
+            load_session(request)
         } else {
-            FutErr(actix_web::error::ErrorUnauthorized("unauthorized"))
-        }
+            match self.0.load(request) {
+                Some(claims) => load_token(request, claims),
+                None => Box::new(FutOk(None)),
+            }
+        };
+        Box::new(identity.and_then(|identity| {
+            if identity.is_some() {
+                Ok(ApiIdentity::new(identity))
+            } else {
+                Err(actix_web::error::ErrorUnauthorized("unauthorized"))
+            }
+        }))
     }
 }
 
@@ -301,21 +313,16 @@ impl ApiIdentityInner {
         ApiIdentityInner { key: key.to_vec() }
     }
 
-    fn load<S>(&self, req: &mut HttpRequest<S>) -> Option<(Uuid, Uuid)> {
-        let from_session = session_creds(req);
-        if from_session.is_some() {
-            return from_session;
-        }
+    fn load<S>(&self, req: &mut HttpRequest<S>) -> Option<Claims> {
         if let Some(header) = req.headers().get("authorization") {
             if let Ok(header) = header.to_str() {
-                if header.to_lowercase().starts_with("bearer") {
+                let is_bearer = header.get(..7).map_or(false, |scheme| scheme.eq_ignore_ascii_case("bearer "));
+                if let (true, Some(token)) = (is_bearer, header.get(7..)) {
                     let validation = Validation::default();
-                    let token_data = match decode::<Claims>(&header[7..], &self.key, &validation) {
-                        Ok(c) => c,
-                        Err(_) => return None,
+                    return match decode::<Claims>(token.trim(), &self.key, &validation) {
+                        Ok(token_data) => Some(token_data.claims),
+                        Err(_) => None,
                     };
-                    let Claims {user_id, group_id, .. } = token_data.claims;
-                    return Some((user_id, group_id));
                 }
             }
         }
@@ -324,11 +331,31 @@ impl ApiIdentityInner {
 }
 
 /// The claims for the JWT
+///
+/// `jti` is the id of the [ApiToken](../models/api_token/struct.ApiToken.html), which is checked
+/// on every request.
 #[derive(Serialize, Deserialize)]
 pub struct Claims {
     iat: i64,
+    exp: i64,
+    jti: Uuid,
     user_id: Uuid,
     group_id: Uuid,
+    scopes: Vec<String>,
+}
+
+impl Claims {
+    /// Construct the claims for an API token
+    pub fn new(token: &ApiToken, group_id: Uuid) -> Claims {
+        Claims {
+            iat: token.created_at.timestamp(),
+            exp: token.expires_at.timestamp(),
+            jti: token.id,
+            user_id: token.user_id,
+            group_id,
+            scopes: token.scopes.clone(),
+        }
+    }
 }
 
 /// The APP identity policy
@@ -349,13 +376,12 @@ impl Default for identity::AppIdentityPolicy {
     }
 }
 
-impl<S> IdentityPolicy<S> for AppIdentityPolicy {
+impl IdentityPolicy<State> for AppIdentityPolicy {
     type Identity = AppIdentity;
-    type Future = FutureResult<AppIdentity, actix_web::Error>;
+    type Future = Box<Future<Item = AppIdentity, Error = AwError>>;
 
-    fn from_request(&self, request: &mut HttpRequest<S>) -> Self::Future {
-        let identity = self.0.load(request);
-        FutOk(AppIdentity::new(identity))
+    fn from_request(&self, request: &mut HttpRequest<State>) -> Self::Future {
+        Box::new(load_session(request).map(AppIdentity::new))
     }
 }
 
@@ -403,8 +429,73 @@ impl AppIdentityInner {
     fn new() -> AppIdentityInner {
         AppIdentityInner { }
     }
+}
 
-    fn load<S>(&self, req: &mut HttpRequest<S>) -> Option<(Uuid, Uuid)> {
-        session_creds(req)
-    }
-}
\ No newline at end of file
+/// Check the session against the server side session store
+///
+/// The credentials in the session cookie are refreshed from the database, so a group change takes
+/// effect with the next request. A revoked session is cleared.
+fn load_session(req: &mut HttpRequest<State>) -> Box<Future<Item = Option<(Uuid, Uuid)>, Error = AwError>> {
+    let session_id = match session_id(req) {
+        Some(session_id) => session_id,
+        None => {
+            // sessions from before the session store can't be revoked, they have to log in again
+            if session_creds(req).is_some() {
+                req.session().clear();
+            }
+            return Box::new(FutOk(None));
+        }
+    };
+
+    let msg = TouchSessionMsg {
+        id: session_id,
+        ip_address: req.peer_addr().map(|addr| addr.ip()),
+        user_agent: user_agent(req),
+    };
+    let fut = req.state().db().send(msg);
+    let req = req.clone();
+    Box::new(fut.from_err().and_then(move |result: Result<Option<(Uuid, Uuid)>>| -> AwResult<Option<(Uuid, Uuid)>> {
+        match result {
+            Ok(Some((user_id, group_id))) => {
+                req.session().set("user_id", user_id)?;
+                req.session().set("group_id", group_id)?;
+                Ok(Some((user_id, group_id)))
+            }
+            Ok(None) => {
+                req.session().clear();
+                Ok(None)
+            }
+            Err(e) => {
+                warn!("failed to load session: {}", e);
+                Ok(None)
+            }
+        }
+    }))
+}
+
+/// Check a JWT against the issued API tokens
+///
+/// The token must not be revoked and must allow the scope of the request. The group is taken from
+/// the user, not from the claims, so a demotion applies to issued tokens as well.
+fn load_token(req: &mut HttpRequest<State>, claims: Claims) -> Box<Future<Item = Option<(Uuid, Uuid)>, Error = AwError>> {
+    let scope = match api_token::required_scope(req.path(), *req.method() == Method::GET) {
+        Some(scope) => scope,
+        None => return Box::new(FutOk(None)),
+    };
+
+    let user_id = claims.user_id;
+    let msg = ValidateTokenMsg {
+        id: claims.jti,
+        user_id,
+        scope,
+    };
+    Box::new(req.state().db().send(msg).from_err().map(move |result: Result<Option<Uuid>>| {
+        match result {
+            Ok(group_id) => group_id.map(|group_id| (user_id, group_id)),
+            Err(e) => {
+                warn!("failed to validate api token: {}", e);
+                None
+            }
+        }
+    }))
+}
@@ -0,0 +1,358 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Login throttling
+//!
+//! The [**LoginThrottle**](struct.LoginThrottle.html) counts failed logins per account and per ip
+//! address in memory. After the free attempts each further failure doubles the delay until the
+//! next attempt is accepted. An account, which reaches `lockout_attempts` failures, is locked for
+//! `lockout_minutes`.
+//!
+//! Throttled attempts are rejected before the database is queried and the password is hashed.
+//! Every accepted attempt is counted as a failure up front and undone after a successful login, so
+//! parallel attempts can't pass the check before their failures are counted.
+
+use super::*;
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+const PRUNE_INTERVAL: u64 = 60;
+
+/// Throttle configuration
+#[derive(Debug, Clone)]
+pub struct ThrottleConfig {
+    /// failed logins per account before the backoff starts
+    pub free_attempts: u32,
+    /// failed logins per ip address before the backoff starts
+    pub ip_free_attempts: u32,
+    /// first delay in seconds
+    pub base_delay: u64,
+    /// maximum delay in seconds
+    pub max_delay: u64,
+    /// failed logins per account, which lock the account
+    pub lockout_attempts: u32,
+    pub lockout_minutes: u64,
+}
+
+/// The delay after `failures` failed logins
+///
+/// The first `free_attempts` failures have no delay, then the delay starts at `base_delay` seconds
+/// and doubles with every failure up to `max_delay` seconds.
+pub fn backoff(failures: u32, free_attempts: u32, base_delay: u64, max_delay: u64) -> Duration {
+    if failures <= free_attempts {
+        return Duration::from_secs(0);
+    }
+    let exponent = (failures - free_attempts - 1).min(32);
+    Duration::from_secs(base_delay.saturating_mul(1u64 << exponent).min(max_delay))
+}
+
+#[derive(Debug, Clone)]
+struct Failures {
+    count: u32,
+    last_failure: Instant,
+    blocked_until: Instant,
+}
+
+impl Failures {
+    fn new(now: Instant) -> Self {
+        Failures {
+            count: 0,
+            last_failure: now,
+            blocked_until: now,
+        }
+    }
+
+    fn add(&mut self, free_attempts: u32, config: &ThrottleConfig, now: Instant) {
+        self.count = self.count.saturating_add(1);
+        self.last_failure = now;
+        self.blocked_until = now + backoff(self.count, free_attempts, config.base_delay, config.max_delay);
+    }
+
+    fn undo(&mut self, free_attempts: u32, config: &ThrottleConfig) {
+        self.count = self.count.saturating_sub(1);
+        self.blocked_until = self.last_failure + backoff(self.count, free_attempts, config.base_delay, config.max_delay);
+    }
+
+    fn wait(&self, now: Instant) -> Option<Duration> {
+        if self.blocked_until > now {
+            Some(self.blocked_until.duration_since(now))
+        } else {
+            None
+        }
+    }
+}
+
+/// Tracks the failed logins per account and ip address
+pub struct LoginThrottle {
+    config: ThrottleConfig,
+    accounts: HashMap<String, Failures>,
+    ips: HashMap<IpAddr, Failures>,
+    last_prune: Instant,
+}
+
+impl LoginThrottle {
+    /// Construct a new `LoginThrottle` instance
+    pub fn new(config: ThrottleConfig) -> Self {
+        LoginThrottle {
+            config,
+            accounts: HashMap::new(),
+            ips: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Construct a new `LoginThrottle` with the login settings
+    pub fn from_settings() -> Self {
+        let settings = SETTINGS.read().unwrap();
+        let login = &settings.login;
+        Self::new(ThrottleConfig {
+            free_attempts: login.free_attempts,
+            ip_free_attempts: login.ip_free_attempts,
+            base_delay: login.base_delay,
+            max_delay: login.max_delay,
+            lockout_attempts: login.lockout_attempts,
+            lockout_minutes: login.lockout_minutes,
+        })
+    }
+
+    /// Check if a login attempt is allowed
+    ///
+    /// # Errors
+    ///
+    /// Returns the time to wait, if the attempt should be rejected.
+    pub fn check(&mut self, username: &str, ip_address: Option<IpAddr>, now: Instant) -> ::std::result::Result<(), Duration> {
+        if now.duration_since(self.last_prune) >= Duration::from_secs(PRUNE_INTERVAL) {
+            self.prune(now);
+        }
+
+        let account_wait = self.accounts.get(&username.to_lowercase()).and_then(|f| f.wait(now));
+        let ip_wait = ip_address.and_then(|ip| self.ips.get(&ip)).and_then(|f| f.wait(now));
+        match account_wait.into_iter().chain(ip_wait).max() {
+            Some(wait) => Err(wait),
+            None => Ok(()),
+        }
+    }
+
+    /// Reserve a login attempt
+    ///
+    /// The attempt is counted as a failure, before the credentials are verified. Returns `true` if
+    /// the attempt locked the account.
+    ///
+    /// # Errors
+    ///
+    /// Returns the time to wait, if the attempt should be rejected.
+    pub fn reserve(&mut self, username: &str, ip_address: Option<IpAddr>, now: Instant) -> ::std::result::Result<bool, Duration> {
+        self.check(username, ip_address, now)?;
+        Ok(self.fail(username, ip_address, now))
+    }
+
+    /// Count a failed login
+    ///
+    /// Returns `true` if the failure locked the account.
+    pub fn fail(&mut self, username: &str, ip_address: Option<IpAddr>, now: Instant) -> bool {
+        let config = &self.config;
+        if let Some(ip_address) = ip_address {
+            self.ips
+                .entry(ip_address)
+                .or_insert_with(|| Failures::new(now))
+                .add(config.ip_free_attempts, config, now);
+        }
+
+        let account = self.accounts
+            .entry(username.to_lowercase())
+            .or_insert_with(|| Failures::new(now));
+        account.add(config.free_attempts, config, now);
+        if config.lockout_attempts > 0 && account.count >= config.lockout_attempts {
+            // the attempts start over after the lockout, another series locks the account again
+            account.count = 0;
+            account.blocked_until = now + Duration::from_secs(config.lockout_minutes * 60);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Forget the failed logins of an account after a successful login
+    ///
+    /// The failure reserved for the attempt is taken back from the ip address.
+    pub fn succeed(&mut self, username: &str, ip_address: Option<IpAddr>) {
+        self.accounts.remove(&username.to_lowercase());
+        let config = &self.config;
+        if let Some(failures) = ip_address.and_then(|ip| self.ips.get_mut(&ip)) {
+            failures.undo(config.ip_free_attempts, config);
+        }
+    }
+
+    /// Remove all entries, which are no longer blocked and had no failure for `lockout_minutes`
+    pub fn prune(&mut self, now: Instant) {
+        let forget_after = Duration::from_secs(self.config.lockout_minutes * 60);
+        let keep = |failures: &Failures| {
+            failures.blocked_until > now || now.duration_since(failures.last_failure) < forget_after
+        };
+        self.accounts.retain(|_, failures| keep(failures));
+        self.ips.retain(|_, failures| keep(failures));
+        self.last_prune = now;
+    }
+}
+
+impl Actor for LoginThrottle {
+    type Context = Context<Self>;
+}
+
+/// Check if a login attempt is allowed and reserve it, before the credentials are verified
+///
+/// The attempt counts as failed until a `LoginSucceededMsg` follows. Returns `true` if the attempt
+/// locked the account.
+pub struct CheckLoginMsg {
+    pub username: String,
+    pub ip_address: Option<IpAddr>,
+}
+
+impl Message for CheckLoginMsg {
+    type Result = Result<bool>;
+}
+
+impl Handler<CheckLoginMsg> for LoginThrottle {
+    type Result = Result<bool>;
+
+    fn handle(&mut self, msg: CheckLoginMsg, _: &mut Self::Context) -> Self::Result {
+        match self.reserve(&msg.username, msg.ip_address, Instant::now()) {
+            Ok(locked) => Ok(locked),
+            Err(wait) => bail!("too many failed logins, try again in {} seconds", wait.as_secs() + 1),
+        }
+    }
+}
+
+/// Reset the failed logins of an account and undo the reserved attempt
+pub struct LoginSucceededMsg {
+    pub username: String,
+    pub ip_address: Option<IpAddr>,
+}
+
+impl Message for LoginSucceededMsg {
+    type Result = ();
+}
+
+impl Handler<LoginSucceededMsg> for LoginThrottle {
+    type Result = ();
+
+    fn handle(&mut self, msg: LoginSucceededMsg, _: &mut Self::Context) -> Self::Result {
+        self.succeed(&msg.username, msg.ip_address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn config() -> ThrottleConfig {
+        ThrottleConfig {
+            free_attempts: 2,
+            ip_free_attempts: 5,
+            base_delay: 1,
+            max_delay: 60,
+            lockout_attempts: 6,
+            lockout_minutes: 15,
+        }
+    }
+
+    fn ip() -> Option<IpAddr> {
+        Some(IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)))
+    }
+
+    #[test]
+    fn exponential_backoff() {
+        assert_eq!(Duration::from_secs(0), backoff(2, 2, 1, 60));
+        assert_eq!(Duration::from_secs(1), backoff(3, 2, 1, 60));
+        assert_eq!(Duration::from_secs(2), backoff(4, 2, 1, 60));
+        assert_eq!(Duration::from_secs(32), backoff(8, 2, 1, 60));
+        assert_eq!(Duration::from_secs(60), backoff(9, 2, 1, 60));
+        assert_eq!(Duration::from_secs(60), backoff(200, 2, 1, 60));
+    }
+
+    #[test]
+    fn throttle_and_lockout() {
+        let now = Instant::now();
+        let mut throttle = LoginThrottle::new(config());
+        assert!(!throttle.fail("user", ip(), now));
+        assert!(!throttle.fail("User", ip(), now));
+        assert!(throttle.check("user", ip(), now).is_ok());
+        assert!(!throttle.fail("user", ip(), now));
+        assert_eq!(Err(Duration::from_secs(1)), throttle.check("USER", None, now));
+        // the ip address is not blocked yet, other accounts are not affected
+        assert!(throttle.check("other", ip(), now).is_ok());
+
+        let mut now = now + Duration::from_secs(1);
+        assert!(throttle.check("user", ip(), now).is_ok());
+        assert!(!throttle.fail("user", None, now));
+        assert!(!throttle.fail("user", None, now));
+        assert!(throttle.fail("user", None, now));
+        assert_eq!(Err(Duration::from_secs(15 * 60)), throttle.check("user", None, now));
+
+        // a successful login resets the account
+        now += Duration::from_secs(15 * 60);
+        assert!(throttle.check("user", None, now).is_ok());
+        throttle.fail("user", None, now);
+        throttle.fail("user", None, now);
+        throttle.succeed("user", None);
+        throttle.fail("user", None, now);
+        assert!(throttle.check("user", None, now).is_ok());
+    }
+
+    #[test]
+    fn reserve_attempts() {
+        let now = Instant::now();
+        let mut throttle = LoginThrottle::new(config());
+        // parallel attempts are counted before any of them is verified
+        assert_eq!(Ok(false), throttle.reserve("user", ip(), now));
+        assert_eq!(Ok(false), throttle.reserve("user", ip(), now));
+        assert_eq!(Ok(false), throttle.reserve("user", ip(), now));
+        assert_eq!(Err(Duration::from_secs(1)), throttle.reserve("user", ip(), now));
+
+        // a successful login takes the reserved attempt back
+        throttle.succeed("user", ip());
+        assert!(throttle.check("user", ip(), now).is_ok());
+        assert_eq!(2, throttle.ips[&ip().unwrap()].count);
+
+        let mut throttle = LoginThrottle::new(ThrottleConfig { lockout_attempts: 3, ..config() });
+        let mut now = now;
+        assert_eq!(Ok(false), throttle.reserve("user", None, now));
+        assert_eq!(Ok(false), throttle.reserve("user", None, now));
+        now += Duration::from_secs(1);
+        assert_eq!(Ok(true), throttle.reserve("user", None, now));
+        assert_eq!(Err(Duration::from_secs(15 * 60)), throttle.check("user", None, now));
+    }
+
+    #[test]
+    fn ip_backoff() {
+        let now = Instant::now();
+        let mut throttle = LoginThrottle::new(config());
+        for i in 0..6 {
+            throttle.fail(&format!("user{}", i), ip(), now);
+        }
+        assert_eq!(Err(Duration::from_secs(1)), throttle.check("other", ip(), now));
+        assert!(throttle.check("other", None, now).is_ok());
+
+        throttle.prune(now + Duration::from_secs(15 * 60));
+        assert!(throttle.ips.is_empty());
+        assert!(throttle.accounts.is_empty());
+    }
+}
@@ -28,6 +28,7 @@
 extern crate actix;
 extern crate actix_redis;
 extern crate actix_web;
+extern crate ammonia;
 extern crate futures;
 #[macro_use]
 extern crate diesel;
@@ -78,11 +79,13 @@ extern crate pretty_assertions;
 
 pub mod api;
 pub mod app;
+pub mod chat_server;
 mod cleanup;
 pub mod db;
 mod error;
 pub mod handlers;
 pub mod identity;
+pub mod login_throttle;
 pub mod models;
 mod schema;
 pub mod settings;
@@ -117,9 +120,12 @@ use error::*;
 use handlers::user::RequireUserMsg;
 use identity::RequestIdentity;
 use models::acl::{Acl, Permission, UserSubject};
+use chat_server::ChatServer;
+use login_throttle::LoginThrottle;
 use settings::Settings;
 use state::{AclContainer, State};
 use template::Template;
+use tracker::checker::PeerChecker;
 
 lazy_static! {
     pub(crate) static ref SETTINGS: RwLock<Settings> = RwLock::new(Settings::new().unwrap());
@@ -132,11 +138,29 @@ fn main() {
     let sys = actix::System::new("ripalt");
     let pool = db::init_pool();
     let acl = state::init_acl(&pool);
+    let ban_list = state::init_ban_list(&pool);
 
     // Start n parallel db executors
     let cloned_pool = pool.clone();
+    let checker_pool = pool.clone();
     let addr = SyncArbiter::start(num_cpus::get(), move || DbExecutor::new(pool.clone()));
 
+    // Start the peer connectability checkers, if enabled
+    let peer_checker = if SETTINGS.read().unwrap().tracker.connectability_check {
+        let cache = PeerChecker::new_cache();
+        Some(SyncArbiter::start(num_cpus::get(), move || {
+            PeerChecker::new(checker_pool.clone(), Arc::clone(&cache))
+        }))
+    } else {
+        None
+    };
+
+    // Start the chat broadcast server
+    let chat_server: Addr<Syn, _> = Arbiter::start(|_| ChatServer::default());
+
+    // Start the login throttle, which counts the failed logins in memory
+    let login_throttle: Addr<Syn, _> = Arbiter::start(|_| LoginThrottle::from_settings());
+
     // Create a new Tera object and wrap it in some thread safe boxes
     // RwLock is needed for the file watcher below, to reload templates when they are changed.
     let tpl = template::init_tera(Arc::clone(&acl));
@@ -169,9 +193,9 @@ fn main() {
     // start the main http server
     HttpServer::new(move || {
         vec![
-            tracker::build(addr.clone()),
-            api::build(addr.clone(), acl.clone()),
-            app::build(addr.clone(), tpl.clone(), acl.clone()),
+            tracker::build(addr.clone(), acl.clone(), ban_list.clone(), peer_checker.clone()),
+            api::build(addr.clone(), acl.clone(), chat_server.clone()),
+            app::build(addr.clone(), tpl.clone(), acl.clone(), ban_list.clone(), login_throttle.clone()),
         ]
     }).shutdown_timeout(2)
         .bind(http_bind)
@@ -229,6 +253,25 @@ fn session_creds<S>(req: &mut actix_web::HttpRequest<S>) -> Option<(Uuid, Uuid)>
     Some((user_id, group_id))
 }
 
+fn session_id<S>(req: &actix_web::HttpRequest<S>) -> Option<Uuid> {
+    req.session().get::<Uuid>("session_id").unwrap_or(None)
+}
+
+/// Store a new server side session in the session cookie
+fn start_session<S>(req: &actix_web::HttpRequest<S>, session: &models::session::UserSession, group_id: Uuid) -> actix_web::Result<()> {
+    req.session().set("session_id", session.id)?;
+    req.session().set("user_id", session.user_id)?;
+    req.session().set("group_id", group_id)
+}
+
+fn user_agent<S>(req: &actix_web::HttpRequest<S>) -> String {
+    req.headers()
+        .get(header::USER_AGENT)
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .unwrap_or("")
+        .to_string()
+}
+
 trait RequestUser {
     fn current_user(&self) -> Option<models::User>;
 }
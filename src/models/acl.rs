@@ -21,8 +21,24 @@
 use super::schema::{acl_group_rules, acl_user_rules};
 use super::*;
 use std::collections::HashMap;
+use models::request::TorrentRequest;
 use models::torrent::TorrentComment;
 
+/// ACL namespace of the team, members of groups with `read` permission are team members
+pub const TEAM_NAMESPACE: &str = "team";
+/// ACL namespace of the request board, `read` to browse, `create` to post, vote, add bounties and fill
+pub const REQUEST_NAMESPACE: &str = "request";
+/// ACL namespace to moderate requests of other users
+pub const REQUEST_MODERATE_NAMESPACE: &str = "request#moderate";
+/// ACL namespace to view the bonus point ledger of other users
+pub const BONUS_AUDIT_NAMESPACE: &str = "bonus#audit";
+/// ACL namespace of the promotion rules, `read` to view the rules and the report, `write` to edit them
+pub const PROMOTION_NAMESPACE: &str = "group#promotion";
+/// ACL namespace of the warnings and bans, `read` to view them, `write` to warn and ban users
+pub const USER_MODERATE_NAMESPACE: &str = "user#moderate";
+/// ACL namespace of the IP and email domain ban lists, `read` to view them, `write` to edit them
+pub const BAN_LIST_NAMESPACE: &str = "user#ban_list";
+
 /// ACL permissions
 #[derive(DbEnum, Debug, PartialEq, PartialOrd, Clone, Copy)]
 pub enum Permission {
@@ -74,6 +90,56 @@ trait Rule {
     fn role(&self) -> &Uuid;
 }
 
+/// Check if the members of a group are team members
+pub fn is_team_group(group_id: &Uuid, db: &PgConnection) -> bool {
+    GroupRule::allowed(group_id, TEAM_NAMESPACE, &Permission::Read, db)
+}
+
+/// Copy the group and user rules of the namespace `from` to the namespace `to`
+pub fn copy_rules(from: &str, to: &str, db: &PgConnection) -> Result<usize> {
+    let group_rules: Vec<GroupRule> = acl_group_rules::table
+        .filter(acl_group_rules::namespace.eq(from))
+        .load::<GroupRule>(db)?
+        .into_iter()
+        .map(|rule| GroupRule::new(to, rule.group_id, rule.permission))
+        .collect();
+    let user_rules: Vec<UserRule> = acl_user_rules::table
+        .filter(acl_user_rules::namespace.eq(from))
+        .load::<UserRule>(db)?
+        .into_iter()
+        .map(|rule| UserRule { id: Uuid::new_v4(), namespace: to.to_string(), user_id: rule.user_id, permission: rule.permission })
+        .collect();
+
+    let num = diesel::insert_into(acl_group_rules::table)
+        .values(&group_rules)
+        .execute(db)
+        .map_err(|e| -> Error { format!("failed to copy group rules: {}", e).into() })?;
+    Ok(num + diesel::insert_into(acl_user_rules::table)
+        .values(&user_rules)
+        .execute(db)
+        .map_err(|e| -> Error { format!("failed to copy user rules: {}", e).into() })?)
+}
+
+/// Permit the group `perm` in the namespace `ns`
+pub fn grant_group(ns: &str, group_id: &Uuid, perm: Permission, db: &PgConnection) -> Result<usize> {
+    diesel::insert_into(acl_group_rules::table)
+        .values(&GroupRule::new(ns, *group_id, perm))
+        .execute(db)
+        .map_err(|e| format!("failed to save group rule: {}", e).into())
+}
+
+/// Delete all group and user rules of the namespace `ns`
+pub fn delete_rules(ns: &str, db: &PgConnection) -> Result<usize> {
+    let num = diesel::delete(acl_group_rules::table)
+        .filter(acl_group_rules::namespace.eq(ns))
+        .execute(db)
+        .map_err(|e| -> Error { format!("failed to delete group rules: {}", e).into() })?;
+    Ok(num + diesel::delete(acl_user_rules::table)
+        .filter(acl_user_rules::namespace.eq(ns))
+        .execute(db)
+        .map_err(|e| -> Error { format!("failed to delete user rules: {}", e).into() })?)
+}
+
 /// The ACL Group Rules
 #[derive(Queryable, Debug, Associations, Identifiable, Insertable, PartialEq)]
 #[table_name = "acl_group_rules"]
@@ -86,6 +152,15 @@ struct GroupRule {
 }
 
 impl GroupRule {
+    fn new(ns: &str, group_id: Uuid, permission: Permission) -> Self {
+        GroupRule {
+            id: Uuid::new_v4(),
+            namespace: ns.to_string(),
+            group_id,
+            permission,
+        }
+    }
+
     /// Load the corresponding group rule from the database and check if permits the action `perm`
     ///
     /// `group` - the group to test
@@ -274,10 +349,10 @@ impl Acl {
     /// # Panics
     ///
     /// This function panics if the underlying database shits the bed.
-    #[allow(dead_code)]
     pub fn reload(&mut self, db: &PgConnection) {
         self.group_rules.clear();
         self.user_rules.clear();
+        self.groups.clear();
         self.load(db);
     }
 
@@ -366,6 +441,11 @@ impl<'a> UserSubject<'a> {
     pub fn is_allowed(&self, ns: &str, perm: &Permission) -> bool {
         self.acl.is_allowed(self.user_id, self.group_id, ns, perm)
     }
+
+    /// Check if the user is a team member
+    pub fn is_team(&self) -> bool {
+        self.is_allowed(TEAM_NAMESPACE, &Permission::Read)
+    }
 }
 
 impl<'a> Subject<Torrent> for UserSubject<'a> {
@@ -392,7 +472,7 @@ impl<'a> Subject<User> for UserSubject<'a> {
 
 impl<'a> Subject<chat::ChatRoom> for UserSubject<'a> {
     fn may(&self, obj: &chat::ChatRoom, perm: &Permission) -> bool {
-        self.acl().is_allowed(self.user_id(), self.group_id(), &format!("chat#{}", obj.to_string()), perm)
+        self.acl().is_allowed(self.user_id(), self.group_id(), &obj.acl_namespace(), perm)
     }
 }
 
@@ -412,6 +492,16 @@ impl<'a> Subject<TorrentComment> for UserSubject<'a> {
     }
 }
 
+impl<'a> Subject<TorrentRequest> for UserSubject<'a> {
+    fn may(&self, obj: &TorrentRequest, perm: &Permission) -> bool {
+        if obj.user_id == *self.user_id() {
+            return true;
+        }
+
+        self.acl().is_allowed(self.user_id(), self.group_id(), REQUEST_MODERATE_NAMESPACE, perm)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,217 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! API tokens
+//!
+//! An [**ApiToken**](struct.ApiToken.html) is the server side record of an issued JWT. The JWT
+//! carries the id of the record, every request checks that the token was not revoked and allows
+//! the requested scope.
+//!
+//! Scopes are `{area}:read` for `GET` requests and `{area}:write` for all other requests, where
+//! the area is the first path segment after `/api/v1`, e.g. `message:read`.
+
+use super::*;
+
+use chrono::Duration;
+
+use schema::api_tokens;
+use schema::api_tokens::dsl as at;
+
+/// The API areas, which can be accessed with a token
+pub const SCOPE_AREAS: &[&str] = &["chat", "comment", "message", "notification", "user"];
+/// Maximum length of the token name
+pub const MAX_NAME_LENGTH: usize = 64;
+
+/// All valid scopes
+pub fn all_scopes() -> Vec<String> {
+    SCOPE_AREAS
+        .iter()
+        .flat_map(|area| vec![format!("{}:read", area), format!("{}:write", area)])
+        .collect()
+}
+
+/// The scope needed for a request to `path`
+///
+/// Returns `None` if the path can't be accessed with a token.
+pub fn required_scope(path: &str, read: bool) -> Option<String> {
+    let area = path.trim_left_matches("/api/v1/").split('/').next()?;
+    if !SCOPE_AREAS.contains(&area) {
+        return None;
+    }
+    Some(format!("{}:{}", area, if read { "read" } else { "write" }))
+}
+
+/// An issued API token
+///
+/// Each instance represents a row in the database. Revoked tokens are kept until they expire.
+#[derive(Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "api_tokens"]
+pub struct ApiToken {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub name: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Timestamp,
+    pub last_used_at: Option<Timestamp>,
+    pub revoked_at: Option<Timestamp>,
+    pub created_at: Timestamp,
+}
+
+impl ApiToken {
+    /// Constructs a new `ApiToken` instance, which expires after `days`.
+    pub fn new(user_id: Uuid, name: String, scopes: Vec<String>, days: i64) -> Self {
+        let now = Utc::now();
+        ApiToken {
+            id: Uuid::new_v4(),
+            user_id,
+            name,
+            scopes,
+            expires_at: now + Duration::days(days),
+            last_used_at: None,
+            revoked_at: None,
+            created_at: now,
+        }
+    }
+
+    /// Check the name and the scopes of a new token
+    pub fn validate(&self) -> Result<()> {
+        let name_length = self.name.trim().chars().count();
+        if name_length == 0 || name_length > MAX_NAME_LENGTH {
+            bail!("the name must have 1 to {} characters", MAX_NAME_LENGTH);
+        }
+        if self.scopes.is_empty() {
+            bail!("at least one scope is required");
+        }
+        let scopes = all_scopes();
+        if let Some(scope) = self.scopes.iter().find(|scope| !scopes.contains(scope)) {
+            bail!("invalid scope: {}", scope);
+        }
+        Ok(())
+    }
+
+    /// Save the token into the database
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        self.insert_into(api_tokens::table)
+            .execute(db)
+            .map_err(|e| format!("failed to save api token: {}", e).into())
+    }
+
+    /// Fetch the tokens of the user, the newest first
+    pub fn for_user(user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        api_tokens::table
+            .filter(at::user_id.eq(user_id))
+            .order_by(at::created_at.desc())
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Check if the token is neither revoked nor expired
+    pub fn is_active(&self) -> bool {
+        self.revoked_at.is_none() && self.expires_at > Utc::now()
+    }
+
+    /// Check if the token allows the scope
+    pub fn allows(&self, scope: &str) -> bool {
+        self.scopes.iter().any(|s| s == scope)
+    }
+
+    /// Check a token for a request and record the use
+    ///
+    /// Returns the current group id of the user, or `None` if the token is revoked, expired or
+    /// does not allow the scope. The group is taken from the user, so demotions apply to issued
+    /// tokens.
+    pub fn check(id: &Uuid, user_id: &Uuid, scope: &str, db: &PgConnection) -> Result<Option<Uuid>> {
+        let token = api_tokens::table
+            .filter(at::id.eq(id))
+            .filter(at::user_id.eq(user_id))
+            .first::<Self>(db)
+            .optional()?;
+        let token = match token {
+            Some(ref token) if token.is_active() && token.allows(scope) => token,
+            _ => return Ok(None),
+        };
+
+        let user = match User::find(user_id, db) {
+            Some(user) => user,
+            None => return Ok(None),
+        };
+        if moderation::check_status(&user, db).is_err() {
+            return Ok(None);
+        }
+
+        let touch = match token.last_used_at {
+            Some(last_used_at) => Utc::now().signed_duration_since(last_used_at) > Duration::seconds(TOUCH_INTERVAL),
+            None => true,
+        };
+        if touch {
+            diesel::update(api_tokens::table.find(id))
+                .set(at::last_used_at.eq(Utc::now()))
+                .execute(db)?;
+        }
+
+        Ok(Some(user.group_id))
+    }
+
+    /// Revoke a token of the user
+    pub fn revoke(id: &Uuid, user_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::update(api_tokens::table)
+            .filter(at::id.eq(id))
+            .filter(at::user_id.eq(user_id))
+            .filter(at::revoked_at.is_null())
+            .set(at::revoked_at.eq(Utc::now()))
+            .execute(db)
+            .map_err(|e| format!("failed to revoke api token: {}", e).into())
+    }
+
+    /// Delete expired tokens, their JWTs are rejected anyway
+    pub fn prune(db: &PgConnection) -> Result<usize> {
+        diesel::delete(api_tokens::table)
+            .filter(at::expires_at.lt(Utc::now()))
+            .execute(db)
+            .map_err(|e| format!("failed to prune api tokens: {}", e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes() {
+        assert_eq!(2 * SCOPE_AREAS.len(), all_scopes().len());
+        assert_eq!(Some(String::from("message:read")), required_scope("/api/v1/message/messages", true));
+        assert_eq!(Some(String::from("chat:write")), required_scope("/api/v1/chat/publish", false));
+        assert_eq!(None, required_scope("/api/v1/auth/token", false));
+        assert_eq!(None, required_scope("/api/v1/", true));
+    }
+
+    #[test]
+    fn validate_token() {
+        let user_id = Uuid::new_v4();
+        let token = ApiToken::new(user_id, String::from("client"), vec![String::from("message:read")], 30);
+        assert!(token.validate().is_ok());
+        assert!(token.is_active());
+        assert!(token.allows("message:read"));
+        assert!(!token.allows("message:write"));
+
+        assert!(ApiToken::new(user_id, String::from(" "), vec![String::from("message:read")], 30).validate().is_err());
+        assert!(ApiToken::new(user_id, String::from("client"), Vec::new(), 30).validate().is_err());
+        assert!(ApiToken::new(user_id, String::from("client"), vec![String::from("admin")], 30).validate().is_err());
+        assert!(!ApiToken::new(user_id, String::from("client"), vec![String::from("user:read")], 0).is_active());
+    }
+}
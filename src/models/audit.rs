@@ -0,0 +1,80 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Audit log of security relevant events of an account
+
+use super::*;
+
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+
+use schema::user_audit_log;
+
+/// The account was locked after too many failed logins
+pub const ACTION_LOGIN_LOCKOUT: &str = "login_lockout";
+
+/// Number of entries shown on the user profile
+pub const AUDIT_LOG_LIMIT: i64 = 50;
+
+/// An entry of the audit log
+///
+/// Each instance represents a row in the database.
+#[derive(Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "user_audit_log"]
+pub struct AuditEntry {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub action: String,
+    /// the address, which caused the event
+    #[serde(serialize_with = "serialize_optional_ip")]
+    pub ip_address: Option<IpNetwork>,
+    pub details: String,
+    pub created_at: Timestamp,
+}
+
+impl AuditEntry {
+    /// Constructs a new `AuditEntry` instance.
+    pub fn new(user_id: Uuid, action: &str, ip_address: Option<IpAddr>, details: String) -> Self {
+        AuditEntry {
+            id: Uuid::new_v4(),
+            user_id,
+            action: action.to_string(),
+            ip_address: ip_address.map(IpNetwork::from),
+            details,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Fetch the latest entries of the user
+    pub fn for_user(user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        user_audit_log::table
+            .filter(user_audit_log::dsl::user_id.eq(user_id))
+            .order_by(user_audit_log::dsl::created_at.desc())
+            .limit(AUDIT_LOG_LIMIT)
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Save the entry into the database
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        self.insert_into(user_audit_log::table)
+            .execute(db)
+            .map_err(|e| format!("failed to save audit log entry: {}", e).into())
+    }
+}
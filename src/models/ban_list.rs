@@ -0,0 +1,231 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! IP and email domain ban lists
+//!
+//! [**IpBan**](struct.IpBan.html) bans an address range (CIDR), [**EmailDomainBan**](struct.EmailDomainBan.html)
+//! bans an email domain and its subdomains, e.g. disposable email providers.
+//!
+//! Both lists are cached in the [**BanList**](struct.BanList.html), which is checked on signup,
+//! login and announce.
+
+use super::*;
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+use ipnetwork::IpNetwork;
+use serde::Serializer;
+
+use schema::{email_domain_bans, ip_bans};
+
+/// A banned address range
+///
+/// Each instance represents a row in the database.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "ip_bans"]
+pub struct IpBan {
+    pub id: Uuid,
+    /// the banned network, a single address is a network with the full prefix
+    #[serde(serialize_with = "serialize_network")]
+    pub network: IpNetwork,
+    pub reason: String,
+    /// the admin who added the ban
+    pub issuer_id: Uuid,
+    pub created_at: Timestamp,
+}
+
+impl IpBan {
+    /// Constructs a new `IpBan` instance.
+    pub fn new(network: IpNetwork, reason: String, issuer_id: Uuid) -> Self {
+        IpBan {
+            id: Uuid::new_v4(),
+            network,
+            reason,
+            issuer_id,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Fetch all banned networks
+    pub fn all(db: &PgConnection) -> Vec<Self> {
+        ip_bans::table
+            .order_by(ip_bans::dsl::network.asc())
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Save the ban into the database
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        self.insert_into(ip_bans::table)
+            .execute(db)
+            .map_err(|e| format!("failed to save ip ban: {}", e).into())
+    }
+
+    /// Delete a ban
+    pub fn delete(id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::delete(ip_bans::table.find(id))
+            .execute(db)
+            .map_err(|e| format!("failed to delete ip ban: {}", e).into())
+    }
+}
+
+fn serialize_network<S: Serializer>(network: &IpNetwork, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(network)
+}
+
+/// A banned email domain
+///
+/// Each instance represents a row in the database.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "email_domain_bans"]
+pub struct EmailDomainBan {
+    pub id: Uuid,
+    /// the banned domain in lower case, subdomains are banned as well
+    pub domain: String,
+    pub reason: String,
+    /// the admin who added the ban
+    pub issuer_id: Uuid,
+    pub created_at: Timestamp,
+}
+
+impl EmailDomainBan {
+    /// Constructs a new `EmailDomainBan` instance.
+    pub fn new(domain: &str, reason: String, issuer_id: Uuid) -> Self {
+        EmailDomainBan {
+            id: Uuid::new_v4(),
+            domain: domain.trim().trim_left_matches('@').to_lowercase(),
+            reason,
+            issuer_id,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Fetch all banned domains
+    pub fn all(db: &PgConnection) -> Vec<Self> {
+        email_domain_bans::table
+            .order_by(email_domain_bans::dsl::domain.asc())
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Save the ban into the database
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        self.insert_into(email_domain_bans::table)
+            .execute(db)
+            .map_err(|e| format!("failed to save email domain ban: {}", e).into())
+    }
+
+    /// Delete a ban
+    pub fn delete(id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::delete(email_domain_bans::table.find(id))
+            .execute(db)
+            .map_err(|e| format!("failed to delete email domain ban: {}", e).into())
+    }
+}
+
+/// The in memory ban lists
+#[derive(Debug, Default)]
+pub struct BanList {
+    networks: Vec<(IpNetwork, String)>,
+    domains: HashMap<String, String>,
+}
+
+impl BanList {
+    /// Create a new empty `BanList` object
+    pub fn new() -> Self {
+        Self {
+            networks: Vec::new(),
+            domains: HashMap::new(),
+        }
+    }
+
+    /// Load the ban lists from the database
+    ///
+    /// Also used to reload the lists after a change.
+    pub fn load(&mut self, db: &PgConnection) {
+        self.networks = IpBan::all(db).into_iter().map(|b| (b.network, b.reason)).collect();
+        self.domains = EmailDomainBan::all(db).into_iter().map(|b| (b.domain, b.reason)).collect();
+    }
+
+    /// Add a banned network
+    pub fn add_network(&mut self, network: IpNetwork, reason: String) {
+        self.networks.push((network, reason));
+    }
+
+    /// Add a banned email domain
+    pub fn add_domain(&mut self, domain: &str, reason: String) {
+        self.domains.insert(domain.to_lowercase(), reason);
+    }
+
+    /// The reason, if the address is in a banned network
+    ///
+    /// IPv4-mapped IPv6 addresses (`::ffff:a.b.c.d`), as seen behind a dual-stack listener, are
+    /// matched against the IPv4 networks.
+    pub fn ip_banned(&self, ip: &IpAddr) -> Option<&str> {
+        let ip = match *ip {
+            IpAddr::V6(v6) if v6.segments()[..6] == [0, 0, 0, 0, 0, 0xffff] => v6.to_ipv4().map(IpAddr::V4).unwrap_or(*ip),
+            ip => ip,
+        };
+        self.networks
+            .iter()
+            .find(|(network, _)| network.contains(ip))
+            .map(|(_, reason)| &reason[..])
+    }
+
+    /// The reason, if the domain of the email address or one of its parent domains is banned
+    pub fn email_banned(&self, email: &str) -> Option<&str> {
+        let mut domain = match email.rfind('@') {
+            Some(pos) => email[pos + 1..].to_lowercase(),
+            None => return None,
+        };
+        loop {
+            if let Some(reason) = self.domains.get(&domain) {
+                return Some(&reason[..]);
+            }
+            domain = match domain.find('.') {
+                Some(pos) => domain[pos + 1..].to_string(),
+                None => return None,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ban_list() {
+        let mut bans = BanList::new();
+        bans.add_network("10.1.0.0/16".parse().unwrap(), String::from("proxy"));
+        bans.add_network("2001:db8::/32".parse().unwrap(), String::new());
+        bans.add_domain("Mailinator.com", String::from("disposable"));
+
+        assert_eq!(Some("proxy"), bans.ip_banned(&"10.1.2.3".parse().unwrap()));
+        assert_eq!(None, bans.ip_banned(&"10.2.0.1".parse().unwrap()));
+        assert_eq!(Some(""), bans.ip_banned(&"2001:db8::1".parse().unwrap()));
+        assert_eq!(Some("proxy"), bans.ip_banned(&"::ffff:10.1.2.3".parse().unwrap()));
+        assert_eq!(None, bans.ip_banned(&"::ffff:10.2.0.1".parse().unwrap()));
+
+        assert_eq!(Some("disposable"), bans.email_banned("user@mailinator.com"));
+        assert_eq!(Some("disposable"), bans.email_banned("user@mx.MAILINATOR.com"));
+        assert_eq!(None, bans.email_banned("user@notmailinator.com"));
+        assert_eq!(None, bans.email_banned("user@example.com"));
+    }
+}
@@ -0,0 +1,433 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Bonus points
+//!
+//! Seeders earn points every hour, weighted by the size and the age of the torrent and the number
+//! of its seeders. The points are spent in the store. Every change of a balance is recorded in
+//! the ledger (`bonus_transactions`), together with the new balance.
+
+use super::*;
+
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use chrono::Duration;
+
+use schema::{bonus_transactions, freeleech_torrents, peers, torrent_list, user_bonus, user_profiles, users};
+use schema::user_bonus::dsl as b;
+use settings::Bonus;
+
+/// Ledger kind of the hourly seeding award
+pub const SEEDING: &str = "seeding";
+/// Ledger kind of a used freeleech token
+pub const FREELEECH_USED: &str = "freeleech_used";
+/// Maximum length of a custom title
+pub const MAX_TITLE_LENGTH: usize = 32;
+
+const GIB: f64 = 1_073_741_824.0;
+
+/// Points for seeding a torrent for an hour
+///
+/// The size counts logarithmic, the age linear up to a year and rare torrents are preferred.
+pub fn hourly_points(size: i64, age: Duration, seeders: i64, settings: &Bonus) -> f64 {
+    let size_factor = 1.0 + settings.size_weight * (1.0 + size.max(0) as f64 / GIB).log2();
+    let age_factor = 1.0 + settings.age_weight * age.num_days().max(0).min(365) as f64 / 365.0;
+    let seeder_factor = 1.0 + settings.seeder_weight / seeders.max(1) as f64;
+    settings.points_per_hour * size_factor * age_factor * seeder_factor
+}
+
+/// Award the points for an hour of seeding to all current seeders
+///
+/// Returns the number of users, who got points.
+pub fn award_seeding(db: &PgConnection) -> Result<usize> {
+    let seeding = peers::table
+        .select((peers::dsl::user_id, peers::dsl::torrent_id))
+        .filter(peers::dsl::seeder.eq(true))
+        .distinct()
+        .load::<(Uuid, Uuid)>(db)?;
+    if seeding.is_empty() {
+        return Ok(0);
+    }
+
+    let mut torrent_ids: Vec<Uuid> = seeding.iter().map(|&(_, torrent_id)| torrent_id).collect();
+    torrent_ids.sort();
+    torrent_ids.dedup();
+    let torrents: HashMap<Uuid, (i64, Timestamp, i64)> = torrent_list::table
+        .select((
+            torrent_list::dsl::id,
+            torrent_list::dsl::size,
+            torrent_list::dsl::created_at,
+            torrent_list::dsl::seeder,
+        ))
+        .filter(torrent_list::dsl::id.eq_any(&torrent_ids))
+        .load::<(Uuid, i64, Timestamp, i64)>(db)?
+        .into_iter()
+        .map(|(id, size, created_at, seeder)| (id, (size, created_at, seeder)))
+        .collect();
+
+    let now = Utc::now();
+    let mut earned: HashMap<Uuid, (f64, usize)> = HashMap::new();
+    {
+        let settings = SETTINGS.read().unwrap();
+        for (user_id, torrent_id) in seeding {
+            if let Some(&(size, created_at, seeder)) = torrents.get(&torrent_id) {
+                let entry = earned.entry(user_id).or_insert((0.0, 0));
+                entry.0 += hourly_points(size, now.signed_duration_since(created_at), seeder, &settings.bonus);
+                entry.1 += 1;
+            }
+        }
+    }
+
+    db.transaction::<_, Error, _>(|| {
+        let mut num = 0;
+        for (user_id, (points, torrents)) in earned {
+            let points = points.round() as i64;
+            if points > 0 {
+                book(&user_id, SEEDING, points, &format!("seeding {} torrents", torrents), db)?;
+                num += 1;
+            }
+        }
+        Ok(num)
+    })
+}
+
+/// Change the balance of a user and record the transaction in the ledger
+///
+/// Must be called within a transaction. Fails if the balance would become negative.
+fn book(user_id: &Uuid, kind: &str, amount: i64, description: &str, db: &PgConnection) -> Result<i64> {
+    diesel::insert_into(user_bonus::table)
+        .values(b::user_id.eq(user_id))
+        .on_conflict_do_nothing()
+        .execute(db)?;
+    let balance = diesel::update(user_bonus::table.find(user_id))
+        .filter(b::points.ge(-amount))
+        .set((b::points.eq(b::points + amount), b::updated_at.eq(Utc::now())))
+        .returning(b::points)
+        .get_result::<i64>(db)
+        .optional()?
+        .ok_or_else(|| -> Error { "not enough bonus points".into() })?;
+    diesel::insert_into(bonus_transactions::table)
+        .values(&NewBonusTransaction::new(user_id, kind, amount, balance, description))
+        .execute(db)?;
+
+    Ok(balance)
+}
+
+/// Check and trim a custom title
+pub fn check_title(title: &str) -> Result<&str> {
+    let title = title.trim();
+    if title.is_empty() {
+        bail!("the title must not be empty");
+    }
+    if title.chars().count() > MAX_TITLE_LENGTH {
+        bail!("the title must not be longer than {} characters", MAX_TITLE_LENGTH);
+    }
+    Ok(title)
+}
+
+/// An item of the store
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StoreItem {
+    /// upload credit (`User.uploaded`)
+    Upload,
+    /// a custom title, which is the flair of the profile
+    Title,
+    /// a token, which makes a torrent freeleech for the user
+    Freeleech,
+}
+
+impl StoreItem {
+    /// The name used in the store form and as kind in the ledger
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            StoreItem::Upload => "upload",
+            StoreItem::Title => "title",
+            StoreItem::Freeleech => "freeleech",
+        }
+    }
+
+    /// The price in points
+    pub fn price(&self, settings: &Bonus) -> i64 {
+        match *self {
+            StoreItem::Upload => settings.upload_price,
+            StoreItem::Title => settings.title_price,
+            StoreItem::Freeleech => settings.freeleech_price,
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a str> for StoreItem {
+    type Error = Error;
+
+    fn try_from(s: &'a str) -> Result<Self> {
+        match s {
+            "upload" => Ok(StoreItem::Upload),
+            "title" => Ok(StoreItem::Title),
+            "freeleech" => Ok(StoreItem::Freeleech),
+            _ => bail!("unknown item: {}", s),
+        }
+    }
+}
+
+/// The balance of a user
+#[derive(Debug, Clone, Queryable, Identifiable, Serialize)]
+#[table_name = "user_bonus"]
+#[primary_key(user_id)]
+pub struct UserBonus {
+    pub user_id: Uuid,
+    pub points: i64,
+    /// bought and not yet used freeleech tokens
+    pub freeleech_tokens: i32,
+    pub updated_at: Timestamp,
+}
+
+impl UserBonus {
+    /// Find the balance of a user, users without transactions have an empty balance
+    pub fn find(user_id: &Uuid, db: &PgConnection) -> Self {
+        user_bonus::table
+            .find(user_id)
+            .first::<Self>(db)
+            .unwrap_or_else(|_| UserBonus {
+                user_id: *user_id,
+                points: 0,
+                freeleech_tokens: 0,
+                updated_at: Utc::now(),
+            })
+    }
+
+    /// Buy an item from the store
+    ///
+    /// `title` is the new custom title, if a title is bought.
+    ///
+    /// # Returns
+    ///
+    /// the new balance
+    pub fn buy(user_id: &Uuid, item: StoreItem, title: Option<&str>, db: &PgConnection) -> Result<i64> {
+        let (price, upload_amount) = {
+            let settings = SETTINGS.read().unwrap();
+            (item.price(&settings.bonus), settings.bonus.upload_amount)
+        };
+        let title = match item {
+            StoreItem::Title => Some(check_title(title.unwrap_or_default())?),
+            _ => None,
+        };
+        let description = match item {
+            StoreItem::Upload => format!("{} MiB upload credit", upload_amount / (1024 * 1024)),
+            StoreItem::Title => format!("custom title: {}", title.unwrap_or_default()),
+            StoreItem::Freeleech => String::from("freeleech token"),
+        };
+
+        db.transaction::<_, Error, _>(|| {
+            let balance = book(user_id, item.as_str(), -price, &description, db)?;
+            match item {
+                StoreItem::Upload => {
+                    diesel::update(users::table.find(user_id))
+                        .set(users::dsl::uploaded.eq(users::dsl::uploaded + upload_amount))
+                        .execute(db)?;
+                }
+                StoreItem::Title => {
+                    diesel::insert_into(user_profiles::table)
+                        .values((user_profiles::dsl::id.eq(user_id), user_profiles::dsl::flair.eq(title)))
+                        .on_conflict(on_constraint("user_profiles_pkey"))
+                        .do_update()
+                        .set(user_profiles::dsl::flair.eq(title))
+                        .execute(db)?;
+                }
+                StoreItem::Freeleech => {
+                    diesel::update(user_bonus::table.find(user_id))
+                        .set(b::freeleech_tokens.eq(b::freeleech_tokens + 1))
+                        .execute(db)?;
+                }
+            }
+            Ok(balance)
+        })
+    }
+
+    /// Use a freeleech token for a torrent
+    ///
+    /// # Returns
+    ///
+    /// the time until the downloads of the torrent are not counted for the user
+    pub fn use_freeleech_token(user_id: &Uuid, torrent: &Torrent, db: &PgConnection) -> Result<Timestamp> {
+        use schema::freeleech_torrents::dsl as f;
+
+        if FreeleechTorrent::find_active(user_id, &torrent.id, db).is_some() {
+            bail!("the torrent is already freeleech for you");
+        }
+        let hours = SETTINGS.read().unwrap().bonus.freeleech_hours;
+        let expires_at = Utc::now() + Duration::hours(hours);
+
+        db.transaction::<_, Error, _>(|| {
+            let num = diesel::update(user_bonus::table.find(user_id))
+                .filter(b::freeleech_tokens.gt(0))
+                .set((b::freeleech_tokens.eq(b::freeleech_tokens - 1), b::updated_at.eq(Utc::now())))
+                .execute(db)?;
+            if num == 0 {
+                bail!("you have no freeleech token");
+            }
+            diesel::insert_into(freeleech_torrents::table)
+                .values((f::user_id.eq(user_id), f::torrent_id.eq(&torrent.id), f::expires_at.eq(&expires_at)))
+                .on_conflict(on_constraint("freeleech_torrents_pkey"))
+                .do_update()
+                .set((f::expires_at.eq(&expires_at), f::created_at.eq(Utc::now())))
+                .execute(db)?;
+            book(user_id, FREELEECH_USED, 0, &format!("freeleech token used for {}", torrent.name), db)?;
+            Ok(expires_at)
+        })
+    }
+}
+
+/// A transaction in the ledger
+#[derive(Debug, Clone, Queryable, Identifiable, Serialize)]
+#[table_name = "bonus_transactions"]
+pub struct BonusTransaction {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// `seeding`, `freeleech_used` or the name of the bought item
+    pub kind: String,
+    /// earned points are positive, spent points negative
+    pub amount: i64,
+    /// the balance after the transaction
+    pub balance: i64,
+    pub description: String,
+    pub created_at: Timestamp,
+}
+
+impl BonusTransaction {
+    /// Fetch a page of the ledger of a user, newest first
+    pub fn for_user(user_id: &Uuid, page: i64, per_page: i64, db: &PgConnection) -> Vec<Self> {
+        bonus_transactions::table
+            .filter(bonus_transactions::dsl::user_id.eq(user_id))
+            .order(bonus_transactions::dsl::created_at.desc())
+            .offset((page - 1).max(0) * per_page)
+            .limit(per_page)
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Time of the last seeding award
+    pub fn last_award(db: &PgConnection) -> Option<Timestamp> {
+        use diesel::dsl::max;
+
+        bonus_transactions::table
+            .select(max(bonus_transactions::dsl::created_at))
+            .filter(bonus_transactions::dsl::kind.eq(SEEDING))
+            .first::<Option<Timestamp>>(db)
+            .ok()
+            .and_then(|ts| ts)
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "bonus_transactions"]
+struct NewBonusTransaction<'a> {
+    id: Uuid,
+    user_id: &'a Uuid,
+    kind: &'a str,
+    amount: i64,
+    balance: i64,
+    description: &'a str,
+}
+
+impl<'a> NewBonusTransaction<'a> {
+    fn new(user_id: &'a Uuid, kind: &'a str, amount: i64, balance: i64, description: &'a str) -> Self {
+        let id = Uuid::new_v4();
+        Self { id, user_id, kind, amount, balance, description }
+    }
+}
+
+/// A torrent, which is freeleech for a user
+#[derive(Debug, Clone, Queryable, Serialize)]
+pub struct FreeleechTorrent {
+    pub user_id: Uuid,
+    pub torrent_id: Uuid,
+    pub expires_at: Timestamp,
+    pub created_at: Timestamp,
+}
+
+impl FreeleechTorrent {
+    /// Find a freeleech, which has not expired yet
+    pub fn find_active(user_id: &Uuid, torrent_id: &Uuid, db: &PgConnection) -> Option<Self> {
+        freeleech_torrents::table
+            .find((user_id, torrent_id))
+            .filter(freeleech_torrents::dsl::expires_at.gt(Utc::now()))
+            .first::<Self>(db)
+            .ok()
+    }
+
+    /// Delete expired freeleeches
+    pub fn prune(db: &PgConnection) -> Result<usize> {
+        diesel::delete(freeleech_torrents::table)
+            .filter(freeleech_torrents::dsl::expires_at.le(Utc::now()))
+            .execute(db)
+            .map_err(|e| e.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> Bonus {
+        Bonus {
+            points_per_hour: 1.0,
+            size_weight: 1.0,
+            age_weight: 1.0,
+            seeder_weight: 1.0,
+            upload_price: 500,
+            upload_amount: 1_073_741_824,
+            title_price: 2000,
+            freeleech_price: 750,
+            freeleech_hours: 48,
+        }
+    }
+
+    #[test]
+    fn points() {
+        let settings = settings();
+        let gib = 1_073_741_824;
+        // 1 GiB, new, the only seeder: 1 * 2 * 1 * 2
+        assert_eq!(4.0, hourly_points(gib, Duration::zero(), 1, &settings));
+        // bigger, older and rarer torrents earn more
+        let base = hourly_points(gib, Duration::days(30), 10, &settings);
+        assert!(hourly_points(3 * gib, Duration::days(30), 10, &settings) > base);
+        assert!(hourly_points(gib, Duration::days(60), 10, &settings) > base);
+        assert!(hourly_points(gib, Duration::days(30), 2, &settings) > base);
+        // the age counts up to a year
+        assert_eq!(
+            hourly_points(gib, Duration::days(365), 1, &settings),
+            hourly_points(gib, Duration::days(1000), 1, &settings)
+        );
+    }
+
+    #[test]
+    fn items() {
+        for item in &[StoreItem::Upload, StoreItem::Title, StoreItem::Freeleech] {
+            assert_eq!(*item, StoreItem::try_from(item.as_str()).unwrap());
+        }
+        assert!(StoreItem::try_from("unknown").is_err());
+    }
+
+    #[test]
+    fn title() {
+        assert_eq!("Seeder", check_title(" Seeder ").unwrap());
+        assert!(check_title("  ").is_err());
+        assert!(check_title(&"x".repeat(MAX_TITLE_LENGTH + 1)).is_err());
+    }
+}
@@ -28,6 +28,8 @@ pub struct Category {
     pub name: String,
     pub created_at: Timestamp,
     pub updated_at: Timestamp,
+    /// keep the web seeds (`url-list`) of uploaded torrents in downloads
+    pub allow_web_seeds: bool,
 }
 
 impl Category {
@@ -54,6 +56,7 @@ impl Default for Category {
             name: Default::default(),
             created_at: Utc::now(),
             updated_at: Utc::now(),
+            allow_web_seeds: true,
         }
     }
 }
\ No newline at end of file
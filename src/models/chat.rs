@@ -18,69 +18,125 @@
 
 //! Chat models
 //!
-//! [**ChatRoom**](enum.Chatroom.html) is used to identify the chatroom.
+//! [**ChatRoom**](struct.ChatRoom.html) is the chatroom, which represents a row in the database.
 //!
 //! [**ChatMessage**](struct.ChatMessage.html) is the data struct, which represents a row in the database.
 //!
 //! [**ChatMessageWithUser**](struct.ChatMessageWithUser.html) is the same as `ChatMessage`, but with the user name and user group added.
 //!
 //! [**NewChatMessage**](struct.NewChatMessage.html) can be used to create new messages.
+//!
+//! [**ChatMute**](struct.ChatMute.html) represents a user muted in a chatroom.
+//!
+//! [**ChatMention**](struct.ChatMention.html) represents a user mentioned in a chat message.
 
 use super::*;
 
+use schema::chat_mentions;
+use schema::chat_mentions::dsl as mn;
 use schema::chat_messages;
 use schema::chat_messages::dsl as cm;
-use std::convert::TryFrom;
+use schema::chat_mutes;
+use schema::chat_mutes::dsl as mu;
+use schema::chat_rooms;
+use schema::chat_rooms::dsl as cr;
 
 /// The chatroom
 ///
+/// Each instance represents a row in the database.
+///
 /// Can be used with the [**UserSubject**](../../models/acl/struct.UserSubject.html) object to check the permissions of the user.
-#[derive(PartialEq)]
-pub enum ChatRoom {
-    /// Public (all registered users) chatroom.
-    /// When converted to `i16` it has the value `1`.
-    Public,
-    /// Team (moderator+) only chatroom.
-    /// When converted to `i16` it has the value `2`.
-    Team,
-}
-
-impl TryFrom<i16> for ChatRoom {
-    type Error = Error;
-
-    fn try_from(value: i16) -> Result<Self> {
-        match value {
-            1 => Ok(ChatRoom::Public),
-            2 => Ok(ChatRoom::Team),
-            _ => bail!("no chatroom with this id")
-        }
-    }
+/// The ACL namespace of a chatroom is `chat#<slug>`.
+#[derive(Clone, Debug, Queryable, Identifiable, AsChangeset, Serialize)]
+#[table_name = "chat_rooms"]
+pub struct ChatRoom {
+    /// the chatroom id, referenced by `ChatMessage::chat`
+    pub id: i16,
+    /// short name, used for the ACL namespace
+    pub slug: String,
+    /// display name
+    pub name: String,
+    /// the topic of the chatroom
+    pub topic: String,
+    /// timestamp when the chatroom was created
+    pub created_at: Timestamp,
+    /// timestamp when the chatroom was last updated
+    pub updated_at: Timestamp,
+    /// minimum seconds between two messages of the same user, `0` disables the slow mode
+    pub slow_mode: i32,
 }
 
-impl Into<i16> for ChatRoom {
-    fn into(self) -> i16 {
-        match self {
-            ChatRoom::Public => 1,
-            ChatRoom::Team => 2,
-        }
+/// Slug reserved for the ACL namespace `chat#admin`, which is used to manage the chatrooms
+pub const ADMIN_SLUG: &str = "admin";
+
+impl ChatRoom {
+    /// Find a chatroom by its id
+    pub fn find(id: i16, db: &PgConnection) -> Option<Self> {
+        chat_rooms::table.find(id).first::<Self>(db).ok()
+    }
+
+    /// Fetch all chatrooms
+    pub fn all(db: &PgConnection) -> Vec<Self> {
+        chat_rooms::table
+            .order_by(cr::id.asc())
+            .load::<Self>(db)
+            .unwrap_or_default()
     }
-}
 
-impl PartialEq<i16> for ChatRoom {
-    fn eq(&self, other: &i16) -> bool {
-        match ChatRoom::try_from(*other) {
-            Ok(other) => other == *self,
-            Err(_) => false,
+    /// The ACL namespace of the chatroom
+    pub fn acl_namespace(&self) -> String {
+        format!("chat#{}", self.slug)
+    }
+
+    /// Check if `slug` can be used for a chatroom
+    ///
+    /// Slugs must consist of lowercase letters, numbers, `_` and `-` and begin with a letter.
+    pub fn is_valid_slug(slug: &str) -> bool {
+        if slug.is_empty() || slug.len() > 32 || slug == ADMIN_SLUG {
+            return false;
         }
+        let mut chars = slug.chars();
+        chars.next().map(|c| c.is_ascii_lowercase()).unwrap_or(false)
+            && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+    }
+
+    /// Save the changes to the database
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        diesel::update(self)
+            .set(self)
+            .execute(db)
+            .map_err(|e| format!("failed to update chatroom: {}", e).into())
+    }
+
+    /// Delete the chatroom and all of its messages
+    pub fn delete(&self, db: &PgConnection) -> Result<usize> {
+        diesel::delete(self)
+            .execute(db)
+            .map_err(|e| format!("failed to delete chatroom: {}", e).into())
     }
 }
 
-impl ToString for ChatRoom {
-    fn to_string(&self) -> String {
-        match self {
-            ChatRoom::Public => "public".to_string(),
-            ChatRoom::Team => "team".to_string(),
-        }
+/// A new chatroom
+#[derive(Insertable)]
+#[table_name = "chat_rooms"]
+pub struct NewChatRoom<'a> {
+    slug: &'a str,
+    name: &'a str,
+    topic: &'a str,
+    slow_mode: i32,
+}
+
+impl<'a> NewChatRoom<'a> {
+    /// Constructs a new `NewChatRoom` instance.
+    pub fn new(slug: &'a str, name: &'a str, topic: &'a str, slow_mode: i32) -> NewChatRoom<'a> {
+        NewChatRoom { slug, name, topic, slow_mode }
+    }
+
+    /// Save the chatroom into the database.
+    pub fn save(&self, db: &PgConnection) -> Result<ChatRoom> {
+        self.insert_into(chat_rooms::table)
+            .get_result::<ChatRoom>(db)
+            .map_err(|e| format!("failed to create chatroom: {}", e).into())
     }
 }
 
@@ -95,12 +151,60 @@ pub struct ChatMessage {
     pub id: Uuid,
     /// user id
     pub user_id: Uuid,
-    /// chatroom. Can be converted to [**ChatRoom**](enum.Chatroom.html).
+    /// chatroom. The id of the [**ChatRoom**](struct.ChatRoom.html).
     pub chat: i16,
     /// the actual message
     pub message: String,
     /// timestamp when the message was created
     pub created_at: Timestamp,
+    /// timestamp when the message was deleted by a moderator
+    pub deleted_at: Option<Timestamp>,
+    /// the message rendered as HTML, empty for messages written before rendering was added
+    pub rendered: String,
+}
+
+impl ChatMessage {
+    /// Find a message by its id
+    pub fn find(id: &Uuid, db: &PgConnection) -> Option<Self> {
+        chat_messages::table.find(id).first::<Self>(db).ok()
+    }
+
+    /// Find the latest message of the user in a chatroom
+    pub fn latest_for_user(chat: i16, user_id: &Uuid, db: &PgConnection) -> Option<Self> {
+        chat_messages::table
+            .filter(cm::chat.eq(chat))
+            .filter(cm::user_id.eq(user_id))
+            .order_by(cm::created_at.desc())
+            .first::<Self>(db)
+            .ok()
+    }
+
+    /// Mark the message as deleted
+    ///
+    /// The message is kept, so clients learn about the deletion on their next fetch.
+    pub fn delete(&mut self, db: &PgConnection) -> Result<usize> {
+        let now = Utc::now();
+        let res = diesel::update(&*self)
+            .set(cm::deleted_at.eq(now))
+            .execute(db)
+            .map_err(|e| -> Error { format!("failed to delete chat message: {}", e).into() })?;
+        self.deleted_at = Some(now);
+        Ok(res)
+    }
+
+    /// Remove all messages created before `created_before` and all messages deleted before
+    /// `deleted_before` from the database
+    pub fn prune(created_before: Option<Timestamp>, deleted_before: Timestamp, db: &PgConnection) -> Result<usize> {
+        let mut num = diesel::delete(chat_messages::table)
+            .filter(cm::deleted_at.lt(deleted_before))
+            .execute(db)?;
+        if let Some(created_before) = created_before {
+            num += diesel::delete(chat_messages::table)
+                .filter(cm::created_at.lt(created_before))
+                .execute(db)?;
+        }
+        Ok(num)
+    }
 }
 
 /// Chat message data structure
@@ -116,7 +220,7 @@ pub struct ChatMessageWithUser {
     pub id: Uuid,
     /// user id
     pub user_id: Uuid,
-    /// chatroom. Can be converted to [**ChatRoom**](enum.Chatroom.html).
+    /// chatroom. The id of the [**ChatRoom**](struct.ChatRoom.html).
     pub chat: i16,
     /// the actual message
     pub message: String,
@@ -126,6 +230,10 @@ pub struct ChatMessageWithUser {
     pub user_name: String,
     /// user group
     pub user_group: Uuid,
+    /// timestamp when the message was deleted. Deleted messages have an empty `message`.
+    pub deleted_at: Option<Timestamp>,
+    /// the message rendered as HTML
+    pub rendered: String,
 }
 
 impl ChatMessageWithUser {
@@ -133,7 +241,8 @@ impl ChatMessageWithUser {
     ///
     /// Filters by `chat`.
     ///
-    /// If `since` is `Some` Timestamp, it only returns message newer than the timestamp.
+    /// If `since` is `Some` Timestamp, it only returns message newer than the timestamp, and
+    /// messages deleted after the timestamp. Otherwise deleted messages are skipped.
     ///
     /// Returns at most `limit` messages
     pub fn messages_for_chat(chat: i16, since: Option<Timestamp>, limit: i64, db: &PgConnection) -> Vec<Self> {
@@ -142,17 +251,28 @@ impl ChatMessageWithUser {
 
         let mut query = chat_messages::table.into_boxed()
             .inner_join(users::table)
-            .select((cm::id, cm::user_id, cm::chat, cm::message, cm::created_at, u::name, u::group_id))
+            .select((cm::id, cm::user_id, cm::chat, cm::message, cm::created_at, u::name, u::group_id, cm::deleted_at, cm::rendered))
             .filter(cm::chat.eq(chat));
 
         if let Some(since) = since {
-            query = query.filter(cm::created_at.gt(since))
+            query = query.filter(cm::created_at.gt(since).or(cm::deleted_at.gt(since)))
+        } else {
+            query = query.filter(cm::deleted_at.is_null())
         }
 
         query.order_by(cm::created_at.desc())
             .limit(limit)
             .load::<Self>(db)
             .unwrap_or_default()
+            .into_iter()
+            .map(|mut msg: Self| {
+                if msg.deleted_at.is_some() {
+                    msg.message.clear();
+                    msg.rendered.clear();
+                }
+                msg
+            })
+            .collect()
     }
 }
 
@@ -166,6 +286,8 @@ impl From<ChatMessage> for ChatMessageWithUser {
             created_at: msg.created_at,
             user_name: String::default(),
             user_group: Uuid::default(),
+            deleted_at: msg.deleted_at,
+            rendered: msg.rendered,
         }
     }
 }
@@ -178,17 +300,19 @@ pub struct NewChatMessage<'a> {
     user_id: &'a Uuid,
     chat: &'a i16,
     message: &'a str,
+    rendered: &'a str,
 }
 
 impl<'a> NewChatMessage<'a> {
     /// Constructs a new `NewChatMessage` instance.
-    pub fn new(user_id: &'a Uuid, chat: &'a i16, message: &'a str) -> NewChatMessage<'a> {
+    pub fn new(user_id: &'a Uuid, chat: &'a i16, message: &'a str, rendered: &'a str) -> NewChatMessage<'a> {
         let id = Uuid::new_v4();
         NewChatMessage {
             id,
             user_id,
             chat,
             message,
+            rendered,
         }
     }
 
@@ -199,3 +323,179 @@ impl<'a> NewChatMessage<'a> {
             .map_err(|e| format!("failed to create chat message: {}", e).into())
     }
 }
+
+/// A user muted in a chatroom
+///
+/// Each instance represents a row in the database.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "chat_mutes"]
+pub struct ChatMute {
+    /// the unique mute id
+    pub id: Uuid,
+    /// chatroom. The id of the [**ChatRoom**](struct.ChatRoom.html).
+    pub chat: i16,
+    /// the muted user
+    pub user_id: Uuid,
+    /// the moderator who muted the user
+    pub issuer_id: Uuid,
+    /// the reason shown to the muted user
+    pub reason: String,
+    /// timestamp when the mute ends
+    pub expires_at: Timestamp,
+    /// timestamp when the user was muted
+    pub created_at: Timestamp,
+}
+
+impl ChatMute {
+    /// Constructs a new `ChatMute` instance.
+    pub fn new(chat: i16, user_id: Uuid, issuer_id: Uuid, reason: String, expires_at: Timestamp) -> Self {
+        ChatMute {
+            id: Uuid::new_v4(),
+            chat,
+            user_id,
+            issuer_id,
+            reason,
+            expires_at,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Find the active mute for the user in a chatroom
+    pub fn find_active(chat: i16, user_id: &Uuid, db: &PgConnection) -> Option<Self> {
+        chat_mutes::table
+            .filter(mu::chat.eq(chat))
+            .filter(mu::user_id.eq(user_id))
+            .filter(mu::expires_at.gt(Utc::now()))
+            .first::<Self>(db)
+            .ok()
+    }
+
+    /// Save the mute into the database
+    ///
+    /// An existing mute of the user in the chatroom is replaced.
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        use diesel::pg::upsert::on_constraint;
+        self.insert_into(chat_mutes::table)
+            .on_conflict(on_constraint("chat_mutes_chat_user_id_key"))
+            .do_update()
+            .set((
+                mu::issuer_id.eq(&self.issuer_id),
+                mu::reason.eq(&self.reason),
+                mu::expires_at.eq(&self.expires_at),
+                mu::created_at.eq(&self.created_at),
+            ))
+            .execute(db)
+            .map_err(|e| format!("failed to save chat mute: {}", e).into())
+    }
+
+    /// Remove the mute of the user in a chatroom
+    pub fn delete(chat: i16, user_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::delete(chat_mutes::table)
+            .filter(mu::chat.eq(chat))
+            .filter(mu::user_id.eq(user_id))
+            .execute(db)
+            .map_err(|e| format!("failed to delete chat mute: {}", e).into())
+    }
+
+    /// Remove all expired mutes
+    pub fn prune(db: &PgConnection) -> Result<usize> {
+        diesel::delete(chat_mutes::table)
+            .filter(mu::expires_at.le(Utc::now()))
+            .execute(db)
+            .map_err(|e| format!("failed to prune chat mutes: {}", e).into())
+    }
+}
+
+/// A user mentioned in a chat message
+///
+/// Each instance represents a row in the database.
+#[derive(Clone, Debug, Queryable, Serialize)]
+pub struct ChatMention {
+    /// the message
+    pub message_id: Uuid,
+    /// the mentioned user
+    pub user_id: Uuid,
+    /// whether the user has seen the mention
+    pub seen: bool,
+    /// timestamp when the user was mentioned
+    pub created_at: Timestamp,
+}
+
+impl ChatMention {
+    /// Record the mentions of `user_ids` in a message
+    pub fn create(message_id: &Uuid, user_ids: &[Uuid], db: &PgConnection) -> Result<usize> {
+        let values: Vec<_> = user_ids
+            .iter()
+            .map(|user_id| (mn::message_id.eq(message_id), mn::user_id.eq(user_id)))
+            .collect();
+        diesel::insert_into(chat_mentions::table)
+            .values(&values)
+            .on_conflict_do_nothing()
+            .execute(db)
+            .map_err(|e| format!("failed to create chat mentions: {}", e).into())
+    }
+
+    /// Count the unseen mentions of the user
+    pub fn unseen_count(user_id: &Uuid, db: &PgConnection) -> i64 {
+        chat_mentions::table
+            .filter(mn::user_id.eq(user_id))
+            .filter(mn::seen.eq(false))
+            .count()
+            .get_result(db)
+            .unwrap_or(0)
+    }
+
+    /// Mark all mentions of the user as seen
+    pub fn mark_seen(user_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::update(chat_mentions::table)
+            .filter(mn::user_id.eq(user_id))
+            .filter(mn::seen.eq(false))
+            .set(mn::seen.eq(true))
+            .execute(db)
+            .map_err(|e| format!("failed to update chat mentions: {}", e).into())
+    }
+}
+
+/// Check if the slow mode of a chatroom allows a new message
+///
+/// Returns the seconds the user has to wait, if the last message was sent too recently.
+pub fn slow_mode_wait(slow_mode: i32, last_message: Option<Timestamp>, now: Timestamp) -> Option<i64> {
+    if slow_mode <= 0 {
+        return None;
+    }
+    let last_message = last_message?;
+    let elapsed = now.signed_duration_since(last_message).num_seconds();
+    if elapsed < i64::from(slow_mode) {
+        Some(i64::from(slow_mode) - elapsed)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_slug() {
+        assert!(ChatRoom::is_valid_slug("public"));
+        assert!(ChatRoom::is_valid_slug("team-2"));
+        assert!(ChatRoom::is_valid_slug("off_topic"));
+        assert!(!ChatRoom::is_valid_slug(""));
+        assert!(!ChatRoom::is_valid_slug("admin"));
+        assert!(!ChatRoom::is_valid_slug("2nd"));
+        assert!(!ChatRoom::is_valid_slug("Public"));
+        assert!(!ChatRoom::is_valid_slug("chat#room"));
+        assert!(!ChatRoom::is_valid_slug(&"a".repeat(33)));
+    }
+
+    #[test]
+    fn slow_mode() {
+        use chrono::Duration;
+        let now = Utc::now();
+        assert_eq!(None, slow_mode_wait(0, Some(now), now));
+        assert_eq!(None, slow_mode_wait(30, None, now));
+        assert_eq!(Some(20), slow_mode_wait(30, Some(now - Duration::seconds(10)), now));
+        assert_eq!(None, slow_mode_wait(30, Some(now - Duration::seconds(30)), now));
+    }
+}
@@ -17,9 +17,26 @@
  */
 
 //! Use group model
+//!
+//! Users are moved between groups by the promotion rules. A rule belongs to the group it leads
+//! to: members of the parent group are promoted, when they meet the rule, members of the group
+//! are demoted to the parent group, when they no longer meet it. Excluded groups (the team groups
+//! and `promotion.excluded_groups`) are never changed, the rules are not applied as long as no
+//! group is excluded.
 
 use super::*;
 use super::schema::groups;
+use super::schema::promotion_rules;
+use super::schema::users;
+use super::user::STATUS_ACTIVE;
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::Duration;
+use diesel::sql_types;
+
+use models::acl::is_team_group;
+use models::message::Message;
 
 #[derive(Queryable, Debug, Identifiable, Associations, PartialEq, Insertable, AsChangeset, Serialize)]
 #[table_name = "groups"]
@@ -49,4 +66,420 @@ impl Group {
     pub fn find(id: &Uuid, db: &PgConnection) -> Option<Group> {
         groups::dsl::groups.find(id).first::<Group>(db).ok()
     }
-}
\ No newline at end of file
+
+    /// Fetch all groups, ordered by name
+    pub fn all(db: &PgConnection) -> Vec<Group> {
+        groups::table.order_by(groups::dsl::name).load::<Group>(db).unwrap_or_default()
+    }
+
+    /// Fetch the ids of all active users in the groups
+    pub fn member_ids(group_ids: &[Uuid], db: &PgConnection) -> Vec<Uuid> {
+        users::table
+            .select(users::dsl::id)
+            .filter(users::dsl::group_id.eq_any(group_ids))
+            .filter(users::dsl::status.eq(STATUS_ACTIVE))
+            .load::<Uuid>(db)
+            .unwrap_or_default()
+    }
+}
+
+/// The requirements for the members of a group
+#[derive(Queryable, Debug, Clone, Identifiable, Serialize)]
+#[table_name = "promotion_rules"]
+pub struct PromotionRule {
+    pub id: Uuid,
+    pub group_id: Uuid,
+    pub min_age_days: i32,
+    /// minimum uploaded bytes
+    pub min_uploaded: i64,
+    pub min_ratio: f64,
+    /// minimum number of uploaded torrents
+    pub min_uploads: i32,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl PromotionRule {
+    /// Fetch all rules
+    pub fn all(db: &PgConnection) -> Vec<Self> {
+        promotion_rules::table.load::<Self>(db).unwrap_or_default()
+    }
+
+    /// Create or replace the rule of a group
+    pub fn save(
+        group_id: &Uuid,
+        min_age_days: i32,
+        min_uploaded: i64,
+        min_ratio: f64,
+        min_uploads: i32,
+        db: &PgConnection,
+    ) -> Result<usize> {
+        use schema::promotion_rules::dsl as p;
+
+        diesel::insert_into(promotion_rules::table)
+            .values((
+                p::id.eq(Uuid::new_v4()),
+                p::group_id.eq(group_id),
+                p::min_age_days.eq(min_age_days),
+                p::min_uploaded.eq(min_uploaded),
+                p::min_ratio.eq(min_ratio),
+                p::min_uploads.eq(min_uploads),
+            ))
+            .on_conflict(on_constraint("promotion_rules_group_id_key"))
+            .do_update()
+            .set((
+                p::min_age_days.eq(min_age_days),
+                p::min_uploaded.eq(min_uploaded),
+                p::min_ratio.eq(min_ratio),
+                p::min_uploads.eq(min_uploads),
+                p::updated_at.eq(Utc::now()),
+            ))
+            .execute(db)
+            .map_err(|e| format!("failed to save promotion rule: {}", e).into())
+    }
+
+    /// Delete the rule of a group
+    pub fn delete_for_group(group_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::delete(promotion_rules::table)
+            .filter(promotion_rules::dsl::group_id.eq(group_id))
+            .execute(db)
+            .map_err(|e| format!("failed to delete promotion rule: {}", e).into())
+    }
+
+    /// Check if the user meets the rule, users with active warnings never do
+    pub fn is_met(&self, stats: &MemberStats, now: &Timestamp) -> bool {
+        now.signed_duration_since(stats.created_at) >= Duration::days(i64::from(self.min_age_days))
+            && stats.uploaded >= self.min_uploaded
+            && stats.ratio() >= self.min_ratio
+            && stats.uploads >= i64::from(self.min_uploads)
+            && stats.warnings == 0
+    }
+}
+
+/// The numbers of a user, which are checked against the promotion rules
+#[derive(Debug, QueryableByName)]
+pub struct MemberStats {
+    #[sql_type = "sql_types::Uuid"]
+    pub id: Uuid,
+    #[sql_type = "sql_types::Varchar"]
+    pub name: String,
+    #[sql_type = "sql_types::Uuid"]
+    pub group_id: Uuid,
+    #[sql_type = "sql_types::Timestamptz"]
+    pub created_at: Timestamp,
+    #[sql_type = "sql_types::BigInt"]
+    pub uploaded: i64,
+    #[sql_type = "sql_types::BigInt"]
+    pub downloaded: i64,
+    /// number of uploaded torrents
+    #[sql_type = "sql_types::BigInt"]
+    pub uploads: i64,
+    /// number of active warnings
+    #[sql_type = "sql_types::BigInt"]
+    pub warnings: i64,
+}
+
+impl MemberStats {
+    /// Load the numbers of all active users in the groups
+    pub fn load(group_ids: &[Uuid], db: &PgConnection) -> Result<Vec<Self>> {
+        diesel::sql_query(
+            "SELECT u.id, u.name, u.group_id, u.created_at, u.uploaded, u.downloaded, \
+             (SELECT count(*) FROM torrents t WHERE t.user_id = u.id) AS uploads, \
+             (SELECT count(*) FROM user_warnings w WHERE w.user_id = u.id AND w.revoked_at IS NULL \
+             AND w.expires_at > now()) AS warnings \
+             FROM users u WHERE u.status = $1 AND u.group_id = ANY($2)",
+        ).bind::<sql_types::SmallInt, _>(STATUS_ACTIVE)
+            .bind::<sql_types::Array<sql_types::Uuid>, _>(group_ids)
+            .load::<Self>(db)
+            .map_err(|e| format!("failed to load member stats: {}", e).into())
+    }
+
+    /// Ratio of uploaded to downloaded bytes, users without downloads have an infinite ratio
+    pub fn ratio(&self) -> f64 {
+        if self.downloaded <= 0 {
+            ::std::f64::INFINITY
+        } else {
+            self.uploaded as f64 / self.downloaded as f64
+        }
+    }
+}
+
+/// A group change of a user
+#[derive(Debug, Serialize)]
+pub struct GroupChange {
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub from_id: Uuid,
+    pub from_name: String,
+    pub to_id: Uuid,
+    pub to_name: String,
+    /// `false` if the user is demoted
+    pub promoted: bool,
+}
+
+/// The groups, whose members are never moved
+///
+/// These are the team groups and the groups named in `promotion.excluded_groups`.
+pub fn excluded_groups(groups: &[Group], db: &PgConnection) -> HashSet<Uuid> {
+    let settings = SETTINGS.read().unwrap();
+    let names = &settings.promotion.excluded_groups;
+    groups
+        .iter()
+        .filter(|g| names.contains(&g.name) || is_team_group(&g.id, db))
+        .map(|g| g.id)
+        .collect()
+}
+
+/// The groups and rules, which decide the group of a user
+pub struct Promotion {
+    groups: Vec<Group>,
+    rules: HashMap<Uuid, PromotionRule>,
+    team: HashSet<Uuid>,
+}
+
+impl Promotion {
+    /// Construct a new `Promotion` instance
+    pub fn new(groups: Vec<Group>, rules: Vec<PromotionRule>, team: HashSet<Uuid>) -> Self {
+        let rules = rules.into_iter().map(|rule| (rule.group_id, rule)).collect();
+        Promotion { groups, rules, team }
+    }
+
+    /// Load all groups, rules and excluded groups
+    pub fn load(db: &PgConnection) -> Self {
+        let groups = Group::all(db);
+        let team = excluded_groups(&groups, db);
+        Promotion::new(groups, PromotionRule::all(db), team)
+    }
+
+    fn group(&self, id: &Uuid) -> Option<&Group> {
+        self.groups.iter().find(|g| g.id == *id)
+    }
+
+    fn is_ruled(&self, id: &Uuid) -> bool {
+        self.rules.contains_key(id) && !self.team.contains(id)
+    }
+
+    /// The groups, whose members may be moved
+    ///
+    /// Without any excluded group the staff can't be told apart, so nobody is moved.
+    fn affected_groups(&self) -> Vec<Uuid> {
+        if self.team.is_empty() {
+            return Vec::new();
+        }
+        self.groups
+            .iter()
+            .filter(|g| !self.team.contains(&g.id))
+            .filter(|g| {
+                self.is_ruled(&g.id) || self.groups.iter().any(|c| c.parent_id == Some(g.id) && self.is_ruled(&c.id))
+            })
+            .map(|g| g.id)
+            .collect()
+    }
+
+    /// The group, the user belongs to according to the rules
+    pub fn target_group(&self, stats: &MemberStats, now: &Timestamp) -> Uuid {
+        let mut current = stats.group_id;
+        if self.team.contains(&current) {
+            return current;
+        }
+
+        // every step moves the user one level, so there are never more steps than groups
+        for _ in 0..self.groups.len() {
+            if self.is_ruled(&current) && !self.rules[&current].is_met(stats, now) {
+                match self.group(&current).and_then(|g| g.parent_id) {
+                    Some(parent) if !self.team.contains(&parent) => {
+                        current = parent;
+                        continue;
+                    }
+                    _ => break,
+                }
+            }
+
+            let next = self.groups
+                .iter()
+                .find(|g| g.parent_id == Some(current) && self.is_ruled(&g.id) && self.rules[&g.id].is_met(stats, now));
+            match next {
+                Some(group) => current = group.id,
+                None => break,
+            }
+        }
+
+        current
+    }
+
+    /// Check if `to` is below `from` in the hierarchy
+    fn is_descendant(&self, to: &Uuid, from: &Uuid) -> bool {
+        let mut current = self.group(to).and_then(|g| g.parent_id);
+        for _ in 0..self.groups.len() {
+            match current {
+                Some(id) if id == *from => return true,
+                Some(id) => current = self.group(&id).and_then(|g| g.parent_id),
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Evaluate the rules for all users in the affected groups, without changing anything
+    pub fn changes(&self, db: &PgConnection) -> Result<Vec<GroupChange>> {
+        let group_ids = self.affected_groups();
+        if group_ids.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let now = Utc::now();
+        let mut changes: Vec<GroupChange> = MemberStats::load(&group_ids, db)?
+            .into_iter()
+            .filter_map(|stats| {
+                let target = self.target_group(&stats, &now);
+                if target == stats.group_id {
+                    return None;
+                }
+                let name = |id: &Uuid| self.group(id).map(|g| g.name.clone()).unwrap_or_default();
+                Some(GroupChange {
+                    user_id: stats.id,
+                    user_name: stats.name.clone(),
+                    from_id: stats.group_id,
+                    from_name: name(&stats.group_id),
+                    to_id: target,
+                    to_name: name(&target),
+                    promoted: self.is_descendant(&target, &stats.group_id),
+                })
+            })
+            .collect();
+        changes.sort_by(|a, b| a.user_name.cmp(&b.user_name));
+
+        Ok(changes)
+    }
+
+    /// Move the users and send them a system message
+    ///
+    /// Returns the number of moved users.
+    pub fn apply(&self, changes: &[GroupChange], db: &PgConnection) -> Result<usize> {
+        let mut num = 0;
+        for change in changes {
+            // the user may have been moved by the staff in the meantime
+            let moved = diesel::update(users::table.find(&change.user_id))
+                .filter(users::dsl::group_id.eq(&change.from_id))
+                .set((users::dsl::group_id.eq(&change.to_id), users::dsl::updated_at.eq(Utc::now())))
+                .execute(db)?;
+            if moved == 0 {
+                continue;
+            }
+
+            let (subject, body) = if change.promoted {
+                (
+                    format!("You have been promoted to {}", change.to_name),
+                    format!("Congratulations, you have been promoted from {} to {}.", change.from_name, change.to_name),
+                )
+            } else {
+                (
+                    format!("You have been moved to {}", change.to_name),
+                    format!(
+                        "You no longer meet the requirements of {} and have been moved to {}.",
+                        change.from_name, change.to_name
+                    ),
+                )
+            };
+            // the user is already moved, a failed message must not stop the other changes
+            if let Err(e) = Message::send_system(&[change.user_id], &subject, &body, db) {
+                warn!("failed to notify {} about the group change: {}", change.user_name, e);
+            }
+            num += 1;
+        }
+
+        Ok(num)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group(name: &str, parent_id: Option<Uuid>) -> Group {
+        Group {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            parent_id,
+            ..Default::default()
+        }
+    }
+
+    fn rule(group: &Group, min_uploaded: i64, min_ratio: f64) -> PromotionRule {
+        PromotionRule {
+            id: Uuid::new_v4(),
+            group_id: group.id,
+            min_age_days: 7,
+            min_uploaded,
+            min_ratio,
+            min_uploads: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn stats(group_id: Uuid, uploaded: i64, downloaded: i64) -> MemberStats {
+        MemberStats {
+            id: Uuid::new_v4(),
+            name: String::from("user"),
+            group_id,
+            created_at: Utc::now() - Duration::days(30),
+            uploaded,
+            downloaded,
+            uploads: 0,
+            warnings: 0,
+        }
+    }
+
+    #[test]
+    fn target_group() {
+        let user = group("User", None);
+        let power = group("Power User", Some(user.id));
+        let elite = group("Elite", Some(power.id));
+        let staff = group("Staff", Some(elite.id));
+        let rules = vec![rule(&power, 100, 1.0), rule(&elite, 1000, 2.0), rule(&staff, 0, 0.0)];
+        let mut team = HashSet::new();
+        team.insert(staff.id);
+        let (user_id, power_id, elite_id, staff_id) = (user.id, power.id, elite.id, staff.id);
+        let promotion = Promotion::new(vec![user, power, elite, staff], rules, team);
+        let now = Utc::now();
+
+        // promotion over several levels, but never into the team
+        assert_eq!(elite_id, promotion.target_group(&stats(user_id, 5000, 0), &now));
+        assert_eq!(power_id, promotion.target_group(&stats(user_id, 500, 400), &now));
+        assert_eq!(user_id, promotion.target_group(&stats(user_id, 50, 0), &now));
+        // demotion
+        assert_eq!(user_id, promotion.target_group(&stats(elite_id, 50, 100), &now));
+        assert_eq!(power_id, promotion.target_group(&stats(elite_id, 1500, 1000), &now));
+        // team members are never moved
+        assert_eq!(staff_id, promotion.target_group(&stats(staff_id, 0, 100), &now));
+        // new accounts are not promoted
+        let mut new = stats(user_id, 5000, 0);
+        new.created_at = now;
+        assert_eq!(user_id, promotion.target_group(&new, &now));
+        // warned users are neither promoted nor kept in a ruled group
+        let mut warned = stats(power_id, 5000, 0);
+        warned.warnings = 1;
+        assert_eq!(user_id, promotion.target_group(&warned, &now));
+
+        assert!(promotion.is_descendant(&elite_id, &user_id));
+        assert!(!promotion.is_descendant(&user_id, &elite_id));
+        let mut affected = promotion.affected_groups();
+        affected.sort();
+        let mut expected = vec![user_id, power_id, elite_id];
+        expected.sort();
+        assert_eq!(expected, affected);
+    }
+
+    #[test]
+    fn no_excluded_groups() {
+        let user = group("User", None);
+        let power = group("Power User", Some(user.id));
+        let staff = group("Staff", Some(power.id));
+        let rules = vec![rule(&power, 100, 1.0), rule(&staff, 0, 0.0)];
+        let promotion = Promotion::new(vec![user, power, staff], rules, HashSet::new());
+
+        // without excluded groups the staff would be moved like everybody else
+        assert!(promotion.affected_groups().is_empty());
+    }
+}
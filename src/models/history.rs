@@ -0,0 +1,327 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! IP and client history of the users
+//!
+//! Logins and announces record the address of the user, announces also the client (peer id and
+//! user agent), each with the first and the last time it was seen.
+//!
+//! [**Duplicates**](struct.Duplicates.html) correlates the history to find users, which share
+//! addresses, passkeys or clients, and passkeys announcing from several addresses or clients.
+
+use super::*;
+
+use std::net::IpAddr;
+
+use chrono::Duration;
+use diesel::sql_types;
+use ipnetwork::IpNetwork;
+use serde::Serializer;
+
+use super::session::truncate_user_agent;
+
+use schema::{user_client_history, user_ip_history};
+use schema::user_client_history::dsl as ch;
+use schema::user_ip_history::dsl as ih;
+
+/// History source of a login
+pub const SOURCE_LOGIN: &str = "login";
+/// History source of an announce
+pub const SOURCE_ANNOUNCE: &str = "announce";
+
+/// An address of a user
+#[derive(Debug, Queryable, Serialize)]
+pub struct IpHistory {
+    pub user_id: Uuid,
+    #[serde(serialize_with = "serialize_ip")]
+    pub ip_address: IpNetwork,
+    /// `login` or `announce`
+    pub source: String,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+}
+
+impl IpHistory {
+    /// Record that the user was seen with the address
+    ///
+    /// A known address is only updated, if it was last seen more than `TOUCH_INTERVAL` seconds
+    /// ago, so frequent announces don't write the same row over and over.
+    pub fn record(user_id: &Uuid, ip_address: &IpAddr, source: &str, db: &PgConnection) -> Result<usize> {
+        let now = Utc::now();
+        diesel::sql_query(
+            "INSERT INTO user_ip_history (user_id, ip_address, source, first_seen, last_seen) \
+             VALUES ($1, $2, $3, $4, $4) \
+             ON CONFLICT (user_id, ip_address, source) DO UPDATE SET last_seen = EXCLUDED.last_seen \
+             WHERE user_ip_history.last_seen < $5",
+        ).bind::<sql_types::Uuid, _>(user_id)
+            .bind::<sql_types::Inet, _>(IpNetwork::from(*ip_address))
+            .bind::<sql_types::Varchar, _>(source)
+            .bind::<sql_types::Timestamptz, _>(now)
+            .bind::<sql_types::Timestamptz, _>(now - Duration::seconds(TOUCH_INTERVAL))
+            .execute(db)
+            .map_err(|e| format!("failed to record ip history: {}", e).into())
+    }
+
+    /// Fetch the addresses of the user, the latest first
+    pub fn for_user(user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        user_ip_history::table
+            .filter(ih::user_id.eq(user_id))
+            .order_by(ih::last_seen.desc())
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Delete the addresses, which were not seen since `before`
+    pub fn prune(before: &Timestamp, db: &PgConnection) -> Result<usize> {
+        diesel::delete(user_ip_history::table)
+            .filter(ih::last_seen.lt(before))
+            .execute(db)
+            .map_err(|e| format!("failed to prune ip history: {}", e).into())
+    }
+}
+
+fn serialize_peer_id<S: Serializer>(peer_id: &[u8], serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&String::from_utf8_lossy(peer_id))
+}
+
+/// A client of a user
+#[derive(Debug, Queryable, Serialize)]
+pub struct ClientHistory {
+    pub user_id: Uuid,
+    #[serde(serialize_with = "serialize_peer_id")]
+    pub peer_id: Bytes,
+    pub user_agent: String,
+    pub first_seen: Timestamp,
+    pub last_seen: Timestamp,
+}
+
+impl ClientHistory {
+    /// Record that the user announced with the client
+    ///
+    /// The user agent is truncated to the column length. A known client is only updated, if it
+    /// was last seen more than `TOUCH_INTERVAL` seconds ago or its user agent changed.
+    pub fn record(user_id: &Uuid, peer_id: &[u8], user_agent: &str, db: &PgConnection) -> Result<usize> {
+        let now = Utc::now();
+        diesel::sql_query(
+            "INSERT INTO user_client_history (user_id, peer_id, user_agent, first_seen, last_seen) \
+             VALUES ($1, $2, $3, $4, $4) \
+             ON CONFLICT (user_id, peer_id) DO UPDATE \
+             SET user_agent = EXCLUDED.user_agent, last_seen = EXCLUDED.last_seen \
+             WHERE user_client_history.last_seen < $5 OR user_client_history.user_agent <> EXCLUDED.user_agent",
+        ).bind::<sql_types::Uuid, _>(user_id)
+            .bind::<sql_types::Bytea, _>(peer_id)
+            .bind::<sql_types::Varchar, _>(truncate_user_agent(user_agent))
+            .bind::<sql_types::Timestamptz, _>(now)
+            .bind::<sql_types::Timestamptz, _>(now - Duration::seconds(TOUCH_INTERVAL))
+            .execute(db)
+            .map_err(|e| format!("failed to record client history: {}", e).into())
+    }
+
+    /// Fetch the clients of the user, the latest first
+    pub fn for_user(user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        user_client_history::table
+            .filter(ch::user_id.eq(user_id))
+            .order_by(ch::last_seen.desc())
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Delete the clients, which were not seen since `before`
+    pub fn prune(before: &Timestamp, db: &PgConnection) -> Result<usize> {
+        diesel::delete(user_client_history::table)
+            .filter(ch::last_seen.lt(before))
+            .execute(db)
+            .map_err(|e| format!("failed to prune client history: {}", e).into())
+    }
+}
+
+/// A user, who shares the `value` with other users
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct DuplicateRow {
+    /// the shared address or peer id
+    #[sql_type = "sql_types::Text"]
+    pub value: String,
+    #[sql_type = "sql_types::Uuid"]
+    pub user_id: Uuid,
+    #[sql_type = "sql_types::Varchar"]
+    pub user_name: String,
+    /// the history source or the user agent
+    #[sql_type = "sql_types::Varchar"]
+    pub detail: String,
+    #[sql_type = "sql_types::Timestamptz"]
+    pub first_seen: Timestamp,
+    #[sql_type = "sql_types::Timestamptz"]
+    pub last_seen: Timestamp,
+}
+
+/// The users sharing a value
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub value: String,
+    pub users: Vec<DuplicateRow>,
+}
+
+/// Groups rows, which are ordered by their value
+pub fn group_rows(rows: Vec<DuplicateRow>) -> Vec<DuplicateGroup> {
+    let mut groups: Vec<DuplicateGroup> = Vec::new();
+    for row in rows {
+        let new_group = match groups.last() {
+            Some(group) => group.value != row.value,
+            None => true,
+        };
+        if new_group {
+            groups.push(DuplicateGroup {
+                value: row.value.clone(),
+                users: Vec::new(),
+            });
+        }
+        if let Some(group) = groups.last_mut() {
+            group.users.push(row);
+        }
+    }
+    groups
+}
+
+/// A passkey, which announced from several addresses or clients
+#[derive(Debug, QueryableByName, Serialize)]
+pub struct PasskeySpread {
+    #[sql_type = "sql_types::Uuid"]
+    pub user_id: Uuid,
+    #[sql_type = "sql_types::Varchar"]
+    pub user_name: String,
+    /// number of distinct announce addresses
+    #[sql_type = "sql_types::BigInt"]
+    pub ips: i64,
+    /// number of distinct user agents
+    #[sql_type = "sql_types::BigInt"]
+    pub clients: i64,
+    #[sql_type = "sql_types::Timestamptz"]
+    pub last_seen: Timestamp,
+}
+
+/// Users sharing addresses, passkeys or clients
+#[derive(Debug, Serialize)]
+pub struct Duplicates {
+    /// addresses used by several users
+    pub ips: Vec<DuplicateGroup>,
+    /// passkeys announced from the login address of another user
+    pub passkeys: Vec<DuplicateGroup>,
+    /// passkeys announced from several addresses or clients, the most spread first
+    pub passkey_spread: Vec<PasskeySpread>,
+    /// peer ids announced by several users
+    pub clients: Vec<DuplicateGroup>,
+}
+
+impl Duplicates {
+    /// Correlate the history seen since `since`
+    pub fn find(since: &Timestamp, db: &PgConnection) -> Result<Self> {
+        let ips = diesel::sql_query(
+            "SELECT host(h.ip_address) AS value, u.id AS user_id, u.name AS user_name, h.source AS detail, \
+             h.first_seen, h.last_seen \
+             FROM user_ip_history h JOIN users u ON u.id = h.user_id \
+             WHERE h.last_seen >= $1 AND h.ip_address IN ( \
+             SELECT ip_address FROM user_ip_history WHERE last_seen >= $1 \
+             GROUP BY ip_address HAVING count(DISTINCT user_id) > 1) \
+             ORDER BY value, user_name, detail",
+        ).bind::<sql_types::Timestamptz, _>(since)
+            .load::<DuplicateRow>(db)?;
+
+        let passkeys = diesel::sql_query(
+            "SELECT host(a.ip_address) AS value, u.id AS user_id, u.name AS user_name, a.source AS detail, \
+             a.first_seen, a.last_seen \
+             FROM user_ip_history a JOIN users u ON u.id = a.user_id \
+             WHERE a.source = $2 AND a.last_seen >= $1 AND EXISTS ( \
+             SELECT 1 FROM user_ip_history l WHERE l.ip_address = a.ip_address AND l.source = $3 \
+             AND l.user_id <> a.user_id AND l.last_seen >= $1) \
+             UNION ALL \
+             SELECT host(l.ip_address) AS value, u.id AS user_id, u.name AS user_name, l.source AS detail, \
+             l.first_seen, l.last_seen \
+             FROM user_ip_history l JOIN users u ON u.id = l.user_id \
+             WHERE l.source = $3 AND l.last_seen >= $1 AND EXISTS ( \
+             SELECT 1 FROM user_ip_history a WHERE a.ip_address = l.ip_address AND a.source = $2 \
+             AND a.user_id <> l.user_id AND a.last_seen >= $1) \
+             ORDER BY value, detail, user_name",
+        ).bind::<sql_types::Timestamptz, _>(since)
+            .bind::<sql_types::Varchar, _>(SOURCE_ANNOUNCE)
+            .bind::<sql_types::Varchar, _>(SOURCE_LOGIN)
+            .load::<DuplicateRow>(db)?;
+
+        // peer ids change with every client restart, so clients are told apart by their user agent
+        let passkey_spread = diesel::sql_query(
+            "SELECT u.id AS user_id, u.name AS user_name, coalesce(i.ips, 0) AS ips, \
+             coalesce(c.clients, 0) AS clients, greatest(i.last_seen, c.last_seen) AS last_seen \
+             FROM ( \
+             SELECT user_id, count(DISTINCT ip_address) AS ips, max(last_seen) AS last_seen \
+             FROM user_ip_history WHERE source = $2 AND last_seen >= $1 GROUP BY user_id) i \
+             FULL JOIN ( \
+             SELECT user_id, count(DISTINCT user_agent) AS clients, max(last_seen) AS last_seen \
+             FROM user_client_history WHERE last_seen >= $1 GROUP BY user_id) c USING (user_id) \
+             JOIN users u ON u.id = user_id \
+             WHERE coalesce(i.ips, 0) > 1 OR coalesce(c.clients, 0) > 1 \
+             ORDER BY ips DESC, clients DESC, user_name",
+        ).bind::<sql_types::Timestamptz, _>(since)
+            .bind::<sql_types::Varchar, _>(SOURCE_ANNOUNCE)
+            .load::<PasskeySpread>(db)?;
+
+        let clients = diesel::sql_query(
+            "SELECT encode(c.peer_id, 'escape') AS value, u.id AS user_id, u.name AS user_name, \
+             c.user_agent AS detail, c.first_seen, c.last_seen \
+             FROM user_client_history c JOIN users u ON u.id = c.user_id \
+             WHERE c.last_seen >= $1 AND c.peer_id IN ( \
+             SELECT peer_id FROM user_client_history WHERE last_seen >= $1 \
+             GROUP BY peer_id HAVING count(DISTINCT user_id) > 1) \
+             ORDER BY value, user_name",
+        ).bind::<sql_types::Timestamptz, _>(since)
+            .load::<DuplicateRow>(db)?;
+
+        Ok(Duplicates {
+            ips: group_rows(ips),
+            passkeys: group_rows(passkeys),
+            passkey_spread,
+            clients: group_rows(clients),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(value: &str, user_name: &str) -> DuplicateRow {
+        DuplicateRow {
+            value: value.to_string(),
+            user_id: Uuid::new_v4(),
+            user_name: user_name.to_string(),
+            detail: String::from(SOURCE_LOGIN),
+            first_seen: Utc::now(),
+            last_seen: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn group_duplicates() {
+        let rows = vec![row("10.0.0.1", "alice"), row("10.0.0.1", "bob"), row("10.0.0.2", "carol"), row("10.0.0.2", "dave")];
+        let groups = group_rows(rows);
+        assert_eq!(2, groups.len());
+        assert_eq!("10.0.0.1", groups[0].value);
+        assert_eq!(vec!["alice", "bob"], groups[0].users.iter().map(|u| &u.user_name[..]).collect::<Vec<&str>>());
+        assert_eq!("10.0.0.2", groups[1].value);
+        assert_eq!(2, groups[1].users.len());
+        assert!(group_rows(Vec::new()).is_empty());
+    }
+}
@@ -21,9 +21,16 @@
 use super::*;
 use schema::{message_folders, messages};
 use diesel::pg::Pg;
+use diesel::sql_types;
+
+/// Folders every user has, they can not be renamed or deleted
+pub const SYSTEM_FOLDERS: &[&str] = &["inbox", "sent", "system"];
 
 /// A message folder
-#[derive(Identifiable, Queryable, Insertable, Associations, Serialize)]
+///
+/// `purge` is the number of days after which messages in the folder are deleted, `0` keeps them
+/// forever.
+#[derive(Identifiable, Queryable, Insertable, AsChangeset, Associations, Serialize)]
 #[belongs_to(User)]
 pub struct MessageFolder {
     pub id: Uuid,
@@ -46,6 +53,52 @@ impl MessageFolder {
             .first::<Self>(db)
             .ok()
     }
+
+    /// Fetch all folders of a user.
+    ///
+    /// The system folders come first, followed by the custom folders ordered by name.
+    pub fn fetch_for_user(user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        let mut folders = message_folders::table
+            .filter(message_folders::dsl::user_id.eq(user_id))
+            .order_by(message_folders::dsl::name)
+            .load::<Self>(db)
+            .unwrap_or_default();
+        folders.sort_by_key(|f| SYSTEM_FOLDERS.iter().position(|n| *n == f.name).unwrap_or_else(|| SYSTEM_FOLDERS.len()));
+        folders
+    }
+
+    /// Check if this is one of the [SYSTEM_FOLDERS](constant.SYSTEM_FOLDERS.html)
+    pub fn is_system(&self) -> bool {
+        SYSTEM_FOLDERS.contains(&&self.name[..])
+    }
+
+    /// Check if `name` is a valid name for a custom folder
+    pub fn is_valid_name(name: &str) -> bool {
+        let len = name.chars().count();
+        len > 0
+            && len <= 100
+            && name.trim() == name
+            && !name.contains('/')
+            && !SYSTEM_FOLDERS.contains(&name)
+    }
+
+    /// Save the changed folder into the database.
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        diesel::update(self)
+            .set(self)
+            .execute(db)
+            .map_err(|e| format!("failed to update message folder: {}", e).into())
+    }
+
+    /// Delete the folder and all messages in it.
+    pub fn delete(&self, db: &PgConnection) -> Result<usize> {
+        if self.is_system() {
+            bail!("system folders can not be deleted");
+        }
+        diesel::delete(self)
+            .execute(db)
+            .map_err(|e| format!("failed to delete message folder: {}", e).into())
+    }
 }
 
 /// A new message folder
@@ -75,10 +128,18 @@ impl<'a> NewMessageFolder<'a> {
             .execute(db)
             .map_err(|e| format!("failed to insert message folder: {}", e).into())
     }
+
+    /// Save the message folder into the database and return it.
+    pub fn create(&self, db: &PgConnection) -> Result<MessageFolder> {
+        self.insert_into(message_folders::table)
+            .get_result::<MessageFolder>(db)
+            .map_err(|e| format!("failed to insert message folder: {}", e).into())
+    }
 }
 
 /// A message
-#[derive(Identifiable, Queryable, Insertable, Associations, Serialize, Clone)]
+#[derive(Identifiable, Queryable, QueryableByName, Insertable, Associations, Serialize, Clone)]
+#[table_name = "messages"]
 #[belongs_to(MessageFolder, foreign_key = "folder_id")]
 #[belongs_to(User, foreign_key = "receiver_id")]
 pub struct Message {
@@ -90,6 +151,10 @@ pub struct Message {
     pub body: String,
     pub is_read: bool,
     pub created_at: Timestamp,
+    /// ID of the first message of the conversation, the message itself if it is not a reply
+    pub thread_id: Uuid,
+    /// ID of the message this message is a reply to
+    pub parent_id: Option<Uuid>,
 }
 
 impl Message {
@@ -113,6 +178,21 @@ impl Message {
             .unwrap_or_default()
     }
 
+    /// Fetch all messages of a conversation, which are in the folders of a user.
+    ///
+    /// The oldest messages come first, so both received messages and the copies of the sent
+    /// messages are shown in order.
+    pub fn fetch_thread(thread_id: &Uuid, user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        messages::table
+            .inner_join(message_folders::table)
+            .select(messages::all_columns)
+            .filter(messages::thread_id.eq(thread_id))
+            .filter(message_folders::user_id.eq(user_id))
+            .order_by(messages::created_at)
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
     fn by_folder(folder_id: &Uuid) -> messages::BoxedQuery<Pg> {
         messages::table.into_boxed()
             .filter(messages::folder_id.eq(folder_id))
@@ -134,6 +214,67 @@ impl Message {
             .map_err(|e| format!("failed to copy message: {}", e).into())
     }
 
+    /// Search all messages of a user.
+    ///
+    /// Uses the PostgreSQL full-text search on subject and body, the newest messages come first.
+    pub fn search(user_id: &Uuid, query: &str, limit: i64, db: &PgConnection) -> Result<Vec<Self>> {
+        diesel::sql_query(
+            "SELECT m.* FROM messages m INNER JOIN message_folders f ON f.id = m.folder_id \
+             WHERE f.user_id = $1 \
+             AND to_tsvector('simple', m.subject || ' ' || m.body) @@ plainto_tsquery('simple', $2) \
+             ORDER BY m.created_at DESC LIMIT $3",
+        ).bind::<sql_types::Uuid, _>(user_id)
+            .bind::<sql_types::Text, _>(query)
+            .bind::<sql_types::BigInt, _>(limit)
+            .load::<Self>(db)
+            .map_err(|e| format!("failed to search messages: {}", e).into())
+    }
+
+    /// Delete all messages, which are older than the `purge` days of their folder.
+    pub fn purge(db: &PgConnection) -> Result<usize> {
+        diesel::sql_query(
+            "DELETE FROM messages m USING message_folders f \
+             WHERE f.id = m.folder_id AND f.purge > 0 \
+             AND m.created_at < now() - f.purge * interval '1 day'",
+        ).execute(db)
+            .map_err(|e| format!("failed to purge messages: {}", e).into())
+    }
+
+    /// Send a system message to many users at once.
+    ///
+    /// The message is put into the `system` folder of every receiver, which has one. System
+    /// messages have no sender and are not subject to the `accept_messages` setting.
+    pub fn send_system(receivers: &[Uuid], subject: &str, body: &str, db: &PgConnection) -> Result<usize> {
+        let folders = message_folders::table
+            .select((message_folders::id, message_folders::user_id))
+            .filter(message_folders::name.eq("system"))
+            .filter(message_folders::user_id.eq_any(receivers))
+            .load::<(Uuid, Uuid)>(db)
+            .map_err(|e| -> Error { format!("failed to load system folders: {}", e).into() })?;
+        if folders.is_empty() {
+            return Ok(0);
+        }
+
+        let new_messages: Vec<NewMessage> = folders
+            .iter()
+            .map(|&(ref folder_id, ref user_id)| NewMessage::system(folder_id, user_id, subject, body))
+            .collect();
+        diesel::insert_into(messages::table)
+            .values(&new_messages)
+            .execute(db)
+            .map_err(|e| format!("failed to insert system messages: {}", e).into())
+    }
+
+    /// Move this message to another folder.
+    pub fn move_to_folder(&mut self, folder_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        let res = diesel::update(&*self)
+            .set(messages::folder_id.eq(folder_id))
+            .execute(db)
+            .map_err(|e| -> Error { format!("failed to move message: {}", e).into() })?;
+        self.folder_id = *folder_id;
+        Ok(res)
+    }
+
     /// Delete this message
     pub fn delete(&self, db: &PgConnection) -> Result<usize> {
         diesel::delete(messages::table)
@@ -144,12 +285,7 @@ impl Message {
 
     /// Get the owning user
     pub fn owner(&self, folder: &MessageFolder) -> Uuid {
-        match &folder.name[..] {
-            "inbox" => self.receiver_id,
-            "sent" => self.sender_id.unwrap(),
-            "system" => self.receiver_id,
-            _ => unreachable!(),
-        }
+        folder.user_id
     }
 
     /// Mark this message as read
@@ -182,6 +318,75 @@ impl Message {
     }
 }
 
+impl<'a> From<NewMessage<'a>> for Message {
+    fn from(msg: NewMessage<'a>) -> Self {
+        Message {
+            id: msg.id,
+            folder_id: *msg.folder_id,
+            sender_id: msg.sender_id.cloned(),
+            receiver_id: *msg.receiver_id,
+            subject: msg.subject.to_string(),
+            body: msg.body.to_string(),
+            is_read: false,
+            created_at: Utc::now(),
+            thread_id: msg.thread_id,
+            parent_id: msg.parent_id,
+        }
+    }
+}
+
+/// Who may send messages to a user, stored in the `accept_messages` property
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AcceptMessages {
+    /// everyone, except blocked users
+    All,
+    /// only users in the friends list
+    Friends,
+    /// only the team
+    Team,
+}
+
+impl<'a> From<&'a str> for AcceptMessages {
+    fn from(s: &'a str) -> Self {
+        match s {
+            "friends" => AcceptMessages::Friends,
+            "team" => AcceptMessages::Team,
+            _ => AcceptMessages::All,
+        }
+    }
+}
+
+/// How a new message is handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delivery {
+    /// put the message into the inbox of the receiver
+    Deliver,
+    /// silently drop the message, the sender is blocked
+    Drop,
+    /// reject the message, the receiver does not accept messages from the sender
+    Reject,
+}
+
+impl Delivery {
+    /// Decide how a message is handled
+    ///
+    /// Messages from the team always get through, even if the sender is blocked. System messages
+    /// have no sender and are not subject to these rules.
+    pub fn decide(accept: AcceptMessages, from_team: bool, is_friend: bool, is_blocked: bool) -> Self {
+        if from_team {
+            return Delivery::Deliver;
+        }
+        if is_blocked {
+            return Delivery::Drop;
+        }
+        match accept {
+            AcceptMessages::All => Delivery::Deliver,
+            AcceptMessages::Friends if is_friend => Delivery::Deliver,
+            _ => Delivery::Reject,
+        }
+    }
+}
+
 /// A new message
 #[derive(Identifiable, Insertable, Clone)]
 #[table_name = "messages"]
@@ -192,6 +397,8 @@ pub struct NewMessage<'a> {
     receiver_id: &'a Uuid,
     subject: &'a str,
     body: &'a str,
+    thread_id: Uuid,
+    parent_id: Option<Uuid>,
 }
 
 impl<'a> NewMessage<'a> {
@@ -203,16 +410,45 @@ impl<'a> NewMessage<'a> {
         subject: &'a str,
         body: &'a str,
     ) -> Self {
+        let id = Uuid::new_v4();
         NewMessage {
-            id: Uuid::new_v4(),
+            id,
             folder_id,
             sender_id: Some(sender_id),
             receiver_id,
             subject,
             body,
+            thread_id: id,
+            parent_id: None,
+        }
+    }
+
+    /// Construct a new system message, which has no sender.
+    pub fn system(folder_id: &'a Uuid, receiver_id: &'a Uuid, subject: &'a str, body: &'a str) -> Self {
+        let id = Uuid::new_v4();
+        NewMessage {
+            id,
+            folder_id,
+            sender_id: None,
+            receiver_id,
+            subject,
+            body,
+            thread_id: id,
+            parent_id: None,
         }
     }
 
+    /// Make the message a reply to `parent`, it joins the conversation of the parent.
+    pub fn set_parent(&mut self, parent: &Message) {
+        self.thread_id = parent.thread_id;
+        self.parent_id = Some(parent.id);
+    }
+
+    /// Set the folder of the message
+    pub fn set_folder(&mut self, folder_id: &'a Uuid) {
+        self.folder_id = folder_id;
+    }
+
     /// Save the message into the database.
     pub fn save(&self, db: &PgConnection) -> Result<Message> {
         self.insert_into(messages::table)
@@ -220,3 +456,59 @@ impl<'a> NewMessage<'a> {
             .map_err(|e| format!("failed to insert message: {}", e).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept_messages() {
+        assert_eq!(AcceptMessages::All, AcceptMessages::from("all"));
+        assert_eq!(AcceptMessages::Friends, AcceptMessages::from("friends"));
+        assert_eq!(AcceptMessages::Team, AcceptMessages::from("team"));
+        assert_eq!(AcceptMessages::All, AcceptMessages::from("unknown"));
+    }
+
+    #[test]
+    fn reply_joins_thread() {
+        let (folder_id, sender_id, receiver_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        let first = Message::from(NewMessage::new(&folder_id, &sender_id, &receiver_id, "Hi", "Hello"));
+        assert_eq!(first.id, first.thread_id);
+        assert_eq!(None, first.parent_id);
+
+        let mut reply = NewMessage::new(&folder_id, &receiver_id, &sender_id, "Re: Hi", "Hello back");
+        reply.set_parent(&first);
+        let reply = Message::from(reply);
+        assert_eq!(first.thread_id, reply.thread_id);
+        assert_eq!(Some(first.id), reply.parent_id);
+
+        let mut second = NewMessage::new(&folder_id, &sender_id, &receiver_id, "Re: Re: Hi", "Bye");
+        second.set_parent(&reply);
+        assert_eq!(first.thread_id, Message::from(second).thread_id);
+    }
+
+    #[test]
+    fn folder_name() {
+        assert!(MessageFolder::is_valid_name("Archive"));
+        assert!(MessageFolder::is_valid_name("Torrents 2018"));
+        assert!(!MessageFolder::is_valid_name(""));
+        assert!(!MessageFolder::is_valid_name(" padded "));
+        assert!(!MessageFolder::is_valid_name("a/b"));
+        assert!(!MessageFolder::is_valid_name("inbox"));
+        assert!(!MessageFolder::is_valid_name(&"x".repeat(101)));
+    }
+
+    #[test]
+    fn delivery() {
+        use self::AcceptMessages::*;
+
+        assert_eq!(Delivery::Deliver, Delivery::decide(All, false, false, false));
+        assert_eq!(Delivery::Drop, Delivery::decide(All, false, true, true));
+        assert_eq!(Delivery::Reject, Delivery::decide(Friends, false, false, false));
+        assert_eq!(Delivery::Deliver, Delivery::decide(Friends, false, true, false));
+        assert_eq!(Delivery::Drop, Delivery::decide(Friends, false, false, true));
+        assert_eq!(Delivery::Reject, Delivery::decide(Team, false, true, false));
+        assert_eq!(Delivery::Deliver, Delivery::decide(Team, true, false, false));
+        assert_eq!(Delivery::Deliver, Delivery::decide(All, true, false, true));
+    }
+}
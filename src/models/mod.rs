@@ -22,6 +22,8 @@ use super::*;
 
 use diesel;
 use diesel::pg::upsert::on_constraint;
+use ipnetwork::IpNetwork;
+use serde::Serializer;
 
 use SETTINGS;
 
@@ -33,6 +35,23 @@ pub type Timestamp = DateTime<Utc>;
 /// Convenient wrapper around `Vec<u8>`
 pub type Bytes = Vec<u8>;
 
+/// Seconds between two writes of a usage timestamp (`last_seen`, `last_used_at`), so frequent
+/// requests don't update the same row over and over
+pub const TOUCH_INTERVAL: i64 = 60;
+
+/// Serializes a stored address without the network prefix, for `#[serde(serialize_with)]`
+pub fn serialize_ip<S: Serializer>(ip_address: &IpNetwork, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(&ip_address.ip())
+}
+
+/// Same as `serialize_ip`, for addresses which may be unknown
+pub fn serialize_optional_ip<S: Serializer>(ip_address: &Option<IpNetwork>, serializer: S) -> ::std::result::Result<S::Ok, S::Error> {
+    match ip_address {
+        Some(ip_address) => serialize_ip(ip_address, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
 pub use self::category::Category;
 pub use self::group::Group;
 pub use self::message::{Message, MessageFolder};
@@ -42,11 +61,21 @@ pub use self::torrent::{Torrent, TorrentFile, TorrentImage, TorrentList, Torrent
 pub use self::user::{HasUser, MaybeHasUser, Property, User, username};
 
 pub mod acl;
+pub mod api_token;
+pub mod audit;
+pub mod ban_list;
+pub mod bonus;
 pub mod category;
 pub mod chat;
 pub mod group;
+pub mod history;
 pub mod message;
+pub mod moderation;
+pub mod notification;
 pub mod peer;
+pub mod relation;
+pub mod session;
+pub mod request;
 pub mod static_content;
 pub mod torrent;
 pub mod user;
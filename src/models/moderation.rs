@@ -0,0 +1,310 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Warnings and bans
+//!
+//! [**UserWarning**](struct.UserWarning.html) is a warning issued by a moderator. Active warnings
+//! reduce the privileges of the user, warnings with a severity of at least
+//! `warning.block_downloads_severity` block downloads.
+//!
+//! [**UserBan**](struct.UserBan.html) bans a user permanently or until the expiry. A banned user
+//! has the status `STATUS_BANNED`, expired bans are lifted automatically.
+
+use super::*;
+
+use models::user::{STATUS_ACTIVE, STATUS_BANNED};
+use schema::{user_bans, user_warnings, users};
+use schema::user_bans::dsl as ub;
+use schema::user_warnings::dsl as uw;
+
+/// Highest severity of a warning
+pub const MAX_SEVERITY: i16 = 3;
+
+/// A warning of a user
+///
+/// Each instance represents a row in the database.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "user_warnings"]
+pub struct UserWarning {
+    /// the unique warning id
+    pub id: Uuid,
+    /// the warned user
+    pub user_id: Uuid,
+    /// the moderator who warned the user
+    pub issuer_id: Uuid,
+    /// the reason shown to the warned user
+    pub reason: String,
+    /// from `1` to [`MAX_SEVERITY`](constant.MAX_SEVERITY.html)
+    pub severity: i16,
+    /// timestamp when the warning ends
+    pub expires_at: Timestamp,
+    /// timestamp when a moderator revoked the warning
+    pub revoked_at: Option<Timestamp>,
+    /// timestamp when the user was warned
+    pub created_at: Timestamp,
+}
+
+impl UserWarning {
+    /// Constructs a new `UserWarning` instance.
+    pub fn new(user_id: Uuid, issuer_id: Uuid, reason: String, severity: i16, expires_at: Timestamp) -> Self {
+        UserWarning {
+            id: Uuid::new_v4(),
+            user_id,
+            issuer_id,
+            reason,
+            severity,
+            expires_at,
+            revoked_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Find a warning by its id
+    pub fn find(id: &Uuid, db: &PgConnection) -> Option<Self> {
+        user_warnings::table.find(id).first::<Self>(db).ok()
+    }
+
+    /// Fetch all warnings of the user, the latest first
+    pub fn for_user(user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        user_warnings::table
+            .filter(uw::user_id.eq(user_id))
+            .order_by(uw::created_at.desc())
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// The highest severity of the active warnings of the user
+    pub fn active_severity(user_id: &Uuid, db: &PgConnection) -> Option<i16> {
+        user_warnings::table
+            .select(diesel::dsl::max(uw::severity))
+            .filter(uw::user_id.eq(user_id))
+            .filter(uw::revoked_at.is_null())
+            .filter(uw::expires_at.gt(Utc::now()))
+            .first::<Option<i16>>(db)
+            .unwrap_or(None)
+    }
+
+    /// Check if the active warnings of the user block downloads
+    pub fn blocks_downloads(user_id: &Uuid, db: &PgConnection) -> bool {
+        let severity = SETTINGS.read().unwrap().warning.block_downloads_severity;
+        severity > 0 && UserWarning::active_severity(user_id, db).map(|s| s >= severity).unwrap_or(false)
+    }
+
+    /// Check if the warning is neither revoked nor expired
+    pub fn is_active(&self, now: &Timestamp) -> bool {
+        self.revoked_at.is_none() && self.expires_at > *now
+    }
+
+    /// Save the warning into the database
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        self.insert_into(user_warnings::table)
+            .execute(db)
+            .map_err(|e| format!("failed to save warning: {}", e).into())
+    }
+
+    /// Revoke an active warning
+    pub fn revoke(id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::update(user_warnings::table.find(id))
+            .filter(uw::revoked_at.is_null())
+            .set(uw::revoked_at.eq(Utc::now()))
+            .execute(db)
+            .map_err(|e| format!("failed to revoke warning: {}", e).into())
+    }
+}
+
+/// A warning with the name of the issuer
+#[derive(Debug, Serialize)]
+pub struct UserWarningResponse {
+    #[serde(flatten)]
+    pub warning: UserWarning,
+    pub issuer_name: Option<String>,
+    pub active: bool,
+}
+
+impl UserWarningResponse {
+    pub fn new(warning: UserWarning, db: &PgConnection) -> Self {
+        let issuer_name = username(&warning.issuer_id, db);
+        let active = warning.is_active(&Utc::now());
+        Self { warning, issuer_name, active }
+    }
+}
+
+/// A ban of a user
+///
+/// Each instance represents a row in the database.
+#[derive(Clone, Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "user_bans"]
+pub struct UserBan {
+    /// the unique ban id
+    pub id: Uuid,
+    /// the banned user
+    pub user_id: Uuid,
+    /// the moderator who banned the user
+    pub issuer_id: Uuid,
+    /// the reason shown to the banned user
+    pub reason: String,
+    /// timestamp when the ban ends, `None` for a permanent ban
+    pub expires_at: Option<Timestamp>,
+    /// timestamp when the ban was lifted
+    pub lifted_at: Option<Timestamp>,
+    /// timestamp when the user was banned
+    pub created_at: Timestamp,
+}
+
+impl UserBan {
+    /// Constructs a new `UserBan` instance.
+    pub fn new(user_id: Uuid, issuer_id: Uuid, reason: String, expires_at: Option<Timestamp>) -> Self {
+        UserBan {
+            id: Uuid::new_v4(),
+            user_id,
+            issuer_id,
+            reason,
+            expires_at,
+            lifted_at: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    /// Find the ban of the user, which is not lifted yet
+    pub fn find_active(user_id: &Uuid, db: &PgConnection) -> Option<Self> {
+        user_bans::table
+            .filter(ub::user_id.eq(user_id))
+            .filter(ub::lifted_at.is_null())
+            .order_by(ub::created_at.desc())
+            .first::<Self>(db)
+            .ok()
+    }
+
+    /// Check if the ban has ended
+    pub fn is_expired(&self, now: &Timestamp) -> bool {
+        match self.expires_at {
+            Some(ref expires_at) => expires_at <= now,
+            None => false,
+        }
+    }
+
+    /// The message shown to the banned user
+    pub fn describe(&self) -> String {
+        let until = match self.expires_at {
+            Some(ref expires_at) => format!("banned until {}", expires_at.format("%Y-%m-%d %H:%M UTC")),
+            None => String::from("banned permanently"),
+        };
+        if self.reason.is_empty() {
+            until
+        } else {
+            format!("{}: {}", until, self.reason)
+        }
+    }
+
+    /// Save the ban into the database and set the status of the user to banned
+    ///
+    /// A ban, which is not lifted yet, is replaced.
+    pub fn save(&self, db: &PgConnection) -> Result<()> {
+        db.transaction::<_, Error, _>(|| {
+            diesel::update(user_bans::table)
+                .filter(ub::user_id.eq(&self.user_id))
+                .filter(ub::lifted_at.is_null())
+                .set(ub::lifted_at.eq(Utc::now()))
+                .execute(db)?;
+            self.insert_into(user_bans::table).execute(db)?;
+            diesel::update(users::table.find(&self.user_id))
+                .set(users::dsl::status.eq(STATUS_BANNED))
+                .execute(db)?;
+            Ok(())
+        }).map_err(|e| format!("failed to save ban: {}", e).into())
+    }
+
+    /// Lift the ban of the user and activate the user again
+    pub fn lift(user_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        db.transaction::<_, Error, _>(|| {
+            let num = diesel::update(user_bans::table)
+                .filter(ub::user_id.eq(user_id))
+                .filter(ub::lifted_at.is_null())
+                .set(ub::lifted_at.eq(Utc::now()))
+                .execute(db)?;
+            diesel::update(users::table.find(user_id))
+                .filter(users::dsl::status.eq(STATUS_BANNED))
+                .set(users::dsl::status.eq(STATUS_ACTIVE))
+                .execute(db)?;
+            Ok(num)
+        }).map_err(|e| format!("failed to lift ban: {}", e).into())
+    }
+
+    /// Lift all expired bans
+    pub fn lift_expired(db: &PgConnection) -> Result<usize> {
+        let user_ids = user_bans::table
+            .select(ub::user_id)
+            .filter(ub::lifted_at.is_null())
+            .filter(ub::expires_at.le(Utc::now()))
+            .load::<Uuid>(db)?;
+
+        let mut num = 0;
+        for user_id in &user_ids {
+            num += UserBan::lift(user_id, db)?;
+        }
+        Ok(num)
+    }
+}
+
+/// Check if the user may log in and use the tracker
+///
+/// An expired ban is lifted. Banned users get the reason and the expiry of the ban as error.
+pub fn check_status(user: &User, db: &PgConnection) -> Result<()> {
+    match user.status {
+        STATUS_ACTIVE => Ok(()),
+        STATUS_BANNED => match UserBan::find_active(&user.id, db) {
+            Some(ref ban) if ban.is_expired(&Utc::now()) => {
+                UserBan::lift(&user.id, db)?;
+                Ok(())
+            }
+            Some(ban) => bail!(ban.describe()),
+            None => bail!("banned"),
+        },
+        _ => bail!("user not active"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    #[test]
+    fn warning_is_active() {
+        let now = Utc::now();
+        let mut warning = UserWarning::new(Uuid::new_v4(), Uuid::new_v4(), String::new(), 1, now + Duration::days(1));
+        assert!(warning.is_active(&now));
+        assert!(!warning.is_active(&(now + Duration::days(2))));
+        warning.revoked_at = Some(now);
+        assert!(!warning.is_active(&now));
+    }
+
+    #[test]
+    fn ban_describe() {
+        let expires_at = Utc.ymd(2018, 6, 10).and_hms(12, 30, 0);
+        let mut ban = UserBan::new(Uuid::new_v4(), Uuid::new_v4(), String::from("cheating"), Some(expires_at));
+        assert_eq!("banned until 2018-06-10 12:30 UTC: cheating", ban.describe());
+        assert!(ban.is_expired(&(expires_at + Duration::seconds(1))));
+        assert!(!ban.is_expired(&(expires_at - Duration::seconds(1))));
+
+        ban.expires_at = None;
+        ban.reason = String::new();
+        assert_eq!("banned permanently", ban.describe());
+        assert!(!ban.is_expired(&Utc::now()));
+    }
+}
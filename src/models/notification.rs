@@ -0,0 +1,425 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Notifications
+//!
+//! Notifications are kept apart from the messages. Every user chooses per kind, if a notification
+//! is shown on the site or sent as system message. The choice is stored in the `notify_<kind>`
+//! property.
+
+use super::*;
+
+use std::collections::HashMap;
+
+use regex::Regex;
+use serde_json::Value;
+
+use models::message::Message;
+use models::user::Property;
+use schema::{notifications, user_properties};
+
+lazy_static! {
+    static ref QUOTED_USER: Regex = Regex::new(r"(?m)^>\s*@(?P<user>[a-zA-Z][a-zA-Z0-9_\-]+)").unwrap();
+}
+
+/// Maximum number of users notified about a quote
+pub const MAX_QUOTED_USERS: usize = 10;
+/// Maximum length of the title, longer titles are truncated
+pub const MAX_TITLE_LENGTH: usize = 255;
+
+/// The kind of a notification
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NotificationKind {
+    /// a new private message
+    Message,
+    /// a comment on an own upload
+    Comment,
+    /// a comment quoting the user
+    Quote,
+    /// a deleted torrent, which the user uploaded or snatched
+    TorrentDeleted,
+    /// an action of the staff, e.g. a deleted comment or a mute
+    Staff,
+    /// a filled, paid out or deleted request
+    Request,
+    /// a reseed request for a torrent, which the user uploaded or snatched
+    Reseed,
+    /// a security event of the account, e.g. a lockout after failed logins
+    Security,
+}
+
+impl NotificationKind {
+    /// All kinds, in the order they are shown in the settings
+    pub const ALL: &'static [NotificationKind] = &[
+        NotificationKind::Message,
+        NotificationKind::Comment,
+        NotificationKind::Quote,
+        NotificationKind::TorrentDeleted,
+        NotificationKind::Staff,
+        NotificationKind::Request,
+        NotificationKind::Reseed,
+        NotificationKind::Security,
+    ];
+
+    /// The name stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            NotificationKind::Message => "message",
+            NotificationKind::Comment => "comment",
+            NotificationKind::Quote => "quote",
+            NotificationKind::TorrentDeleted => "torrent_deleted",
+            NotificationKind::Staff => "staff",
+            NotificationKind::Request => "request",
+            NotificationKind::Reseed => "reseed",
+            NotificationKind::Security => "security",
+        }
+    }
+
+    /// The description shown in the settings
+    pub fn label(&self) -> &'static str {
+        match *self {
+            NotificationKind::Message => "New messages",
+            NotificationKind::Comment => "Comments on your uploads",
+            NotificationKind::Quote => "Comments quoting you",
+            NotificationKind::TorrentDeleted => "Deleted torrents you uploaded or snatched",
+            NotificationKind::Staff => "Staff actions",
+            NotificationKind::Request => "Requests",
+            NotificationKind::Reseed => "Reseed requests",
+            NotificationKind::Security => "Security events of your account",
+        }
+    }
+
+    /// The name of the user property, which holds the delivery
+    pub fn property_name(&self) -> String {
+        format!("notify_{}", self.as_str())
+    }
+
+    /// Check if the kind can be delivered this way
+    ///
+    /// A new private message is never announced with another private message.
+    pub fn allows(&self, delivery: NotificationDelivery) -> bool {
+        !(*self == NotificationKind::Message && delivery == NotificationDelivery::Pm)
+    }
+}
+
+/// How a notification is delivered
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NotificationDelivery {
+    /// shown in the notification center
+    Site,
+    /// sent as system message
+    Pm,
+}
+
+impl NotificationDelivery {
+    /// All deliveries, in the order they are shown in the settings
+    pub const ALL: &'static [NotificationDelivery] = &[NotificationDelivery::Site, NotificationDelivery::Pm];
+
+    /// The description shown in the settings
+    pub fn label(&self) -> &'static str {
+        match *self {
+            NotificationDelivery::Site => "Site",
+            NotificationDelivery::Pm => "System message",
+        }
+    }
+
+    /// The value stored in the user property
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            NotificationDelivery::Site => "site",
+            NotificationDelivery::Pm => "pm",
+        }
+    }
+}
+
+impl<'a> From<&'a str> for NotificationDelivery {
+    fn from(s: &'a str) -> Self {
+        match s {
+            "pm" => NotificationDelivery::Pm,
+            _ => NotificationDelivery::Site,
+        }
+    }
+}
+
+/// A delivery, which can be chosen for a kind
+#[derive(Debug, Serialize)]
+pub struct DeliveryOption {
+    pub value: &'static str,
+    pub label: &'static str,
+}
+
+/// A notification kind with the delivery chosen by the user, rendered in the settings
+#[derive(Debug, Serialize)]
+pub struct NotificationSetting {
+    /// the name of the user property and the form field
+    pub property: String,
+    pub label: &'static str,
+    pub delivery: &'static str,
+    pub options: Vec<DeliveryOption>,
+}
+
+impl NotificationSetting {
+    /// The settings of all kinds, deliveries which were not chosen default to the site
+    pub fn for_properties(properties: &HashMap<String, Property>) -> Vec<Self> {
+        NotificationKind::ALL
+            .iter()
+            .map(|kind| {
+                let property = kind.property_name();
+                let delivery = properties
+                    .get(&property)
+                    .and_then(|p| p.value().as_str())
+                    .map(NotificationDelivery::from)
+                    .and_then(|delivery| if kind.allows(delivery) { Some(delivery) } else { None })
+                    .unwrap_or(NotificationDelivery::Site);
+                NotificationSetting {
+                    property,
+                    label: kind.label(),
+                    delivery: delivery.as_str(),
+                    options: NotificationDelivery::ALL
+                        .iter()
+                        .filter(|d| kind.allows(**d))
+                        .map(|d| DeliveryOption { value: d.as_str(), label: d.label() })
+                        .collect(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// A notification
+#[derive(Debug, Queryable, Identifiable, Serialize)]
+pub struct Notification {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    pub kind: String,
+    pub title: String,
+    pub body: String,
+    pub link: Option<String>,
+    pub is_read: bool,
+    pub created_at: Timestamp,
+}
+
+impl Notification {
+    /// Send a notification to users
+    ///
+    /// The notification is delivered the way every receiver has chosen for the kind.
+    pub fn send(
+        receivers: &[Uuid],
+        kind: NotificationKind,
+        title: &str,
+        body: &str,
+        link: Option<&str>,
+        db: &PgConnection,
+    ) -> Result<usize> {
+        if receivers.is_empty() {
+            return Ok(0);
+        }
+
+        let title = truncate_title(title);
+        let deliveries = Self::deliveries(receivers, kind, db);
+        let mut site = Vec::new();
+        let mut pm = Vec::new();
+        for receiver in receivers {
+            match deliveries.get(receiver).cloned().unwrap_or(NotificationDelivery::Site) {
+                NotificationDelivery::Pm => pm.push(*receiver),
+                NotificationDelivery::Site => site.push(*receiver),
+            }
+        }
+
+        let mut num = 0;
+        if !site.is_empty() {
+            let new_notifications: Vec<NewNotification> = site
+                .iter()
+                .map(|user_id| NewNotification::new(user_id, kind, title, body, link))
+                .collect();
+            num += diesel::insert_into(notifications::table)
+                .values(&new_notifications)
+                .execute(db)
+                .map_err(|e| -> Error { format!("failed to insert notifications: {}", e).into() })?;
+        }
+        if !pm.is_empty() {
+            let body = match link {
+                Some(link) => format!("{}\n\n[{}]({})", body, link, link),
+                None => body.to_string(),
+            };
+            num += Message::send_system(&pm, title, &body, db)?;
+        }
+
+        Ok(num)
+    }
+
+    /// Fetch the chosen deliveries of the users for a kind
+    ///
+    /// Users, who did not choose a delivery, are missing in the map.
+    fn deliveries(user_ids: &[Uuid], kind: NotificationKind, db: &PgConnection) -> HashMap<Uuid, NotificationDelivery> {
+        use schema::user_properties::dsl as p;
+
+        user_properties::table
+            .select((p::user_id, p::value))
+            .filter(p::name.eq(kind.property_name()))
+            .filter(p::user_id.eq_any(user_ids))
+            .load::<(Uuid, Value)>(db)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(user_id, value)| {
+                let delivery = NotificationDelivery::from(value.as_str()?);
+                if kind.allows(delivery) {
+                    Some((user_id, delivery))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Fetch the latest notifications of a user
+    pub fn fetch_for_user(user_id: &Uuid, only_unread: bool, limit: i64, db: &PgConnection) -> Vec<Self> {
+        use schema::notifications::dsl as n;
+
+        let mut query = notifications::table.into_boxed().filter(n::user_id.eq(user_id));
+        if only_unread {
+            query = query.filter(n::is_read.eq(false));
+        }
+        query
+            .order_by(n::created_at.desc())
+            .limit(limit)
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Count the unread notifications of a user
+    pub fn unread_count(user_id: &Uuid, db: &PgConnection) -> i64 {
+        use schema::notifications::dsl as n;
+
+        notifications::table
+            .filter(n::user_id.eq(user_id))
+            .filter(n::is_read.eq(false))
+            .count()
+            .get_result(db)
+            .unwrap_or(0)
+    }
+
+    /// Mark notifications of a user as read, all of them if `ids` is empty
+    pub fn mark_read(ids: &[Uuid], user_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        use schema::notifications::dsl as n;
+
+        let res = if ids.is_empty() {
+            diesel::update(notifications::table)
+                .filter(n::user_id.eq(user_id))
+                .filter(n::is_read.eq(false))
+                .set(n::is_read.eq(true))
+                .execute(db)
+        } else {
+            diesel::update(notifications::table)
+                .filter(n::user_id.eq(user_id))
+                .filter(n::id.eq_any(ids))
+                .set(n::is_read.eq(true))
+                .execute(db)
+        };
+        res.map_err(|e| format!("failed to update notifications: {}", e).into())
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "notifications"]
+struct NewNotification<'a> {
+    id: Uuid,
+    user_id: &'a Uuid,
+    kind: &'static str,
+    title: &'a str,
+    body: &'a str,
+    link: Option<&'a str>,
+}
+
+impl<'a> NewNotification<'a> {
+    fn new(user_id: &'a Uuid, kind: NotificationKind, title: &'a str, body: &'a str, link: Option<&'a str>) -> Self {
+        NewNotification {
+            id: Uuid::new_v4(),
+            user_id,
+            kind: kind.as_str(),
+            title,
+            body,
+            link,
+        }
+    }
+}
+
+/// Truncate a title to `MAX_TITLE_LENGTH` characters
+///
+/// Titles are built from torrent and request names, which may be longer than the column.
+fn truncate_title(title: &str) -> &str {
+    match title.char_indices().nth(MAX_TITLE_LENGTH) {
+        Some((end, _)) => &title[..end],
+        None => title,
+    }
+}
+
+/// Get the names of the users quoted in a text
+///
+/// A quote is a line starting with `> @username`, as produced by the quote buttons.
+pub fn quoted_users(text: &str) -> Vec<String> {
+    let mut users: Vec<String> = Vec::new();
+    for quote in QUOTED_USER.captures_iter(text) {
+        let user = quote["user"].to_string();
+        if !users.contains(&user) && users.len() < MAX_QUOTED_USERS {
+            users.push(user);
+        }
+    }
+    users
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn title_length() {
+        assert_eq!("short", truncate_title("short"));
+        let title = "ä".repeat(300);
+        assert_eq!(MAX_TITLE_LENGTH, truncate_title(&title).chars().count());
+    }
+
+    #[test]
+    fn delivery() {
+        assert_eq!(NotificationDelivery::Pm, NotificationDelivery::from("pm"));
+        assert_eq!(NotificationDelivery::Site, NotificationDelivery::from("site"));
+        assert_eq!(NotificationDelivery::Site, NotificationDelivery::from("unknown"));
+        assert!(!NotificationKind::Message.allows(NotificationDelivery::Pm));
+        assert!(NotificationKind::Comment.allows(NotificationDelivery::Pm));
+        assert_eq!("notify_torrent_deleted", NotificationKind::TorrentDeleted.property_name());
+
+        let user_id = Uuid::new_v4();
+        let mut properties = HashMap::new();
+        properties.insert(String::from("notify_comment"), Property::new(String::from("notify_comment"), "pm", &user_id));
+        properties.insert(String::from("notify_message"), Property::new(String::from("notify_message"), "pm", &user_id));
+        let settings = NotificationSetting::for_properties(&properties);
+        assert_eq!(NotificationKind::ALL.len(), settings.len());
+        assert_eq!("site", settings[0].delivery);
+        assert_eq!(1, settings[0].options.len());
+        assert_eq!("notify_comment", settings[1].property);
+        assert_eq!("pm", settings[1].delivery);
+        assert_eq!(2, settings[1].options.len());
+    }
+
+    #[test]
+    fn quotes() {
+        let text = "> @alice wrote:\n> hello\n\n> @bob wrote:\n>@alice again\nthanks @carol";
+        assert_eq!(vec!["alice".to_string(), "bob".to_string()], quoted_users(text));
+        assert!(quoted_users("no quotes @alice").is_empty());
+    }
+}
@@ -47,6 +47,7 @@ pub struct Peer {
     pub created_at: Timestamp,
     pub finished_at: Option<Timestamp>,
     pub updated_at: Timestamp,
+    pub connectable: Option<bool>,
 }
 
 impl Peer {
@@ -92,6 +93,36 @@ impl Peer {
             .unwrap_or_else(|_| Vec::new())
     }
 
+    /// Returns at most `limit` random candidates for an announce response
+    ///
+    /// Peers of the announcing user are never returned, if `crypto_only` is `true` only peers
+    /// supporting encryption are returned. The candidates are sampled randomly, so all peers of a
+    /// swarm are handed out and not only the latest ones.
+    pub fn candidates_for_announce(
+        torrent_id: &Uuid,
+        seeder: bool,
+        user_id: &Uuid,
+        crypto_only: bool,
+        limit: i64,
+        db: &PgConnection,
+    ) -> Vec<Self> {
+        use schema::peers::dsl;
+        let mut query = dsl::peers
+            .filter(dsl::torrent_id.eq(torrent_id))
+            .filter(dsl::seeder.eq(seeder))
+            .filter(dsl::user_id.ne(user_id))
+            .into_boxed();
+        if crypto_only {
+            query = query.filter(dsl::crypto_enabled.eq(true));
+        }
+
+        query
+            .order(diesel::dsl::sql::<diesel::sql_types::Double>("random()"))
+            .limit(limit)
+            .load::<Self>(db)
+            .unwrap_or_else(|_| Vec::new())
+    }
+
     pub fn find_for_announce(torrent_id: &Uuid, user_id: &Uuid, peer_id: &[u8], db: &PgConnection) -> Option<Self> {
         use schema::peers::dsl;
         dsl::peers
@@ -118,6 +149,16 @@ impl Peer {
             .execute(db)
             .chain_err(|| "peer delete failed")
     }
+
+    /// Store the result of a connectability check
+    pub fn set_connectable(id: &Uuid, connectable: bool, db: &PgConnection) -> Result<usize> {
+        use schema::peers::dsl;
+        diesel::update(peers::table)
+            .set(dsl::connectable.eq(connectable))
+            .filter(dsl::id.eq(id))
+            .execute(db)
+            .chain_err(|| "peer update failed")
+    }
 }
 
 impl HasUser for Peer {
@@ -0,0 +1,124 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Friends and blocked users
+//!
+//! Both lists are one-sided, adding a user as friend or blocking a user does not change the list of
+//! the other user.
+
+use super::*;
+
+use schema::user_blocks;
+use schema::user_blocks::dsl as ub;
+use schema::user_friends;
+use schema::user_friends::dsl as uf;
+use schema::users;
+use schema::users::dsl as u;
+
+/// The kind of a relation between two users
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Relation {
+    /// the other user is in the friends list
+    Friend,
+    /// the other user is blocked
+    Block,
+}
+
+/// A user in the friends or block list
+#[derive(Debug, Queryable, Serialize)]
+pub struct RelatedUser {
+    /// the user id
+    pub id: Uuid,
+    /// the user name
+    pub name: String,
+    /// the group of the user
+    pub group_id: Uuid,
+    /// timestamp when the user was added to the list
+    pub created_at: Timestamp,
+}
+
+impl Relation {
+    /// Add `other_id` to the list of `user_id`
+    pub fn add(self, user_id: &Uuid, other_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        if user_id == other_id {
+            bail!("you can not add yourself");
+        }
+        let res = match self {
+            Relation::Friend => diesel::insert_into(user_friends::table)
+                .values((uf::user_id.eq(user_id), uf::friend_id.eq(other_id)))
+                .on_conflict_do_nothing()
+                .execute(db),
+            Relation::Block => diesel::insert_into(user_blocks::table)
+                .values((ub::user_id.eq(user_id), ub::blocked_id.eq(other_id)))
+                .on_conflict_do_nothing()
+                .execute(db),
+        };
+        res.map_err(|e| format!("failed to add user: {}", e).into())
+    }
+
+    /// Remove `other_id` from the list of `user_id`
+    pub fn remove(self, user_id: &Uuid, other_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        let res = match self {
+            Relation::Friend => diesel::delete(user_friends::table)
+                .filter(uf::user_id.eq(user_id))
+                .filter(uf::friend_id.eq(other_id))
+                .execute(db),
+            Relation::Block => diesel::delete(user_blocks::table)
+                .filter(ub::user_id.eq(user_id))
+                .filter(ub::blocked_id.eq(other_id))
+                .execute(db),
+        };
+        res.map_err(|e| format!("failed to remove user: {}", e).into())
+    }
+
+    /// Check if `other_id` is in the list of `user_id`
+    pub fn exists(self, user_id: &Uuid, other_id: &Uuid, db: &PgConnection) -> bool {
+        let res = match self {
+            Relation::Friend => user_friends::table
+                .filter(uf::user_id.eq(user_id))
+                .filter(uf::friend_id.eq(other_id))
+                .count()
+                .get_result::<i64>(db),
+            Relation::Block => user_blocks::table
+                .filter(ub::user_id.eq(user_id))
+                .filter(ub::blocked_id.eq(other_id))
+                .count()
+                .get_result::<i64>(db),
+        };
+        res.map(|count| count > 0).unwrap_or(false)
+    }
+
+    /// Fetch the list of `user_id`, ordered by name
+    pub fn list(self, user_id: &Uuid, db: &PgConnection) -> Vec<RelatedUser> {
+        let res = match self {
+            Relation::Friend => user_friends::table
+                .inner_join(users::table.on(uf::friend_id.eq(u::id)))
+                .select((u::id, u::name, u::group_id, uf::created_at))
+                .filter(uf::user_id.eq(user_id))
+                .order_by(u::name)
+                .load::<RelatedUser>(db),
+            Relation::Block => user_blocks::table
+                .inner_join(users::table.on(ub::blocked_id.eq(u::id)))
+                .select((u::id, u::name, u::group_id, ub::created_at))
+                .filter(ub::user_id.eq(user_id))
+                .order_by(u::name)
+                .load::<RelatedUser>(db),
+        };
+        res.unwrap_or_default()
+    }
+}
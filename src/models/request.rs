@@ -0,0 +1,617 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Torrent requests
+//!
+//! A request asks for a release, which is not on the tracker yet. Bounties are taken from the
+//! upload credit (`User.uploaded`) of the users, who add them, and are held in escrow until the
+//! request is either paid out to the filler or deleted, which refunds every bounty.
+
+use super::*;
+
+use models::notification::{Notification, NotificationKind};
+use schema::{categories, torrent_request_bounties, torrent_request_votes, torrent_requests, users};
+use schema::torrent_requests::dsl as r;
+use schema::users::dsl as u;
+
+/// A torrent request
+///
+/// Can be used with the [**UserSubject**](../../models/acl/struct.UserSubject.html) object to check the permissions of the user.
+/// The requester may always edit and delete the request, other users need the permissions on the
+/// `request#moderate` namespace.
+#[derive(Debug, Clone, Queryable, Identifiable, Serialize)]
+#[table_name = "torrent_requests"]
+pub struct TorrentRequest {
+    pub id: Uuid,
+    /// the requester
+    pub user_id: Uuid,
+    pub category_id: Uuid,
+    pub title: String,
+    pub description: String,
+    /// sum of all bounties in bytes
+    pub bounty: i64,
+    pub votes: i32,
+    /// the torrent, which fills the request
+    pub torrent_id: Option<Uuid>,
+    /// the user, who filled the request
+    pub filled_by: Option<Uuid>,
+    pub filled_at: Option<Timestamp>,
+    /// timestamp when the bounty was paid out to the filler
+    pub paid_at: Option<Timestamp>,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+}
+
+impl TorrentRequest {
+    /// Find a request by its id
+    pub fn find(id: &Uuid, db: &PgConnection) -> Option<Self> {
+        torrent_requests::table.find(id).first::<Self>(db).ok()
+    }
+
+    /// Update title, category and description
+    pub fn update(&mut self, title: &str, category_id: &Uuid, description: &str, db: &PgConnection) -> Result<()> {
+        let now = Utc::now();
+        diesel::update(torrent_requests::table.find(&self.id))
+            .set((
+                r::title.eq(title),
+                r::category_id.eq(category_id),
+                r::description.eq(description),
+                r::updated_at.eq(now),
+            ))
+            .execute(db)
+            .map_err(|e| -> Error { format!("failed to update request: {}", e).into() })?;
+
+        self.title = title.to_string();
+        self.category_id = *category_id;
+        self.description = description.to_string();
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Add a bounty, which is taken from the upload credit of the user
+    pub fn add_bounty(&mut self, user_id: &Uuid, amount: i64, db: &PgConnection) -> Result<()> {
+        if amount <= 0 {
+            bail!("the bounty must be positive");
+        }
+        if self.filled_by.is_some() {
+            bail!("the request is already filled");
+        }
+
+        let id = self.id;
+        let bounty = db.transaction::<_, Error, _>(|| {
+            escrow(user_id, amount, db)?;
+            diesel::insert_into(torrent_request_bounties::table)
+                .values(&NewRequestBounty::new(&id, user_id, amount))
+                .execute(db)?;
+            diesel::update(torrent_requests::table.find(&id))
+                .filter(r::filled_by.is_null())
+                .set((r::bounty.eq(r::bounty + amount), r::updated_at.eq(Utc::now())))
+                .returning(r::bounty)
+                .get_result::<i64>(db)
+                .optional()?
+                .ok_or_else(|| "the request is already filled".into())
+        })?;
+
+        self.bounty = bounty;
+        Ok(())
+    }
+
+    /// Vote for the request, every user has one vote
+    ///
+    /// # Returns
+    ///
+    /// `false` if the user already voted
+    pub fn vote(&mut self, user_id: &Uuid, db: &PgConnection) -> Result<bool> {
+        use schema::torrent_request_votes::dsl as v;
+
+        let id = self.id;
+        let votes = db.transaction::<_, Error, _>(|| {
+            let num = diesel::insert_into(torrent_request_votes::table)
+                .values((v::request_id.eq(&id), v::user_id.eq(user_id)))
+                .on_conflict_do_nothing()
+                .execute(db)?;
+            if num == 0 {
+                return Ok(None);
+            }
+            diesel::update(torrent_requests::table.find(&id))
+                .set(r::votes.eq(r::votes + 1))
+                .returning(r::votes)
+                .get_result::<i32>(db)
+                .map(Some)
+                .map_err(|e| e.into())
+        })?;
+
+        match votes {
+            Some(votes) => {
+                self.votes = votes;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Check if the user voted for the request
+    pub fn voted(&self, user_id: &Uuid, db: &PgConnection) -> bool {
+        use schema::torrent_request_votes::dsl as v;
+
+        torrent_request_votes::table
+            .filter(v::request_id.eq(&self.id))
+            .filter(v::user_id.eq(user_id))
+            .count()
+            .get_result::<i64>(db)
+            .map(|count| count > 0)
+            .unwrap_or(false)
+    }
+
+    /// Fill the request with a torrent
+    ///
+    /// The torrent must be uploaded by the filler after the request was posted. The requester can
+    /// not fill the own request.
+    pub fn fill(&mut self, torrent: &Torrent, user_id: &Uuid, db: &PgConnection) -> Result<()> {
+        if self.filled_by.is_some() {
+            bail!("the request is already filled");
+        }
+        if self.user_id == *user_id {
+            bail!("you can not fill your own request");
+        }
+        if torrent.user_id.as_ref() != Some(user_id) {
+            bail!("the torrent must be uploaded by you");
+        }
+        if torrent.created_at < self.created_at {
+            bail!("the torrent must be uploaded after the request was posted");
+        }
+
+        let now = Utc::now();
+        let num = diesel::update(torrent_requests::table.find(&self.id))
+            .filter(r::filled_by.is_null())
+            .set((
+                r::torrent_id.eq(&torrent.id),
+                r::filled_by.eq(user_id),
+                r::filled_at.eq(now),
+                r::updated_at.eq(now),
+            ))
+            .execute(db)
+            .map_err(|e| -> Error { format!("failed to fill request: {}", e).into() })?;
+        if num == 0 {
+            bail!("the request is already filled");
+        }
+
+        self.torrent_id = Some(torrent.id);
+        self.filled_by = Some(*user_id);
+        self.filled_at = Some(now);
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Remove the fill, the request is open again
+    pub fn reset_fill(&mut self, db: &PgConnection) -> Result<()> {
+        if self.filled_by.is_none() {
+            bail!("the request is not filled");
+        }
+        if self.paid_at.is_some() {
+            bail!("the bounty is already paid out");
+        }
+
+        let now = Utc::now();
+        let num = diesel::update(torrent_requests::table.find(&self.id))
+            .filter(r::paid_at.is_null())
+            .set((
+                r::torrent_id.eq(None::<Uuid>),
+                r::filled_by.eq(None::<Uuid>),
+                r::filled_at.eq(None::<Timestamp>),
+                r::updated_at.eq(now),
+            ))
+            .execute(db)
+            .map_err(|e| -> Error { format!("failed to reset request: {}", e).into() })?;
+        if num == 0 {
+            bail!("the bounty is already paid out");
+        }
+
+        self.torrent_id = None;
+        self.filled_by = None;
+        self.filled_at = None;
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Pay the bounty out to the filler
+    ///
+    /// The update locks the request, so the filler is credited with the committed bounty.
+    pub fn pay_out(&mut self, db: &PgConnection) -> Result<()> {
+        let filled_by = match self.filled_by {
+            Some(filled_by) => filled_by,
+            None => bail!("the request is not filled"),
+        };
+        if self.paid_at.is_some() {
+            bail!("the bounty is already paid out");
+        }
+
+        let id = self.id;
+        let now = Utc::now();
+        let bounty = db.transaction::<_, Error, _>(|| {
+            let bounty = diesel::update(torrent_requests::table.find(&id))
+                .filter(r::paid_at.is_null())
+                .set((r::paid_at.eq(now), r::updated_at.eq(now)))
+                .returning(r::bounty)
+                .get_result::<i64>(db)
+                .optional()?
+                .ok_or_else(|| -> Error { "the bounty is already paid out".into() })?;
+            diesel::update(users::table.find(&filled_by))
+                .set(u::uploaded.eq(u::uploaded + bounty))
+                .execute(db)?;
+            Ok(bounty)
+        })?;
+
+        self.bounty = bounty;
+        self.paid_at = Some(now);
+        self.updated_at = now;
+        Ok(())
+    }
+
+    /// Delete the request, the bounties are refunded if they are not paid out yet
+    ///
+    /// The refund is done by the `torrent_requests_refund_bounties` trigger, so the bounties are
+    /// also refunded if the request is deleted with the account of the requester.
+    pub fn delete(&self, db: &PgConnection) -> Result<usize> {
+        diesel::delete(torrent_requests::table.find(&self.id))
+            .execute(db)
+            .map_err(|e| format!("failed to delete request: {}", e).into())
+    }
+
+    /// Fetch the bounties, the largest first
+    pub fn bounties(&self, db: &PgConnection) -> Vec<RequestBounty> {
+        use schema::torrent_request_bounties::dsl as b;
+
+        torrent_request_bounties::table
+            .inner_join(users::table)
+            .select((b::user_id, u::name, b::amount, b::created_at))
+            .filter(b::request_id.eq(&self.id))
+            .order_by(b::amount.desc())
+            .load::<RequestBounty>(db)
+            .unwrap_or_default()
+    }
+
+    /// Fetch the ids of the requester and the users, who added a bounty
+    pub fn contributor_ids(&self, db: &PgConnection) -> Vec<Uuid> {
+        let mut ids = vec![self.user_id];
+        for bounty in self.bounties(db) {
+            if !ids.contains(&bounty.user_id) {
+                ids.push(bounty.user_id);
+            }
+        }
+        ids
+    }
+
+    /// Notify the filler about the payout
+    pub fn notify_paid(&self, db: &PgConnection) -> Result<usize> {
+        match self.filled_by {
+            Some(filled_by) => Notification::send(
+                &[filled_by],
+                NotificationKind::Request,
+                &format!("Bounty for \"{}\" paid out", self.title),
+                "The bounty of the request has been added to your upload credit.",
+                Some(&self.link()),
+                db,
+            ),
+            None => Ok(0),
+        }
+    }
+
+    /// The link to the request page
+    pub fn link(&self) -> String {
+        format!("/request/{}", self.id)
+    }
+
+    /// Pay out all fills, which were not confirmed or rejected before `filled_before`
+    pub fn pay_out_expired(filled_before: Timestamp, db: &PgConnection) -> Result<usize> {
+        let requests = torrent_requests::table
+            .filter(r::filled_at.lt(filled_before))
+            .filter(r::torrent_id.is_not_null())
+            .filter(r::paid_at.is_null())
+            .load::<Self>(db)
+            .map_err(|e| -> Error { format!("failed to load filled requests: {}", e).into() })?;
+
+        let mut num = 0;
+        for mut request in requests {
+            if let Err(e) = request.pay_out(db) {
+                warn!("failed to pay out request {}: {}", request.id, e);
+                continue;
+            }
+            if let Err(e) = request.notify_paid(db) {
+                warn!("failed to notify filler of request {}: {}", request.id, e);
+            }
+            num += 1;
+        }
+
+        Ok(num)
+    }
+
+    /// Open the unpaid requests again, which lost their torrent
+    pub fn reopen_orphaned(db: &PgConnection) -> Result<usize> {
+        diesel::update(torrent_requests::table)
+            .filter(r::torrent_id.is_null())
+            .filter(r::filled_by.is_not_null())
+            .filter(r::paid_at.is_null())
+            .set((r::filled_by.eq(None::<Uuid>), r::filled_at.eq(None::<Timestamp>)))
+            .execute(db)
+            .map_err(|e| format!("failed to reopen requests: {}", e).into())
+    }
+}
+
+impl HasUser for TorrentRequest {
+    fn user_id(&self) -> &Uuid {
+        &self.user_id
+    }
+}
+
+/// Take `amount` bytes from the upload credit of a user
+fn escrow(user_id: &Uuid, amount: i64, db: &PgConnection) -> Result<()> {
+    let num = diesel::update(users::table.find(user_id))
+        .filter(u::uploaded.ge(amount))
+        .set(u::uploaded.eq(u::uploaded - amount))
+        .execute(db)?;
+    if num == 0 {
+        bail!("not enough upload credit");
+    }
+
+    Ok(())
+}
+
+/// A new torrent request
+#[derive(Insertable)]
+#[table_name = "torrent_requests"]
+pub struct NewTorrentRequest<'a> {
+    id: Uuid,
+    user_id: &'a Uuid,
+    category_id: &'a Uuid,
+    title: &'a str,
+    description: &'a str,
+}
+
+impl<'a> NewTorrentRequest<'a> {
+    /// Constructs a new `NewTorrentRequest` instance.
+    pub fn new(user_id: &'a Uuid, category_id: &'a Uuid, title: &'a str, description: &'a str) -> Self {
+        NewTorrentRequest {
+            id: Uuid::new_v4(),
+            user_id,
+            category_id,
+            title,
+            description,
+        }
+    }
+
+    /// Create the request with the first bounty of the requester
+    pub fn create(&self, bounty: i64, db: &PgConnection) -> Result<TorrentRequest> {
+        db.transaction::<_, Error, _>(|| {
+            let mut request = diesel::insert_into(torrent_requests::table)
+                .values(self)
+                .get_result::<TorrentRequest>(db)?;
+            request.add_bounty(self.user_id, bounty, db)?;
+            Ok(request)
+        })
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "torrent_request_bounties"]
+struct NewRequestBounty<'a> {
+    id: Uuid,
+    request_id: &'a Uuid,
+    user_id: &'a Uuid,
+    amount: i64,
+}
+
+impl<'a> NewRequestBounty<'a> {
+    fn new(request_id: &'a Uuid, user_id: &'a Uuid, amount: i64) -> Self {
+        NewRequestBounty {
+            id: Uuid::new_v4(),
+            request_id,
+            user_id,
+            amount,
+        }
+    }
+}
+
+/// A bounty with the name of the user
+#[derive(Debug, Queryable, Serialize)]
+pub struct RequestBounty {
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub amount: i64,
+    pub created_at: Timestamp,
+}
+
+/// Filter for the state of a request
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestStatus {
+    /// not filled yet
+    Open,
+    /// filled, but not paid out yet
+    Filled,
+    /// filled and paid out
+    Done,
+    /// all requests
+    All,
+}
+
+impl RequestStatus {
+    /// The name used in the search form
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RequestStatus::Open => "open",
+            RequestStatus::Filled => "filled",
+            RequestStatus::Done => "done",
+            RequestStatus::All => "all",
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RequestStatus {
+    fn from(s: &'a str) -> Self {
+        match s {
+            "filled" => RequestStatus::Filled,
+            "done" => RequestStatus::Done,
+            "all" => RequestStatus::All,
+            _ => RequestStatus::Open,
+        }
+    }
+}
+
+/// Order of the request list
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RequestOrder {
+    /// the newest first
+    Newest,
+    /// the most votes first
+    Votes,
+    /// the largest bounty first
+    Bounty,
+}
+
+impl RequestOrder {
+    /// The name used in the search form
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            RequestOrder::Newest => "newest",
+            RequestOrder::Votes => "votes",
+            RequestOrder::Bounty => "bounty",
+        }
+    }
+}
+
+impl<'a> From<&'a str> for RequestOrder {
+    fn from(s: &'a str) -> Self {
+        match s {
+            "votes" => RequestOrder::Votes,
+            "bounty" => RequestOrder::Bounty,
+            _ => RequestOrder::Newest,
+        }
+    }
+}
+
+/// A request in the request list
+#[derive(Debug, Queryable, Serialize)]
+pub struct RequestListItem {
+    pub id: Uuid,
+    pub title: String,
+    pub category_id: Uuid,
+    pub category_name: String,
+    pub user_id: Uuid,
+    pub user_name: String,
+    pub bounty: i64,
+    pub votes: i32,
+    pub filled_by: Option<Uuid>,
+    pub paid_at: Option<Timestamp>,
+    pub created_at: Timestamp,
+}
+
+impl RequestListItem {
+    /// Search the requests
+    ///
+    /// `title` matches a part of the title, `page` starts at `1`.
+    pub fn search(
+        title: Option<&str>,
+        category_id: Option<&Uuid>,
+        status: RequestStatus,
+        order: RequestOrder,
+        page: i64,
+        per_page: i64,
+        db: &PgConnection,
+    ) -> Vec<Self> {
+        use schema::categories::dsl as c;
+
+        let mut query = torrent_requests::table
+            .inner_join(categories::table)
+            .inner_join(users::table.on(r::user_id.eq(u::id)))
+            .select((
+                r::id,
+                r::title,
+                r::category_id,
+                c::name,
+                r::user_id,
+                u::name,
+                r::bounty,
+                r::votes,
+                r::filled_by,
+                r::paid_at,
+                r::created_at,
+            ))
+            .into_boxed();
+
+        if let Some(title) = title {
+            query = query.filter(r::title.ilike(format!("%{}%", title)));
+        }
+        if let Some(category_id) = category_id {
+            query = query.filter(r::category_id.eq(category_id));
+        }
+        query = match status {
+            RequestStatus::Open => query.filter(r::filled_by.is_null()),
+            RequestStatus::Filled => query.filter(r::filled_by.is_not_null()).filter(r::paid_at.is_null()),
+            RequestStatus::Done => query.filter(r::paid_at.is_not_null()),
+            RequestStatus::All => query,
+        };
+        query = match order {
+            RequestOrder::Newest => query.order_by(r::created_at.desc()),
+            RequestOrder::Votes => query.order_by((r::votes.desc(), r::created_at.desc())),
+            RequestOrder::Bounty => query.order_by((r::bounty.desc(), r::created_at.desc())),
+        };
+
+        query
+            .offset((page.max(1) - 1) * per_page)
+            .limit(per_page)
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+}
+
+/// Parse the torrent id of a fill
+///
+/// Accepts the id itself or a link to the torrent page.
+pub fn parse_torrent_id(torrent: &str) -> Option<Uuid> {
+    torrent
+        .trim()
+        .trim_right_matches('/')
+        .rsplit('/')
+        .next()
+        .and_then(|id| Uuid::parse_str(id).ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn torrent_id() {
+        let id = Uuid::new_v4();
+        assert_eq!(Some(id), parse_torrent_id(&id.to_string()));
+        assert_eq!(Some(id), parse_torrent_id(&format!(" https://tracker.example/torrent/{}/ ", id)));
+        assert_eq!(None, parse_torrent_id("https://tracker.example/torrents"));
+    }
+
+    #[test]
+    fn status_and_order() {
+        for status in &[RequestStatus::Open, RequestStatus::Filled, RequestStatus::Done, RequestStatus::All] {
+            assert_eq!(*status, RequestStatus::from(status.as_str()));
+        }
+        assert_eq!(RequestStatus::Open, RequestStatus::from("unknown"));
+        for order in &[RequestOrder::Newest, RequestOrder::Votes, RequestOrder::Bounty] {
+            assert_eq!(*order, RequestOrder::from(order.as_str()));
+        }
+        assert_eq!(RequestOrder::Newest, RequestOrder::from("unknown"));
+    }
+}
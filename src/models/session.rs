@@ -0,0 +1,160 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Server side sessions
+//!
+//! The session cookie only carries the id of a [**UserSession**](struct.UserSession.html).
+//! Every request checks the session against the database and takes the group from the user,
+//! so revoked sessions and group changes take effect immediately.
+
+use super::*;
+
+use std::net::IpAddr;
+
+use chrono::Duration;
+use ipnetwork::IpNetwork;
+
+use schema::{user_sessions, users};
+use schema::user_sessions::dsl as us;
+
+/// Maximum length of the stored user agent
+pub const MAX_USER_AGENT_LENGTH: usize = 255;
+
+/// A session of a user
+///
+/// Each instance represents a row in the database.
+#[derive(Debug, Queryable, Identifiable, Insertable, Serialize)]
+#[table_name = "user_sessions"]
+pub struct UserSession {
+    pub id: Uuid,
+    pub user_id: Uuid,
+    /// the address of the latest request
+    #[serde(serialize_with = "serialize_optional_ip")]
+    pub ip_address: Option<IpNetwork>,
+    /// the user agent of the latest request
+    pub user_agent: String,
+    pub created_at: Timestamp,
+    pub last_seen: Timestamp,
+}
+
+/// Truncate the user agent to the column length
+pub fn truncate_user_agent(user_agent: &str) -> String {
+    user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect()
+}
+
+impl UserSession {
+    /// Constructs a new `UserSession` instance.
+    pub fn new(user_id: Uuid, ip_address: Option<IpAddr>, user_agent: &str) -> Self {
+        let now = Utc::now();
+        UserSession {
+            id: Uuid::new_v4(),
+            user_id,
+            ip_address: ip_address.map(IpNetwork::from),
+            user_agent: truncate_user_agent(user_agent),
+            created_at: now,
+            last_seen: now,
+        }
+    }
+
+    /// Save the session into the database
+    pub fn save(&self, db: &PgConnection) -> Result<usize> {
+        self.insert_into(user_sessions::table)
+            .execute(db)
+            .map_err(|e| format!("failed to save session: {}", e).into())
+    }
+
+    /// Fetch the sessions of the user, the latest first
+    pub fn for_user(user_id: &Uuid, db: &PgConnection) -> Vec<Self> {
+        user_sessions::table
+            .filter(us::user_id.eq(user_id))
+            .order_by(us::last_seen.desc())
+            .load::<Self>(db)
+            .unwrap_or_default()
+    }
+
+    /// Check a session and record the request
+    ///
+    /// Returns the user id and the current group id of the user, or `None` if the session does
+    /// not exist (anymore).
+    pub fn touch(id: &Uuid, ip_address: Option<IpAddr>, user_agent: &str, db: &PgConnection) -> Result<Option<(Uuid, Uuid)>> {
+        let session = user_sessions::table
+            .inner_join(users::table)
+            .select((us::user_id, users::dsl::group_id, us::ip_address, us::last_seen))
+            .filter(us::id.eq(id))
+            .first::<(Uuid, Uuid, Option<IpNetwork>, Timestamp)>(db)
+            .optional()?;
+
+        let (user_id, group_id, last_ip, last_seen) = match session {
+            Some(session) => session,
+            None => return Ok(None),
+        };
+
+        let ip_address = ip_address.map(IpNetwork::from);
+        if ip_address != last_ip || Utc::now().signed_duration_since(last_seen) > Duration::seconds(TOUCH_INTERVAL) {
+            diesel::update(user_sessions::table.find(id))
+                .set((
+                    us::ip_address.eq(ip_address),
+                    us::user_agent.eq(truncate_user_agent(user_agent)),
+                    us::last_seen.eq(Utc::now()),
+                ))
+                .execute(db)?;
+        }
+
+        Ok(Some((user_id, group_id)))
+    }
+
+    /// Revoke a session of the user
+    pub fn revoke(id: &Uuid, user_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::delete(user_sessions::table)
+            .filter(us::id.eq(id))
+            .filter(us::user_id.eq(user_id))
+            .execute(db)
+            .map_err(|e| format!("failed to revoke session: {}", e).into())
+    }
+
+    /// Revoke all sessions of the user except `keep_id`
+    pub fn revoke_others(keep_id: &Uuid, user_id: &Uuid, db: &PgConnection) -> Result<usize> {
+        diesel::delete(user_sessions::table)
+            .filter(us::id.ne(keep_id))
+            .filter(us::user_id.eq(user_id))
+            .execute(db)
+            .map_err(|e| format!("failed to revoke sessions: {}", e).into())
+    }
+
+    /// Delete the sessions, which were not used since `before`
+    pub fn prune(before: &Timestamp, db: &PgConnection) -> Result<usize> {
+        diesel::delete(user_sessions::table)
+            .filter(us::last_seen.lt(before))
+            .execute(db)
+            .map_err(|e| format!("failed to prune sessions: {}", e).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_session() {
+        let user_agent = "a".repeat(300);
+        let session = UserSession::new(Uuid::new_v4(), Some("10.0.0.1".parse().unwrap()), &user_agent);
+        assert_eq!(MAX_USER_AGENT_LENGTH, session.user_agent.len());
+        assert_eq!(Some("10.0.0.1".parse().unwrap()), session.ip_address.map(|ip| ip.ip()));
+        assert_eq!(session.created_at, session.last_seen);
+    }
+}
@@ -19,6 +19,8 @@
 use super::*;
 use markdown;
 use schema::static_content;
+use template::helper::escape_html;
+use util::html::sanitize;
 
 #[derive(Queryable, Identifiable, Insertable, AsChangeset, Serialize)]
 #[table_name = "static_content"]
@@ -54,12 +56,15 @@ impl Content {
         static_content::table.find(id).first::<Self>(db).ok()
     }
 
+    /// Render the content as sanitized HTML
+    ///
+    /// Content of an unknown type is escaped.
     pub fn render(&self) -> String {
         if self.content_type == "text/markdown" {
-            return markdown::to_html(&self.content);
+            return sanitize(&markdown::to_html(&self.content));
         }
 
-        self.content.clone()
+        escape_html(&self.content)
     }
 
     pub fn save(&self, db: &PgConnection) -> Result<usize> {
@@ -69,3 +74,28 @@ impl Content {
             .map_err(|e| format!("failed content: {}", e).into())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util::html::tests::{assert_safe, XSS_PAYLOADS};
+
+    fn content(content: &str, content_type: &str) -> Content {
+        Content::new(
+            "test".to_string(),
+            "Test".to_string(),
+            content.to_string(),
+            content_type.to_string(),
+            Utc::now(),
+            Utc::now(),
+        )
+    }
+
+    #[test]
+    fn render_xss() {
+        for payload in XSS_PAYLOADS {
+            assert_safe(payload, &content(payload, "text/markdown").render());
+            assert_safe(payload, &content(payload, "text/plain").render());
+        }
+    }
+}
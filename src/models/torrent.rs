@@ -22,6 +22,8 @@ use super::schema::*;
 use super::*;
 use super::{category::Category, user::User};
 use models::acl::Subject;
+use models::bonus::{FreeleechTorrent, UserBonus};
+use chrono::Duration;
 
 #[derive(Debug, Queryable, Insertable, AsChangeset, Identifiable, Associations, Serialize)]
 #[table_name = "torrents"]
@@ -109,6 +111,13 @@ impl Torrent {
             .map_err(|e| format!("failed to delete torrent: {}", e).into())
     }
 
+    /// Check whether the torrent had no seeder since the given time
+    ///
+    /// A torrent which never had a seeder counts as dead since its upload.
+    pub fn dead_since(&self, seeder: i64, since: &Timestamp) -> bool {
+        seeder == 0 && self.last_seeder.unwrap_or(self.created_at) < *since
+    }
+
     pub fn comments(&self, db: &PgConnection) -> Vec<TorrentComment> {
         TorrentComment::find_for_torrent(&self.id, db)
     }
@@ -332,6 +341,12 @@ pub struct TorrentMsg {
     pub files: Vec<TorrentFile>,
     pub peers: Vec<(Peer, String)>,
     pub comments: Vec<TorrentCommentResponse>,
+    pub reseed: Option<TorrentReseedResponse>,
+    pub may_reseed: bool,
+    /// expiry of the freeleech of the current user
+    pub freeleech_until: Option<Timestamp>,
+    /// unused freeleech tokens of the current user
+    pub freeleech_tokens: i32,
     pub timezone: i32,
 }
 
@@ -345,6 +360,16 @@ impl TorrentMsg {
             let torrent_user_name = torrent.user_name(db);
             let category = Category::find(&torrent.category_id, db).ok_or("category not found")?;
             let comments = torrent.comments(db).into_iter().map(|c| TorrentCommentResponse::new(c, &db, &subj)).collect();
+            let reseed = TorrentReseed::find_latest(id, db).map(|r| TorrentReseedResponse::new(r, db));
+            let may_reseed = match reseed {
+                Some(ref r) if r.reseed.reseeded_at.is_none() => false,
+                _ => {
+                    let (seeder, _) = TorrentList::peer_count(id, db);
+                    torrent.dead_since(seeder, &TorrentReseed::dead_threshold())
+                }
+            };
+            let freeleech_until = FreeleechTorrent::find_active(subj.user_id(), id, db).map(|f| f.expires_at);
+            let freeleech_tokens = UserBonus::find(subj.user_id(), db).freeleech_tokens;
             let timezone = SETTINGS.read().unwrap().user.default_timezone;
 
             Ok(TorrentMsg {
@@ -356,6 +381,10 @@ impl TorrentMsg {
                 files,
                 peers,
                 comments,
+                reseed,
+                may_reseed,
+                freeleech_until,
+                freeleech_tokens,
                 timezone,
             })
         } else {
@@ -397,13 +426,27 @@ impl TorrentList {
             .unwrap_or_default()
     }
 
-    pub fn peer_count_scrape(info_hash: &[u8], db: &PgConnection) -> (i64, i64, i32) {
+    /// Fetch the scrape counts for all given info hashes in a single query
+    ///
+    /// Returns a list of (info hash, seeder, leecher, completed). Unknown info hashes are omitted.
+    pub fn peer_count_scrape(info_hashes: &[Bytes], db: &PgConnection) -> Vec<(Bytes, i64, i64, i32)> {
         use schema::torrent_list::dsl;
 
         schema::torrent_list::table
-            .select((dsl::seeder, dsl::leecher, dsl::completed))
-            .filter(dsl::info_hash.eq(info_hash))
-            .first::<(i64, i64, i32)>(db)
+            .select((dsl::info_hash, dsl::seeder, dsl::leecher, dsl::completed))
+            .filter(dsl::info_hash.eq_any(info_hashes))
+            .load::<(Bytes, i64, i64, i32)>(db)
+            .unwrap_or_default()
+    }
+
+    /// Fetch the scrape counts for all visible torrents
+    pub fn scrape_all(db: &PgConnection) -> Vec<(Bytes, i64, i64, i32)> {
+        use schema::torrent_list::dsl;
+
+        schema::torrent_list::table
+            .select((dsl::info_hash, dsl::seeder, dsl::leecher, dsl::completed))
+            .filter(dsl::visible.eq(true))
+            .load::<(Bytes, i64, i64, i32)>(db)
             .unwrap_or_default()
     }
 }
@@ -432,6 +475,17 @@ impl Transfer {
             .first::<Transfer>(db)
             .ok()
     }
+
+    /// Fetch the ids of all users, who completed the torrent
+    pub fn snatcher_ids(torrent_id: &Uuid, db: &PgConnection) -> Vec<Uuid> {
+        transfers::table
+            .select(transfers::dsl::user_id)
+            .filter(transfers::dsl::torrent_id.eq(torrent_id))
+            .filter(transfers::dsl::completed_at.is_not_null())
+            .load::<Uuid>(db)
+            .unwrap_or_default()
+    }
+
     pub fn save(&self, db: &PgConnection) -> Result<usize> {
         diesel::insert_into(transfers::table)
             .values(self)
@@ -536,6 +590,14 @@ impl TorrentComment {
             .unwrap_or_default()
     }
 
+    pub fn torrent_id(&self) -> &Uuid {
+        &self.torrent_id
+    }
+
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
     pub fn set_content(&mut self, content: String) -> String {
         std::mem::replace(&mut self.content, content)
     }
@@ -635,3 +697,130 @@ pub struct UpdateTorrentComment<'a> {
     content: &'a str,
     updated_at: &'a Timestamp,
 }
+
+/// A request to reseed a torrent without seeders
+#[derive(Debug, Queryable, Identifiable, Associations, Serialize)]
+#[table_name = "torrent_reseeds"]
+#[belongs_to(Torrent)]
+pub struct TorrentReseed {
+    pub id: Uuid,
+    pub torrent_id: Uuid,
+    pub user_id: Uuid,
+    pub created_at: Timestamp,
+    pub reseeded_by: Option<Uuid>,
+    pub reseeded_at: Option<Timestamp>,
+}
+
+impl TorrentReseed {
+    /// Find the most recent reseed request for a torrent
+    pub fn find_latest(torrent_id: &Uuid, db: &PgConnection) -> Option<Self> {
+        torrent_reseeds::table
+            .filter(torrent_reseeds::dsl::torrent_id.eq(torrent_id))
+            .order(torrent_reseeds::dsl::created_at.desc())
+            .first::<Self>(db)
+            .ok()
+    }
+
+    /// Find the pending reseed request for a torrent
+    pub fn find_open(torrent_id: &Uuid, db: &PgConnection) -> Option<Self> {
+        torrent_reseeds::table
+            .filter(torrent_reseeds::dsl::torrent_id.eq(torrent_id))
+            .filter(torrent_reseeds::dsl::reseeded_at.is_null())
+            .first::<Self>(db)
+            .ok()
+    }
+
+    /// Count the reseed requests a user made since the given time
+    pub fn count_since(user_id: &Uuid, since: &Timestamp, db: &PgConnection) -> i64 {
+        torrent_reseeds::table
+            .filter(torrent_reseeds::dsl::user_id.eq(user_id))
+            .filter(torrent_reseeds::dsl::created_at.gt(since))
+            .count()
+            .get_result(db)
+            .unwrap_or(0)
+    }
+
+    /// Torrents without seeders since this time may be requested for a reseed
+    pub fn dead_threshold() -> Timestamp {
+        let days = SETTINGS.read().unwrap().torrent.reseed_after_days;
+        Utc::now() - Duration::days(days)
+    }
+
+    /// Mark the pending reseed request for a torrent as fulfilled by the user
+    ///
+    /// Returns the updated request, or `None` if there was no pending request.
+    pub fn complete(torrent_id: &Uuid, user_id: &Uuid, db: &PgConnection) -> Result<Option<Self>> {
+        use schema::torrent_reseeds::dsl;
+        diesel::update(torrent_reseeds::table)
+            .filter(dsl::torrent_id.eq(torrent_id))
+            .filter(dsl::reseeded_at.is_null())
+            .set((dsl::reseeded_by.eq(user_id), dsl::reseeded_at.eq(Utc::now())))
+            .get_result::<Self>(db)
+            .optional()
+            .chain_err(|| "reseed update failed")
+    }
+}
+
+#[derive(Insertable)]
+#[table_name = "torrent_reseeds"]
+pub struct NewTorrentReseed<'a> {
+    id: Uuid,
+    torrent_id: &'a Uuid,
+    user_id: &'a Uuid,
+}
+
+impl<'a> NewTorrentReseed<'a> {
+    pub fn new(torrent_id: &'a Uuid, user_id: &'a Uuid) -> Self {
+        let id = Uuid::new_v4();
+        Self { id, torrent_id, user_id }
+    }
+
+    /// Insert the reseed request
+    ///
+    /// Returns `None` if there already is an open request for the torrent.
+    pub fn create(&self, db: &PgConnection) -> Result<Option<TorrentReseed>> {
+        diesel::insert_into(torrent_reseeds::table)
+            .values(self)
+            .on_conflict_do_nothing()
+            .get_result::<TorrentReseed>(db)
+            .optional()
+            .map_err(|e| format!("reseed request insert failed: {}", e).into())
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TorrentReseedResponse {
+    #[serde(flatten)]
+    pub reseed: TorrentReseed,
+    pub user_name: Option<String>,
+    pub reseeded_by_name: Option<String>,
+}
+
+impl TorrentReseedResponse {
+    pub fn new(reseed: TorrentReseed, db: &PgConnection) -> Self {
+        let user_name = username(&reseed.user_id, db);
+        let reseeded_by_name = reseed.reseeded_by.as_ref().and_then(|id| username(id, db));
+        Self { reseed, user_name, reseeded_by_name }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dead_since() {
+        let since = Utc::now() - Duration::days(7);
+        let mut torrent = Torrent::default();
+        torrent.created_at = Utc::now() - Duration::days(30);
+        assert!(torrent.dead_since(0, &since));
+        assert!(!torrent.dead_since(1, &since));
+        torrent.last_seeder = Some(Utc::now() - Duration::days(1));
+        assert!(!torrent.dead_since(0, &since));
+        torrent.last_seeder = Some(Utc::now() - Duration::days(8));
+        assert!(torrent.dead_since(0, &since));
+        torrent.last_seeder = None;
+        torrent.created_at = Utc::now();
+        assert!(!torrent.dead_since(0, &since));
+    }
+}
@@ -21,7 +21,11 @@
 use super::schema::*;
 use super::*;
 use ipnetwork::IpNetwork;
+use models::audit::AuditEntry;
+use models::history::{ClientHistory, IpHistory};
 use models::message::NewMessageFolder;
+use models::moderation::{UserBan, UserWarningResponse};
+use models::relation::RelatedUser;
 use ring::digest;
 use serde::{ser::SerializeStruct, Serialize, Serializer};
 use serde_json::Value;
@@ -41,7 +45,6 @@ pub const STATUS_ACTIVE: i16 = 2;
 #[allow(dead_code)]
 pub const STATUS_LOCKED: i16 = 3;
 /// Banned (permanent or temporary) users
-#[allow(dead_code)]
 pub const STATUS_BANNED: i16 = 4;
 
 const SALTBYTES: usize = 32;
@@ -189,6 +192,23 @@ impl User {
         query.execute(db).chain_err(|| "user update failed")
     }
 
+    /// Add transferred bytes to the stats of the `User`
+    ///
+    /// The stats are incremented in the database, so concurrent changes of the upload credit
+    /// (e.g. request bounties) are not overwritten.
+    pub fn add_transfer(&mut self, uploaded: i64, downloaded: i64, db: &PgConnection) -> Result<usize> {
+        use schema::users::dsl;
+        let (new_uploaded, new_downloaded) = diesel::update(users::table)
+            .set((dsl::uploaded.eq(dsl::uploaded + uploaded), dsl::downloaded.eq(dsl::downloaded + downloaded)))
+            .filter(dsl::id.eq(&self.id))
+            .returning((dsl::uploaded, dsl::downloaded))
+            .get_result::<(i64, i64)>(db)
+            .chain_err(|| "user update failed")?;
+        self.uploaded = new_uploaded;
+        self.downloaded = new_downloaded;
+        Ok(1)
+    }
+
     /// Update the `last_active` timestamp to now.
     pub fn update_last_active(&mut self, db: &PgConnection) -> Result<usize> {
         use schema::users::dsl;
@@ -418,6 +438,20 @@ pub struct UserProfileMsg {
     pub timezone: i32,
     pub may_view_passcode: bool,
     pub profile: UserProfile,
+    pub friends: Vec<RelatedUser>,
+    pub blocks: Vec<RelatedUser>,
+    pub is_friend: bool,
+    pub is_blocked: bool,
+    /// warnings, shown to the user and the moderators
+    pub warnings: Vec<UserWarningResponse>,
+    pub ban: Option<UserBan>,
+    pub may_view_warnings: bool,
+    pub may_moderate: bool,
+    /// addresses, clients and the audit log, shown to the moderators
+    pub may_view_history: bool,
+    pub ip_history: Vec<IpHistory>,
+    pub client_history: Vec<ClientHistory>,
+    pub audit_log: Vec<AuditEntry>,
 }
 
 #[derive(Debug, Serialize, Queryable, Identifiable)]
@@ -484,13 +518,14 @@ pub struct UserConnection {
     user_agent: String,
     ip_address: IpNetwork,
     port: i32,
+    connectable: Option<bool>,
 }
 
 impl UserConnection {
     pub fn fetch_for_user(id: &Uuid, db: &PgConnection) -> Vec<UserConnection> {
         use schema::peers::dsl as p;
         peers::table
-            .select((p::id, p::user_agent, p::ip_address, p::port))
+            .select((p::id, p::user_agent, p::ip_address, p::port, p::connectable))
             .filter(p::user_id.eq(id))
             .order_by(p::ip_address.asc())
             .load::<UserConnection>(db)
@@ -503,11 +538,12 @@ impl Serialize for UserConnection {
     where
         S: Serializer,
     {
-        let mut root = serializer.serialize_struct("user_connection", 4)?;
+        let mut root = serializer.serialize_struct("user_connection", 5)?;
         root.serialize_field("id", &self.id)?;
         root.serialize_field("user_agent", &self.user_agent)?;
         root.serialize_field("ip_address", &self.ip_address.to_string())?;
         root.serialize_field("port", &self.port)?;
+        root.serialize_field("connectable", &self.connectable)?;
         root.end()
     }
 }
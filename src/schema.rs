@@ -20,12 +20,47 @@ table! {
     }
 }
 
+table! {
+    api_tokens (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        name -> Varchar,
+        scopes -> Array<Text>,
+        expires_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    bonus_transactions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        kind -> Varchar,
+        amount -> Int8,
+        balance -> Int8,
+        description -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     categories (id) {
         id -> Uuid,
         name -> Varchar,
         created_at -> Timestamptz,
         updated_at -> Timestamptz,
+        allow_web_seeds -> Bool,
+    }
+}
+
+table! {
+    chat_mentions (message_id, user_id) {
+        message_id -> Uuid,
+        user_id -> Uuid,
+        seen -> Bool,
+        created_at -> Timestamptz,
     }
 }
 
@@ -36,6 +71,32 @@ table! {
         chat -> Int2,
         message -> Text,
         created_at -> Timestamptz,
+        deleted_at -> Nullable<Timestamptz>,
+        rendered -> Text,
+    }
+}
+
+table! {
+    chat_mutes (id) {
+        id -> Uuid,
+        chat -> Int2,
+        user_id -> Uuid,
+        issuer_id -> Uuid,
+        reason -> Text,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    chat_rooms (id) {
+        id -> Int2,
+        slug -> Varchar,
+        name -> Varchar,
+        topic -> Text,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+        slow_mode -> Int4,
     }
 }
 
@@ -56,6 +117,25 @@ table! {
     }
 }
 
+table! {
+    email_domain_bans (id) {
+        id -> Uuid,
+        domain -> Varchar,
+        reason -> Text,
+        issuer_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    freeleech_torrents (user_id, torrent_id) {
+        user_id -> Uuid,
+        torrent_id -> Uuid,
+        expires_at -> Timestamptz,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     groups (id) {
         id -> Uuid,
@@ -66,6 +146,16 @@ table! {
     }
 }
 
+table! {
+    ip_bans (id) {
+        id -> Uuid,
+        network -> Inet,
+        reason -> Text,
+        issuer_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     message_folders (id) {
         id -> Uuid,
@@ -85,6 +175,21 @@ table! {
         body -> Text,
         is_read -> Bool,
         created_at -> Timestamptz,
+        thread_id -> Uuid,
+        parent_id -> Nullable<Uuid>,
+    }
+}
+
+table! {
+    notifications (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        kind -> Varchar,
+        title -> Varchar,
+        body -> Text,
+        link -> Nullable<Varchar>,
+        is_read -> Bool,
+        created_at -> Timestamptz,
     }
 }
 
@@ -108,6 +213,20 @@ table! {
         created_at -> Timestamptz,
         finished_at -> Nullable<Timestamptz>,
         updated_at -> Timestamptz,
+        connectable -> Nullable<Bool>,
+    }
+}
+
+table! {
+    promotion_rules (id) {
+        id -> Uuid,
+        group_id -> Uuid,
+        min_age_days -> Int4,
+        min_uploaded -> Int8,
+        min_ratio -> Float8,
+        min_uploads -> Int4,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -189,6 +308,53 @@ table! {
     }
 }
 
+table! {
+    torrent_request_bounties (id) {
+        id -> Uuid,
+        request_id -> Uuid,
+        user_id -> Uuid,
+        amount -> Int8,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    torrent_request_votes (request_id, user_id) {
+        request_id -> Uuid,
+        user_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    torrent_reseeds (id) {
+        id -> Uuid,
+        torrent_id -> Uuid,
+        user_id -> Uuid,
+        created_at -> Timestamptz,
+        reseeded_by -> Nullable<Uuid>,
+        reseeded_at -> Nullable<Timestamptz>,
+    }
+}
+
+table! {
+    torrent_requests (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        category_id -> Uuid,
+        title -> Varchar,
+        description -> Text,
+        bounty -> Int8,
+        votes -> Int4,
+        torrent_id -> Nullable<Uuid>,
+        filled_by -> Nullable<Uuid>,
+        filled_at -> Nullable<Timestamptz>,
+        paid_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        updated_at -> Timestamptz,
+    }
+}
+
 table! {
     torrents (id) {
         id -> Uuid,
@@ -221,6 +387,74 @@ table! {
     }
 }
 
+table! {
+    user_audit_log (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        action -> Varchar,
+        ip_address -> Nullable<Inet>,
+        details -> Text,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    user_bans (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        issuer_id -> Uuid,
+        reason -> Text,
+        expires_at -> Nullable<Timestamptz>,
+        lifted_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    user_blocks (user_id, blocked_id) {
+        user_id -> Uuid,
+        blocked_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    user_bonus (user_id) {
+        user_id -> Uuid,
+        points -> Int8,
+        freeleech_tokens -> Int4,
+        updated_at -> Timestamptz,
+    }
+}
+
+table! {
+    user_client_history (user_id, peer_id) {
+        user_id -> Uuid,
+        peer_id -> Bytea,
+        user_agent -> Varchar,
+        first_seen -> Timestamptz,
+        last_seen -> Timestamptz,
+    }
+}
+
+table! {
+    user_friends (user_id, friend_id) {
+        user_id -> Uuid,
+        friend_id -> Uuid,
+        created_at -> Timestamptz,
+    }
+}
+
+table! {
+    user_ip_history (user_id, ip_address, source) {
+        user_id -> Uuid,
+        ip_address -> Inet,
+        source -> Varchar,
+        first_seen -> Timestamptz,
+        last_seen -> Timestamptz,
+    }
+}
+
 table! {
     user_profiles (id) {
         id -> Uuid,
@@ -241,6 +475,17 @@ table! {
     }
 }
 
+table! {
+    user_sessions (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        ip_address -> Nullable<Inet>,
+        user_agent -> Varchar,
+        created_at -> Timestamptz,
+        last_seen -> Timestamptz,
+    }
+}
+
 table! {
     user_transfer (id) {
         id -> Uuid,
@@ -258,6 +503,19 @@ table! {
     }
 }
 
+table! {
+    user_warnings (id) {
+        id -> Uuid,
+        user_id -> Uuid,
+        issuer_id -> Uuid,
+        reason -> Text,
+        severity -> Int2,
+        expires_at -> Timestamptz,
+        revoked_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+    }
+}
+
 table! {
     users (id) {
         id -> Uuid,
@@ -279,20 +537,42 @@ table! {
 
 joinable!(acl_group_rules -> groups (group_id));
 joinable!(acl_user_rules -> users (user_id));
+joinable!(api_tokens -> users (user_id));
+joinable!(bonus_transactions -> users (user_id));
+joinable!(chat_mentions -> chat_messages (message_id));
+joinable!(chat_mentions -> users (user_id));
+joinable!(chat_messages -> chat_rooms (chat));
 joinable!(chat_messages -> users (user_id));
+joinable!(chat_mutes -> chat_rooms (chat));
+joinable!(freeleech_torrents -> torrents (torrent_id));
+joinable!(freeleech_torrents -> users (user_id));
 joinable!(message_folders -> users (user_id));
 joinable!(messages -> message_folders (folder_id));
+joinable!(notifications -> users (user_id));
 joinable!(peers -> torrents (torrent_id));
 joinable!(peers -> users (user_id));
+joinable!(promotion_rules -> groups (group_id));
 joinable!(torrent_comments -> torrents (torrent_id));
 joinable!(torrent_comments -> users (user_id));
 joinable!(torrent_images -> torrents (torrent_id));
+joinable!(torrent_request_bounties -> torrent_requests (request_id));
+joinable!(torrent_request_bounties -> users (user_id));
+joinable!(torrent_request_votes -> torrent_requests (request_id));
+joinable!(torrent_request_votes -> users (user_id));
+joinable!(torrent_requests -> categories (category_id));
+joinable!(torrent_requests -> torrents (torrent_id));
+joinable!(torrent_reseeds -> torrents (torrent_id));
 joinable!(torrents -> categories (category_id));
 joinable!(torrents -> users (user_id));
 joinable!(transfers -> torrents (torrent_id));
 joinable!(transfers -> users (user_id));
+joinable!(user_bonus -> users (user_id));
+joinable!(user_audit_log -> users (user_id));
+joinable!(user_client_history -> users (user_id));
+joinable!(user_ip_history -> users (user_id));
 joinable!(user_profiles -> users (id));
 joinable!(user_properties -> users (user_id));
+joinable!(user_sessions -> users (user_id));
 joinable!(user_transfer -> users (user_id));
 joinable!(user_transfer -> torrents (torrent_id));
 joinable!(users -> groups (group_id));
@@ -300,22 +580,45 @@ joinable!(users -> groups (group_id));
 allow_tables_to_appear_in_same_query!(
     acl_group_rules,
     acl_user_rules,
+    api_tokens,
+    bonus_transactions,
     categories,
+    chat_mentions,
     chat_messages,
+    chat_mutes,
+    chat_rooms,
+    email_domain_bans,
+    freeleech_torrents,
     groups,
+    ip_bans,
     message_folders,
     messages,
+    notifications,
     peers,
+    promotion_rules,
     static_content,
     torrent_comments,
     torrent_files,
     torrent_images,
     torrent_meta_files,
     torrent_nfos,
+    torrent_request_bounties,
+    torrent_request_votes,
+    torrent_requests,
+    torrent_reseeds,
     torrents,
     transfers,
+    user_audit_log,
+    user_bans,
+    user_blocks,
+    user_bonus,
+    user_client_history,
+    user_friends,
+    user_ip_history,
     user_profiles,
     user_properties,
+    user_sessions,
     user_transfer,
+    user_warnings,
     users,
 );
@@ -38,6 +38,16 @@ pub struct User {
     pub avatar_thumbnail_width: u32,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct Login {
+    pub free_attempts: u32,
+    pub ip_free_attempts: u32,
+    pub base_delay: u64,
+    pub max_delay: u64,
+    pub lockout_attempts: u32,
+    pub lockout_minutes: u64,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct Email {
     pub enabled: bool,
@@ -46,14 +56,78 @@ pub struct Email {
 #[derive(Debug, Deserialize)]
 pub struct Tracker {
     pub announce_url: String,
+    pub backup_announce_urls: Vec<String>,
     pub comment: String,
     pub default_numwant: u16,
+    pub max_numwant: u16,
     pub interval: u16,
+    pub interval_jitter: u16,
+    pub min_interval: u16,
+    pub ip_burst: u32,
+    pub ip_rate: f64,
+    pub passcode_burst: u32,
+    pub passcode_rate: f64,
+    pub max_scrape_hashes: usize,
+    pub full_scrape: bool,
+    pub full_scrape_interval: u16,
+    pub connectability_check: bool,
+    pub connectability_timeout: u64,
+    pub connectability_cache_ttl: u64,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct Torrent {
     pub image_thumbnail_width: u32,
+    pub reseed_after_days: i64,
+    pub reseed_requests_per_day: i64,
+    pub reseed_bonus: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Chat {
+    pub retention_days: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub min_bounty: i64,
+    pub confirm_timeout: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Promotion {
+    pub interval: i64,
+    /// names of the groups, which are never changed, in addition to the team groups
+    #[serde(default)]
+    pub excluded_groups: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Warning {
+    pub block_downloads_severity: i16,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct History {
+    pub retention_days: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Api {
+    pub token_max_days: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Bonus {
+    pub points_per_hour: f64,
+    pub size_weight: f64,
+    pub age_weight: f64,
+    pub seeder_weight: f64,
+    pub upload_price: i64,
+    pub upload_amount: i64,
+    pub title_price: i64,
+    pub freeleech_price: i64,
+    pub freeleech_hours: i64,
 }
 
 #[derive(Debug, Deserialize)]
@@ -63,14 +137,23 @@ pub struct Settings {
     pub session_name: String,
     pub session_secret: String,
     pub session_strict: bool,
+    pub session_idle_days: i64,
     pub domain: String,
     pub https: bool,
     pub bind: String,
     pub database: Database,
     pub user: User,
+    pub login: Login,
     pub email: Email,
     pub tracker: Tracker,
     pub torrent: Torrent,
+    pub chat: Chat,
+    pub request: Request,
+    pub bonus: Bonus,
+    pub promotion: Promotion,
+    pub warning: Warning,
+    pub history: History,
+    pub api: Api,
 }
 
 impl Settings {
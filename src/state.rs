@@ -20,10 +20,15 @@ use super::*;
 
 use std::sync::{Arc, RwLock, RwLockReadGuard};
 
+use chat_server::ChatServer;
 use db::{DbConn, DbExecutor, Pool};
+use login_throttle::LoginThrottle;
 use models::acl::Acl;
+use models::ban_list::BanList;
+use std::net::IpAddr;
 use template::TemplateContainer;
 use template::TemplateSystem;
+use tracker::checker::PeerChecker;
 
 #[derive(Clone)]
 pub struct AclContainer {
@@ -48,8 +53,7 @@ impl AclContainer {
     /// # Panics
     ///
     /// This function panics if the underlying database shits the bed.
-    #[allow(dead_code)]
-    pub fn reload(&mut self, db: &PgConnection) {
+    pub fn reload(&self, db: &PgConnection) {
         self.inner.write().unwrap().reload(db)
     }
 
@@ -74,11 +78,56 @@ impl AclContainer {
     }
 }
 
+/// Shared, cached IP and email domain ban lists
+#[derive(Clone, Default)]
+pub struct BanListContainer {
+    inner: Arc<RwLock<BanList>>
+}
+
+impl BanListContainer {
+    pub fn new(ban_list: Arc<RwLock<BanList>>) -> Self {
+        Self{inner: ban_list}
+    }
+
+    /// Reload the ban lists from the database
+    pub fn reload(&self, db: &PgConnection) {
+        self.inner.write().unwrap().load(db)
+    }
+
+    /// Fails with the reason, if the address is in a banned network
+    pub fn check_ip(&self, ip: &IpAddr) -> Result<()> {
+        match self.inner.read().unwrap().ip_banned(ip) {
+            Some("") => bail!("your ip address is banned"),
+            Some(reason) => bail!("your ip address is banned: {}", reason),
+            None => Ok(()),
+        }
+    }
+
+    /// Fails with the reason, if the domain of the email address is banned
+    pub fn check_email(&self, email: &str) -> Result<()> {
+        match self.inner.read().unwrap().email_banned(email) {
+            Some("") => bail!("the email domain is not allowed"),
+            Some(reason) => bail!("the email domain is not allowed: {}", reason),
+            None => Ok(()),
+        }
+    }
+}
+
+impl ::std::fmt::Debug for BanListContainer {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.write_str("BanListContainer")
+    }
+}
+
 /// State represents the shared state for the application
 pub struct State {
     db: Addr<Syn, DbExecutor>,
     acl: AclContainer,
+    ban_list: BanListContainer,
     template: Option<TemplateContainer>,
+    peer_checker: Option<Addr<Syn, PeerChecker>>,
+    chat_server: Option<Addr<Syn, ChatServer>>,
+    login_throttle: Option<Addr<Syn, LoginThrottle>>,
 }
 
 impl State {
@@ -87,7 +136,11 @@ impl State {
         State {
             db,
             acl: AclContainer::new(acl),
+            ban_list: BanListContainer::default(),
             template: None,
+            peer_checker: None,
+            chat_server: None,
+            login_throttle: None,
         }
     }
 
@@ -96,6 +149,26 @@ impl State {
         self.template = Some(template);
     }
 
+    /// Set the ban lists
+    pub fn set_ban_list(&mut self, ban_list: Arc<RwLock<BanList>>) {
+        self.ban_list = BanListContainer::new(ban_list);
+    }
+
+    /// Set the peer connectability checker
+    pub fn set_peer_checker(&mut self, peer_checker: Addr<Syn, PeerChecker>) {
+        self.peer_checker = Some(peer_checker);
+    }
+
+    /// Set the chat broadcast server
+    pub fn set_chat_server(&mut self, chat_server: Addr<Syn, ChatServer>) {
+        self.chat_server = Some(chat_server);
+    }
+
+    /// Set the login throttle
+    pub fn set_login_throttle(&mut self, login_throttle: Addr<Syn, LoginThrottle>) {
+        self.login_throttle = Some(login_throttle);
+    }
+
     /// Get the database object
     pub fn db(&self) -> &Addr<Syn, DbExecutor> {
         &self.db
@@ -106,6 +179,26 @@ impl State {
         &self.acl
     }
 
+    /// Get the ban lists
+    pub fn ban_list(&self) -> &BanListContainer {
+        &self.ban_list
+    }
+
+    /// Get the peer connectability checker, if connectability checks are enabled
+    pub fn peer_checker(&self) -> Option<&Addr<Syn, PeerChecker>> {
+        self.peer_checker.as_ref()
+    }
+
+    /// Get the chat broadcast server
+    pub fn chat_server(&self) -> Option<&Addr<Syn, ChatServer>> {
+        self.chat_server.as_ref()
+    }
+
+    /// Get the login throttle
+    pub fn login_throttle(&self) -> Option<&Addr<Syn, LoginThrottle>> {
+        self.login_throttle.as_ref()
+    }
+
     /// Get the Template object
     pub fn template(&self) -> RwLockReadGuard<TemplateSystem> {
         match &self.template {
@@ -120,4 +213,11 @@ pub fn init_acl(pool: &Pool) -> Arc<RwLock<Acl>> {
     acl.load(&DbConn(pool.get().unwrap()));
 
     Arc::new(RwLock::new(acl))
-}
\ No newline at end of file
+}
+
+pub fn init_ban_list(pool: &Pool) -> Arc<RwLock<BanList>> {
+    let mut ban_list = BanList::new();
+    ban_list.load(&DbConn(pool.get().unwrap()));
+
+    Arc::new(RwLock::new(ban_list))
+}
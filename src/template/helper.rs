@@ -22,6 +22,7 @@ use std::collections::HashMap;
 use std::fmt::Write;
 
 use markdown;
+use regex::{Captures, Regex};
 use tera::{from_value, Result, Value};
 
 use tera::GlobalFn;
@@ -98,13 +99,65 @@ pub fn duration(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     }
 }
 
+/// Render markdown, the resulting HTML is sanitized
 pub fn markdown(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     match value {
-        Value::String(s) => Ok(Value::String(markdown::to_html(&s))),
+        Value::String(s) => Ok(Value::String(util::html::sanitize(&markdown::to_html(&s)))),
         _ => bail!("markdown: not a string"),
     }
 }
 
+lazy_static! {
+    static ref HTML_TAG: Regex = Regex::new(r"<(/?)([a-zA-Z0-9]+)([^>]*)>").unwrap();
+    static ref LINK_ATTRS: Regex =
+        Regex::new(r#"^ href=["'](https?://|/)[^"'<>]*["']( title=["'][^"'<>]*["'])?$"#).unwrap();
+}
+
+/// Escape `&`, `<`, `>`, `"` and `'`
+pub fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn is_inline_tag(tag: &Captures) -> bool {
+    let closing = !tag[1].is_empty();
+    let attrs = &tag[3];
+    match &tag[2] {
+        "strong" | "em" | "code" => attrs.is_empty(),
+        "a" => if closing { attrs.is_empty() } else { LINK_ATTRS.is_match(attrs) },
+        _ => false,
+    }
+}
+
+/// Render a single line of markdown, allowing only a safe subset of inline elements
+///
+/// Allowed are strong, emphasis, code and links to http(s) or local urls. If the rendered markdown
+/// contains anything else, the escaped text is returned instead.
+pub fn inline_markdown(text: &str) -> String {
+    let line = text.lines().collect::<Vec<&str>>().join(" ");
+    let html = markdown::to_html(line.trim());
+    let html = html.trim();
+    if !html.starts_with("<p>") || !html.ends_with("</p>") {
+        return escape_html(text);
+    }
+    let inner = &html[3..html.len() - 4];
+    if HTML_TAG.captures_iter(inner).all(|tag| is_inline_tag(&tag)) {
+        inner.to_string()
+    } else {
+        escape_html(text)
+    }
+}
+
 pub fn quote(value: Value, _: HashMap<String, Value>) -> Result<Value> {
     match value {
         Value::String(s) => Ok(Value::String(
@@ -177,3 +230,34 @@ pub fn is_allowed(acl: AclContainer) -> GlobalFn {
         Ok(Value::Bool(acl.is_allowed(&user_id, &group_id, &ns, &perm)))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inline_markdown_subset() {
+        assert_eq!("<strong>bold</strong> text", inline_markdown("**bold** text"));
+        assert_eq!("", inline_markdown(""));
+        assert!(inline_markdown("<script>alert(1)</script>").find("<script>").is_none());
+        assert!(inline_markdown("[x](javascript:alert(1))").find("href").is_none());
+        assert!(inline_markdown("# heading").find("<h1>").is_none());
+    }
+
+    #[test]
+    fn markdown_xss() {
+        use util::html::tests::{assert_safe, XSS_PAYLOADS};
+
+        for payload in XSS_PAYLOADS {
+            match markdown(Value::String(payload.to_string()), HashMap::new()) {
+                Ok(Value::String(html)) => assert_safe(payload, &html),
+                _ => panic!("markdown failed for {:?}", payload),
+            }
+        }
+    }
+
+    #[test]
+    fn escape() {
+        assert_eq!("&lt;a href=&quot;x&quot;&gt;&amp;&#x27;", escape_html("<a href=\"x\">&'"));
+    }
+}
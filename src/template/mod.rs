@@ -33,7 +33,7 @@ use serde::Serialize;
 pub type TemplateContainer = Arc<RwLock<Tera>>;
 pub type TemplateSystem = Tera;
 
-mod helper;
+pub mod helper;
 
 /// Initialize the Tera template system
 pub fn init_tera(acl: Arc<RwLock<Acl>>) -> TemplateContainer {
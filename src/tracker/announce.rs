@@ -28,10 +28,17 @@ use uuid::Uuid;
 
 use std::collections::HashMap;
 use std::convert::TryFrom;
-use std::net::{IpAddr};
+use std::net::{IpAddr, SocketAddr};
 use std::str::FromStr;
 
-use models::{self, Torrent, TorrentList, User, torrent::Transfer};
+use models::{self, Torrent, TorrentList, User, torrent::{TorrentReseed, Transfer}};
+use models::bonus::FreeleechTorrent;
+use models::history::{self, ClientHistory, IpHistory};
+use models::moderation::{self, UserWarning};
+use models::notification::{Notification, NotificationKind};
+use super::checker::CheckPeerMsg;
+use super::limiter::AnnounceKey;
+use super::selection::PeerSelection;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Event {
@@ -189,6 +196,29 @@ impl<S> TryFrom<HttpRequest<S>> for AnnounceRequest {
     }
 }
 
+impl AnnounceRequest {
+    /// Get the address of the client
+    pub fn ip_address(&self) -> &IpAddr {
+        &self.ip_address
+    }
+
+    /// Get the key for the announce rate limiter
+    pub fn limiter_key(&self) -> AnnounceKey {
+        let regular = match self.event {
+            Event::None => true,
+            Event::Started | Event::Stopped | Event::Completed => false,
+        };
+
+        AnnounceKey {
+            ip_address: self.ip_address,
+            passcode: &self.passcode,
+            info_hash: &self.info_hash,
+            peer_id: &self.peer_id,
+            regular,
+        }
+    }
+}
+
 impl Message for AnnounceRequest {
     type Result = Result<AnnounceResponse>;
 }
@@ -209,8 +239,23 @@ impl Handler<AnnounceRequest> for DbExecutor {
         let conn = self.conn();
         let mut user =
             User::find_by_passcode(&msg.passcode, &conn).ok_or_else(|| "invalid passcode")?;
+        moderation::check_status(&user, &conn)?;
+        // stopped and completed announces are accepted, so the final transfer stats are recorded
+        let leeching = match msg.event {
+            Event::Stopped | Event::Completed => false,
+            Event::None | Event::Started => msg.left > 0,
+        };
+        if leeching && UserWarning::blocks_downloads(&user.id, &conn) {
+            bail!("downloads are blocked by an active warning");
+        }
         let mut torrent =
             Torrent::find_by_info_hash(&msg.info_hash, &conn).ok_or_else(|| "invalid info hash")?;
+        if let Err(e) = IpHistory::record(&user.id, &msg.ip_address, history::SOURCE_ANNOUNCE, &conn) {
+            warn!("{}", e);
+        }
+        if let Err(e) = ClientHistory::record(&user.id, &msg.peer_id, &msg.user_agent, &conn) {
+            warn!("{}", e);
+        }
         let peer = match models::Peer::find_for_announce(&torrent.id, &user.id, &msg.peer_id, &conn)
             {
                 Some(mut peer) => {
@@ -261,6 +306,7 @@ impl Handler<AnnounceRequest> for DbExecutor {
                             None
                         },
                         updated_at: Utc::now(),
+                        connectable: None,
                     }
                 }
             };
@@ -279,8 +325,6 @@ impl Handler<AnnounceRequest> for DbExecutor {
         trace!("add upload: {}", add_upload);
         trace!("add seed time: {}", add_time_seeded);
 
-        user.downloaded += add_download;
-        user.uploaded += add_upload;
         torrent.last_action = Some(Utc::now());
         torrent.visible = true;
 
@@ -309,16 +353,70 @@ impl Handler<AnnounceRequest> for DbExecutor {
         }
 
         torrent.save(&conn)?;
-        user.save(&conn)?;
+        // downloads of a freeleech torrent are recorded in the transfer, but not in the user stats
+        let user_download = if add_download > 0 && FreeleechTorrent::find_active(&user.id, &torrent.id, &conn).is_some() {
+            0
+        } else {
+            add_download
+        };
+        user.add_transfer(add_upload, user_download, &conn)?;
         transfer.save(&conn)?;
 
-        let want = !peer.seeder;
-        let mut peers = models::Peer::peers_for_torrent(&torrent.id, want, i64::from(msg.numwant), &conn);
-        let rest = msg.numwant - peers.len() as u16;
-        if rest > 0 {
-            let mut peers2 = models::Peer::peers_for_torrent(&torrent.id, !want, i64::from(rest), &conn);
-            peers.append(&mut peers2);
+        // a returning seeder fulfills a pending reseed request
+        match msg.event {
+            Event::Stopped => {}
+            _ if new_peer && peer.seeder => {
+                // the announce is already recorded, a failure here must not fail the announce
+                match TorrentReseed::complete(&torrent.id, &user.id, &conn) {
+                    Ok(Some(ref reseed)) if reseed.user_id != user.id => {
+                        let bonus = SETTINGS.read().map_err(|e| format!("{}", e))?.torrent.reseed_bonus;
+                        if bonus > 0 {
+                            if let Err(e) = user.add_transfer(bonus, 0, &conn) {
+                                warn!("failed to credit the reseed bonus: {}", e);
+                            }
+                        }
+                        let title = format!("Torrent reseeded: {}", torrent.name);
+                        let body = format!("{} is seeding the torrent again.", user.name);
+                        let link = format!("/torrent/{}", torrent.id);
+                        if let Err(e) = Notification::send(&[reseed.user_id], NotificationKind::Reseed, &title, &body, Some(&link), &conn) {
+                            warn!("failed to notify about a reseed: {}", e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("failed to complete the reseed request: {}", e),
+                }
+            }
+            _ => {}
         }
+
+        // only new peers are checked, the result is kept until the peer stops
+        let check_peer = match msg.event {
+            Event::Stopped => None,
+            _ if new_peer => Some(CheckPeerMsg {
+                id: peer.id,
+                addr: SocketAddr::new(msg.ip_address, msg.port),
+                info_hash: msg.info_hash.to_vec(),
+            }),
+            _ => None,
+        };
+
+        // leechers get connectable peers first
+        let want = !peer.seeder;
+        let max_numwant = SETTINGS.read().map_err(|e| format!("{}", e))?.tracker.max_numwant;
+        let selection = PeerSelection::new(user.id, msg.ip_address, msg.require_crypto, want, msg.numwant, max_numwant);
+        let mut peers = if selection.numwant() > 0 {
+            // fetch more random candidates than needed, so the selection has something to choose from
+            let limit = i64::from(max_numwant);
+            let wanted = models::Peer::candidates_for_announce(&torrent.id, want, &user.id, msg.require_crypto, limit, &conn);
+            let others = if wanted.len() < usize::from(selection.numwant()) {
+                models::Peer::candidates_for_announce(&torrent.id, !want, &user.id, msg.require_crypto, limit, &conn)
+            } else {
+                Vec::new()
+            };
+            selection.select(wanted, others, &mut ::rand::thread_rng())
+        } else {
+            Vec::new()
+        };
         // if the client does not support crypto, set the crypto flag for all peers to false,
         // to avoid returning the crypto_port in the serialize step.
         if !(msg.support_crypto || msg.require_crypto) {
@@ -335,6 +433,7 @@ impl Handler<AnnounceRequest> for DbExecutor {
             compact: msg.compact,
             no_peer_id: msg.no_peer_id,
             tracker_id: msg.tracker_id.map(|v| v.to_vec()),
+            check_peer,
         })
     }
 }
@@ -350,6 +449,7 @@ pub struct AnnounceResponse {
     compact: bool,
     no_peer_id: bool,
     crypto_flags: bool,
+    check_peer: Option<CheckPeerMsg>,
 }
 
 impl AnnounceResponse {
@@ -380,4 +480,8 @@ impl AnnounceResponse {
     pub fn crypto_flags(&self) -> bool {
         self.crypto_flags
     }
+
+    pub fn check_peer(&mut self) -> Option<CheckPeerMsg> {
+        self.check_peer.take()
+    }
 }
\ No newline at end of file
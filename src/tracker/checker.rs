@@ -0,0 +1,210 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Peer connectability checks
+//!
+//! When a new peer announces, the tracker tries to open a TCP connection to the peer and performs
+//! a BitTorrent handshake. The result is stored with the peer, so users can see if their client is
+//! reachable and leechers can be handed connectable peers first.
+
+use super::*;
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use db::{DbConn, Pool};
+
+const PROTOCOL: &[u8] = b"BitTorrent protocol";
+const PEER_ID: &[u8] = b"-RA0001-connectcheck";
+const HANDSHAKE_LEN: usize = 68;
+
+/// Check results are shared between all checker threads
+type CheckCache = Arc<Mutex<HashMap<SocketAddr, (bool, Instant)>>>;
+
+/// Checks if peers accept incoming connections
+pub struct PeerChecker {
+    pool: Pool,
+    cache: CheckCache,
+    timeout: Duration,
+    cache_ttl: Duration,
+}
+
+impl PeerChecker {
+    /// Create a new PeerChecker
+    pub fn new(pool: Pool, cache: CheckCache) -> Self {
+        let (timeout, cache_ttl) = {
+            let settings = SETTINGS.read().unwrap();
+            (settings.tracker.connectability_timeout, settings.tracker.connectability_cache_ttl)
+        };
+
+        PeerChecker {
+            pool,
+            cache,
+            timeout: Duration::from_secs(timeout),
+            cache_ttl: Duration::from_secs(cache_ttl),
+        }
+    }
+
+    /// Create a new, empty result cache
+    pub fn new_cache() -> CheckCache {
+        Arc::new(Mutex::new(HashMap::new()))
+    }
+
+    fn cached(&self, addr: &SocketAddr, now: Instant) -> Option<bool> {
+        let mut cache = self.cache.lock().unwrap();
+        let cache_ttl = self.cache_ttl;
+        cache.retain(|_, &mut (_, checked_at)| now.duration_since(checked_at) < cache_ttl);
+        cache.get(addr).map(|&(connectable, _)| connectable)
+    }
+}
+
+impl Actor for PeerChecker {
+    type Context = SyncContext<Self>;
+}
+
+/// Check if the peer `id` is reachable at `addr`
+#[derive(Debug, Clone)]
+pub struct CheckPeerMsg {
+    pub id: Uuid,
+    pub addr: SocketAddr,
+    pub info_hash: Vec<u8>,
+}
+
+impl Message for CheckPeerMsg {
+    type Result = Result<bool>;
+}
+
+impl Handler<CheckPeerMsg> for PeerChecker {
+    type Result = Result<bool>;
+
+    fn handle(&mut self, msg: CheckPeerMsg, _ctx: &mut Self::Context) -> Self::Result {
+        let connectable = match self.cached(&msg.addr, Instant::now()) {
+            Some(connectable) => connectable,
+            None => {
+                let connectable = check_peer(&msg.addr, &msg.info_hash, self.timeout);
+                self.cache.lock().unwrap().insert(msg.addr, (connectable, Instant::now()));
+                connectable
+            }
+        };
+        trace!("peer {} connectable: {}", msg.addr, connectable);
+
+        let conn = DbConn(self.pool.get().chain_err(|| "failed to get db connection")?);
+        models::Peer::set_connectable(&msg.id, connectable, &conn)?;
+
+        Ok(connectable)
+    }
+}
+
+/// Build the handshake message the checker sends to the peer
+pub fn handshake_message(info_hash: &[u8], peer_id: &[u8]) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HANDSHAKE_LEN);
+    message.push(PROTOCOL.len() as u8);
+    message.extend_from_slice(PROTOCOL);
+    message.extend_from_slice(&[0u8; 8]);
+    message.extend_from_slice(info_hash);
+    message.extend_from_slice(peer_id);
+    message
+}
+
+/// Check if `response` is a valid handshake for `info_hash`
+///
+/// The peer id is not checked, since clients are free to withhold it in the handshake.
+pub fn is_valid_handshake(response: &[u8], info_hash: &[u8]) -> bool {
+    let pstrlen = PROTOCOL.len();
+    if response.len() < 1 + pstrlen + 8 + info_hash.len() {
+        return false;
+    }
+    if response[0] as usize != pstrlen || &response[1..=pstrlen] != PROTOCOL {
+        return false;
+    }
+    let offset = 1 + pstrlen + 8;
+    &response[offset..offset + info_hash.len()] == info_hash
+}
+
+/// Connect to the peer and exchange handshakes
+pub fn check_peer(addr: &SocketAddr, info_hash: &[u8], timeout: Duration) -> bool {
+    let mut stream = match TcpStream::connect_timeout(addr, timeout) {
+        Ok(stream) => stream,
+        Err(e) => {
+            trace!("failed to connect to {}: {}", addr, e);
+            return false;
+        }
+    };
+    if stream.set_read_timeout(Some(timeout)).is_err() || stream.set_write_timeout(Some(timeout)).is_err() {
+        return false;
+    }
+    if stream.write_all(&handshake_message(info_hash, PEER_ID)).is_err() {
+        return false;
+    }
+
+    let mut response = vec![0u8; 1 + PROTOCOL.len() + 8 + info_hash.len()];
+    match stream.read_exact(&mut response) {
+        Ok(_) => is_valid_handshake(&response, info_hash),
+        Err(e) => {
+            trace!("failed to read handshake from {}: {}", addr, e);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const INFO_HASH: &[u8] = b"AAAAAAAAAAAAAAAAAAAA";
+
+    #[test]
+    fn handshake() {
+        let message = handshake_message(INFO_HASH, PEER_ID);
+        assert_eq!(HANDSHAKE_LEN, message.len());
+        assert_eq!(19, message[0]);
+        assert!(is_valid_handshake(&message, INFO_HASH));
+        assert_eq!(false, is_valid_handshake(&message, b"BBBBBBBBBBBBBBBBBBBB"));
+        assert_eq!(false, is_valid_handshake(&message[..40], INFO_HASH));
+        assert_eq!(false, is_valid_handshake(b"HTTP/1.1 400 Bad Request\r\n\r\n", INFO_HASH));
+    }
+
+    #[test]
+    fn check_listening_peer() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = vec![0u8; HANDSHAKE_LEN];
+            stream.read_exact(&mut request).unwrap();
+            stream.write_all(&handshake_message(INFO_HASH, b"-XX0001-000000000000")).unwrap();
+        });
+
+        assert!(check_peer(&addr, INFO_HASH, Duration::from_secs(2)));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn check_closed_port() {
+        let addr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        assert_eq!(false, check_peer(&addr, INFO_HASH, Duration::from_secs(2)));
+    }
+}
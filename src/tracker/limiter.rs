@@ -0,0 +1,331 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Announce rate limiting
+//!
+//! The limiter lives in memory, so announces which are rejected never reach the database. It also
+//! enforces the minimum interval between two full scrapes of the same passcode.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use SETTINGS;
+
+const PRUNE_INTERVAL: u64 = 60;
+
+lazy_static! {
+    pub(crate) static ref LIMITER: Mutex<AnnounceLimiter> = Mutex::new(AnnounceLimiter::from_settings());
+}
+
+/// A simple token bucket
+///
+/// The bucket holds at most `capacity` tokens and is refilled with `rate` tokens per second.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    rate: f64,
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    /// Construct a new, full `TokenBucket`
+    pub fn new(capacity: u32, rate: f64, now: Instant) -> Self {
+        let capacity = f64::from(capacity);
+        TokenBucket {
+            capacity,
+            rate,
+            tokens: capacity,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.duration_since(self.updated_at);
+        let elapsed = elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1_000_000_000f64;
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.updated_at = now;
+    }
+
+    /// Take a token from the bucket
+    ///
+    /// Returns `false` if the bucket is empty.
+    pub fn take(&mut self, now: Instant) -> bool {
+        self.refill(now);
+        if self.tokens >= 1f64 {
+            self.tokens -= 1f64;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Check if the bucket would be full at `now`
+    pub fn is_full(&self, now: Instant) -> bool {
+        let mut bucket = self.clone();
+        bucket.refill(now);
+        bucket.tokens >= bucket.capacity
+    }
+}
+
+/// Limiter configuration
+#[derive(Debug, Clone)]
+pub struct LimiterConfig {
+    /// minimum seconds between two regular announces of the same peer
+    pub min_interval: u16,
+    /// size of the per ip address token bucket
+    pub ip_burst: u32,
+    /// refill rate of the per ip address token bucket in tokens per second
+    pub ip_rate: f64,
+    /// size of the per passcode token bucket
+    pub passcode_burst: u32,
+    /// refill rate of the per passcode token bucket in tokens per second
+    pub passcode_rate: f64,
+    /// minimum seconds between two full scrapes of the same passcode
+    pub full_scrape_interval: u16,
+}
+
+/// A single announce as seen by the limiter
+pub struct AnnounceKey<'a> {
+    pub ip_address: IpAddr,
+    pub passcode: &'a [u8],
+    pub info_hash: &'a [u8],
+    pub peer_id: &'a [u8],
+    /// `true` if the announce carries no event, announces with an event bypass the min interval
+    pub regular: bool,
+}
+
+impl<'a> AnnounceKey<'a> {
+    fn peer_key(&self) -> Vec<u8> {
+        let mut key = Vec::with_capacity(self.passcode.len() + self.info_hash.len() + self.peer_id.len());
+        key.extend_from_slice(self.passcode);
+        key.extend_from_slice(self.info_hash);
+        key.extend_from_slice(self.peer_id);
+        key
+    }
+}
+
+/// Tracks the last announce per peer, the last full scrape per passcode and the token buckets per
+/// ip address and passcode.
+pub struct AnnounceLimiter {
+    config: LimiterConfig,
+    last_announce: HashMap<Vec<u8>, Instant>,
+    last_full_scrape: HashMap<Vec<u8>, Instant>,
+    ip_buckets: HashMap<IpAddr, TokenBucket>,
+    passcode_buckets: HashMap<Vec<u8>, TokenBucket>,
+    last_prune: Instant,
+}
+
+impl AnnounceLimiter {
+    /// Construct a new `AnnounceLimiter` instance
+    pub fn new(config: LimiterConfig) -> Self {
+        AnnounceLimiter {
+            config,
+            last_announce: HashMap::new(),
+            last_full_scrape: HashMap::new(),
+            ip_buckets: HashMap::new(),
+            passcode_buckets: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /// Construct a new `AnnounceLimiter` with the tracker settings
+    pub fn from_settings() -> Self {
+        let settings = SETTINGS.read().unwrap();
+        let tracker = &settings.tracker;
+        Self::new(LimiterConfig {
+            min_interval: tracker.min_interval,
+            ip_burst: tracker.ip_burst,
+            ip_rate: tracker.ip_rate,
+            passcode_burst: tracker.passcode_burst,
+            passcode_rate: tracker.passcode_rate,
+            full_scrape_interval: tracker.full_scrape_interval,
+        })
+    }
+
+    /// Check if the announce is allowed
+    ///
+    /// # Errors
+    ///
+    /// Returns the failure reason, if the announce should be rejected.
+    pub fn check(&mut self, announce: &AnnounceKey, now: Instant) -> Result<(), String> {
+        if now.duration_since(self.last_prune) >= Duration::from_secs(PRUNE_INTERVAL) {
+            self.prune(now);
+        }
+
+        let config = &self.config;
+        let allowed = self.ip_buckets
+            .entry(announce.ip_address)
+            .or_insert_with(|| TokenBucket::new(config.ip_burst, config.ip_rate, now))
+            .take(now);
+        if !allowed {
+            return Err("rate limit exceeded".to_string());
+        }
+        let allowed = self.passcode_buckets
+            .entry(announce.passcode.to_vec())
+            .or_insert_with(|| TokenBucket::new(config.passcode_burst, config.passcode_rate, now))
+            .take(now);
+        if !allowed {
+            return Err("rate limit exceeded".to_string());
+        }
+
+        let key = announce.peer_key();
+        let min_interval = Duration::from_secs(u64::from(config.min_interval));
+        if announce.regular {
+            if let Some(last) = self.last_announce.get(&key) {
+                if now.duration_since(*last) < min_interval {
+                    return Err(format!("announce too early, min interval is {} seconds", config.min_interval));
+                }
+            }
+        }
+        self.last_announce.insert(key, now);
+
+        Ok(())
+    }
+
+    /// Check if a full scrape of the passcode is allowed
+    ///
+    /// # Errors
+    ///
+    /// Returns the failure reason, if the last full scrape was less than `full_scrape_interval`
+    /// seconds ago.
+    pub fn check_full_scrape(&mut self, passcode: &[u8], now: Instant) -> Result<(), String> {
+        let interval = Duration::from_secs(u64::from(self.config.full_scrape_interval));
+        if let Some(last) = self.last_full_scrape.get(passcode) {
+            if now.duration_since(*last) < interval {
+                return Err(format!("full scrape too early, min interval is {} seconds", self.config.full_scrape_interval));
+            }
+        }
+        self.last_full_scrape.insert(passcode.to_vec(), now);
+
+        Ok(())
+    }
+
+    /// Remove all entries, which no longer have any effect
+    pub fn prune(&mut self, now: Instant) {
+        let min_interval = Duration::from_secs(u64::from(self.config.min_interval));
+        let full_scrape_interval = Duration::from_secs(u64::from(self.config.full_scrape_interval));
+        self.last_announce.retain(|_, last| now.duration_since(*last) < min_interval);
+        self.last_full_scrape.retain(|_, last| now.duration_since(*last) < full_scrape_interval);
+        self.ip_buckets.retain(|_, bucket| !bucket.is_full(now));
+        self.passcode_buckets.retain(|_, bucket| !bucket.is_full(now));
+        self.last_prune = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    fn config() -> LimiterConfig {
+        LimiterConfig {
+            min_interval: 300,
+            ip_burst: 100,
+            ip_rate: 1f64,
+            passcode_burst: 100,
+            passcode_rate: 1f64,
+            full_scrape_interval: 3600,
+        }
+    }
+
+    fn announce<'a>(passcode: &'a [u8], regular: bool) -> AnnounceKey<'a> {
+        AnnounceKey {
+            ip_address: IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+            passcode,
+            info_hash: b"AAAAAAAAAAAAAAAAAAAA",
+            peer_id: b"-XX0001-000000000000",
+            regular,
+        }
+    }
+
+    #[test]
+    fn token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, 0.5f64, now);
+        assert!(bucket.take(now));
+        assert!(bucket.take(now));
+        assert_eq!(bucket.take(now), false);
+        assert_eq!(bucket.take(now + Duration::from_secs(1)), false);
+        assert!(bucket.take(now + Duration::from_secs(3)));
+        assert_eq!(bucket.is_full(now + Duration::from_secs(3)), false);
+        assert!(bucket.is_full(now + Duration::from_secs(10)));
+    }
+
+    #[test]
+    fn min_interval() {
+        let now = Instant::now();
+        let mut limiter = AnnounceLimiter::new(config());
+        assert!(limiter.check(&announce(b"passcode", true), now).is_ok());
+        assert!(limiter.check(&announce(b"passcode", true), now + Duration::from_secs(10)).is_err());
+        // other peers are not affected
+        assert!(limiter.check(&announce(b"other", true), now + Duration::from_secs(10)).is_ok());
+        assert!(limiter.check(&announce(b"passcode", true), now + Duration::from_secs(300)).is_ok());
+    }
+
+    #[test]
+    fn events_bypass_min_interval() {
+        let now = Instant::now();
+        let mut limiter = AnnounceLimiter::new(config());
+        assert!(limiter.check(&announce(b"passcode", true), now).is_ok());
+        assert!(limiter.check(&announce(b"passcode", false), now + Duration::from_secs(1)).is_ok());
+        // a client restarting the torrent sends `stopped` and `started` right after each other
+        assert!(limiter.check(&announce(b"passcode", false), now + Duration::from_secs(2)).is_ok());
+        assert!(limiter.check(&announce(b"passcode", true), now + Duration::from_secs(3)).is_err());
+    }
+
+    #[test]
+    fn full_scrape_interval() {
+        let now = Instant::now();
+        let mut limiter = AnnounceLimiter::new(config());
+        assert!(limiter.check_full_scrape(b"passcode", now).is_ok());
+        assert!(limiter.check_full_scrape(b"passcode", now + Duration::from_secs(10)).is_err());
+        assert!(limiter.check_full_scrape(b"other", now + Duration::from_secs(10)).is_ok());
+        assert!(limiter.check_full_scrape(b"passcode", now + Duration::from_secs(3600)).is_ok());
+        limiter.prune(now + Duration::from_secs(7201));
+        assert!(limiter.last_full_scrape.is_empty());
+    }
+
+    #[test]
+    fn rate_limit() {
+        let now = Instant::now();
+        let mut config = config();
+        config.passcode_burst = 2;
+        config.passcode_rate = 0f64;
+        let mut limiter = AnnounceLimiter::new(config);
+        assert!(limiter.check(&announce(b"passcode", false), now).is_ok());
+        assert!(limiter.check(&announce(b"passcode", false), now).is_ok());
+        assert_eq!(Err("rate limit exceeded".to_string()), limiter.check(&announce(b"passcode", false), now));
+    }
+
+    #[test]
+    fn prune() {
+        let now = Instant::now();
+        let mut limiter = AnnounceLimiter::new(config());
+        limiter.check(&announce(b"passcode", true), now).unwrap();
+        limiter.prune(now);
+        assert_eq!(1, limiter.last_announce.len());
+        assert_eq!(1, limiter.ip_buckets.len());
+        limiter.prune(now + Duration::from_secs(301));
+        assert!(limiter.last_announce.is_empty());
+        assert!(limiter.ip_buckets.is_empty());
+        assert!(limiter.passcode_buckets.is_empty());
+    }
+}
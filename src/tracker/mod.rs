@@ -24,17 +24,33 @@ use serde_bencode;
 
 use std::convert::TryFrom;
 use std::net::{IpAddr, SocketAddr};
+use std::time::Instant;
+
+use rand::Rng;
 
 use self::announce::{AnnounceRequest, AnnounceResponse};
+use self::checker::PeerChecker;
 use self::scrape::{ScrapeFile, ScrapeRequest, ScrapeResponse};
 use models;
+use models::ban_list::BanList;
 
 mod announce;
+pub mod checker;
+mod limiter;
 mod scrape;
-
-pub fn build(db: Addr<Syn, DbExecutor>) -> App<State> {
-    let acl = Arc::new(RwLock::new(Acl::new()));
-    let state = State::new(db, acl);
+mod selection;
+
+pub fn build(
+    db: Addr<Syn, DbExecutor>,
+    acl: Arc<RwLock<Acl>>,
+    ban_list: Arc<RwLock<BanList>>,
+    peer_checker: Option<Addr<Syn, PeerChecker>>,
+) -> App<State> {
+    let mut state = State::new(db, acl);
+    state.set_ban_list(ban_list);
+    if let Some(peer_checker) = peer_checker {
+        state.set_peer_checker(peer_checker);
+    }
 
     App::with_state(state)
         .middleware(Logger::default())
@@ -43,7 +59,9 @@ pub fn build(db: Addr<Syn, DbExecutor>) -> App<State> {
         .resource("/announce/{passcode}", |r| {
             r.method(Method::GET).f(tracker::announce)
         })
-        .resource("/scrape", |r| r.method(Method::GET).f(tracker::scrape))
+        .resource("/scrape/{passcode}", |r| {
+            r.method(Method::GET).f(tracker::scrape)
+        })
 }
 
 #[derive(Debug, Clone)]
@@ -351,17 +369,37 @@ fn announce(
         Err(e) => return Either::A(BencodeResponse::failure(&format!("{}", e))),
     };
     trace!("AnnounceRequest: {:#?}", announce);
+    if let Err(e) = req.state().ban_list().check_ip(announce.ip_address()) {
+        return Either::A(BencodeResponse::failure(&format!("{}", e)));
+    }
+    {
+        let mut limiter = match limiter::LIMITER.lock() {
+            Ok(limiter) => limiter,
+            Err(e) => return Either::A(BencodeResponse::failure(&format!("{}", e))),
+        };
+        if let Err(reason) = limiter.check(&announce.limiter_key(), Instant::now()) {
+            return Either::A(BencodeResponse::failure(&reason));
+        }
+    }
+    let peer_checker = req.state().peer_checker().cloned();
     Either::B(
         req.state()
             .db()
             .send(announce)
             .from_err()
-            .and_then(|result: Result<AnnounceResponse>| match result {
+            .and_then(move |result: Result<AnnounceResponse>| match result {
                 Ok(mut resp) => {
                     trace!("response data: {:#?}", resp);
+                    if let (Some(peer_checker), Some(check)) = (peer_checker, resp.check_peer()) {
+                        peer_checker.do_send(check);
+                    }
                     let mut data = AnnounceData::default();
-                    data.interval = match SETTINGS.read() {
-                        Ok(s) => s.tracker.interval,
+                    match SETTINGS.read() {
+                        Ok(s) => {
+                            let jitter = ::rand::thread_rng().gen_range(0, u32::from(s.tracker.interval_jitter) + 1);
+                            data.interval = (u32::from(s.tracker.interval) + jitter).min(u32::from(u16::max_value())) as u16;
+                            data.min_interval = Some(s.tracker.min_interval);
+                        }
                         Err(e) => {
                             return Err(actix_web::error::ErrorInternalServerError(format!(
                                 "{}",
@@ -405,7 +443,7 @@ fn scrape(
         Ok(scrape) => scrape,
         Err(e) => return Either::A(BencodeResponse::failure(&format!("{}", e))),
     };
-    trace!("AnnounceRequest: {:#?}", scrape);
+    trace!("ScrapeRequest: {:#?}", scrape);
 
     Either::B(
         req.state()
@@ -417,6 +455,7 @@ fn scrape(
                     trace!("response data: {:#?}", resp);
                     let mut data = ScrapeData::default();
                     data.min_interval = match SETTINGS.read() {
+                        Ok(ref s) if resp.full_scrape => Some(s.tracker.full_scrape_interval),
                         Ok(s) => Some(s.tracker.interval),
                         Err(_) => None,
                     };
@@ -18,49 +18,82 @@
 
 use super::*;
 
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use url::percent_encoding::percent_decode;
 
-#[derive(Debug, Clone)]
+use models::User;
+use models::moderation;
+
+#[derive(Clone)]
 pub struct ScrapeRequest {
     info_hashes: Vec<Vec<u8>>,
+    passcode: Vec<u8>,
+    acl: AclContainer,
+}
+
+impl ScrapeRequest {
+    /// Returns `true` if the client did not ask for specific info hashes
+    pub fn is_full_scrape(&self) -> bool {
+        self.info_hashes.is_empty()
+    }
+}
+
+impl std::fmt::Debug for ScrapeRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("ScrapeRequest")
+            .field("info_hashes", &self.info_hashes)
+            .field("passcode", &self.passcode)
+            .finish()
+    }
 }
 
-impl<S> TryFrom<HttpRequest<S>> for ScrapeRequest {
+impl TryFrom<HttpRequest<State>> for ScrapeRequest {
     type Error = Error;
 
-    fn try_from(req: HttpRequest<S>) -> Result<Self> {
+    fn try_from(req: HttpRequest<State>) -> Result<Self> {
         trace!("Request: {:#?}", req);
         trace!("uri: {:#?}", req.uri());
-        let query_str = req.uri().query().ok_or_else(|| "no query")?;
-        trace!("query: {:#?}", query_str);
-        let iter = query_str.split('&');
-        let mut info_hashes_str: Vec<&str> = Vec::new();
-        for part in iter {
-            if let Some(pos) = part.find('=') {
-                let key = &part[0..pos];
-                if key == "info_hash" {
-                    let value = &part[pos + 1..];
-                    info_hashes_str.push(value);
-                }
-            }
-        }
-        trace!("info_hashes: {:#?}", info_hashes_str);
-        let mut info_hashes: Vec<Vec<u8>> = Vec::new();
-        for info_hash in info_hashes_str {
-            let info_hash = percent_decode(info_hash.as_bytes()).if_any();
-            if let Some(info_hash) = info_hash {
-                info_hashes.push(info_hash);
-            }
+        let info_hashes = info_hashes(req.uri().query().unwrap_or_default());
+        trace!("info_hashes: {:#?}", info_hashes);
+
+        let max_hashes = SETTINGS
+            .read()
+            .map_err(|e| format!("{}", e))?
+            .tracker
+            .max_scrape_hashes;
+        if info_hashes.len() > max_hashes {
+            bail!("too many info hashes (max: {})", max_hashes);
         }
 
-        if info_hashes.is_empty() {
-            bail!("no info hashes provided");
-        }
+        let passcode = req.match_info()
+            .get("passcode")
+            .ok_or_else(|| "passcode not in query")?;
+        let passcode = util::from_hex(passcode)?;
+
+        Ok(ScrapeRequest{info_hashes, passcode, acl: req.state().acl().clone()})
+    }
+}
 
-        Ok(ScrapeRequest{info_hashes})
+/// Extract all `info_hash` values from the query string
+fn info_hashes(query_str: &str) -> Vec<Vec<u8>> {
+    trace!("query: {:#?}", query_str);
+    let iter = query_str.split('&');
+    let mut info_hashes: Vec<Vec<u8>> = Vec::new();
+    for part in iter {
+        if let Some(pos) = part.find('=') {
+            let key = &part[0..pos];
+            if key == "info_hash" {
+                let value = &part[pos + 1..];
+                if let Some(info_hash) = percent_decode(value.as_bytes()).if_any() {
+                    info_hashes.push(info_hash);
+                }
+            }
+        }
     }
+
+    info_hashes
 }
 
 impl Message for ScrapeRequest {
@@ -72,20 +105,46 @@ impl Handler<ScrapeRequest> for DbExecutor {
 
     fn handle(&mut self, msg: ScrapeRequest, _ctx: &mut Self::Context) -> <Self as Handler<ScrapeRequest>>::Result {
         let conn = self.conn();
-        let mut files: Vec<ScrapeFile> = Vec::new();
+        let user = User::find_by_passcode(&msg.passcode, &conn).ok_or_else(|| "invalid passcode")?;
+        moderation::check_status(&user, &conn)?;
+
+        if msg.is_full_scrape() {
+            let full_scrape = SETTINGS.read().map_err(|e| format!("{}", e))?.tracker.full_scrape;
+            if !full_scrape || !msg.acl.is_allowed(&user.id, &user.group_id, "tracker#full_scrape", &Permission::Read) {
+                bail!("full scrape is not allowed");
+            }
+            super::limiter::LIMITER
+                .lock()
+                .map_err(|e| format!("{}", e))?
+                .check_full_scrape(&msg.passcode, Instant::now())?;
 
+            let files = models::TorrentList::scrape_all(&conn)
+                .into_iter()
+                .map(|(info_hash, complete, incomplete, downloaded)| ScrapeFile{info_hash, complete, incomplete, downloaded})
+                .collect();
+
+            return Ok(ScrapeResponse{files, full_scrape: true});
+        }
+
+        let mut counts: HashMap<Vec<u8>, (i64, i64, i32)> = models::TorrentList::peer_count_scrape(&msg.info_hashes, &conn)
+            .into_iter()
+            .map(|(info_hash, complete, incomplete, downloaded)| (info_hash, (complete, incomplete, downloaded)))
+            .collect();
+
+        let mut files: Vec<ScrapeFile> = Vec::with_capacity(msg.info_hashes.len());
         for info_hash in msg.info_hashes {
-            let (complete, incomplete, downloaded) = models::TorrentList::peer_count_scrape(&info_hash, &conn);
+            let (complete, incomplete, downloaded) = counts.remove(&info_hash).unwrap_or_default();
             files.push(ScrapeFile{info_hash, complete, incomplete, downloaded});
         }
 
-        Ok(ScrapeResponse{files})
+        Ok(ScrapeResponse{files, full_scrape: false})
     }
 }
 
 #[derive(Debug)]
 pub struct ScrapeResponse {
     pub files: Vec<ScrapeFile>,
+    pub full_scrape: bool,
 }
 
 #[derive(Debug)]
@@ -126,4 +185,15 @@ mod tests {
         let actual = serde_bencode::to_string(&file).unwrap();
         assert_eq!(expected, actual);
     }
+
+    #[test]
+    fn parse_info_hashes() {
+        let query = "info_hash=%41%41%41%41%41%41%41%41%41%41%41%41%41%41%41%41%41%41%41%41&passkey=foo&info_hash=%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E%4E";
+        let expected = vec![[65u8; 20].to_vec(), [78u8; 20].to_vec()];
+        assert_eq!(expected, info_hashes(query));
+
+        let expected: Vec<Vec<u8>> = Vec::new();
+        assert_eq!(expected, info_hashes(""));
+        assert_eq!(expected, info_hashes("passkey=foo&info_hash"));
+    }
 }
\ No newline at end of file
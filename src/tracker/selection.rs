@@ -0,0 +1,240 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Peer selection for announce responses
+
+use std::net::IpAddr;
+
+use rand::Rng;
+use uuid::Uuid;
+
+use models::Peer;
+
+/// Selects the peers returned to an announcing client
+#[derive(Debug, Clone)]
+pub struct PeerSelection {
+    user_id: Uuid,
+    ip_address: IpAddr,
+    require_crypto: bool,
+    prefer_connectable: bool,
+    numwant: u16,
+}
+
+impl PeerSelection {
+    /// Create a new PeerSelection
+    ///
+    /// `numwant` is capped to `max_numwant`.
+    pub fn new(
+        user_id: Uuid,
+        ip_address: IpAddr,
+        require_crypto: bool,
+        prefer_connectable: bool,
+        numwant: u16,
+        max_numwant: u16,
+    ) -> Self {
+        PeerSelection {
+            user_id,
+            ip_address,
+            require_crypto,
+            prefer_connectable,
+            numwant: numwant.min(max_numwant),
+        }
+    }
+
+    /// Get the number of peers to return
+    pub fn numwant(&self) -> u16 {
+        self.numwant
+    }
+
+    /// Check if `peer` may be returned to the client
+    pub fn accepts(&self, peer: &Peer) -> bool {
+        peer.user_id != self.user_id && (!self.require_crypto || peer.crypto_enabled)
+    }
+
+    /// Peers with a lower rank are returned first
+    fn rank(&self, peer: &Peer) -> (bool, bool) {
+        let other_family = peer.ip_address.ip().is_ipv4() != self.ip_address.is_ipv4();
+        let not_connectable = self.prefer_connectable && peer.connectable != Some(true);
+        (other_family, not_connectable)
+    }
+
+    fn order<R: Rng>(&self, peers: Vec<Peer>, rng: &mut R) -> Vec<Peer> {
+        let mut peers: Vec<Peer> = peers.into_iter().filter(|p| self.accepts(p)).collect();
+        rng.shuffle(&mut peers);
+        // the sort is stable, so peers with the same rank stay shuffled
+        peers.sort_by_key(|p| self.rank(p));
+        peers
+    }
+
+    /// Select at most `numwant` peers
+    ///
+    /// `wanted` are peers in the opposite seed state of the client, `others` are only used if not
+    /// enough `wanted` peers are available.
+    pub fn select<R: Rng>(&self, wanted: Vec<Peer>, others: Vec<Peer>, rng: &mut R) -> Vec<Peer> {
+        let numwant = usize::from(self.numwant);
+        let mut peers = self.order(wanted, rng);
+        peers.truncate(numwant);
+        if peers.len() < numwant {
+            let rest = numwant - peers.len();
+            peers.extend(self.order(others, rng).into_iter().take(rest));
+        }
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+    use ipnetwork::IpNetwork;
+    use rand::{SeedableRng, XorShiftRng};
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn v4() -> IpAddr {
+        IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1))
+    }
+
+    fn v6() -> IpAddr {
+        IpAddr::V6(Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1))
+    }
+
+    fn peer(user_id: Uuid, ip_address: IpAddr, crypto_enabled: bool, connectable: Option<bool>) -> Peer {
+        Peer {
+            id: Uuid::new_v4(),
+            torrent_id: Uuid::nil(),
+            user_id,
+            ip_address: IpNetwork::from(ip_address),
+            port: 6881,
+            bytes_uploaded: 0,
+            bytes_downloaded: 0,
+            bytes_left: 0,
+            seeder: true,
+            peer_id: b"-XX0001-000000000000".to_vec(),
+            user_agent: "test".to_string(),
+            crypto_enabled,
+            crypto_port: None,
+            offset_uploaded: 0,
+            offset_downloaded: 0,
+            created_at: Utc::now(),
+            finished_at: None,
+            updated_at: Utc::now(),
+            connectable,
+        }
+    }
+
+    fn peers(n: usize) -> Vec<Peer> {
+        (0..n).map(|_| peer(Uuid::new_v4(), v4(), false, None)).collect()
+    }
+
+    fn ids(peers: &[Peer]) -> Vec<Uuid> {
+        peers.iter().map(|p| p.id).collect()
+    }
+
+    fn rng() -> XorShiftRng {
+        XorShiftRng::from_seed([1, 2, 3, 4])
+    }
+
+    #[test]
+    fn cap_numwant() {
+        let selection = PeerSelection::new(Uuid::new_v4(), v4(), false, false, 200, 50);
+        assert_eq!(50, selection.numwant());
+        let selection = PeerSelection::new(Uuid::new_v4(), v4(), false, false, 10, 50);
+        assert_eq!(10, selection.numwant());
+
+        let selection = PeerSelection::new(Uuid::new_v4(), v4(), false, false, 200, 5);
+        assert_eq!(5, selection.select(peers(10), peers(10), &mut rng()).len());
+    }
+
+    #[test]
+    fn exclude_self() {
+        let user_id = Uuid::new_v4();
+        let selection = PeerSelection::new(user_id, v4(), false, false, 50, 50);
+        let mut wanted = peers(3);
+        wanted.push(peer(user_id, v4(), false, None));
+        let others = vec![peer(user_id, v6(), false, None)];
+
+        let selected = selection.select(wanted, others, &mut rng());
+        assert_eq!(3, selected.len());
+        assert!(selected.iter().all(|p| p.user_id != user_id));
+    }
+
+    #[test]
+    fn shuffle() {
+        let selection = PeerSelection::new(Uuid::new_v4(), v4(), false, false, 50, 50);
+        let wanted = peers(20);
+        let expected = ids(&wanted);
+
+        let selected = ids(&selection.select(wanted, Vec::new(), &mut rng()));
+        assert_ne!(expected, selected);
+        let mut expected = expected;
+        let mut selected = selected;
+        expected.sort();
+        selected.sort();
+        assert_eq!(expected, selected);
+    }
+
+    #[test]
+    fn prefer_address_family() {
+        let selection = PeerSelection::new(Uuid::new_v4(), v6(), false, false, 3, 50);
+        let mut wanted = peers(5);
+        wanted.push(peer(Uuid::new_v4(), v6(), false, None));
+        wanted.push(peer(Uuid::new_v4(), v6(), false, None));
+
+        let selected = selection.select(wanted, Vec::new(), &mut rng());
+        assert_eq!(3, selected.len());
+        assert!(selected[0].ip_address.is_ipv6());
+        assert!(selected[1].ip_address.is_ipv6());
+        assert!(selected[2].ip_address.is_ipv4());
+    }
+
+    #[test]
+    fn require_crypto() {
+        let selection = PeerSelection::new(Uuid::new_v4(), v4(), true, false, 50, 50);
+        let mut wanted = peers(3);
+        wanted.push(peer(Uuid::new_v4(), v4(), true, None));
+        let others = vec![peer(Uuid::new_v4(), v4(), true, None), peer(Uuid::new_v4(), v4(), false, None)];
+
+        let selected = selection.select(wanted, others, &mut rng());
+        assert_eq!(2, selected.len());
+        assert!(selected.iter().all(|p| p.crypto_enabled));
+    }
+
+    #[test]
+    fn prefer_connectable() {
+        let selection = PeerSelection::new(Uuid::new_v4(), v4(), false, true, 2, 50);
+        let mut wanted = peers(5);
+        wanted.push(peer(Uuid::new_v4(), v4(), false, Some(true)));
+        wanted.push(peer(Uuid::new_v4(), v4(), false, Some(true)));
+
+        let selected = selection.select(wanted, Vec::new(), &mut rng());
+        assert!(selected.iter().all(|p| p.connectable == Some(true)));
+    }
+
+    #[test]
+    fn top_up_with_others() {
+        let selection = PeerSelection::new(Uuid::new_v4(), v4(), false, false, 5, 50);
+        let wanted = peers(3);
+        let wanted_ids = ids(&wanted);
+
+        let selected = selection.select(wanted, peers(5), &mut rng());
+        assert_eq!(5, selected.len());
+        for id in &ids(&selected[..3]) {
+            assert!(wanted_ids.contains(id));
+        }
+    }
+}
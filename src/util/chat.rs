@@ -0,0 +1,166 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! Chat message parsing and rendering
+//!
+//! Supported are `/me` actions, `@username` mentions and torrent links (`torrent:<id>`). The rest
+//! of the message is rendered with [inline_markdown](../../template/helper/fn.inline_markdown.html).
+
+use std::collections::HashMap;
+
+use regex::{Captures, Regex};
+use uuid::Uuid;
+
+use template::helper::{escape_html, inline_markdown};
+
+const ACTION_PREFIX: &str = "/me ";
+/// Maximum number of mentions and torrent links resolved per message
+pub const MAX_REFERENCES: usize = 10;
+
+lazy_static! {
+    static ref REFERENCE: Regex = Regex::new(
+        r"\B@(?P<user>[a-zA-Z][a-zA-Z0-9_\-]+)|\btorrent:(?P<torrent>[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12})\b"
+    ).unwrap();
+    static ref PLACEHOLDER: Regex = Regex::new("\u{e000}([0-9]+)\u{e001}").unwrap();
+}
+
+/// A parsed chat message
+#[derive(Debug, Default, PartialEq)]
+pub struct ParsedMessage {
+    /// `true` if the message starts with `/me`
+    pub action: bool,
+    /// the message without the `/me` prefix
+    pub body: String,
+    /// the mentioned user names, without duplicates
+    pub mentions: Vec<String>,
+    /// the linked torrent ids, without duplicates
+    pub torrents: Vec<Uuid>,
+}
+
+/// Parse a chat message
+pub fn parse(message: &str) -> ParsedMessage {
+    let message = message.trim();
+    let (action, body) = if message.starts_with(ACTION_PREFIX) {
+        (true, message[ACTION_PREFIX.len()..].trim_left())
+    } else {
+        (false, message)
+    };
+
+    let mut parsed = ParsedMessage {
+        action,
+        body: body.to_string(),
+        ..Default::default()
+    };
+    for reference in REFERENCE.captures_iter(body) {
+        if let Some(user) = reference.name("user") {
+            let user = user.as_str().to_string();
+            if !parsed.mentions.contains(&user) && parsed.mentions.len() < MAX_REFERENCES {
+                parsed.mentions.push(user);
+            }
+        }
+        if let Some(torrent) = reference.name("torrent").and_then(|id| Uuid::parse_str(id.as_str()).ok()) {
+            if !parsed.torrents.contains(&torrent) && parsed.torrents.len() < MAX_REFERENCES {
+                parsed.torrents.push(torrent);
+            }
+        }
+    }
+
+    parsed
+}
+
+/// Render the HTML of a parsed message
+///
+/// `users` maps the mentioned user names to their ids, `torrents` maps the torrent ids to their
+/// names. Unknown references are rendered as text.
+pub fn render(parsed: &ParsedMessage, users: &HashMap<String, Uuid>, torrents: &HashMap<Uuid, String>) -> String {
+    // references are replaced by placeholders, so markdown does not touch them
+    let mut references = Vec::new();
+    let body = REFERENCE.replace_all(&parsed.body, |reference: &Captures| {
+        let html = if let Some(user) = reference.name("user") {
+            match users.get(user.as_str()) {
+                Some(id) => format!(
+                    r#"<a class="chat-mention" data-user-id="{}" href="/user/{}">@{}</a>"#,
+                    id,
+                    id,
+                    escape_html(user.as_str())
+                ),
+                None => escape_html(&reference[0]),
+            }
+        } else {
+            let id = Uuid::parse_str(&reference["torrent"]).ok();
+            match id.and_then(|id| torrents.get(&id).map(|name| (id, name))) {
+                Some((id, name)) => format!(r#"<a class="chat-torrent" href="/torrent/{}">{}</a>"#, id, escape_html(name)),
+                None => escape_html(&reference[0]),
+            }
+        };
+        references.push(html);
+        format!("\u{e000}{}\u{e001}", references.len() - 1)
+    });
+
+    let html = inline_markdown(&body);
+    PLACEHOLDER
+        .replace_all(&html, |placeholder: &Captures| {
+            placeholder[1]
+                .parse::<usize>()
+                .ok()
+                .and_then(|i| references.get(i).cloned())
+                .unwrap_or_default()
+        })
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_action() {
+        let parsed = parse("/me waves");
+        assert!(parsed.action);
+        assert_eq!("waves", parsed.body);
+
+        let parsed = parse("/mean");
+        assert!(!parsed.action);
+        assert_eq!("/mean", parsed.body);
+    }
+
+    #[test]
+    fn parse_references() {
+        let id = Uuid::new_v4();
+        let parsed = parse(&format!("@alice @bob_1 @alice mail@example.com torrent:{} torrent:{}", id, id));
+        assert_eq!(vec!["alice".to_string(), "bob_1".to_string()], parsed.mentions);
+        assert_eq!(vec![id], parsed.torrents);
+    }
+
+    #[test]
+    fn render_references() {
+        let user_id = Uuid::new_v4();
+        let torrent_id = Uuid::new_v4();
+        let mut users = HashMap::new();
+        users.insert("alice".to_string(), user_id);
+        let mut torrents = HashMap::new();
+        torrents.insert(torrent_id, "<Some Torrent>".to_string());
+
+        let parsed = parse(&format!("hi @alice and @bob, see torrent:{}", torrent_id));
+        let html = render(&parsed, &users, &torrents);
+        assert!(html.contains(&format!(r#"data-user-id="{}""#, user_id)));
+        assert!(html.contains("@bob"));
+        assert!(html.contains(&format!(r#"<a class="chat-torrent" href="/torrent/{}">&lt;Some Torrent&gt;</a>"#, torrent_id)));
+        assert!(!html.contains('\u{e000}'));
+    }
+}
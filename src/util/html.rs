@@ -0,0 +1,118 @@
+/*
+ * ripalt
+ * Copyright (C) 2018 Daniel Müller
+ *
+ * This program is free software: you can redistribute it and/or modify
+ * it under the terms of the GNU General Public License as published by
+ * the Free Software Foundation, either version 3 of the License, or
+ * (at your option) any later version.
+ *
+ * This program is distributed in the hope that it will be useful,
+ * but WITHOUT ANY WARRANTY; without even the implied warranty of
+ * MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+ * GNU General Public License for more details.
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program.  If not, see <http://www.gnu.org/licenses/>.
+ */
+
+//! HTML sanitization for user content
+
+use ammonia::Builder;
+
+/// Tags produced by the markdown renderer
+const TAGS: &[&str] = &[
+    "a", "blockquote", "br", "code", "del", "em", "h1", "h2", "h3", "h4", "h5", "h6", "hr", "img", "li", "ol", "p",
+    "pre", "strong", "table", "tbody", "td", "th", "thead", "tr", "ul",
+];
+
+/// Attributes allowed on all tags
+const GENERIC_ATTRIBUTES: &[&str] = &["class", "lang", "title"];
+
+/// Allowed url schemes for links and images
+const URL_SCHEMES: &[&str] = &["http", "https", "ftp", "magnet", "mailto"];
+
+/// Sanitize rendered HTML
+///
+/// Only whitelisted tags and attributes are kept, scripts, event handlers, styles and urls with
+/// other schemes (e.g. `javascript:`) are removed.
+pub fn sanitize(html: &str) -> String {
+    Builder::default()
+        .tags(TAGS.iter().cloned().collect())
+        .url_schemes(URL_SCHEMES.iter().cloned().collect())
+        .generic_attributes(GENERIC_ATTRIBUTES.iter().cloned().collect())
+        .link_rel(Some("noopener noreferrer nofollow"))
+        .clean(html)
+        .to_string()
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use regex::Regex;
+
+    /// Known XSS payloads
+    pub const XSS_PAYLOADS: &[&str] = &[
+        "<script>alert(1)</script>",
+        "<SCRIPT SRC=//evil.example/xss.js></SCRIPT>",
+        "<img src=x onerror=alert(1)>",
+        "<img src=\"javascript:alert(1)\">",
+        "<svg onload=alert(1)>",
+        "<svg><script>alert(1)</script></svg>",
+        "<body onload=alert(1)>",
+        "<iframe src=\"javascript:alert(1)\"></iframe>",
+        "<object data=\"javascript:alert(1)\"></object>",
+        "<embed src=\"javascript:alert(1)\">",
+        "<a href=\"javascript:alert(1)\">x</a>",
+        "<a href=\"jav&#x09;ascript:alert(1)\">x</a>",
+        "<a href=\"JaVaScRiPt:alert(1)\">x</a>",
+        "<a href=\"vbscript:msgbox(1)\">x</a>",
+        "<a href=\"data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==\">x</a>",
+        "<a href=\"#\" onclick=\"alert(1)\">x</a>",
+        "<div style=\"background:url(javascript:alert(1))\">x</div>",
+        "<style>body{background:url(javascript:alert(1))}</style>",
+        "<meta http-equiv=\"refresh\" content=\"0;url=javascript:alert(1)\">",
+        "<form action=\"javascript:alert(1)\"><input type=submit></form>",
+        "<math><mi xlink:href=\"javascript:alert(1)\">x</mi></math>",
+        "<details open ontoggle=alert(1)>",
+        "\"><script>alert(1)</script>",
+        "<scr<script>ipt>alert(1)</scr</script>ipt>",
+        "[x](javascript:alert(1))",
+        "[x](JAVASCRIPT:alert(1))",
+        "![x](javascript:alert(1))",
+        "[x](data:text/html;base64,PHNjcmlwdD5hbGVydCgxKTwvc2NyaXB0Pg==)",
+        "[x](\"onmouseover=\"alert(1))",
+        "<http://x.example/\"onmouseover=\"alert(1)>",
+    ];
+
+    /// Panics if `html` contains dangerous tags, event handlers or urls
+    pub fn assert_safe(payload: &str, html: &str) {
+        let tags = Regex::new(r"(?i)<\s*/?\s*(script|iframe|object|embed|svg|math|style|meta|form|body|details)\b").unwrap();
+        let handlers = Regex::new(r#"(?i)<[^>]*[\s"'/]on[a-z]+\s*="#).unwrap();
+        let urls = Regex::new(r#"(?i)(href|src|action|data)\s*=\s*["']?\s*(javascript|vbscript|data):"#).unwrap();
+        let style = Regex::new(r#"(?i)<[^>]*\sstyle\s*="#).unwrap();
+
+        assert!(!tags.is_match(html), "dangerous tag for {:?}: {}", payload, html);
+        assert!(!handlers.is_match(html), "event handler for {:?}: {}", payload, html);
+        assert!(!urls.is_match(html), "dangerous url for {:?}: {}", payload, html);
+        assert!(!style.is_match(html), "style attribute for {:?}: {}", payload, html);
+    }
+
+    #[test]
+    fn xss_payloads() {
+        for payload in XSS_PAYLOADS {
+            assert_safe(payload, &sanitize(payload));
+        }
+    }
+
+    #[test]
+    fn keep_markdown() {
+        let html = "<h1>Title</h1><p><strong>bold</strong> <em>em</em> <code>code</code></p><ul><li>item</li></ul>";
+        assert_eq!(html, sanitize(html));
+        assert_eq!(
+            "<a href=\"https://example.com/\" rel=\"noopener noreferrer nofollow\">link</a>",
+            sanitize("<a href=\"https://example.com/\">link</a>")
+        );
+        assert_eq!("<img src=\"https://example.com/x.png\" alt=\"x\">", sanitize("<img src=\"https://example.com/x.png\" alt=\"x\">"));
+    }
+}
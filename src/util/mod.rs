@@ -18,6 +18,8 @@
 
 //! Utility functions for ripalt
 
+pub mod chat;
+pub mod html;
 pub mod rand;
 pub mod password;
 pub mod torrent;
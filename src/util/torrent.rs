@@ -116,12 +116,29 @@ pub fn files(data: &[u8]) -> Result<Vec<(String, i64)>> {
     }
 }
 
-pub fn rewrite(data: &[u8], announce_url: &str, comment: &str) -> Result<Vec<u8>> {
+/// Rewrite a torrent meta file for download
+///
+/// The `announce` url and the `comment` are replaced. If `backup_urls` are given, an `announce-list`
+/// with the `announce_url` in the first tier and every backup url in a tier of its own is written,
+/// otherwise any uploaded `announce-list` is removed.
+/// Web seeds (`url-list`) are removed unless `web_seeds` is `true`.
+pub fn rewrite(data: &[u8], announce_url: &str, backup_urls: &[String], comment: &str, web_seeds: bool) -> Result<Vec<u8>> {
     let value = serde_bencode::from_bytes(data)?;
     if let Value::Dict(mut root) = value {
         root.insert(b"announce".to_vec(), Value::Bytes(announce_url.as_bytes().to_vec()));
         root.insert(b"comment".to_vec(), Value::Bytes(comment.as_bytes().to_vec()));
-        root.remove(b"announce-list".as_ref());
+        if backup_urls.is_empty() {
+            root.remove(b"announce-list".as_ref());
+        } else {
+            let mut tiers = vec![Value::List(vec![Value::Bytes(announce_url.as_bytes().to_vec())])];
+            for url in backup_urls {
+                tiers.push(Value::List(vec![Value::Bytes(url.as_bytes().to_vec())]));
+            }
+            root.insert(b"announce-list".to_vec(), Value::List(tiers));
+        }
+        if !web_seeds {
+            root.remove(b"url-list".as_ref());
+        }
 
         serde_bencode::to_bytes(&Value::Dict(root))
             .map_err(|e| e.into())
@@ -172,6 +189,45 @@ mod tests {
         assert_eq!(util::to_hex(&calculated), ih);
     }
 
+    fn rewritten(data: &[u8]) -> ::std::collections::HashMap<Vec<u8>, Value> {
+        match serde_bencode::from_bytes(data).unwrap() {
+            Value::Dict(root) => root,
+            _ => panic!("meta file is no dict"),
+        }
+    }
+
+    #[test]
+    fn test_rewrite() {
+        let t = b"d8:announce15:http://other/an13:announce-listll15:http://other/aneee4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:ee".to_vec();
+        let root = rewritten(&rewrite(&t, "http://tracker/announce/abc", &[], "comment", true).unwrap());
+        assert_eq!(Some(&Value::Bytes(b"http://tracker/announce/abc".to_vec())), root.get(&b"announce".to_vec()));
+        assert_eq!(Some(&Value::Bytes(b"comment".to_vec())), root.get(&b"comment".to_vec()));
+        assert_eq!(None, root.get(&b"announce-list".to_vec()));
+        assert_eq!(util::to_hex(&info_hash(&t).unwrap()), util::to_hex(&info_hash(&serde_bencode::to_bytes(&Value::Dict(root)).unwrap()).unwrap()));
+    }
+
+    #[test]
+    fn test_rewrite_announce_list() {
+        let (_, t) = single_file_torrent();
+        let backup_urls = vec!["https://tracker/announce/abc".to_string(), "udp://tracker:6969/abc".to_string()];
+        let root = rewritten(&rewrite(&t, "http://tracker/announce/abc", &backup_urls, "", true).unwrap());
+        let expected = Value::List(vec![
+            Value::List(vec![Value::Bytes(b"http://tracker/announce/abc".to_vec())]),
+            Value::List(vec![Value::Bytes(b"https://tracker/announce/abc".to_vec())]),
+            Value::List(vec![Value::Bytes(b"udp://tracker:6969/abc".to_vec())]),
+        ]);
+        assert_eq!(Some(&expected), root.get(&b"announce-list".to_vec()));
+    }
+
+    #[test]
+    fn test_rewrite_web_seeds() {
+        let t = b"d4:infod6:lengthi1e4:name1:a12:piece lengthi1e6:pieces0:e8:url-listl17:http://seed/file/ee".to_vec();
+        let root = rewritten(&rewrite(&t, "http://tracker/announce/abc", &[], "", true).unwrap());
+        assert!(root.contains_key(&b"url-list".to_vec()));
+        let root = rewritten(&rewrite(&t, "http://tracker/announce/abc", &[], "", false).unwrap());
+        assert!(!root.contains_key(&b"url-list".to_vec()));
+    }
+
     #[test]
     fn test_files_single() {
         let (_, t) = single_file_torrent();
@@ -17,6 +17,7 @@
  */
 
 use diesel::PgConnection;
+use models::message::AcceptMessages;
 use models::user::Property;
 use uuid::Uuid;
 
@@ -31,3 +32,13 @@ pub fn user_timezone(user_id: &Uuid, db: &PgConnection) -> i32 {
 
     SETTINGS.read().unwrap().user.default_timezone
 }
+
+pub fn user_accept_messages(user_id: &Uuid, db: &PgConnection) -> AcceptMessages {
+    if let Some(prop) = Property::find(user_id, "accept_messages", db) {
+        if let Some(value) = prop.value().as_str() {
+            return AcceptMessages::from(value);
+        }
+    }
+
+    AcceptMessages::from(&SETTINGS.read().unwrap().user.default_accept_messages[..])
+}
@@ -50,17 +50,34 @@ function post_json(url, data) {
 }
 
 function shoutbox_add_line(target, data) {
+    if (data.deleted_at) {
+        $(`li#cm-${data.id}`).remove();
+        return;
+    }
     if ($(`li#cm-${data.id}`).length !== 0) {
         return;
     }
     const options = {hour: '2-digit', minute: '2-digit', second: '2-digit'};
     const date = new Date(data.created_at);
+    const action = data.message.startsWith('/me ');
+    let message = data.rendered ? data.rendered : inline_markdown(action ? data.message.substr(4) : data.message);
+    let user = `<a class="user-group-${data.user_group}" href="/user/${data.user_id}">${data.user_name}</a>`;
     let line = $(`<li id="cm-${data.id}" class="shoutbox-line">`)
         .append($(`<span class="shoutbox-date">[${date.toLocaleString('de-DE', options)}]</span>`))
         .append(' ')
-        .append($(`<span class="shoutbox-user">&lt;<a class="user-group-${data.user_group}" href="/user/${data.user_id}">${data.user_name}</a>&gt;</span>`))
+        .append($(`<span class="shoutbox-user">${action ? `* ${user}` : `&lt;${user}&gt;`}</span>`))
         .append(' ')
-        .append($('<span class="shoutbox-message">').html(inline_markdown(data.message)));
+        .append($('<span class="shoutbox-message">').html(message));
+    line.attr('data-id', data.id);
+    if (action) {
+        line.addClass('shoutbox-action');
+    }
+    if (window.current_user_id && $(`a.chat-mention[data-user-id="${window.current_user_id}"]`, line).length !== 0) {
+        line.addClass('shoutbox-mention');
+    }
+    if (target.parent().attr('data-moderate') === 'true') {
+        line.append(' ').append($('<a href="#" class="shoutbox-delete text-danger small" title="Delete">&times;</a>'));
+    }
 
     target.append(line);
 }
@@ -79,6 +96,9 @@ function inline_markdown(text) {
             console.log('Error parsing url: ', err);
             return match;
         }
+        if (real_url.protocol !== 'http:' && real_url.protocol !== 'https:') {
+            return match;
+        }
         return `<a href="${real_url.href}" target="_blank">${name}</a>`;
     });
 
@@ -119,13 +139,80 @@ function update_messages() {
         .catch(error => console.error('update_messages(): error', error));
 }
 
+function update_notifications() {
+    get_json('/api/v1/notification/unread')
+        .then(data => {
+            let navbar = $('#navbar-notifications');
+            navbar.text(data.unread.toFixed(0));
+            if (data.unread > 0) {
+                navbar.addClass('font-weight-bold');
+                navbar.parent().addClass('text-warning');
+            } else {
+                navbar.removeClass('font-weight-bold');
+                navbar.parent().removeClass('text-warning');
+            }
+        })
+        .catch(error => console.error('update_notifications(): error', error));
+}
+
+function notifications_mark(notifications) {
+    post_json('/api/v1/notification/mark_read', {'notifications': notifications})
+        .then((data) => {
+            if (data.error !== undefined) {
+                $('#notification-error').removeClass('d-none').text(data.error);
+                return;
+            }
+            if (notifications.length === 0) {
+                $('#notification-list li').removeClass('font-weight-bold');
+            }
+            for (let id of notifications) {
+                $(`#notification-${id}`).removeClass('font-weight-bold');
+            }
+            update_notifications();
+        })
+        .catch(error => $('#notification-error').removeClass('d-none').text(error));
+}
+
+function shoutbox_append(chat, data, notify) {
+    let target = $(`#shoutbox-${chat.id}>ul`);
+    // skip messages, which were already received via polling or the WebSocket
+    data = data.filter(message => message.deleted_at || $(`li#cm-${message.id}`).length === 0);
+    if (notify && data.some(message => !message.deleted_at)) {
+        let badge = $(`#${chat.id}-tab:not([class*=active]) span.badge`);
+        if (badge.length === 1) {
+            let new_message = data.filter(message => !message.deleted_at).length;
+            if (badge.text() !== '') {
+                try {
+                    new_message += parseInt(badge.text(), 10);
+                } catch (e) {
+                    console.log(e);
+                }
+            }
+            badge.text(new_message.toFixed(0)).removeClass('invisible');
+            $(`#${chat.id}-tab:not([class*=active])`).one('click', (ev) => {
+                $('span.badge', ev.target).addClass('invisible');
+
+            });
+        }
+    }
+    if (data.length > 0) {
+        data.forEach(message => {
+            shoutbox_add_line(target, message);
+        });
+        let box_target = target.parent();
+        if (box_target.height() < box_target.prop('scrollHeight')) {
+            let scroll_top = box_target.prop('scrollHeight') - box_target.height();
+            box_target.prop('scrollTop', scroll_top);
+        }
+    }
+}
+
 function update_chatrooms() {
     if (chatrooms === undefined) {
         return;
     }
     for (let i = 0; i < chatrooms.length; i++) {
         let chat = chatrooms[i];
-        let target = $(`#shoutbox-${chat.id}>ul`);
         let url = `/api/v1/chat/messages?chat=${chat.nid}`;
         let first_run = true;
         if (chat.last_update !== undefined) {
@@ -138,40 +225,64 @@ function update_chatrooms() {
                     return;
                 }
                 chatrooms[i].last_update = (Date.now() / 1000).toFixed(0);
-                if (!first_run && data.length > 0) {
-                    let badge = $(`#${chat.id}-tab:not([class*=active]) span.badge`);
-                    if (badge.length === 1) {
-                        let new_message = data.length;
-                        if (badge.text() !== '') {
-                            try {
-                                console.log(parseInt(badge.text(), 10));
-                                new_message += parseInt(badge.text(), 10);
-                            } catch (e) {
-                                console.log(e);
-                            }
-                        }
-                        badge.text(new_message.toFixed(0)).removeClass('invisible');
-                        $(`#${chat.id}-tab:not([class*=active])`).one('click', (ev) => {
-                            $('span.badge', ev.target).addClass('invisible');
-
-                        });
-                    }
-                }
-                if (data.length > 0) {
-                    data.reverse().forEach(message => {
-                        shoutbox_add_line(target, message);
-                    });
-                    let box_target = target.parent();
-                    if (box_target.height() < box_target.prop('scrollHeight')) {
-                        let scroll_top = box_target.prop('scrollHeight') - box_target.height();
-                        box_target.prop('scrollTop', scroll_top);
-                    }
-                }
+                shoutbox_append(chat, data.reverse(), !first_run);
             })
             .catch(error => console.error('update_chatrooms(): error', error));
     }
 }
 
+// Receive chat messages via WebSocket, fall back to polling if the connection fails.
+function connect_chatrooms() {
+    if (chatrooms === undefined) {
+        return;
+    }
+    if (window.WebSocket === undefined) {
+        update_chatrooms();
+        window.setInterval(update_chatrooms, 5000);
+        return;
+    }
+    const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+    let socket = new WebSocket(`${protocol}//${window.location.host}/api/v1/chat/ws`);
+    let connected = false;
+    socket.onopen = () => {
+        connected = true;
+        for (let chat of chatrooms) {
+            socket.send(JSON.stringify({subscribe: chat.nid}));
+        }
+        // fetch the messages sent before or while connecting
+        update_chatrooms();
+    };
+    socket.onmessage = (ev) => {
+        let data = JSON.parse(ev.data);
+        if (data.error !== undefined) {
+            console.error('connect_chatrooms(): error', data.error);
+            return;
+        }
+        let chat = chatrooms.find(c => c.nid === data.chat);
+        if (chat !== undefined) {
+            let created_at = Math.floor(new Date(data.created_at).getTime() / 1000);
+            if (chat.last_update === undefined || created_at > Number(chat.last_update)) {
+                chat.last_update = created_at.toFixed(0);
+            }
+            shoutbox_append(chat, [data], true);
+        }
+    };
+    socket.onclose = () => {
+        if (connected) {
+            // reconnect after a lost connection
+            window.setTimeout(connect_chatrooms, 5000);
+        } else {
+            update_chatrooms();
+            window.setInterval(update_chatrooms, 5000);
+        }
+    };
+}
+
+// The room name is read from the data attribute, so it never ends up inside a script.
+function chatroom_delete_confirm(form) {
+    return confirm(`Delete ${$(form).attr('data-name')} and all of its messages?`);
+}
+
 function messages_mark(messages) {
     post_json('/api/v1/message/mark_read', {'messages': messages})
         .then((data) => {
@@ -199,4 +310,54 @@ function messages_delete(messages) {
             }
         })
         .catch(error => $('#message-error').removeClass('d-none').text(error));
+}
+
+function messages_move(messages, folder_id) {
+    post_json('/api/v1/message/move', {'messages': messages, 'folder_id': folder_id})
+        .then((data) => {
+            if (data.error !== undefined) {
+                $('#message-error').removeClass('d-none').text(data.error);
+                return;
+            }
+            for (let mid of data) {
+                $(`#message-${mid}`).remove();
+            }
+        })
+        .catch(error => $('#message-error').removeClass('d-none').text(error));
+}
+
+function message_folders_load() {
+    const system_folders = ['inbox', 'sent', 'system'];
+    const current = $('#message-folders').attr('data-folder');
+    get_json('/api/v1/message/folders')
+        .then((data) => {
+            let select = $('#msg-move-folder').empty();
+            let new_message = $('#message-folders a[href="/message/new"]');
+            for (let folder of data) {
+                select.append($('<option>').val(folder.id).text(folder.name));
+                if (system_folders.includes(folder.name)) {
+                    continue;
+                }
+                let link = $('<a class="list-group-item">')
+                    .attr('href', `/messages/${encodeURIComponent(folder.name)}`)
+                    .text(folder.name);
+                if (folder.name === current) {
+                    link.addClass('active');
+                }
+                link.insertBefore(new_message);
+            }
+        })
+        .catch(error => $('#message-error').removeClass('d-none').text(error));
+}
+
+function message_folder_create(name) {
+    post_json('/api/v1/message/folder/create', {'name': name})
+        .then((data) => {
+            if (data.error !== undefined) {
+                $('#message-error').removeClass('d-none').text(data.error);
+                return;
+            }
+            window.location = `/messages/${encodeURIComponent(data.name)}`;
+        })
+        .catch(error => $('#message-error').removeClass('d-none').text(error));
 }
\ No newline at end of file